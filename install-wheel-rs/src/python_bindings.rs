@@ -42,12 +42,62 @@ impl LockedVenv {
         })
     }
 
-    pub fn install_wheel(&self, py: Python, wheel: PathBuf) -> PyResult<()> {
-        // TODO: Pass those options on to the user
-        // unique_version can be anything since it's only used to monotrail
-        py.allow_threads(|| install_wheel(&self.location, &wheel, true, &[], ""))?;
+    /// Builds a `LockedVenv` that installs into a monotrail package store instead of a venv
+    #[staticmethod]
+    pub fn monotrail(py: Python, monotrail_root: PathBuf, python: PathBuf) -> PyResult<Self> {
+        Ok(Self {
+            location: InstallLocation::Monotrail {
+                monotrail_root: LockedDir::acquire(&monotrail_root)?,
+                python,
+                python_version: (py.version_info().major, py.version_info().minor),
+            },
+        })
+    }
+
+    /// Installs a single wheel.
+    ///
+    /// `unique_version` is only used for monotrail-style installs (ignored for a venv) and can be
+    /// any string that's unique to this install, e.g. a hash of the resolved requirements
+    #[args(compile = "true", extras = "vec![]", unique_version = "String::new()")]
+    pub fn install_wheel(
+        &self,
+        py: Python,
+        wheel: PathBuf,
+        compile: bool,
+        extras: Vec<String>,
+        unique_version: String,
+    ) -> PyResult<()> {
+        py.allow_threads(|| {
+            install_wheel(&self.location, &wheel, compile, &extras, &unique_version)
+        })?;
         Ok(())
     }
+
+    /// Installs a list of wheels under one `py.allow_threads`, returning the error for each wheel
+    /// that failed to install (an empty list means they all succeeded)
+    #[args(compile = "true", extras = "vec![]", unique_version = "String::new()")]
+    pub fn install_wheels(
+        &self,
+        py: Python,
+        wheels: Vec<PathBuf>,
+        compile: bool,
+        extras: Vec<String>,
+        unique_version: String,
+    ) -> PyResult<Vec<(PathBuf, String)>> {
+        let failures = py.allow_threads(|| {
+            wheels
+                .into_iter()
+                .filter_map(|wheel| {
+                    match install_wheel(&self.location, &wheel, compile, &extras, &unique_version)
+                    {
+                        Ok(()) => None,
+                        Err(err) => Some((wheel, err.to_string())),
+                    }
+                })
+                .collect()
+        });
+        Ok(failures)
+    }
 }
 
 #[pymodule]
@@ -0,0 +1,211 @@
+//! An `auditwheel`-style compliance check: derives the manylinux/musllinux tag a wheel's shared
+//! objects are *actually* compatible with, instead of trusting whatever tag is baked into the
+//! wheel filename (what [`crate::inject_and_run::compatible_platform_tags`] does for the
+//! consuming side).
+
+use anyhow::Context;
+use fs_err as fs;
+use goblin::elf::Elf;
+use std::collections::BTreeSet;
+use std::path::Path;
+use walkdir::WalkDir;
+
+/// Dynamic libraries a manylinux wheel is allowed to link against; anything else found in
+/// `DT_NEEDED` is a policy violation, since it means the wheel depends on something that isn't
+/// guaranteed to be on the target system
+const ALLOWED_LIBRARIES: &[&str] = &[
+    "libc.so",
+    "libm.so",
+    "libpthread.so",
+    "libdl.so",
+    "librt.so",
+    "libgcc_s.so",
+    "libstdc++.so",
+    "ld-linux",
+    "ld64.so",
+    "libresolv.so",
+    "libnsl.so",
+    "libutil.so",
+];
+
+/// Best-effort `GLIBCXX_x.y.z`/`CXXABI_x.y` -> required glibc `(major, minor)` table. libstdc++ and
+/// libgcc_s version their own symbols independently of glibc, so there's no formula from one to
+/// the other; this only covers commonly seen anchor points auditwheel itself hardcodes, not the
+/// full table, since the rest would need to be sourced from an actual auditwheel install
+const GLIBCXX_GLIBC_MAP: &[(&str, (u32, u32))] = &[
+    ("GLIBCXX_3.4", (2, 5)),
+    ("GLIBCXX_3.4.9", (2, 9)),
+    ("GLIBCXX_3.4.14", (2, 12)),
+    ("GLIBCXX_3.4.19", (2, 14)),
+    ("GLIBCXX_3.4.21", (2, 17)),
+    ("GLIBCXX_3.4.22", (2, 17)),
+    ("CXXABI_1.3", (2, 5)),
+    ("CXXABI_1.3.7", (2, 17)),
+];
+
+/// What auditing a wheel's shared objects found
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct AuditResult {
+    /// Every versioned symbol requirement collected from `.gnu.version_r`, e.g. `GLIBC_2.29` or
+    /// `GLIBCXX_3.4.21`
+    pub required_symbols: BTreeSet<String>,
+    /// The lowest `manylinux_{major}_{minor}` tag every required glibc symbol version is
+    /// satisfied by, or `None` if no `.so` in the wheel links against glibc at all
+    pub manylinux: Option<(u32, u32)>,
+    /// `DT_NEEDED` entries that aren't in [`ALLOWED_LIBRARIES`], by `.so` file (relative to the
+    /// wheel root) that requires them
+    pub forbidden_libraries: Vec<(String, String)>,
+    /// `.so` files (relative to the wheel root) that link against `libpython`, which a manylinux
+    /// wheel must not do (the extension has to work against any compatible CPython build)
+    pub links_libpython: Vec<String>,
+}
+
+impl AuditResult {
+    /// Whether the wheel has no forbidden libraries and no libpython linkage. The glibc
+    /// requirement in `manylinux` isn't part of this check; see [`Self::check_against_declared_policy`]
+    pub fn is_compliant(&self) -> bool {
+        self.forbidden_libraries.is_empty() && self.links_libpython.is_empty()
+    }
+
+    /// Checks the audited requirements against the manylinux policy a caller claims the wheel
+    /// satisfies (typically parsed from the wheel's own filename tag), erroring out if the
+    /// wheel's shared objects actually need something newer, or if it has any disallowed library
+    /// or libpython linkage
+    pub fn check_against_declared_policy(&self, declared: (u32, u32)) -> anyhow::Result<()> {
+        if let Some(required) = self.manylinux {
+            anyhow::ensure!(
+                required <= declared,
+                "Wheel declares manylinux_{}_{} but its shared objects require manylinux_{}_{}",
+                declared.0,
+                declared.1,
+                required.0,
+                required.1
+            );
+        }
+        anyhow::ensure!(
+            self.is_compliant(),
+            "Wheel links against disallowed libraries or libpython: {:?}, {:?}",
+            self.forbidden_libraries,
+            self.links_libpython
+        );
+        Ok(())
+    }
+}
+
+/// Walks every `.so` under `extracted_wheel_dir` and merges each file's audit into one result
+pub fn audit_wheel(extracted_wheel_dir: &Path) -> anyhow::Result<AuditResult> {
+    let mut result = AuditResult::default();
+    for entry in WalkDir::new(extracted_wheel_dir) {
+        let entry = entry.context("Failed to walk extracted wheel")?;
+        if entry.file_type().is_file()
+            && entry
+                .path()
+                .extension()
+                .map(|extension| extension == "so")
+                .unwrap_or(false)
+            || entry
+                .path()
+                .to_string_lossy()
+                .contains(".so.")
+        {
+            let relative = entry
+                .path()
+                .strip_prefix(extracted_wheel_dir)
+                .unwrap_or(entry.path())
+                .to_string_lossy()
+                .to_string();
+            let elf_result = audit_elf(entry.path())
+                .with_context(|| format!("Failed to audit {}", relative))?;
+            result.required_symbols.extend(elf_result.required_symbols);
+            result.manylinux = match (result.manylinux, elf_result.manylinux) {
+                (None, other) => other,
+                (existing, None) => existing,
+                (Some(a), Some(b)) => Some(a.max(b)),
+            };
+            result.forbidden_libraries.extend(
+                elf_result
+                    .forbidden_libraries
+                    .into_iter()
+                    .map(|(_, library)| (relative.clone(), library)),
+            );
+            if elf_result.links_libpython.first().is_some() {
+                result.links_libpython.push(relative);
+            }
+        }
+    }
+    Ok(result)
+}
+
+/// Audits a single ELF shared object: collects every `GLIBC_x.y`/`GLIBCXX_x.y.z`/`CXXABI_x.y`
+/// version requirement from its `.gnu.version_r` (verneed) section, maps the maximum one to the
+/// manylinux tag it requires, and checks `DT_NEEDED` against [`ALLOWED_LIBRARIES`]
+///
+/// In the returned result, `forbidden_libraries` is keyed by `so_path` exactly as given and
+/// `links_libpython` lists the matching `libpython` library names; [`audit_wheel`] replaces both
+/// with the `.so` path relative to the wheel root
+pub fn audit_elf(so_path: &Path) -> anyhow::Result<AuditResult> {
+    let bytes = fs::read(so_path)?;
+    let elf = Elf::parse(&bytes).with_context(|| format!("{} is not a valid ELF file", so_path.display()))?;
+
+    let mut manylinux = None;
+    let mut required_symbols = BTreeSet::new();
+    if let Some(verneed) = &elf.verneed {
+        for (need, auxes) in verneed.iter() {
+            let library = elf
+                .dynstrtab
+                .get_at(need.vn_file)
+                .unwrap_or_default();
+            for aux in auxes.iter() {
+                let version = elf.dynstrtab.get_at(aux.vna_name).unwrap_or_default();
+                required_symbols.insert(version.to_string());
+                let required = if library == "GLIBC" || version.starts_with("GLIBC_") {
+                    parse_major_minor(version.trim_start_matches("GLIBC_"))
+                } else if version.starts_with("GLIBCXX_") || version.starts_with("CXXABI_") {
+                    GLIBCXX_GLIBC_MAP
+                        .iter()
+                        .find(|(needle, _)| *needle == version)
+                        .map(|(_, glibc)| *glibc)
+                } else {
+                    None
+                };
+                manylinux = match (manylinux, required) {
+                    (None, other) => other,
+                    (existing, None) => existing,
+                    (Some(a), Some(b)) => Some(a.max(b)),
+                };
+            }
+        }
+    }
+
+    let needed: BTreeSet<&str> = elf.libraries.iter().copied().collect();
+    let forbidden_libraries = needed
+        .into_iter()
+        .filter(|library| !is_allowed(library))
+        .map(|library| (so_path.to_string_lossy().to_string(), library.to_string()))
+        .collect::<Vec<_>>();
+    let links_libpython = elf
+        .libraries
+        .iter()
+        .filter(|library| library.starts_with("libpython"))
+        .map(|library| library.to_string())
+        .collect();
+
+    Ok(AuditResult {
+        required_symbols,
+        manylinux,
+        forbidden_libraries,
+        links_libpython,
+    })
+}
+
+fn is_allowed(library: &str) -> bool {
+    library.starts_with("libpython") || ALLOWED_LIBRARIES.iter().any(|allowed| library.starts_with(allowed))
+}
+
+/// Parses `"2.17"` into `(2, 17)`, ignoring anything after the minor component (e.g. a patch
+/// level some distros append)
+fn parse_major_minor(version: &str) -> Option<(u32, u32)> {
+    let (major, rest) = version.split_once('.')?;
+    let minor = rest.split('.').next()?;
+    Some((major.parse().ok()?, minor.parse().ok()?))
+}
@@ -1,26 +1,37 @@
-use crate::inject_and_run::{parse_plus_arg, run_python_args};
+use crate::env_registry;
+use crate::gc::{gc, DEFAULT_NEVER_REMOVE};
+use crate::inject_and_run::{discover_python_versions, parse_plus_arg, run_python_args};
 use crate::install::{filter_installed, install_all};
 use crate::markers::marker_environment_from_python;
-use crate::monotrail::{cli_from_git, monotrail_root, run_command};
+use crate::monotrail::{cli_from_git, monotrail_root, run_command, FinderData};
+use crate::nix_export::export_nix;
 use crate::package_index::download_distribution;
 use crate::poetry_integration::read_dependencies::{read_poetry_specs, read_toml_files};
 use crate::poetry_integration::run::poetry_run;
 use crate::ppipx;
-use crate::requirements_txt::RequirementsTxt;
+use crate::ppipx::{ppipx_list, ppipx_uninstall, ppipx_upgrade};
+use crate::poetry_integration::lock::ResolutionMode;
+use crate::publish::publish;
+use crate::requirements_txt::{RequirementOrUrl, RequirementsTxt};
 use crate::spec::RequestedSpec;
+use crate::standalone_python::{fetch_versions, install_python, list_installed};
 use crate::utils::cache_dir;
 use crate::venv_parser::get_venv_python_version;
 use crate::verify_installation::verify_installation;
 use anyhow::{bail, Context};
 use clap::Parser;
-use install_wheel_rs::{compatible_tags, Arch, InstallLocation, Os, WheelInstallerError};
+use fs_err as fs;
+use install_wheel_rs::{
+    compatible_tags, Arch, InstallLocation, InterpreterKind, Os, WheelInstallerError,
+};
 use pep440_rs::Operator;
 use pep508_rs::VersionOrUrl;
 use std::env;
 use std::env::current_dir;
+use std::ffi::OsStr;
 use std::path::{Path, PathBuf};
 use std::process::Command;
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
 
 #[derive(Parser, Debug)]
 pub struct PoetryOptions {
@@ -36,12 +47,34 @@ pub struct PoetryOptions {
     /// Directory with the pyproject.toml, defaults to the current directory
     #[clap(long)]
     root: Option<PathBuf>,
-    /// Only relevant for venv install
-    #[clap(long)]
-    skip_existing: bool,
     /// Compile python sources to bytecode
     #[clap(long)]
     compile: bool,
+    /// Install into a dedicated, reusable venv for this project at this python version (e.g.
+    /// `3.9`) instead of the one found by `--venv`/`VIRTUAL_ENV`/`.venv` -- created on first use
+    /// and rebuilt if its interpreter moves on, see [`crate::env_registry::find_or_create_env`]
+    #[clap(long, short = 'p')]
+    python_version: Option<String>,
+}
+
+/// `monotrail env create/list/remove`, see [`crate::env_registry`]
+#[derive(clap::Subcommand, Debug)]
+pub enum EnvCmd {
+    /// Create (or reuse, if already up to date) the venv for a project at a given python version
+    Create {
+        /// e.g. `3.9` or `3.9.12`
+        python_version: String,
+        /// Directory with the pyproject.toml, defaults to the current directory
+        #[clap(long)]
+        root: Option<PathBuf>,
+    },
+    /// List every registered venv
+    List,
+    /// Remove a registered venv by name (as shown by `list`)
+    Remove {
+        /// The venv's name, as shown by `monotrail env list`
+        name: String,
+    },
 }
 
 /// Either `python ...` or `command ...`
@@ -52,9 +85,23 @@ pub enum RunSubcommand {
     Args(Vec<String>),
 }
 
-/// The main cli
+/// Top-level CLI entry point: a global `--directory` override parsed before dispatching to
+/// whichever [`Cli`] subcommand was invoked
 #[derive(Parser, Debug)]
 #[clap(version)]
+pub struct Args {
+    /// Run as if invoked from this directory instead of the current one, overriding every
+    /// subcommand's own working-directory default (venv discovery, poetry file reading,
+    /// requirements resolution) uniformly -- the same thing `Run`/`PoetryInstall`'s own `--root`
+    /// already does for just those two
+    #[clap(long, global = true)]
+    pub directory: Option<PathBuf>,
+    #[clap(subcommand)]
+    pub command: Cli,
+}
+
+/// The main cli
+#[derive(clap::Subcommand, Debug)]
 pub enum Cli {
     /// Run with `python` or `command`. This features two subcommands that we unfortunately can't
     /// have as proper subcommands due to a clap bug
@@ -85,9 +132,20 @@ pub enum Cli {
         /// the other, just like tox
         #[clap(long, short)]
         python_version: Vec<String>,
-        /// Directory with the pyproject.toml, defaults to the current directory
+        /// Directory with the pyproject.toml, defaults to the current directory. Also used to
+        /// look up a `.python-version` file, so e.g. `monotrail run --root ~/tools/foo python ...`
+        /// behaves like an installed tool regardless of where it's invoked from
         #[clap(long)]
         root: Option<PathBuf>,
+        /// Resolve dependencies to the lowest version matching their constraints instead of the
+        /// highest, to catch code that actually requires a newer API than it declares
+        #[clap(long)]
+        lowest: bool,
+        /// execline-style chaining: if the command exits 0, exec into `prog arg1 arg2 ...` given
+        /// after a `--` separator, reusing the environment (`PATH`, `sys_executable` shim, ...)
+        /// that was set up for the command just run
+        #[clap(long)]
+        exec_into: bool,
         /// Either `python ...` or `command ...`
         #[clap(subcommand)]
         action: RunSubcommand,
@@ -113,6 +171,23 @@ pub enum Cli {
         /// limitations in clap (https://github.com/clap-rs/clap/discussions/3766)
         args: Vec<String>,
     },
+    /// Lists all packages installed through `ppipx`, with their pinned version, extras and the
+    /// commands they expose
+    PpipxList,
+    /// Removes a package installed through `ppipx`, including all its pinned versions
+    PpipxUninstall {
+        /// The directory name under `ppipx`'s data dir, e.g. `black` or `nox[tox_to_nox]`
+        package_extras: String,
+    },
+    /// Re-resolves the `latest` entry of a package installed through `ppipx`, so a newer matching
+    /// release gets picked up
+    PpipxUpgrade {
+        /// The directory name under `ppipx`'s data dir, e.g. `black` or `nox[tox_to_nox]`
+        package_extras: String,
+        /// Run this python version x.y
+        #[clap(long, short)]
+        python_version: Option<String>,
+    },
     /// Like `git pull <repo> <tmpdir> && cd <tmpdir> && git checkout <rev> && monotrail run <...>`,
     /// mostly here to mirror the python `monotrail.from_git()` function
     FromGit {
@@ -143,6 +218,21 @@ pub enum Cli {
         /// arguments passed verbatim to poetry
         args: Vec<String>,
     },
+    /// Resolves the project's pyproject.toml with the bundled poetry and writes (or refreshes) its
+    /// poetry.lock, the same lockfile `install`/`poetry-install` read directly afterwards (see
+    /// [`read_poetry_specs`]) without invoking poetry again. A thin, discoverable wrapper around
+    /// `monotrail poetry lock`
+    Lock {
+        /// Fail instead of writing if the on-disk poetry.lock is stale, without resolving anything
+        #[clap(long)]
+        check: bool,
+    },
+    /// Manage named, reusable per-project venvs, the same ones `poetry-install -p x.y` looks up
+    /// or creates on demand (see [`crate::env_registry`])
+    Env {
+        #[clap(subcommand)]
+        cmd: EnvCmd,
+    },
     /// Installs the (currently frozen only) dependencies in a virtualenv environment
     ///
     /// Currently, you can either use `-r requirements.txt`, it will use a poetry.lock or error.
@@ -156,6 +246,11 @@ pub enum Cli {
         /// Requirements are already resolved, if not not we'll resolve them (currently with poetry)
         #[clap(long)]
         frozen: bool,
+        /// Require every requirement to pin at least one `--hash` in the requirements.txt and
+        /// verify the downloaded (or cache-hit) artifact against it before installing, like pip's
+        /// hash-checking mode. Rejects the whole install if any requirement has no hash pinned
+        #[clap(long)]
+        require_hashes: bool,
         /// Run single threaded (mostly for profiling)
         #[clap(long)]
         no_parallel: bool,
@@ -177,17 +272,106 @@ pub enum Cli {
         #[clap(flatten)]
         options: PoetryOptions,
     },
+    /// Downloads and unpacks a standalone CPython build into the managed interpreter cache, the
+    /// same cache `run`/`ppipx`/`poetry run` already read from automatically. Useful to pre-warm
+    /// that cache (e.g. in CI, before going offline) or to pin an exact patch instead of whatever
+    /// the automatic provisioning picks
+    PythonInstall {
+        /// The python version to install: `x.y` for the newest matching patch, or `x.y.z` to pin
+        /// an exact patch, e.g. `3.9` or `3.9.12`
+        version: String,
+        /// Remove and re-download an already-cached install instead of reusing it
+        #[clap(long)]
+        force: bool,
+    },
+    /// Lists the standalone CPython builds currently in the managed interpreter cache
+    PythonList,
+    /// Maintenance command: regenerates `versions.json`, the checked-in manifest
+    /// [`crate::standalone_python::find_python`] consults before falling back to a live lookup
+    /// against indygreg/python-build-standalone's releases. Run this and commit the result whenever
+    /// the set of interpreters we offer should change
+    FetchVersions {
+        /// Where to write the regenerated manifest. Defaults to the `versions.json` actually
+        /// embedded by `include_str!` at the crate root, not whatever the current directory
+        /// happens to be, so the default always updates the file the binary was built from
+        #[clap(long, default_value = concat!(env!("CARGO_MANIFEST_DIR"), "/versions.json"))]
+        output: PathBuf,
+    },
+    /// Removes installed packages that are no longer referenced by any of the given `poetry.lock`
+    /// files or by any project's tracking manifest (written automatically whenever `install`
+    /// resolves a lockfile on disk), to reclaim space in the monotrail content store
+    Gc {
+        /// Extra `poetry.lock` files whose packages should be considered still in use, beyond
+        /// what's already tracked; anything installed that isn't reachable from one of these, a
+        /// tracked project, or `--never-remove` is removed
+        #[clap(long)]
+        lockfile: Vec<PathBuf>,
+        /// Package names that are never removed even if unreferenced, e.g. because they're
+        /// needed to bootstrap installation itself. Defaults to pip, setuptools and wheel
+        #[clap(long)]
+        never_remove: Vec<String>,
+        /// Report reclaimable space without actually removing anything
+        #[clap(long)]
+        dry_run: bool,
+    },
+    /// Turns a resolved `FinderData` (e.g. dumped through the python bindings) into a set of
+    /// fixed-output Nix derivations that rebuild the same sprawl layout offline, for pinning a
+    /// monotrail environment into a reproducible artifact
+    ExportNix {
+        /// Json-serialized `FinderData`, as produced by `monotrail.monotrail_from_dir(...).to_json()`
+        finder_data: PathBuf,
+        /// Where to write the generated `.nix` file
+        #[clap(long)]
+        output: PathBuf,
+    },
+    /// Uploads built wheels/sdists to PyPI or a custom repository, like `twine upload`
+    ///
+    /// Repository urls and credentials are read from `MONOTRAIL_REPOSITORIES_<NAME>_URL`,
+    /// `MONOTRAIL_HTTP_BASIC_<NAME>_USERNAME`/`_PASSWORD`, or a `[repositories.<name>]` table in
+    /// `publish.toml` under the monotrail data dir; `--repository pypi` (the default) needs
+    /// neither, since it already knows PyPI's upload url
+    Publish {
+        /// The wheels/sdists to upload. Defaults to every `.whl`/sdist archive directly under
+        /// `dist/` if none are given
+        artifacts: Vec<PathBuf>,
+        /// The repository to upload to, looked up in `publish.toml`/the environment
+        #[clap(long, default_value = "pypi")]
+        repository: String,
+        /// Don't actually upload anything, just print what would be uploaded and where
+        #[clap(long)]
+        dry_run: bool,
+        /// Treat an artifact the repository already has as success instead of an error
+        #[clap(long)]
+        skip_existing: bool,
+    },
 }
 
-/// Builds cache filename, downloads if not present, returns cache filename
+/// Where a downloaded artifact for `name`/`version`/`filename` lives in the cache, shared between
+/// [`download_distribution_cached`] (which downloads into it one file at a time) and
+/// [`crate::install::install_from_lock`] (which pre-fetches a whole batch of these through
+/// [`crate::package_index::download_distributions`] before checking this same path)
+pub fn artifact_cache_path(name: &str, version: &str, filename: &str) -> anyhow::Result<PathBuf> {
+    Ok(cache_dir()?
+        .join("artifacts")
+        .join(name)
+        .join(version)
+        .join(filename))
+}
+
+/// Builds cache filename, downloads if not present, returns cache filename. `expected_hash`, if
+/// given, is always checked before this returns: a freshly downloaded file is hashed while it
+/// streams to disk (see [`crate::package_index::download_distribution`]), and a cache hit is
+/// hashed by re-reading it from disk, so a stale or corrupted cache entry can't silently defeat
+/// the check just because it was downloaded on an earlier run
 pub fn download_distribution_cached(
     name: &str,
     version: &str,
     filename: &str,
     url: &str,
+    credentials: Option<&(String, String)>,
+    expected_hash: Option<&str>,
 ) -> anyhow::Result<PathBuf> {
-    let target_dir = cache_dir()?.join("artifacts").join(name).join(version);
-    let target_file = target_dir.join(&filename);
+    let target_file = artifact_cache_path(name, version, filename)?;
 
     if target_file.is_file() {
         debug!(
@@ -196,12 +380,19 @@ pub fn download_distribution_cached(
             version,
             target_file.display()
         );
+        if let Some(expected_hash) = expected_hash {
+            crate::install::check_file_hash(&target_file, expected_hash)?;
+        }
         return Ok(target_file);
     }
 
     // TODO: Lookup size and show it somewhere if it's large
     debug!("Downloading {} {}", name, version);
-    download_distribution(url, &target_dir, &target_file)?;
+    let target_dir = target_file
+        .parent()
+        .expect("artifact_cache_path always has a parent")
+        .to_path_buf();
+    download_distribution(url, &target_dir, &target_file, credentials, expected_hash)?;
 
     Ok(target_file)
 }
@@ -217,6 +408,7 @@ fn poetry_install(
         get_venv_python_version(venv)?,
         &Os::current()?,
         &Arch::current()?,
+        &InterpreterKind::CPython,
     )?;
     // TODO: don't parse this from a subprocess but do it like maturin
     let pep508_env = marker_environment_from_python(Path::new("python"));
@@ -225,6 +417,24 @@ fn poetry_install(
     } else {
         env::current_dir()?
     };
+    // There's no `--python-version` override here (unlike `Run`): the venv we're installing into
+    // already pins the interpreter, so a `.python-version` file can't change which one we use --
+    // but it's still worth surfacing a mismatch, the same way an explicit `-p` and a differing
+    // file warn each other in `determine_python_version`
+    if let Some(version_file) = crate::inject_and_run::find_python_version_file(&dir) {
+        if let Ok(file_version) = crate::inject_and_run::read_python_version_file(&version_file) {
+            if file_version != python_version {
+                warn!(
+                    "Installing into a python {}.{} venv, but {} pins {}.{}",
+                    python_version.0,
+                    python_version.1,
+                    version_file.display(),
+                    file_version.0,
+                    file_version.1
+                );
+            }
+        }
+    }
     let (poetry_section, poetry_lock, _lockfile) =
         read_toml_files(&dir).context("Failed to read poetry files")?;
     let specs = read_poetry_specs(
@@ -258,11 +468,9 @@ fn poetry_install(
     };
 
     let location = location.acquire_lock()?;
-    let (to_install, mut installed_done) = if options.skip_existing || options.monotrail {
-        filter_installed(&location, &specs, &compatible_tags)?
-    } else {
-        (specs, Vec::new())
-    };
+    // Always check what's already satisfied instead of reinstalling everything: cheap (just
+    // reads dist-info already on disk) and avoids redundant wheel downloads on a repeated install
+    let (to_install, mut installed_done) = filter_installed(&location, &specs, &compatible_tags)?;
     let mut installed_new = install_all(
         &to_install,
         &location,
@@ -283,12 +491,16 @@ pub fn install(
     compile: bool,
     no_parallel: bool,
     frozen: bool,
+    require_hashes: bool,
     venv: Option<&Path>,
     working_dir: Option<&Path>,
 ) -> anyhow::Result<Option<i32>> {
     if !frozen {
         bail!("Needs to be frozen for now");
     }
+    if require_hashes && requirements_files.is_empty() {
+        bail!("--require-hashes only applies to requirements.txt installs, not poetry.lock");
+    }
     let venv = find_venv(venv)?;
     let working_dir = match working_dir {
         None => current_dir().context("Couldn't get current directory ಠ_ಠ")?,
@@ -329,13 +541,29 @@ pub fn install(
             bail!("You can't use requirements files with constraints (`-c`) for installing");
         }
 
-        // TODO(konstin): We lose the hashes here
         requirements
             .requirements
             .iter()
             .map(|req| {
+                let requirement = match &req.requirement {
+                    RequirementOrUrl::NamedRequirement(requirement) => requirement,
+                    RequirementOrUrl::Url(url_requirement) => {
+                        bail!(
+                            "Expected a pinned name==version requirement, found unnamed \
+                             requirement '{}'",
+                            url_requirement.url
+                        );
+                    }
+                };
+                if require_hashes && req.hashes.is_empty() {
+                    bail!(
+                        "In --require-hashes mode, every requirement must have its hashes \
+                         pinned with --hash, but {} has none",
+                        requirement.name
+                    );
+                }
                 if let Some(VersionOrUrl::VersionSpecifier(specifiers)) =
-                    &req.requirement.version_or_url
+                    &requirement.version_or_url
                 {
                     let version = if let [specifier] = specifiers.as_ref() {
                         if *specifier.operator() == Operator::Equal {
@@ -353,26 +581,36 @@ pub fn install(
                         );
                     };
                     Ok(RequestedSpec {
-                        requested: req.to_string(),
-                        name: req.requirement.name.clone(),
+                        requested: requirement.to_string(),
+                        name: requirement.name.clone(),
                         python_version: Some(version.to_string()),
                         source: None,
                         extras: vec![],
                         file_path: None,
                         url: None,
+                        file_hash: None,
+                        hashes: req.hashes.clone(),
                     })
                 } else {
-                    bail!("Missing version for requirement {}", req.requirement.name);
+                    bail!("Missing version for requirement {}", requirement.name);
                 }
             })
             .collect::<Result<_, _>>()?
     };
 
-    let compatible_tags = compatible_tags(python_version, &Os::current()?, &Arch::current()?)?;
+    let compatible_tags = compatible_tags(
+        python_version,
+        &Os::current()?,
+        &Arch::current()?,
+        &InterpreterKind::CPython,
+    )?;
     let location = location.acquire_lock()?;
 
+    // Skip specs that are already satisfied in the venv instead of reinstalling everything
+    let (to_install, _installed) = filter_installed(&location, &specs, &compatible_tags)?;
+
     install_all(
-        &specs,
+        &to_install,
         &location,
         &compatible_tags,
         compile.clone(),
@@ -387,23 +625,91 @@ pub fn install(
 
 /// Dispatches from the Cli
 ///
-/// The second parameter exists to override the venv in tests
-pub fn run_cli(cli: Cli, venv: Option<&Path>) -> anyhow::Result<Option<i32>> {
+/// The second parameter exists to override the venv in tests. `directory`, if given (the
+/// top-level `--directory` flag), is canonicalized and chdir'd into before dispatching, so every
+/// subcommand's own `current_dir()`-based defaults (venv discovery, poetry file reading,
+/// requirements resolution) pick it up uniformly without each one having to thread it through
+pub fn run_cli(
+    cli: Cli,
+    venv: Option<&Path>,
+    directory: Option<&Path>,
+) -> anyhow::Result<Option<i32>> {
+    if let Some(directory) = directory {
+        let directory = directory
+            .canonicalize()
+            .with_context(|| format!("--directory {} doesn't exist", directory.display()))?;
+        env::set_current_dir(&directory)
+            .with_context(|| format!("Couldn't change into --directory {}", directory.display()))?;
+    }
     match cli {
         Cli::Install {
             requirement,
             compile,
             no_parallel,
             frozen,
-        } => install(&requirement, compile, no_parallel, frozen, None, None),
+            require_hashes,
+        } => install(
+            &requirement,
+            compile,
+            no_parallel,
+            frozen,
+            require_hashes,
+            None,
+            None,
+        ),
         Cli::Run {
             extras,
             python_version,
             root,
+            lowest,
+            exec_into,
             action,
         } => {
             let RunSubcommand::Args(args) = action;
             let trail_args = args[1..].to_vec();
+            let resolution_mode = if lowest {
+                ResolutionMode::LowestDirect
+            } else {
+                ResolutionMode::Highest
+            };
+            let (trail_args, exec_into_args) = if exec_into {
+                match trail_args.iter().position(|arg| arg == "--") {
+                    Some(pos) => {
+                        let (before, after) = trail_args.split_at(pos);
+                        (before.to_vec(), Some(after[1..].to_vec()))
+                    }
+                    None => {
+                        bail!(
+                            "--exec-into requires a `--` separator before the program to exec into"
+                        )
+                    }
+                }
+            } else {
+                (trail_args, None)
+            };
+
+            // No `-p` at all: let a `.python-version`/`.python-versions` file in `root` (or the
+            // current directory) fill in the version list, so a file with several lines drives
+            // the tox-style multi-run loop below exactly as if they'd been passed as repeated
+            // `-p` flags. A file with a single line falls into the `len() <= 1` single-run branch
+            // below either way, which already discovers the very same file on its own through
+            // `run_python_args`/`run_command`, so this is a no-op for that case, not a second,
+            // diverging lookup.
+            let python_version = if python_version.is_empty() {
+                match discover_python_versions(root.as_deref()) {
+                    Some((path, versions)) if versions.len() > 1 => {
+                        debug!(
+                            "Using python version(s) {:?} from {}",
+                            versions,
+                            path.display()
+                        );
+                        versions
+                    }
+                    _ => python_version,
+                }
+            } else {
+                python_version
+            };
 
             if python_version.len() <= 1 {
                 let exit_code = match args[0].as_str() {
@@ -412,14 +718,16 @@ pub fn run_cli(cli: Cli, venv: Option<&Path>) -> anyhow::Result<Option<i32>> {
                         python_version.first().map(|x| x.as_str()),
                         root.as_deref(),
                         &extras,
+                        resolution_mode,
                     )?,
                     "command" => run_command(
                         &extras,
                         python_version.first().map(|x| x.as_str()),
                         root.as_deref(),
-                        // If there's no command this will show an error downstream
-                        &args.get(1).unwrap_or(&"".to_string()),
+                        // Without a command name, this lists the installed scripts instead
+                        args.get(1).map(|x| x.as_str()),
                         &trail_args,
+                        exec_into_args.as_deref(),
                     )?,
                     other => bail!("invalid command `{}`, must be 'python' or 'command'", other),
                 };
@@ -436,7 +744,14 @@ pub fn run_cli(cli: Cli, venv: Option<&Path>) -> anyhow::Result<Option<i32>> {
                     // extended to run this in parallel.
                     // Would be nicer to use a fork wrapper here
                     let status = Command::new(env::current_exe()?)
-                        .args(&["run", "-p", &version, "python"])
+                        .args(&["run", "-p", &version])
+                        .args(lowest.then(|| "--lowest").into_iter())
+                        .args(
+                            root.as_deref()
+                                .into_iter()
+                                .flat_map(|root| [OsStr::new("--root"), root.as_os_str()]),
+                        )
+                        .arg("python")
                         .args(&trail_args)
                         .status()
                         .context("Failed to start child process for python version")?;
@@ -461,6 +776,41 @@ pub fn run_cli(cli: Cli, venv: Option<&Path>) -> anyhow::Result<Option<i32>> {
             &args[0],
             &args,
         )?)),
+        Cli::PpipxList => {
+            let entries = ppipx_list()?;
+            if entries.is_empty() {
+                println!("Nothing installed through ppipx yet");
+            } else {
+                for entry in entries {
+                    println!(
+                        "{} {} ({}){}",
+                        entry.package,
+                        entry.version,
+                        entry.package_extras,
+                        if entry.extras.is_empty() {
+                            String::new()
+                        } else {
+                            format!(" [{}]", entry.extras.join(", "))
+                        }
+                    );
+                    for command in entry.commands {
+                        println!("  - {}", command);
+                    }
+                }
+            }
+            Ok(None)
+        }
+        Cli::PpipxUninstall { package_extras } => {
+            ppipx_uninstall(&package_extras)?;
+            Ok(None)
+        }
+        Cli::PpipxUpgrade {
+            package_extras,
+            python_version,
+        } => {
+            ppipx_upgrade(&package_extras, python_version.as_deref())?;
+            Ok(None)
+        }
         Cli::VerifyInstallation { verbose } => {
             let root = monotrail_root().context("Couldn't determine root")?;
 
@@ -489,6 +839,32 @@ pub fn run_cli(cli: Cli, venv: Option<&Path>) -> anyhow::Result<Option<i32>> {
             Ok(None)
         }
         Cli::Poetry { args } => Ok(Some(poetry_run(&args, None)?)),
+        Cli::Lock { check } => {
+            let mut args = vec!["lock".to_string()];
+            if check {
+                args.push("--check".to_string());
+            }
+            Ok(Some(poetry_run(&args, None)?))
+        }
+        Cli::Env { cmd } => {
+            match cmd {
+                EnvCmd::Create {
+                    python_version,
+                    root,
+                } => {
+                    let root = root.map(Ok).unwrap_or_else(current_dir)?;
+                    let venv_path = env_registry::find_or_create_env(&root, &python_version)?;
+                    println!("{}", venv_path.display());
+                }
+                EnvCmd::List => {
+                    for env in env_registry::list_envs()? {
+                        println!("{}", env);
+                    }
+                }
+                EnvCmd::Remove { name } => env_registry::remove_env(&name)?,
+            }
+            Ok(None)
+        }
         Cli::WheelInstall {
             targets,
             compile,
@@ -502,6 +878,7 @@ pub fn run_cli(cli: Cli, venv: Option<&Path>) -> anyhow::Result<Option<i32>> {
                 get_venv_python_version(&venv)?,
                 &Os::current()?,
                 &Arch::current()?,
+                &InterpreterKind::CPython,
             )?;
             let location = InstallLocation::Venv {
                 venv_base: venv_canon,
@@ -524,13 +901,53 @@ pub fn run_cli(cli: Cli, venv: Option<&Path>) -> anyhow::Result<Option<i32>> {
             Ok(None)
         }
         Cli::PoetryInstall { options } => {
-            let venv = find_venv(venv)?;
+            let venv = match &options.python_version {
+                Some(python_version) => {
+                    let root = options.root.clone().map(Ok).unwrap_or_else(current_dir)?;
+                    env_registry::find_or_create_env(&root, python_version)?
+                }
+                None => find_venv(venv)?,
+            };
             let python_version = get_venv_python_version(&venv)?;
             let venv_canon = venv.canonicalize()?;
             poetry_install(&venv, python_version, &venv_canon, &options)
                 .context("Failed to download and install")?;
             Ok(None)
         }
+        Cli::PythonInstall { version, force } => {
+            let (major, minor, patch) = install_python(&version, force)
+                .with_context(|| format!("Failed to install python {}", version))?;
+            match patch {
+                Some(patch) => println!("Installed python {}.{}.{}", major, minor, patch),
+                None => println!("python {}.{} already installed", major, minor),
+            }
+            Ok(None)
+        }
+        Cli::PythonList => {
+            let installed = list_installed()?;
+            if installed.is_empty() {
+                println!("No managed python installs found");
+            }
+            for python in installed {
+                let patch = python
+                    .patch
+                    .map(|patch| patch.to_string())
+                    .unwrap_or_else(|| "latest".to_string());
+                let flavor = if python.full { "full" } else { "install_only" };
+                let status = if python.ok { "ok" } else { "broken" };
+                println!(
+                    "{}.{}.{} ({}, {})",
+                    python.major, python.minor, patch, flavor, status
+                );
+            }
+            Ok(None)
+        }
+        Cli::FetchVersions { output } => {
+            let count = fetch_versions(&output)
+                .with_context(|| format!("Failed to regenerate {}", output.display()))?;
+            println!("Wrote {} entries to {}", count, output.display());
+            Ok(None)
+        }
         Cli::FromGit {
             git_url,
             revision,
@@ -541,7 +958,102 @@ pub fn run_cli(cli: Cli, venv: Option<&Path>) -> anyhow::Result<Option<i32>> {
             let RunSubcommand::Args(args) = action;
             cli_from_git(&git_url, &revision, &extras, python_version, &args)
         }
+        Cli::Gc {
+            lockfile,
+            never_remove,
+            dry_run,
+        } => {
+            let root = monotrail_root().context("Couldn't determine root")?;
+            let lockfiles = lockfile
+                .iter()
+                .map(|path| {
+                    fs::read_to_string(path)
+                        .with_context(|| format!("Failed to read {}", path.display()))
+                })
+                .collect::<anyhow::Result<Vec<String>>>()?;
+            let never_remove = if never_remove.is_empty() {
+                DEFAULT_NEVER_REMOVE
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect()
+            } else {
+                never_remove
+            };
+
+            let report = gc(&root, &lockfiles, &never_remove, dry_run)?;
+            if dry_run {
+                println!(
+                    "Would remove {} package(s), reclaiming {} bytes",
+                    report.removed.len(),
+                    report.bytes_reclaimed
+                );
+            } else {
+                println!(
+                    "Removed {} package(s), reclaiming {} bytes",
+                    report.removed.len(),
+                    report.bytes_reclaimed
+                );
+            }
+            for (name, version, tag) in &report.removed {
+                debug!("Removed {} {} {}", name, version, tag);
+            }
+            Ok(None)
+        }
+        Cli::ExportNix {
+            finder_data,
+            output,
+        } => {
+            let finder_data: FinderData = serde_json::from_str(
+                &fs::read_to_string(&finder_data)
+                    .with_context(|| format!("Failed to read {}", finder_data.display()))?,
+            )
+            .context("Invalid FinderData json")?;
+            let nix = export_nix(&finder_data).context("Failed to generate the nix expression")?;
+            fs::write(&output, nix)
+                .with_context(|| format!("Failed to write {}", output.display()))?;
+            Ok(None)
+        }
+        Cli::Publish {
+            artifacts,
+            repository,
+            dry_run,
+            skip_existing,
+        } => {
+            let artifacts = if artifacts.is_empty() {
+                discover_dist_artifacts()?
+            } else {
+                artifacts
+            };
+            let uploaded = publish(&artifacts, &repository, skip_existing, dry_run)?;
+            println!("Uploaded {} artifact(s) to {}", uploaded, repository);
+            Ok(None)
+        }
+    }
+}
+
+/// Collects every `.whl`/`.tar.gz`/`.tgz`/`.zip` directly under `dist/` (not recursively), mirroring
+/// what `twine upload dist/*` and `poetry publish` default to
+fn discover_dist_artifacts() -> anyhow::Result<Vec<PathBuf>> {
+    let dist_dir = Path::new("dist");
+    let mut artifacts: Vec<_> = fs::read_dir(dist_dir)
+        .with_context(|| format!("Failed to read {}", dist_dir.display()))?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.extension()
+                .and_then(|ext| ext.to_str())
+                .map(|ext| matches!(ext, "whl" | "gz" | "tgz" | "zip"))
+                .unwrap_or(false)
+        })
+        .collect();
+    if artifacts.is_empty() {
+        bail!(
+            "No wheels/sdists given and none found in {}",
+            dist_dir.display()
+        );
     }
+    artifacts.sort();
+    Ok(artifacts)
 }
 
 /// Finds a) an activated venv (`VIRTUAL_ENV`) b) `.venv` in any parent folder c) tells the user
@@ -573,6 +1085,8 @@ pub fn find_venv(venv: Option<&Path>) -> anyhow::Result<PathBuf> {
         bail!(
             "Couldn't find an activated virtualenv not a .venv found in any parent directory. \
                     You can create a virtualenv with `python -m venv .venv`{}. \
+                    If there's no system python around to create one with, `monotrail python-install <x.y>` \
+                    downloads a standalone interpreter you can point `python -m venv` at instead. \
                     See https://virtualenv.pypa.io/en/latest/index.html for more information",
             activation_command
         );
@@ -599,6 +1113,7 @@ mod test {
             false,
             false,
             true,
+            false,
             Some(&venv),
             Some(&working_dir),
         )?;
@@ -0,0 +1,210 @@
+//! Named, reusable per-(project, python-minor-version) venvs, persisted to `envs.toml` under
+//! [`cache_dir`]. Without this, `-p x.y` on `poetry-install`/`run` only ever resolves to whatever
+//! venv happens to already be activated or sit in `.venv` (see [`crate::cli::find_venv`]); this
+//! registry instead looks up (or builds, via a [`crate::standalone_python`]-provisioned
+//! interpreter and `python -m venv`) a venv dedicated to that project and version, so repeat runs
+//! with the same `-p` reuse the venv they built last time instead of erroring or silently picking
+//! up whatever else is lying around.
+
+use crate::monotrail::Implementation;
+use crate::standalone_python::{install_python, provision_python};
+use crate::utils::cache_dir;
+use anyhow::{bail, Context};
+use fs2::FileExt;
+use fs_err as fs;
+use fs_err::File;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tracing::debug;
+
+/// One registered venv: a project (keyed by its canonicalized directory) paired with the python
+/// minor version it was created for
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RegisteredEnv {
+    /// `<project dir name>-<hash>-py<major>.<minor>`, shown by `monotrail env list` and taken by
+    /// `monotrail env remove`
+    name: String,
+    /// Canonicalized directory this venv was created for (the one containing `pyproject.toml`)
+    project_root: PathBuf,
+    /// `(major, minor)` this venv's interpreter was provisioned for
+    python_version: (u8, u8),
+    /// The exact patch [`install_python`] resolved at creation time; `None` if it was never
+    /// pinned, in which case we can't tell a moved-on interpreter from the one we built against
+    /// and just keep reusing the venv (see [`install_python`]'s own doc comment on this ambiguity)
+    patch: Option<u8>,
+    /// Where the venv itself lives, under [`cache_dir`]
+    venv_path: PathBuf,
+}
+
+/// The full `envs.toml` registry: every venv `monotrail env create` (or an implicit `-p`-triggered
+/// creation) has ever made
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct EnvRegistry {
+    #[serde(default, rename = "env")]
+    envs: Vec<RegisteredEnv>,
+}
+
+fn registry_path() -> anyhow::Result<PathBuf> {
+    Ok(cache_dir()?.join("envs.toml"))
+}
+
+impl EnvRegistry {
+    fn load() -> anyhow::Result<Self> {
+        let path = registry_path()?;
+        if !path.is_file() {
+            return Ok(Self::default());
+        }
+        let data = fs::read_to_string(&path)?;
+        toml::from_str(&data).with_context(|| format!("Invalid {}", path.display()))
+    }
+
+    /// Writes `envs.toml` via a temp-file-then-rename, so a process killed mid-write leaves the
+    /// previous, still-valid registry in place instead of a truncated one `load` can't parse
+    fn save(&self) -> anyhow::Result<()> {
+        let path = registry_path()?;
+        let parent = path.parent().context("envs.toml has no parent directory")?;
+        fs::create_dir_all(parent)?;
+        let data = toml::to_string_pretty(self).context("Failed to serialize envs.toml")?;
+        let temp_file = tempfile::NamedTempFile::new_in(parent)?;
+        fs::write(temp_file.path(), data)?;
+        temp_file
+            .persist(&path)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        Ok(())
+    }
+}
+
+/// Holds `envs.toml`'s lock file for the duration of a read-modify-write cycle, so two concurrent
+/// `find_or_create_env`/`remove_env` calls can't both decide to (re)build the same venv, or clobber
+/// each other's registry update -- the same hazard [`provision_python`]'s own install-lock guards
+/// against for the interpreter cache
+struct RegistryLock(#[allow(dead_code)] File);
+
+fn lock_registry() -> anyhow::Result<RegistryLock> {
+    let cache_dir = cache_dir()?;
+    fs::create_dir_all(&cache_dir)?;
+    let lockfile = File::create(cache_dir.join("envs.lock"))?;
+    lockfile.file().lock_exclusive()?;
+    Ok(RegistryLock(lockfile))
+}
+
+/// A short, stable identifier for `project_root`, so two different projects pinned to the same
+/// python version don't collide under the same name
+fn project_slug(project_root: &Path) -> String {
+    let name = project_root
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "project".to_string());
+    let mut hasher = DefaultHasher::new();
+    project_root.hash(&mut hasher);
+    format!("{}-{:x}", name, hasher.finish())
+}
+
+/// Provisions a standalone interpreter for `(major, minor[, patch])` (reusing an already-cached
+/// one if present) and runs `python -m venv` with it into `venv_path`
+fn create_venv(major: u8, minor: u8, patch: Option<u8>, venv_path: &Path) -> anyhow::Result<()> {
+    let (python_context, _) = provision_python(Implementation::CPython, (major, minor), patch)?;
+    if let Some(parent) = venv_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let status = Command::new(&python_context.sys_executable)
+        .args(["-m", "venv"])
+        .arg(venv_path)
+        .status()
+        .with_context(|| {
+            format!(
+                "Failed to run {} -m venv {}",
+                python_context.sys_executable.display(),
+                venv_path.display()
+            )
+        })?;
+    if !status.success() {
+        bail!(
+            "{} -m venv {} failed",
+            python_context.sys_executable.display(),
+            venv_path.display()
+        );
+    }
+    Ok(())
+}
+
+/// Finds (or creates) the venv registered for `project_root` at `python_version` (e.g. `"3.9"` or
+/// `"3.9.12"`, same format [`install_python`] takes). A later call with the same `(project_root,
+/// python_version)` reuses the same venv, unless the interpreter it was pinned to has since moved
+/// to a different patch, in which case the stale venv is torn down and rebuilt against the new one.
+pub fn find_or_create_env(project_root: &Path, python_version: &str) -> anyhow::Result<PathBuf> {
+    let project_root = project_root
+        .canonicalize()
+        .with_context(|| format!("Couldn't canonicalize {}", project_root.display()))?;
+    let (major, minor, resolved_patch) = install_python(python_version, false)?;
+
+    let _lock = lock_registry()?;
+    let mut registry = EnvRegistry::load()?;
+    let existing_index = registry
+        .envs
+        .iter()
+        .position(|env| env.project_root == project_root && env.python_version == (major, minor));
+
+    if let Some(index) = existing_index {
+        let up_to_date = resolved_patch.is_none() || resolved_patch == registry.envs[index].patch;
+        let venv_path = registry.envs[index].venv_path.clone();
+        if up_to_date && venv_path.join("pyvenv.cfg").is_file() {
+            return Ok(venv_path);
+        }
+        debug!(
+            "{} moved from patch {:?} to {:?}, rebuilding its venv",
+            registry.envs[index].name, registry.envs[index].patch, resolved_patch
+        );
+        if venv_path.is_dir() {
+            fs::remove_dir_all(&venv_path).with_context(|| {
+                format!("Failed to remove stale venv at {}", venv_path.display())
+            })?;
+        }
+        registry.envs.remove(index);
+    }
+
+    let name = format!("{}-py{}.{}", project_slug(&project_root), major, minor);
+    let venv_path = cache_dir()?.join("envs").join(&name);
+    create_venv(major, minor, resolved_patch, &venv_path)?;
+
+    registry.envs.push(RegisteredEnv {
+        name,
+        project_root,
+        python_version: (major, minor),
+        patch: resolved_patch,
+        venv_path: venv_path.clone(),
+    });
+    registry.save()?;
+    Ok(venv_path)
+}
+
+/// Lists every registered venv as `<name> -> <venv path>`, for `monotrail env list`
+pub fn list_envs() -> anyhow::Result<Vec<String>> {
+    let registry = EnvRegistry::load()?;
+    Ok(registry
+        .envs
+        .iter()
+        .map(|env| format!("{} -> {}", env.name, env.venv_path.display()))
+        .collect())
+}
+
+/// Removes a registered venv by name (as shown by [`list_envs`]), deleting its directory too, for
+/// `monotrail env remove`
+pub fn remove_env(name: &str) -> anyhow::Result<()> {
+    let _lock = lock_registry()?;
+    let mut registry = EnvRegistry::load()?;
+    let index = registry
+        .envs
+        .iter()
+        .position(|env| env.name == name)
+        .with_context(|| format!("No registered env named {}", name))?;
+    let removed = registry.envs.remove(index);
+    if removed.venv_path.is_dir() {
+        fs::remove_dir_all(&removed.venv_path)
+            .with_context(|| format!("Failed to remove venv at {}", removed.venv_path.display()))?;
+    }
+    registry.save()
+}
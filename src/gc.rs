@@ -0,0 +1,425 @@
+//! Garbage-collects the monotrail content store: removes installed `name/version/tag`
+//! directories that aren't referenced by any of a given set of `poetry.lock` files, or by any
+//! project [`install`](crate::monotrail::install) has recorded a tracking manifest for
+
+use crate::install::InstalledPackage;
+use crate::monotrail::{list_installed, FinderData};
+use crate::poetry_integration::poetry_lock::PoetryLock;
+use crate::spec::directory_unique_version;
+use anyhow::Context;
+use fs_err as fs;
+use install_wheel_rs::LockedDir;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use tracing::debug;
+
+/// Packages that are never removed even if no supplied lockfile references them, mirroring
+/// poetry's own `UNSAFE_PACKAGES`: these bootstrap pip itself, so gc-ing them away would make it
+/// impossible to install anything afterwards
+pub const DEFAULT_NEVER_REMOVE: &[&str] = &["pip", "setuptools", "wheel"];
+
+/// One project's installed packages, as last recorded by [`record_project`]; the unit tracked by
+/// the content store's manifest directory (mirrors how cargo records which packages a lockfile
+/// pulled in, so it can tell which parts of `~/.cargo/registry` are still needed)
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+struct ProjectManifest {
+    /// Canonicalized path of the `poetry.lock` this project was last resolved from
+    lockfile: PathBuf,
+    /// The exact packages that lockfile resolved and installed to
+    packages: Vec<InstalledPackage>,
+}
+
+/// Where [`record_project`] keeps its one-file-per-project manifests
+fn manifests_dir(monotrail_root: &Path) -> PathBuf {
+    monotrail_root.join(".monotrail-manifests")
+}
+
+/// Manifests are named after a hash of their (canonicalized) lockfile path rather than the path
+/// itself, since the latter would have to be escaped to be a valid filename
+fn manifest_filename(lockfile: &Path) -> String {
+    let mut hasher = DefaultHasher::new();
+    lockfile.hash(&mut hasher);
+    format!("{:x}.json", hasher.finish())
+}
+
+/// Records that `lockfile_path` last resolved to exactly `packages`, so a later [`gc`] run knows
+/// those directories are still reachable. Called from [`crate::monotrail::install`] whenever a
+/// lockfile with a known on-disk location was used.
+pub fn record_project(
+    monotrail_root: &Path,
+    lockfile_path: &Path,
+    packages: &[InstalledPackage],
+) -> anyhow::Result<()> {
+    let dir = manifests_dir(monotrail_root);
+    fs::create_dir_all(&dir).context("Failed to create manifests directory")?;
+
+    let lockfile = fs::canonicalize(lockfile_path).unwrap_or_else(|_| lockfile_path.to_path_buf());
+    let manifest = ProjectManifest {
+        lockfile: lockfile.clone(),
+        packages: packages.to_vec(),
+    };
+    let manifest_file = dir.join(manifest_filename(&lockfile));
+    fs::write(
+        &manifest_file,
+        serde_json::to_string_pretty(&manifest).context("Failed to serialize project manifest")?,
+    )
+    .with_context(|| format!("Failed to write {}", manifest_file.display()))?;
+    Ok(())
+}
+
+/// Drops the tracking manifest for `lockfile_path`, e.g. because the project was removed and its
+/// lockfile no longer exists. Returns whether a manifest was actually present.
+pub fn forget_project(monotrail_root: &Path, lockfile_path: &Path) -> anyhow::Result<bool> {
+    let lockfile = fs::canonicalize(lockfile_path).unwrap_or_else(|_| lockfile_path.to_path_buf());
+    let manifest_file = manifests_dir(monotrail_root).join(manifest_filename(&lockfile));
+    if !manifest_file.is_file() {
+        return Ok(false);
+    }
+    fs::remove_file(&manifest_file)
+        .with_context(|| format!("Failed to remove {}", manifest_file.display()))?;
+    Ok(true)
+}
+
+/// Reads back every manifest [`record_project`] has written, dropping (with a debug message) any whose
+/// lockfile has since disappeared -- those projects no longer exist and shouldn't keep their
+/// packages alive forever
+fn load_project_manifests(monotrail_root: &Path) -> anyhow::Result<Vec<ProjectManifest>> {
+    let dir = manifests_dir(monotrail_root);
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let mut manifests = Vec::new();
+    for entry in fs::read_dir(&dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+            continue;
+        }
+        let manifest: ProjectManifest = serde_json::from_str(&fs::read_to_string(&path)?)
+            .with_context(|| format!("Invalid project manifest {}", path.display()))?;
+        if !manifest.lockfile.is_file() {
+            debug!(
+                "{} no longer exists, ignoring its manifest",
+                manifest.lockfile.display()
+            );
+            continue;
+        }
+        manifests.push(manifest);
+    }
+    Ok(manifests)
+}
+
+/// Drops the tracking manifest of every project whose lockfile no longer exists on disk -- those
+/// projects are gone, so there's no reason to keep pinning their packages alive. Returns how many
+/// manifests were forgotten.
+pub fn forget_stale_projects(monotrail_root: &Path) -> anyhow::Result<usize> {
+    let dir = manifests_dir(monotrail_root);
+    if !dir.is_dir() {
+        return Ok(0);
+    }
+
+    let mut forgotten = 0;
+    for entry in fs::read_dir(&dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+            continue;
+        }
+        let manifest: ProjectManifest = serde_json::from_str(&fs::read_to_string(&path)?)
+            .with_context(|| format!("Invalid project manifest {}", path.display()))?;
+        if !manifest.lockfile.is_file() {
+            debug!("Forgetting stale project manifest for {}", manifest.lockfile.display());
+            fs::remove_file(&path).with_context(|| format!("Failed to remove {}", path.display()))?;
+            forgotten += 1;
+        }
+    }
+    Ok(forgotten)
+}
+
+/// What a [`gc`] run removed (or, with `dry_run`, would have removed)
+#[derive(Debug, Clone, Default)]
+pub struct GcReport {
+    /// The `(name, unique_version, tag)` triples that were (or would be) removed
+    pub removed: Vec<(String, String, String)>,
+    /// Total size in bytes of `removed`
+    pub bytes_reclaimed: u64,
+}
+
+/// Returns the normalized `(name, unique_version)` pairs of every package in `lockfile`,
+/// i.e. the set of installed directories that are still reachable from it.
+///
+/// Mirrors [`crate::spec::RequestedSpec::get_unique_version`]: for a git source the version
+/// directory is keyed on the resolved reference rather than the pypi version string, and for a
+/// local `directory` source it's keyed on a path-mtime fingerprint instead (see
+/// [`directory_unique_version`]); `file`/`url` sources fall back to the plain version, same as
+/// having no source at all.
+fn reachable_from_lockfile(lockfile: &str) -> anyhow::Result<HashSet<(String, String)>> {
+    let lock = PoetryLock::from_str(lockfile)?;
+    Ok(lock
+        .package
+        .iter()
+        .map(|package| {
+            let unique_version = package
+                .source
+                .as_ref()
+                .map(|source| match source.source_type.as_str() {
+                    "directory" => {
+                        directory_unique_version(Path::new(&source.url), &package.version)
+                    }
+                    "file" | "url" => package.version.clone(),
+                    _ => source.resolved_reference.clone(),
+                })
+                .unwrap_or_else(|| package.version.clone());
+            (package.name.to_lowercase().replace('-', "_"), unique_version)
+        })
+        .collect())
+}
+
+/// Scans `monotrail_root` and removes every installed `(name, version, tag)` directory that's
+/// neither reachable from `lockfiles` or a tracked project manifest (see [`record_project`]) nor
+/// in `never_remove`. With `dry_run`, nothing is actually removed and the report only says what
+/// would have been.
+pub fn gc(
+    monotrail_root: &Path,
+    lockfiles: &[String],
+    never_remove: &[String],
+    dry_run: bool,
+) -> anyhow::Result<GcReport> {
+    let mut reachable: HashSet<(String, String)> = HashSet::new();
+    for lockfile in lockfiles {
+        reachable.extend(reachable_from_lockfile(lockfile)?);
+    }
+
+    // Tracked projects give us the exact `(name, version, tag)` triple that was installed, which
+    // is strictly more precise than what we can infer from a lockfile alone (a lockfile has no
+    // notion of compatibility tag)
+    let mut reachable_exact: HashSet<(String, String, String)> = HashSet::new();
+    for manifest in load_project_manifests(monotrail_root).context("Failed to read tracked projects")? {
+        for package in manifest.packages {
+            reachable_exact.insert((package.name, package.unique_version, package.tag));
+        }
+    }
+
+    let installed = list_installed(monotrail_root, None)
+        .context("Failed to list installed packages")?;
+
+    let mut report = GcReport::default();
+    for (name, version, tag) in installed {
+        if never_remove.iter().any(|protected| protected == &name) {
+            continue;
+        }
+        if reachable.contains(&(name.clone(), version.clone()))
+            || reachable_exact.contains(&(name.clone(), version.clone(), tag.clone()))
+        {
+            continue;
+        }
+
+        let package_dir = monotrail_root.join(&name).join(&version).join(&tag);
+        let bytes = dir_size(&package_dir)
+            .with_context(|| format!("Failed to size {}", package_dir.display()))?;
+        report.bytes_reclaimed += bytes;
+        report.removed.push((name, version, tag));
+
+        if !dry_run {
+            debug!("Removing {}", package_dir.display());
+            fs::remove_dir_all(&package_dir)?;
+        }
+    }
+
+    if !dry_run {
+        forget_stale_projects(monotrail_root).context("Failed to forget stale project manifests")?;
+        prune_empty_dirs(monotrail_root)?;
+    }
+
+    Ok(report)
+}
+
+/// Like [`gc`], but for callers that already have exact, resolved [`FinderData`]s in hand (e.g. a
+/// long-running finder tracking every environment it has installed for) instead of raw
+/// `poetry.lock` contents: the keep set is the union of `(name, unique_version, tag)` triples
+/// across `keep`'s `sprawl_packages`, which is exact and needs no lockfile re-parsing or version
+/// fuzzing the way [`gc`]'s `reachable_from_lockfile` does.
+///
+/// Acquires the monotrail store's own install lock before touching anything, and skips the run
+/// entirely (returning `Ok(None)`) rather than blocking if another process already holds it --
+/// pruning is opportunistic background maintenance, not worth making an installer wait on.
+/// Directories `list_installed` can't parse as `name/version/tag` are already left out of its
+/// result (it only `warn!`s and skips them), so they're left untouched here too.
+pub fn prune(monotrail_root: &Path, keep: &[FinderData]) -> anyhow::Result<Option<GcReport>> {
+    let locked_root = match LockedDir::try_acquire(monotrail_root)
+        .context("Failed to check the monotrail store lock")?
+    {
+        Some(locked_root) => locked_root,
+        None => {
+            debug!(
+                "Another process holds the lock on {}, skipping prune",
+                monotrail_root.display()
+            );
+            return Ok(None);
+        }
+    };
+
+    let reachable: HashSet<(String, String, String)> = keep
+        .iter()
+        .flat_map(|finder_data| &finder_data.sprawl_packages)
+        .map(|package| {
+            (
+                package.name.clone(),
+                package.unique_version.clone(),
+                package.tag.clone(),
+            )
+        })
+        .collect();
+
+    let installed = list_installed(&locked_root, None)
+        .context("Failed to list installed packages")?;
+
+    let mut report = GcReport::default();
+    for (name, version, tag) in installed {
+        if DEFAULT_NEVER_REMOVE.contains(&name.as_str()) {
+            continue;
+        }
+        if reachable.contains(&(name.clone(), version.clone(), tag.clone())) {
+            continue;
+        }
+
+        let package_dir = locked_root.join(&name).join(&version).join(&tag);
+        let bytes = dir_size(&package_dir)
+            .with_context(|| format!("Failed to size {}", package_dir.display()))?;
+        report.bytes_reclaimed += bytes;
+        report.removed.push((name, version, tag));
+
+        debug!("Removing {}", package_dir.display());
+        fs::remove_dir_all(&package_dir)?;
+    }
+
+    prune_empty_dirs(&locked_root)?;
+
+    Ok(Some(report))
+}
+
+/// Recursively sums up the size of all files under `dir`
+fn dir_size(dir: &Path) -> anyhow::Result<u64> {
+    let mut total = 0;
+    for entry in fs::read_dir(dir)?.flatten() {
+        let metadata = entry.metadata()?;
+        if metadata.is_dir() {
+            total += dir_size(&entry.path())?;
+        } else {
+            total += metadata.len();
+        }
+    }
+    Ok(total)
+}
+
+/// After removing tag directories, their now-possibly-empty `name/version` parents would
+/// otherwise linger forever, so we clean those up too
+pub(crate) fn prune_empty_dirs(monotrail_root: &Path) -> anyhow::Result<()> {
+    for name_dir in fs::read_dir(monotrail_root)
+        .into_iter()
+        .flatten()
+        .flatten()
+    {
+        // Not a `name/version/tag` package directory but our own tracking manifests, see
+        // [`manifests_dir`], or some other stray file rather than a package namespace directory
+        if name_dir.path() == manifests_dir(monotrail_root) || !name_dir.path().is_dir() {
+            continue;
+        }
+        for version_dir in fs::read_dir(name_dir.path()).into_iter().flatten().flatten() {
+            if fs::read_dir(version_dir.path())?.next().is_none() {
+                fs::remove_dir(version_dir.path())?;
+            }
+        }
+        if fs::read_dir(name_dir.path())?.next().is_none() {
+            fs::remove_dir(name_dir.path())?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::gc;
+    use fs_err as fs;
+
+    /// A minimal lock-version 2.0 `poetry.lock` pinning a single package, just enough for
+    /// [`super::reachable_from_lockfile`] to parse
+    const LOCKFILE: &str = r#"
+[[package]]
+name = "kept_pkg"
+version = "1.0.0"
+description = ""
+category = "main"
+optional = false
+python-versions = "*"
+
+[metadata]
+lock-version = "2.0"
+python-versions = "^3.8"
+content-hash = "abc123"
+"#;
+
+    /// Creates a fake `name/version/tag/` install directory with a dummy file in it, so
+    /// [`super::dir_size`] has something to size
+    fn fake_install(monotrail_root: &std::path::Path, name: &str, version: &str) {
+        let package_dir = monotrail_root.join(name).join(version).join("py3-none-any");
+        fs::create_dir_all(&package_dir).unwrap();
+        fs::write(package_dir.join("marker.txt"), "x").unwrap();
+    }
+
+    #[test]
+    fn dry_run_reports_but_does_not_remove() {
+        let root = tempfile::tempdir().unwrap();
+        fake_install(root.path(), "kept_pkg", "1.0.0");
+        fake_install(root.path(), "orphan-pkg", "2.0.0");
+
+        let report = gc(root.path(), &[LOCKFILE.to_string()], &[], true).unwrap();
+
+        assert_eq!(
+            report.removed,
+            vec![(
+                "orphan-pkg".to_string(),
+                "2.0.0".to_string(),
+                "py3-none-any".to_string()
+            )]
+        );
+        // Dry run: nothing actually removed
+        assert!(root.path().join("kept_pkg/1.0.0/py3-none-any").is_dir());
+        assert!(root.path().join("orphan-pkg/2.0.0/py3-none-any").is_dir());
+    }
+
+    #[test]
+    fn removes_unreachable_packages_but_keeps_locked_and_protected_ones() {
+        let root = tempfile::tempdir().unwrap();
+        fake_install(root.path(), "kept_pkg", "1.0.0");
+        fake_install(root.path(), "orphan-pkg", "2.0.0");
+        fake_install(root.path(), "protected-pkg", "3.0.0");
+
+        let report = gc(
+            root.path(),
+            &[LOCKFILE.to_string()],
+            &["protected-pkg".to_string()],
+            false,
+        )
+        .unwrap();
+
+        assert_eq!(
+            report.removed,
+            vec![(
+                "orphan-pkg".to_string(),
+                "2.0.0".to_string(),
+                "py3-none-any".to_string()
+            )]
+        );
+        // Locked in the lockfile: survives
+        assert!(root.path().join("kept_pkg/1.0.0/py3-none-any").is_dir());
+        // Never-remove: survives even though no lockfile references it
+        assert!(root
+            .path()
+            .join("protected-pkg/3.0.0/py3-none-any")
+            .is_dir());
+        // Neither locked nor protected: actually removed this time
+        assert!(!root.path().join("orphan-pkg").exists());
+    }
+}
@@ -1,72 +1,667 @@
-use crate::monotrail::install_specs_to_finder;
+use crate::monotrail::{get_specs, install_specs_to_finder, Implementation};
+use crate::poetry_integration::lock::ResolutionMode;
 use crate::standalone_python::provision_python;
-use crate::{get_specs, DEFAULT_PYTHON_VERSION};
+use crate::{DEFAULT_PYTHON_VERSION, INTERPRETER_INFO_QUERY};
 use anyhow::{bail, format_err, Context};
 use fs_err as fs;
-use libc::{c_int, c_void, wchar_t};
-use std::collections::BTreeMap;
+use goblin::elf::header::{EM_386, EM_AARCH64, EM_ARM, EM_PPC64, EM_S390, EM_X86_64};
+use goblin::elf::Elf;
+use libc::{c_char, c_int, c_ulong, c_void, wchar_t};
+use pep440_rs::{Operator, VersionSpecifiers};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
 use std::env;
 use std::ffi::CString;
+use std::mem;
 use std::path::{Path, PathBuf};
-use tracing::{debug, trace};
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+use std::sync::{Mutex, OnceLock};
+use tracing::{debug, trace, warn};
 use widestring::WideCString;
 
-/// python has idiosyncratic cli options that are hard to replicate with clap, so we roll our own.
-/// Takes args without the first-is-current-program (i.e. python) convention.
+/// Mirrors `PyStatus` from `Include/cpython/initconfig.h`: stable across 3.8-3.12 and part of the
+/// documented (if not stable-ABI) init config API, so unlike [`PyConfig`] this one is low-risk to
+/// hand-roll.
+/// <https://docs.python.org/3/c-api/init_config.html#c.PyStatus>
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct PyStatus {
+    type_: c_int,
+    func: *const c_char,
+    err_msg: *const c_char,
+    exitcode: c_int,
+}
+
+/// <https://docs.python.org/3/c-api/init_config.html#c.PyWideStringList>
+#[repr(C)]
+struct PyWideStringList {
+    length: isize,
+    items: *mut *mut wchar_t,
+}
+
+/// A partial, best-effort mirror of `PyConfig` (`Include/cpython/initconfig.h`), covering every
+/// field up to and including `run_filename` in the exact field order CPython 3.8 uses --
+/// [`crate::DEFAULT_PYTHON_VERSION`] -- so that every field this module writes (`home`,
+/// `program_name`, `argv`, `run_command`, `run_module`, `run_filename`, and the command-line flag
+/// fields such as `isolated`, `site_import` and `warnoptions`) lands at the byte offset a real 3.8
+/// libpython expects. `PyConfig` isn't part of the stable ABI and its layout
+/// is not even stable release to release: 3.9 adds `platlibdir`, 3.10 moves `program_name` out from
+/// right after `argv` down next to `pythonpath_env`/`home` and adds `orig_argv`/`warn_default_encoding`,
+/// and 3.11 adds `safe_path`/`stdlib_dir`. PyPy doesn't use this struct at all. Running against any
+/// interpreter other than a 3.8.x CPython build would misalign every field from `program_name`
+/// onward, so [`inject_and_run_python`] checks both `implementation` and `python_version` against
+/// [`Implementation::CPython`] and [`crate::DEFAULT_PYTHON_VERSION`] and bails before this struct is
+/// ever touched rather than silently writing through the wrong offsets. `_reserved_tail` pads generously
+/// past a real 3.8 instance so the handful of private fields CPython keeps after `run_filename`
+/// don't walk this struct off the end of its allocation. There's no way around the single-version
+/// restriction short of vendoring the header (or a per-version field layout) for every supported
+/// version.
+#[repr(C)]
+struct PyConfig {
+    config_init: c_int,
+    isolated: c_int,
+    use_environment: c_int,
+    dev_mode: c_int,
+    install_signal_handlers: c_int,
+    use_hash_seed: c_int,
+    hash_seed: c_ulong,
+    faulthandler: c_int,
+    tracemalloc: c_int,
+    import_time: c_int,
+    show_ref_count: c_int,
+    show_alloc_count: c_int,
+    dump_refs: c_int,
+    malloc_stats: c_int,
+    filesystem_encoding: *mut wchar_t,
+    filesystem_errors: *mut wchar_t,
+    pycache_prefix: *mut wchar_t,
+    parse_argv: c_int,
+    argv: PyWideStringList,
+    program_name: *mut wchar_t,
+    xoptions: PyWideStringList,
+    warnoptions: PyWideStringList,
+    site_import: c_int,
+    bytes_warning: c_int,
+    inspect: c_int,
+    interactive: c_int,
+    optimization_level: c_int,
+    parser_debug: c_int,
+    write_bytecode: c_int,
+    verbose: c_int,
+    quiet: c_int,
+    user_site_directory: c_int,
+    configure_c_stdio: c_int,
+    buffered_stdio: c_int,
+    stdio_encoding: *mut wchar_t,
+    stdio_errors: *mut wchar_t,
+    check_hash_pycs_mode: *mut wchar_t,
+    pathconfig_warnings: c_int,
+    pythonpath_env: *mut wchar_t,
+    home: *mut wchar_t,
+    module_search_paths_set: c_int,
+    module_search_paths: PyWideStringList,
+    executable: *mut wchar_t,
+    base_executable: *mut wchar_t,
+    prefix: *mut wchar_t,
+    base_prefix: *mut wchar_t,
+    exec_prefix: *mut wchar_t,
+    base_exec_prefix: *mut wchar_t,
+    skip_source_first_line: c_int,
+    run_command: *mut wchar_t,
+    run_module: *mut wchar_t,
+    run_filename: *mut wchar_t,
+    _reserved_tail: [u8; 512],
+}
+
+/// The `sysconfig`/`sys` values we need to locate and load libpython, as reported by
+/// [`INTERPRETER_INFO_QUERY`]. Modeled on pyo3's `InterpreterConfig`.
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct InterpreterInfo {
+    /// `sysconfig.get_config_var("LIBDIR")`
+    pub(crate) libdir: Option<String>,
+    /// `sysconfig.get_config_var("LDLIBRARY")`, e.g. `libpython3.10.so`
+    pub(crate) ldlibrary: Option<String>,
+    /// `sysconfig.get_config_var("INSTSONAME")`, the versioned soname on some distros
+    pub(crate) instsoname: Option<String>,
+    /// `sysconfig.get_config_var("Py_ENABLE_SHARED")`, 0 if libpython is statically linked
+    pub(crate) py_enable_shared: Option<i32>,
+    /// `sysconfig.get_config_var("LDVERSION")`, e.g. `3.10` or `3.10d`
+    pub(crate) ldversion: Option<String>,
+    /// `sys.base_prefix`
+    pub(crate) base_prefix: String,
+    /// `sys.executable`
+    pub(crate) sys_executable: String,
+    /// `sys.version_info[:2]`, e.g. `(3, 10)`
+    pub(crate) python_version: (u8, u8),
+    /// `sys.implementation.name`, e.g. `cpython` or `pypy`
+    pub(crate) implementation_name: String,
+    /// `platform.machine()`, e.g. `x86_64` or `aarch64`
+    pub(crate) machine: String,
+    /// `os.confstr("CS_GNU_LIBC_VERSION")`, e.g. `glibc 2.31`, only set on glibc linux
+    pub(crate) glibc_version: Option<String>,
+    /// `platform.mac_ver()[0]`, e.g. `12.4`, only set on macOS
+    pub(crate) mac_version: Option<String>,
+}
+
+/// Runs the provisioned interpreter once with [`INTERPRETER_INFO_QUERY`] and parses the
+/// printed json into an [`InterpreterInfo`]
+pub(crate) fn probe_interpreter_info(python: &Path) -> anyhow::Result<InterpreterInfo> {
+    let output = Command::new(python)
+        .args(["-S", "-c", INTERPRETER_INFO_QUERY])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .output()
+        .with_context(|| format!("Failed to run the interpreter at {}", python.display()))?;
+    if !output.status.success() {
+        bail!(
+            "Interpreter info probe at {} exited with {}",
+            python.display(),
+            output.status
+        );
+    }
+    serde_json::from_slice(&output.stdout).with_context(|| {
+        format!(
+            "Failed to parse interpreter info from {}: {}",
+            python.display(),
+            String::from_utf8_lossy(&output.stdout)
+        )
+    })
+}
+
+/// The ELF-derived facts [`compatible_platform_tags`] can't get from [`InterpreterInfo`] alone:
+/// the musl dynamic linker's self-reported version (glibc interpreters report their version
+/// through `info.glibc_version` instead, queried straight from the interpreter) and the
+/// architecture read from the ELF header itself, which we prefer over `platform.machine()` since
+/// it's correct even if the interpreter is e.g. a foreign-arch binary run under emulation.
+#[derive(Debug, Default, Clone)]
+struct ElfPlatform {
+    arch: Option<String>,
+    musl_version: Option<(u32, u32)>,
+}
+
+/// Parses `python_binary` as an ELF file to read its `e_machine` architecture and, if it's
+/// dynamically linked against musl, its `PT_INTERP` entry (the musl dynamic linker path). Returns
+/// `None` defaults (not an error) for non-ELF interpreters, e.g. on macOS and Windows.
+fn probe_elf_platform(python_binary: &Path) -> ElfPlatform {
+    let bytes = match fs::read(python_binary) {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            trace!("Couldn't read {} as ELF: {}", python_binary.display(), err);
+            return ElfPlatform::default();
+        }
+    };
+    let elf = match Elf::parse(&bytes) {
+        Ok(elf) => elf,
+        Err(err) => {
+            trace!("{} is not an ELF file: {}", python_binary.display(), err);
+            return ElfPlatform::default();
+        }
+    };
+    let arch = match elf.header.e_machine {
+        EM_X86_64 => Some("x86_64"),
+        EM_AARCH64 => Some("aarch64"),
+        EM_386 => Some("i686"),
+        EM_ARM => Some("armv7l"),
+        EM_S390 => Some("s390x"),
+        EM_PPC64 => Some(if elf.little_endian {
+            "ppc64le"
+        } else {
+            "ppc64"
+        }),
+        _ => None,
+    }
+    .map(String::from);
+    let musl_version = elf
+        .interpreter
+        .filter(|interp| interp.contains("musl"))
+        .and_then(|interp| probe_musl_version(Path::new(interp)));
+    ElfPlatform { arch, musl_version }
+}
+
+/// Runs the musl dynamic linker at `ld_path` with no arguments and scrapes `Version X.Y` from its
+/// self-identification banner on stderr, e.g.:
+/// ```text
+/// musl libc (x86_64)
+/// Version 1.2.2
+/// Dynamic Program Loader
+/// ```
+fn probe_musl_version(ld_path: &Path) -> Option<(u32, u32)> {
+    let output = Command::new(ld_path)
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .output()
+        .ok()?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let (_, version) = stderr.split_once("Version ")?;
+    let (major, minor) = version.split_whitespace().next()?.split_once('.')?;
+    Some((major.parse().ok()?, minor.split('.').next()?.parse().ok()?))
+}
+
+/// Computes the ordered list of compatible platform tags (most to least specific) for an
+/// interpreter, following the approach of uv/packaging's `_manylinux`/`_musllinux` modules:
+/// detect the libc kind and version from the probed interpreter info and, for musl, from the
+/// dynamic linker's own version banner rather than assuming the host toolchain matches the
+/// provisioned one. Falls back to a bare `linux_<arch>` tag (not installable from PyPI, but still
+/// useful for local/sdist matching) when no manylinux/musllinux tag applies. Cached by
+/// `python_binary` since probing spawns subprocesses and re-parses the ELF on every call.
+pub(crate) fn compatible_platform_tags(
+    python_binary: &Path,
+    info: &InterpreterInfo,
+) -> Vec<String> {
+    static CACHE: OnceLock<Mutex<HashMap<PathBuf, Vec<String>>>> = OnceLock::new();
+    let cache = CACHE.get_or_init(|| Mutex::new(HashMap::new()));
+    if let Some(tags) = cache.lock().unwrap().get(python_binary) {
+        return tags.clone();
+    }
+
+    let elf_platform = probe_elf_platform(python_binary);
+    let arch = elf_platform.arch.as_deref().unwrap_or(&info.machine);
+    let mut tags = Vec::new();
+    if let Some(mac_version) = &info.mac_version {
+        if let Some((major, minor)) = mac_version.split_once('.') {
+            if let (Ok(major), Ok(minor)) = (major.parse::<u32>(), minor.parse::<u32>()) {
+                tags.push(format!("macosx_{}_{}_{}", major, minor, arch));
+            }
+        }
+    } else if let Some(glibc_version) = &info.glibc_version {
+        if let Some((_, version)) = glibc_version.split_once(' ') {
+            if let Some((major, minor)) = version.split_once('.') {
+                if let (Ok(major), Ok(minor)) = (major.parse::<u32>(), minor.parse::<u32>()) {
+                    // manylinux_2_{N} is compatible down to its own minor version
+                    for compatible_minor in (0..=minor).rev() {
+                        tags.push(format!("manylinux_{}_{}_{}", major, compatible_minor, arch));
+                    }
+                    // legacy aliases, only defined for glibc 2.5/2.12/2.17
+                    if major == 2 && minor >= 17 {
+                        tags.push(format!("manylinux2014_{}", arch));
+                    }
+                    if major == 2 && minor >= 12 {
+                        tags.push(format!("manylinux2010_{}", arch));
+                    }
+                    if major == 2 && minor >= 5 {
+                        tags.push(format!("manylinux1_{}", arch));
+                    }
+                }
+            }
+        }
+    } else if let Some((major, minor)) = elf_platform.musl_version {
+        // musllinux_X_Y is compatible down to musllinux_X_0, regardless of the detected patch
+        for compatible_minor in (0..=minor).rev() {
+            tags.push(format!("musllinux_{}_{}_{}", major, compatible_minor, arch));
+        }
+    }
+    // Always keep a plain linux fallback so sdist/local builds still resolve to something
+    tags.push(format!("linux_{}", arch));
+
+    cache
+        .lock()
+        .unwrap()
+        .insert(python_binary.to_path_buf(), tags.clone());
+    tags
+}
+
+/// Env var pointing at a small TOML file giving the libpython location, version, implementation
+/// and home directly, read by both [`find_libpython`] and
+/// [`crate::standalone_python::provision_python`] -- see [`PythonConfigFile`] for the format and
+/// rationale.
+pub(crate) const PYTHON_CONFIG_FILE_VAR: &str = "MONOTRAIL_PYTHON_CONFIG";
+
+/// The contents of a [`PYTHON_CONFIG_FILE_VAR`] file: the same facts pyo3's build-time
+/// `PYO3_NO_PYTHON` mode takes instead of querying a live interpreter, just enough to `dlopen` and
+/// embed libpython directly without ever running python -- for sandboxed CI images and air-gapped
+/// deployments where even spawning a throwaway interpreter to probe one is blocked.
+///
+/// ```toml
+/// version = "3.10"
+/// implementation = "cpython"
+/// libpython = "/opt/python3.10/lib/libpython3.10.so"
+/// python_home = "/opt/python3.10"
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct PythonConfigFile {
+    /// `major.minor`, e.g. `3.10`, parsed with [`parse_major_minor`]
+    pub(crate) version: String,
+    /// `cpython` or `pypy`, as [`Implementation::from_sys_implementation_name`] accepts
+    pub(crate) implementation: String,
+    /// Absolute path to the shared libpython to `dlopen` directly, bypassing [`find_libpython`]'s
+    /// own probe of the interpreter entirely
+    pub(crate) libpython: PathBuf,
+    /// `sys.base_prefix`-equivalent, passed to `PYTHONHOME`
+    pub(crate) python_home: PathBuf,
+}
+
+impl PythonConfigFile {
+    /// Reads and parses the file [`PYTHON_CONFIG_FILE_VAR`] points at, if it's set. `None` if the
+    /// var isn't set at all; `Some(Err(_))` if it's set but the file is missing or invalid, so a
+    /// typo'd path fails loudly instead of silently falling back to downloading or probing.
+    pub(crate) fn from_env() -> Option<anyhow::Result<PythonConfigFile>> {
+        let path = env::var_os(PYTHON_CONFIG_FILE_VAR).map(PathBuf::from)?;
+        let result = fs::read_to_string(&path)
+            .with_context(|| {
+                format!(
+                    "Failed to read {} ({})",
+                    path.display(),
+                    PYTHON_CONFIG_FILE_VAR
+                )
+            })
+            .and_then(|contents| {
+                toml::from_str(&contents).with_context(|| {
+                    format!("Invalid {} ({})", path.display(), PYTHON_CONFIG_FILE_VAR)
+                })
+            });
+        Some(result)
+    }
+}
+
+/// Probes `python_home`'s interpreter (assumed to be at `python_home/bin/python3` on unix or
+/// `python_home/python.exe` on windows) and returns the exact path to its shared libpython,
+/// instead of guessing the filename from `cfg!(target_os)`. Bails if the interpreter was built
+/// without a shared libpython (`Py_ENABLE_SHARED == 0`), since there's then nothing to `dlopen`.
+///
+/// Skips probing entirely in favor of [`PYTHON_CONFIG_FILE_VAR`]'s `libpython` path when that's
+/// set, since the whole point of that override is to never spawn an interpreter, not even to ask
+/// it where its own shared library lives.
+fn find_libpython(
+    python_home: &Path,
+    python_version: (u8, u8),
+    implementation: Implementation,
+) -> anyhow::Result<PathBuf> {
+    if let Some(config) = PythonConfigFile::from_env() {
+        return Ok(config?.libpython);
+    }
+    let python_binary = if cfg!(target_os = "windows") {
+        python_home.join("python.exe")
+    } else {
+        python_home.join("bin").join("python3")
+    };
+    let info = probe_interpreter_info(&python_binary).with_context(|| {
+        format!(
+            "Failed to probe interpreter info for {}",
+            python_home.display()
+        )
+    })?;
+    if info.py_enable_shared == Some(0) {
+        bail!(
+            "{} was built without a shared libpython (Py_ENABLE_SHARED=0), it can't be embedded",
+            info.base_prefix
+        );
+    }
+    if cfg!(target_os = "windows") {
+        // sysconfig doesn't have a LIBDIR/LDLIBRARY concept on windows, the dll instead lives
+        // directly in base_prefix, named e.g. `python310.dll` for cpython or `libpypy3.9-c.dll`
+        // for pypy
+        let dll_name = match implementation {
+            Implementation::CPython => format!("python3{}.dll", python_version.1),
+            Implementation::PyPy => {
+                format!("libpypy{}.{}-c.dll", python_version.0, python_version.1)
+            }
+        };
+        return Ok(PathBuf::from(info.base_prefix).join(dll_name));
+    }
+    let libdir = info
+        .libdir
+        .map(PathBuf::from)
+        .unwrap_or_else(|| python_home.join("lib"));
+    // Most builds set LDLIBRARY (and some distros additionally set INSTSONAME, the real versioned
+    // soname LDLIBRARY sometimes just symlinks to); on the rare build that sets neither, falling
+    // back to LDVERSION lets us still guess the conventional shared library name instead of giving
+    // up immediately.
+    let shared_lib_extension = if cfg!(target_os = "macos") {
+        "dylib"
+    } else {
+        "so"
+    };
+    let ldlibrary = info
+        .ldlibrary
+        .or(info.instsoname)
+        .or_else(|| {
+            info.ldversion
+                .map(|ldversion| format!("libpython{}.{}", ldversion, shared_lib_extension))
+        })
+        .context("Interpreter reported neither LDLIBRARY, INSTSONAME nor LDVERSION")?;
+    Ok(libdir.join(ldlibrary))
+}
+
+/// A global python cli flag recognized before the `-c`/`-m`/script/interactive portion of the
+/// command line (e.g. `-O`, `-u`, `-W error`), paired with its argument if it takes one -- carried
+/// out of [`classify_python_args`] so [`inject_and_run_python`] can apply it to the matching
+/// [`PyConfig`] field instead of silently dropping it now that `config.parse_argv = 0` stops
+/// `Py_InitializeFromConfig` from parsing `argv` for these itself.
+struct PythonFlag {
+    flag: String,
+    value: Option<String>,
+}
+
+/// Which of `-c`/`-m`/a script file/`-`/interactive `classify_python_args` found, together with
+/// whatever arguments came after it -- the same split [`PyConfig`]'s
+/// `run_command`/`run_module`/`run_filename` fields need so [`inject_and_run_python`] doesn't have
+/// to let `Py_RunMain` re-parse `argv` itself to find them. `Stdin` gets its own variant even
+/// though CPython itself folds it into `run_filename = "-"`: keeping it distinct here means
+/// [`naive_python_arg_parser`] doesn't have to compare strings to tell "read the script from
+/// stdin" apart from "the script is a file literally named `-`".
+enum PythonInvocation {
+    Command(String, Vec<String>),
+    Module(String, Vec<String>),
+    File(String, Vec<String>),
+    Stdin(Vec<String>),
+    Interactive,
+}
+
+/// Shared option-walking core of [`naive_python_arg_parser`], which has idiosyncratic cli options
+/// that are hard to replicate with clap, so we roll our own. Takes args without the
+/// first-is-current-program (i.e. python) convention.
 ///
 /// `usage: python [option] ... [-c cmd | -m mod | file | -] [arg] ...`
-pub fn naive_python_arg_parser<T: AsRef<str>>(args: &[T]) -> Result<Option<String>, String> {
-    let bool_opts = [
-        "-b", "-B", "-d", "-E", "-h", "-i", "-I", "-O", "-OO", "-q", "-s", "-S", "-u", "-v", "-V",
-        "-x",
+///
+/// `bool_chars`/`arg_opts` below only cover the flags CPython 3.8 documents; any option we don't
+/// recognize falls into the `else` branch and is mistaken for the script filename. That used to be
+/// harmless when `Py_Main` (with `config.parse_argv = 1`) still got the untouched `argv` and
+/// reparsed it authoritatively itself; now that [`inject_and_run_python`] sets `parse_argv = 0`
+/// this is a real, if narrow, regression for any flag not in the lists above.
+///
+/// Like the real interpreter, option scanning stops at the first argument that isn't a recognized
+/// flag: CPython doesn't treat a script's own arguments as further options, and neither do we.
+fn classify_python_args<T: AsRef<str>>(
+    args: &[T],
+) -> Result<(Vec<PythonFlag>, PythonInvocation), String> {
+    // CPython's single-character boolean flags. Unlike `arg_opts` below, these can be clustered
+    // behind one dash (`-vI`) or repeated (`-OO`), so rather than listing every combination we
+    // just check each character of an unrecognized `-...` argument against this set.
+    let bool_chars = [
+        'b', 'B', 'd', 'E', 'h', 'i', 'I', 'O', 'q', 's', 'S', 'u', 'v', 'V', 'x',
     ];
     let arg_opts = ["--check-hash-based-pycs", "-W", "-X"];
-    let mut arg_iter = args.iter();
-    loop {
-        if let Some(arg) = arg_iter.next() {
-            if bool_opts.contains(&arg.as_ref()) {
-                continue;
-            } else if arg_opts.contains(&arg.as_ref()) {
-                let value = arg_iter.next();
-                if value.is_none() {
-                    return Err(format!("Missing argument for {}", arg.as_ref()));
-                }
-                continue;
-            } else if arg.as_ref() == "-c" || arg.as_ref() == "-m" {
-                let value = arg_iter.next();
-                if value.is_none() {
-                    return Err(format!("Missing argument for {}", arg.as_ref()));
-                }
-                return Ok(None);
-            } else {
-                return Ok(Some(arg.as_ref().to_string()));
+    let mut flags = Vec::new();
+    let mut i = 0;
+    while i < args.len() {
+        let arg = args[i].as_ref();
+        let is_clustered_bool_flags = arg.len() > 1
+            && arg.starts_with('-')
+            && !arg.starts_with("--")
+            && arg[1..]
+                .chars()
+                .all(|flag_char| bool_chars.contains(&flag_char));
+        if is_clustered_bool_flags {
+            for flag_char in arg[1..].chars() {
+                flags.push(PythonFlag {
+                    flag: format!("-{}", flag_char),
+                    value: None,
+                });
             }
+            i += 1;
+        } else if arg_opts.contains(&arg) {
+            let value = args
+                .get(i + 1)
+                .ok_or_else(|| format!("Missing argument for {}", arg))?
+                .as_ref()
+                .to_string();
+            flags.push(PythonFlag {
+                flag: arg.to_string(),
+                value: Some(value),
+            });
+            i += 2;
+        } else if arg == "-c" || arg == "-m" {
+            let value = args
+                .get(i + 1)
+                .ok_or_else(|| format!("Missing argument for {}", arg))?
+                .as_ref()
+                .to_string();
+            let rest = args[i + 2..]
+                .iter()
+                .map(|a| a.as_ref().to_string())
+                .collect();
+            let invocation = if arg == "-c" {
+                PythonInvocation::Command(value, rest)
+            } else {
+                PythonInvocation::Module(value, rest)
+            };
+            return Ok((flags, invocation));
+        } else if arg == "-" {
+            let rest = args[i + 1..]
+                .iter()
+                .map(|a| a.as_ref().to_string())
+                .collect();
+            return Ok((flags, PythonInvocation::Stdin(rest)));
         } else {
-            // interactive python shell
-            return Ok(None);
+            let rest = args[i + 1..]
+                .iter()
+                .map(|a| a.as_ref().to_string())
+                .collect();
+            return Ok((flags, PythonInvocation::File(arg.to_string(), rest)));
+        }
+    }
+    // interactive python shell
+    Ok((flags, PythonInvocation::Interactive))
+}
+
+/// Which of `-m module`/a script/`-c command`/stdin/the interactive shell a `python` invocation
+/// line resolves to, as far as dependency resolution cares: only [`PythonRunTarget::Script`] has a
+/// directory to search for `pyproject.toml`/`requirements.txt`, so unlike the internal
+/// [`PythonInvocation`] this drops the trailing script/command arguments `classify_python_args`
+/// otherwise has to thread through.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PythonRunTarget {
+    Module(String),
+    Script(PathBuf),
+    Command(String),
+    Stdin,
+    Repl,
+}
+
+/// python has idiosyncratic cli options that are hard to replicate with clap, so we roll our own.
+/// Takes args without the first-is-current-program (i.e. python) convention.
+///
+/// `usage: python [option] ... [-c cmd | -m mod | file | -] [arg] ...`
+pub fn naive_python_arg_parser<T: AsRef<str>>(args: &[T]) -> Result<PythonRunTarget, String> {
+    Ok(match classify_python_args(args)?.1 {
+        PythonInvocation::Command(command, _) => PythonRunTarget::Command(command),
+        PythonInvocation::Module(module, _) => PythonRunTarget::Module(module),
+        PythonInvocation::File(path, _) => PythonRunTarget::Script(PathBuf::from(path)),
+        PythonInvocation::Stdin(_) => PythonRunTarget::Stdin,
+        PythonInvocation::Interactive => PythonRunTarget::Repl,
+    })
+}
+
+/// The `sys.argv`-equivalent list for one python invocation mode, pulled out of
+/// [`inject_and_run_python`] so it's covered by a test without needing a real interpreter: CPython's
+/// own convention for a bare interactive shell is `sys.argv == ['']`, and each of the three explicit
+/// modes (`-c`, `-m`, a script file) leads with the mode's own marker/value rather than reusing
+/// `argv[0]` from the outer process.
+fn python_invocation_argv(invocation: &PythonInvocation) -> Vec<String> {
+    match invocation {
+        PythonInvocation::Command(_command, rest) => {
+            ["-c".to_string()].iter().chain(rest).cloned().collect()
+        }
+        PythonInvocation::Module(module, rest) => {
+            [module.clone()].iter().chain(rest).cloned().collect()
         }
+        PythonInvocation::File(path, rest) => [path.clone()]
+            .iter()
+            .chain(rest)
+            .cloned()
+            .collect::<Vec<_>>(),
+        // CPython's own convention for reading the script from stdin: sys.argv[0] == "-"
+        PythonInvocation::Stdin(rest) => ["-".to_string()].iter().chain(rest).cloned().collect(),
+        // CPython's own convention for a bare interactive shell: sys.argv == ['']
+        PythonInvocation::Interactive => vec![String::new()],
     }
 }
 
+/// Assembles the finder-injection script passed to `PyRun_SimpleString`: the finder/convert-finder
+/// modules' source followed by `finder_data`, JSON-encoded and re-embedded as a raw python string
+/// literal. Pulled out of [`inject_and_run_python`] so the single-quote escaping is covered by a
+/// test without needing a real interpreter -- `finder_data` is JSON, which only ever produces
+/// escaped double quotes itself, but nothing stops a path or requirement string somewhere inside it
+/// from containing a literal `'` that would otherwise close the raw string literal early.
+fn build_inject_command(finder_data: &str) -> String {
+    // This is a really horrible way to inject that information and it should be done with
+    // PyRun_StringFlags instead
+    let read_json = "finder_data = FinderData.from_json(finder_data_str)";
+    let update_and_activate = "MonotrailFinder.get_singleton().update_and_activate(finder_data)";
+    format!(
+        "{}\n{}\nfinder_data_str=r'{}'\n{}\n{}\nmaybe_debug()\n",
+        include_str!("../python/monotrail/monotrail_finder.py"),
+        include_str!("../python/monotrail/convert_finder_data.py"),
+        // json itself only quotes with `"`, but a string value can still contain a `'`, which
+        // would end the raw `r'...'` literal early; replacing it with json's own unicode escape
+        // for that character keeps the literal closed, and `FinderData.from_json` still reads
+        // back the original text
+        finder_data.replace('\'', "\\u0027"),
+        read_json,
+        update_and_activate
+    )
+}
+
 /// The way we're using to load symbol by symbol with the type generic is really ugly and cumbersome
 /// If you know how to do this with `extern` or even pyo3-ffi directly please tell me
 ///
+/// The finder-injection sequence below (`Py_InitializeFromConfig` + `PyRun_SimpleString` of
+/// `monotrail_finder.py`/`convert_finder_data.py` + `Py_RunMain`) only calls the handful of symbols
+/// every cpyext-compatible build exposes under the same names, so it has no
+/// `implementation`-specific branch of its own the way [`find_libpython`] does for locating the
+/// shared library itself -- `implementation` is checked below to reject anything but CPython
+/// (see [`PyConfig`]) and is otherwise only passed on to pick the right soname.
+///
+/// Extracting the raw `libloading`/`PyConfig` call surface behind a trait (so a test could swap in
+/// a mock that never dlopens a real libpython) was considered and deliberately not done: every call
+/// below writes through `config_ptr` into the hand-rolled [`PyConfig`] layout described on that
+/// struct, which is only valid for the one CPython minor version it was modeled on. A mock
+/// implementation would either have to duplicate that same version-specific unsafe code (defeating
+/// the point) or abstract at a level built on top of it, which just moves the untestable part rather
+/// than removing it. [`python_invocation_argv`] and [`build_inject_command`] above pull out the two
+/// pieces of this function that *are* pure computation on plain Rust values -- argv construction and
+/// the finder-injection script's escaping -- so those are exercised by `tests::` below instead.
+///
 /// Returns the exit code from python
 pub fn inject_and_run_python(
     python_home: &Path,
     python_version: (u8, u8),
+    implementation: Implementation,
     args: &[String],
     finder_data: &str,
 ) -> anyhow::Result<c_int> {
+    // The hand-rolled `PyConfig` above is modeled on exactly one CPython minor version
+    // (`DEFAULT_PYTHON_VERSION`); every other minor version, and every non-CPython implementation
+    // (PyPy's `PyConfig` doesn't match CPython's at all), shuffles the field layout enough that
+    // reading/writing through this struct would walk off into the wrong fields instead of failing
+    // cleanly (see the `PyConfig` doc comment for specifics). Bail here, before any FFI call touches
+    // `config`, rather than let that happen.
+    if implementation != Implementation::CPython || python_version != DEFAULT_PYTHON_VERSION {
+        bail!(
+            "Only CPython {}.{} is supported for in-process execution (got {} {}.{}): the \
+             embedded PyConfig layout is hand-rolled for that one CPython minor version and \
+             doesn't match any other interpreter or version",
+            DEFAULT_PYTHON_VERSION.0,
+            DEFAULT_PYTHON_VERSION.1,
+            implementation,
+            python_version.0,
+            python_version.1
+        );
+    }
     trace!("Loading libpython");
-    let libpython3_so = if cfg!(target_os = "macos") {
-        python_home.join("lib").join(format!(
-            "libpython{}.{}.dylib",
-            python_version.0, python_version.1
-        ))
-    } else {
-        python_home.join("lib").join("libpython3.so")
-    };
+    let libpython3_so = find_libpython(python_home, python_version, implementation)
+        .context("Failed to determine the location of libpython")?;
     let lib = {
         #[cfg(unix)]
         {
@@ -78,7 +673,9 @@ pub fn inject_and_run_python(
         // Entirely untested, but it should at least compile
         #[cfg(windows)]
         unsafe {
-            libloading::os::unix::Windows::Library::new(libpython3_so)?
+            let windows_lib = libloading::os::windows::Library::new(&libpython3_so)
+                .with_context(|| format!("Failed to load {}", libpython3_so.display()))?;
+            libloading::Library::from(windows_lib)
         }
     };
     trace!("Initializing libpython");
@@ -89,11 +686,173 @@ pub fn inject_and_run_python(
         // TODO: Do this via python c api instead
         env::set_var("PYTHONNOUSERSITE", "1");
         env::set_var("PYTHONUTF8", "1");
-        // https://docs.python.org/3/c-api/init.html?highlight=py_initialize#c.Py_Initialize
-        // void Py_Initialize()
-        let initialize: libloading::Symbol<unsafe extern "C" fn() -> c_void> =
-            lib.get(b"Py_Initialize")?;
-        initialize();
+
+        // https://docs.python.org/3/c-api/init_config.html#c.PyConfig_InitPythonConfig
+        // void PyConfig_InitPythonConfig(PyConfig *config)
+        let config_init_python_config: libloading::Symbol<unsafe extern "C" fn(*mut PyConfig)> =
+            lib.get(b"PyConfig_InitPythonConfig")?;
+        // https://docs.python.org/3/c-api/init_config.html#c.PyConfig_SetString
+        // PyStatus PyConfig_SetString(PyConfig *config, wchar_t * const *config_str, const wchar_t *str)
+        let config_set_string: libloading::Symbol<
+            unsafe extern "C" fn(*mut PyConfig, *mut *mut wchar_t, *const wchar_t) -> PyStatus,
+        > = lib.get(b"PyConfig_SetString")?;
+        // https://docs.python.org/3/c-api/init_config.html#c.PyConfig_SetArgv
+        // PyStatus PyConfig_SetArgv(PyConfig *config, Py_ssize_t argc, wchar_t * const *argv)
+        let config_set_argv: libloading::Symbol<
+            unsafe extern "C" fn(*mut PyConfig, isize, *const *const wchar_t) -> PyStatus,
+        > = lib.get(b"PyConfig_SetArgv")?;
+        // https://docs.python.org/3/c-api/init_config.html#c.PyWideStringList_Append
+        // PyStatus PyWideStringList_Append(PyWideStringList *list, const wchar_t *item)
+        let wide_string_list_append: libloading::Symbol<
+            unsafe extern "C" fn(*mut PyWideStringList, *const wchar_t) -> PyStatus,
+        > = lib.get(b"PyWideStringList_Append")?;
+        // https://docs.python.org/3/c-api/init_config.html#c.Py_InitializeFromConfig
+        // PyStatus Py_InitializeFromConfig(const PyConfig *config)
+        let initialize_from_config: libloading::Symbol<
+            unsafe extern "C" fn(*const PyConfig) -> PyStatus,
+        > = lib.get(b"Py_InitializeFromConfig")?;
+        // https://docs.python.org/3/c-api/init_config.html#c.PyConfig_Clear
+        // void PyConfig_Clear(PyConfig *config)
+        let config_clear: libloading::Symbol<unsafe extern "C" fn(*mut PyConfig)> =
+            lib.get(b"PyConfig_Clear")?;
+        // https://docs.python.org/3/c-api/init_config.html#c.PyStatus_Exception
+        // int PyStatus_Exception(PyStatus status)
+        let status_exception: libloading::Symbol<unsafe extern "C" fn(PyStatus) -> c_int> =
+            lib.get(b"PyStatus_Exception")?;
+        // https://docs.python.org/3/c-api/init_config.html#c.Py_ExitStatusException
+        // void Py_ExitStatusException(PyStatus status) (noreturn on error)
+        let exit_status_exception: libloading::Symbol<unsafe extern "C" fn(PyStatus) -> c_void> =
+            lib.get(b"Py_ExitStatusException")?;
+
+        let mut config: PyConfig = mem::zeroed();
+        // A raw pointer to `config` so the helper closures below can reach both the whole struct
+        // (to pass as the `PyConfig*` parameter) and one of its fields (the `wchar_t**` out
+        // parameter) in the same FFI call without the borrow checker seeing that as aliasing --
+        // the same pattern the C API itself uses (`PyConfig_SetString(&config, &config.home, ...)`).
+        let config_ptr: *mut PyConfig = &mut config;
+        config_init_python_config(config_ptr);
+
+        // We already classify `-c`/`-m`/the script file ourselves below, so we don't need
+        // `Py_InitializeFromConfig` to re-parse `argv` for them.
+        (*config_ptr).parse_argv = 0;
+
+        // The strings a `PyConfig_Set*` call needs to keep alive only have to outlive that one
+        // call -- libpython copies them internally -- so a short-lived `WideCString` per call is
+        // fine here, unlike `args_cstring` below which `PyConfig_SetArgv` borrows in place.
+        let check_status = |status: PyStatus| -> anyhow::Result<()> {
+            if status_exception(status) != 0 {
+                exit_status_exception(status);
+                bail!("Py_ExitStatusException returned instead of exiting the process");
+            }
+            Ok(())
+        };
+        let set_config_string = |field: *mut *mut wchar_t, value: &str| -> anyhow::Result<()> {
+            let value = WideCString::from_str(value).unwrap();
+            check_status(config_set_string(config_ptr, field, value.as_ptr()))
+        };
+
+        // `PyConfig_InitPythonConfig` above already allocated `config`'s own buffers, so from here
+        // on `config_clear(config_ptr)` must run on every exit path, success or failure -- wrapping
+        // the rest of the setup in this closure lets us clear unconditionally below instead of
+        // having to remember a `PyConfig_Clear` before every early `?` return.
+        let init_result: anyhow::Result<PyStatus> = (|| {
+            set_config_string(&mut (*config_ptr).home, &python_home.display().to_string())?;
+            set_config_string(&mut (*config_ptr).program_name, &args[0])?;
+
+            let (flags, invocation) = classify_python_args(&args[1..])
+                .map_err(|err| format_err!("Failed to parse python args: {}", err))?;
+            // Apply every flag `classify_python_args` recognized to the matching `PyConfig` field --
+            // since `config.parse_argv` is 0, `Py_InitializeFromConfig` won't see or act on these
+            // itself. `-h`/`-V` are intentionally not handled: real python prints help/the version
+            // and exits before touching `PyConfig` at all, which would need a dedicated early-exit
+            // path here; passing them through silently (the previous behavior too, since `Py_Main`
+            // was the one that used to special-case them) is a known gap.
+            for PythonFlag { flag, value } in &flags {
+                match flag.as_str() {
+                    // `classify_python_args` decomposes clustered/repeated flags like `-OO` into
+                    // one `-O` per character, so `optimization_level` accumulates correctly here
+                    // without a separate `-OO` arm.
+                    "-O" => (*config_ptr).optimization_level += 1,
+                    "-u" => (*config_ptr).buffered_stdio = 0,
+                    "-E" => (*config_ptr).use_environment = 0,
+                    "-s" => (*config_ptr).user_site_directory = 0,
+                    "-S" => (*config_ptr).site_import = 0,
+                    "-v" => (*config_ptr).verbose += 1,
+                    "-q" => (*config_ptr).quiet = 1,
+                    "-i" => {
+                        (*config_ptr).inspect = 1;
+                        (*config_ptr).interactive = 1;
+                    }
+                    "-b" => (*config_ptr).bytes_warning += 1,
+                    "-B" => (*config_ptr).write_bytecode = 0,
+                    "-d" => (*config_ptr).parser_debug += 1,
+                    "-I" => {
+                        (*config_ptr).isolated = 1;
+                        (*config_ptr).use_environment = 0;
+                        (*config_ptr).user_site_directory = 0;
+                    }
+                    "-W" => {
+                        let item =
+                            WideCString::from_str(value.as_deref().unwrap_or_default()).unwrap();
+                        check_status(wide_string_list_append(
+                            &mut (*config_ptr).warnoptions,
+                            item.as_ptr(),
+                        ))?;
+                    }
+                    "-X" => {
+                        let item =
+                            WideCString::from_str(value.as_deref().unwrap_or_default()).unwrap();
+                        check_status(wide_string_list_append(
+                            &mut (*config_ptr).xoptions,
+                            item.as_ptr(),
+                        ))?;
+                    }
+                    "--check-hash-based-pycs" => {
+                        set_config_string(
+                            &mut (*config_ptr).check_hash_pycs_mode,
+                            value.as_deref().unwrap_or_default(),
+                        )?;
+                    }
+                    // -h/-V: see the comment above this loop
+                    _ => {}
+                }
+            }
+
+            match &invocation {
+                PythonInvocation::Command(command, _) => {
+                    set_config_string(&mut (*config_ptr).run_command, command)?;
+                }
+                PythonInvocation::Module(module, _) => {
+                    set_config_string(&mut (*config_ptr).run_module, module)?;
+                }
+                PythonInvocation::File(path, _) => {
+                    set_config_string(&mut (*config_ptr).run_filename, path)?;
+                }
+                // CPython checks `run_filename == "-"` itself and reads the script from stdin
+                PythonInvocation::Stdin(_) => {
+                    set_config_string(&mut (*config_ptr).run_filename, "-")?;
+                }
+                PythonInvocation::Interactive => {}
+            }
+            let argv = python_invocation_argv(&invocation);
+            let argv_cstring: Vec<WideCString> = argv
+                .iter()
+                .map(|arg| WideCString::from_str(arg).unwrap())
+                .collect();
+            let argv_c_char: Vec<*const wchar_t> = argv_cstring
+                .iter()
+                .map(|arg| arg.as_ptr() as *const wchar_t)
+                .collect();
+            check_status(config_set_argv(
+                config_ptr,
+                argv_c_char.len() as isize,
+                argv_c_char.as_ptr(),
+            ))?;
+
+            Ok(initialize_from_config(config_ptr))
+        })();
+        config_clear(config_ptr);
+        check_status(init_result?)?;
 
         debug!("Injecting monotrail");
         // Add our finder
@@ -102,23 +861,7 @@ pub fn inject_and_run_python(
         let run_string: libloading::Symbol<unsafe extern "C" fn(*const char) -> c_int> =
             lib.get(b"PyRun_SimpleString")?;
 
-        // This is a really horrible way to inject that information and it should be done with
-        // PyRun_StringFlags instead
-        let read_json = "finder_data = FinderData.from_json(finder_data_str)";
-        let update_and_activate =
-            "MonotrailFinder.get_singleton().update_and_activate(finder_data)";
-        let command_str = format!(
-            "{}\n{}\nfinder_data_str=r'{}'\n{}\n{}\nmaybe_debug()\n",
-            include_str!("../python/monotrail/monotrail_finder.py"),
-            include_str!("../python/monotrail/convert_finder_data.py"),
-            // TODO: actual encoding strings
-            // This just hopefully works because json uses double quotes so there shouldn't
-            // be any escaped single quotes in there
-            finder_data.replace('\'', r"\u0027"),
-            read_json,
-            update_and_activate
-        );
-
+        let command_str = build_inject_command(finder_data);
         let command = CString::new(command_str.clone()).unwrap();
         let result = run_string(command.as_ptr() as *const char);
         if result != 0 {
@@ -126,23 +869,14 @@ pub fn inject_and_run_python(
             bail!("Injecting monotrail failed. Try RUST_LOG=debug for more info")
         }
 
-        debug!("Running Py_Main: {}", args.join(" "));
-        // run python interpreter as from the cli
-        // https://docs.python.org/3/c-api/veryhigh.html#c.Py_BytesMain
-        let py_main: libloading::Symbol<unsafe extern "C" fn(c_int, *mut *const wchar_t) -> c_int> =
-            lib.get(b"Py_Main")?;
-
-        // env::args panics when there is a non utf-8 string, but converting OsString -> *c_char
-        // is an even bigger mess
-        let args_cstring: Vec<WideCString> = args
-            .iter()
-            .map(|arg| WideCString::from_str(&arg).unwrap())
-            .collect();
-        let mut args_c_char: Vec<*const wchar_t> = args_cstring
-            .iter()
-            .map(|arg| arg.as_ptr() as *const wchar_t)
-            .collect();
-        let exit_code = py_main(args_cstring.len() as c_int, args_c_char.as_mut_ptr());
+        debug!("Running Py_RunMain: {}", args.join(" "));
+        // run python interpreter as from the cli, using the command/module/file/interactive
+        // mode already baked into `config` above instead of letting this re-parse argv
+        // https://docs.python.org/3/c-api/veryhigh.html#c.Py_RunMain
+        // int Py_RunMain(void)
+        let run_main: libloading::Symbol<unsafe extern "C" fn() -> c_int> =
+            lib.get(b"Py_RunMain")?;
+        let exit_code = run_main();
         // > The return value will be 0 if the interpreter exits normally (i.e., without an
         // > exception), 1 if the interpreter exits due to an exception, or 2 if the parameter list
         // > does not represent a valid Python command line.
@@ -154,12 +888,15 @@ pub fn inject_and_run_python(
     }
 }
 
-/// Allows doing `monotrail_python +3.10 -m say.hello`
+/// Allows doing `monotrail_python +3.10 -m say.hello`, `monotrail_python +pypy3.9 -m say.hello`
+/// or `monotrail_python +3.11.7 -m say.hello` to pin an exact patch
 #[allow(clippy::type_complexity)]
-pub fn parse_plus_arg(python_args: &[String]) -> anyhow::Result<(Vec<String>, Option<(u8, u8)>)> {
+pub fn parse_plus_arg(
+    python_args: &[String],
+) -> anyhow::Result<(Vec<String>, Option<(Implementation, u8, u8, Option<u8>)>)> {
     if let Some(first_arg) = python_args.get(0) {
         if first_arg.starts_with('+') {
-            let python_version = parse_major_minor(first_arg)?;
+            let python_version = parse_implementation_version(first_arg)?;
             return Ok((python_args[1..].to_vec(), Some(python_version)));
         }
     }
@@ -183,27 +920,89 @@ pub fn parse_major_minor(version: &str) -> anyhow::Result<(u8, u8)> {
     Ok(python_version)
 }
 
+/// Parses "3.8" to `(3, 8, None)` and "3.8.12" to `(3, 8, Some(12))`, the patch-tolerant
+/// counterpart of [`parse_major_minor`] for spots that can act on an exact patch pin (provisioning
+/// a specific [`crate::standalone_python::provision_python`] build) instead of just discarding it
+/// the way [`major_minor_from_dotted`] does for `.python-version` files
+fn parse_major_minor_patch(version: &str) -> anyhow::Result<(u8, u8, Option<u8>)> {
+    let mut parts = version.trim_start_matches('+').splitn(3, '.');
+    let major = parts
+        .next()
+        .context("Expect +x.y as first argument (missing dot)")?
+        .parse::<u8>()
+        .context("Could not parse value of version_major")?;
+    let minor = parts
+        .next()
+        .context("Expect +x.y as first argument (missing dot)")?
+        .parse::<u8>()
+        .context("Could not parse value of version_minor")?;
+    let patch = parts
+        .next()
+        .map(|patch| {
+            patch
+                .parse::<u8>()
+                .context("Could not parse value of version_patch")
+        })
+        .transpose()?;
+    Ok((major, minor, patch))
+}
+
+/// Parses "3.8" to `(Implementation::CPython, 3, 8, None)`, "pypy3.9" to
+/// `(Implementation::PyPy, 3, 9, None)` and "3.11.7"/"pypy3.9.16" to the same with an exact patch
+/// pinned, the `+x.y[.z]`/`+implementationx.y[.z]` syntax used to select an interpreter on the cli
+/// and through `MONOTRAIL_PYTHON_VERSION`
+pub fn parse_implementation_version(
+    version: &str,
+) -> anyhow::Result<(Implementation, u8, u8, Option<u8>)> {
+    let version = version.trim_start_matches('+');
+    let (implementation, version) = match version.strip_prefix("pypy") {
+        Some(rest) => (Implementation::PyPy, rest),
+        None => (Implementation::CPython, version),
+    };
+    let (major, minor, patch) = parse_major_minor_patch(version)?;
+    Ok((implementation, major, minor, patch))
+}
+
 pub fn run_python_args(
     args: &[String],
     python_version: Option<&str>,
     root: Option<&Path>,
     extras: &[String],
+    resolution_mode: ResolutionMode,
 ) -> anyhow::Result<i32> {
-    let (args, python_version) = determine_python_version(args, python_version)?;
+    // Canonicalize upfront so every place that implicitly relied on the current directory (finding
+    // the dependency file, finding a `.python-version`) agrees on the same, fully resolved project
+    // directory regardless of where monotrail itself was invoked from
+    let root = root
+        .map(|root| {
+            root.canonicalize()
+                .with_context(|| format!("Invalid --root: {}", root.display()))
+        })
+        .transpose()?;
+    let (args, implementation, python_version, patch) =
+        determine_python_version(args, python_version, root.as_deref())?;
 
-    let script = if let Some(root) = root {
+    let script = if let Some(root) = &root {
         Some(root.to_path_buf())
     } else {
-        naive_python_arg_parser(&args)
+        match naive_python_arg_parser(&args)
             .map_err(|err| format_err!("Failed to parse python args: {}", err))?
-            .map(PathBuf::from)
+        {
+            PythonRunTarget::Script(path) => Some(path),
+            PythonRunTarget::Module(_)
+            | PythonRunTarget::Command(_)
+            | PythonRunTarget::Stdin
+            | PythonRunTarget::Repl => None,
+        }
     };
     debug!("run_python_args: {:?}, `{}`", script, args.join(" "));
 
-    let (python_context, python_home) = provision_python(python_version)?;
+    let (python_context, python_home) = provision_python(implementation, python_version, patch)?;
 
-    let (specs, scripts, lockfile) = get_specs(script.as_deref(), extras, &python_context)?;
-    let finder_data = install_specs_to_finder(&specs, scripts, lockfile, None, &python_context)?;
+    let (specs, scripts, lockfile, project_dir) =
+        get_specs(script.as_deref(), extras, resolution_mode, &python_context)?;
+    let finder_data =
+        install_specs_to_finder(&specs, scripts, lockfile, project_dir, &python_context)?;
 
     let args: Vec<_> = [python_context.sys_executable.to_string_lossy().to_string()]
         .into_iter()
@@ -213,6 +1012,7 @@ pub fn run_python_args(
     let exit_code = inject_and_run_python(
         &python_home,
         python_context.version,
+        python_context.implementation,
         &args,
         &serde_json::to_string(&finder_data).unwrap(),
     )
@@ -223,21 +1023,117 @@ pub fn run_python_args(
     Ok(exit_code as i32)
 }
 
-/// There are three possible sources of a python version:
+/// Walks from `start` up to the filesystem root looking for a `.python-version` or (plural,
+/// pyenv-style) `.python-versions` file, the same way pyenv/uv discover a pinned interpreter.
+/// Stops at the first directory that has either, preferring the singular file when a directory
+/// has both.
+pub(crate) fn find_python_version_file(start: &Path) -> Option<PathBuf> {
+    let mut dir = Some(start);
+    while let Some(current) = dir {
+        for filename in [".python-version", ".python-versions"] {
+            let candidate = current.join(filename);
+            if candidate.is_file() {
+                return Some(candidate);
+            }
+        }
+        dir = current.parent();
+    }
+    None
+}
+
+/// Parses the first non-comment line of a `.python-version`/`.python-versions` file, tolerating a
+/// trailing patch version (e.g. `3.10.4`) which `parse_major_minor` alone can't handle, and a PEP
+/// 440 version range (e.g. `>=3.10,<3.13`) as uv accepts. For the plural, multi-version form, this
+/// is the first (highest-priority) entry; the rest are ignored, same as we only ever target a
+/// single interpreter
+pub(crate) fn read_python_version_file(path: &Path) -> anyhow::Result<(u8, u8)> {
+    let content =
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let version_line = content
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty() && !line.starts_with('#'))
+        .with_context(|| format!("{} doesn't contain a version", path.display()))?;
+    if version_line.contains(|c: char| "<>=!~".contains(c)) {
+        return python_version_range_lower_bound(version_line)
+            .with_context(|| format!("Couldn't parse python version range in {}", path.display()));
+    }
+    major_minor_from_dotted(version_line)
+        .with_context(|| format!("Couldn't parse python version in {}", path.display()))
+}
+
+/// Extracts `(major, minor)` from a dotted version string, ignoring any patch component (e.g.
+/// `3.10.4` -> `(3, 10)`)
+fn major_minor_from_dotted(version: &str) -> anyhow::Result<(u8, u8)> {
+    let mut components = version.splitn(3, '.');
+    let major_minor = format!(
+        "{}.{}",
+        components.next().unwrap_or_default(),
+        components.next().unwrap_or_default()
+    );
+    parse_major_minor(&major_minor)
+}
+
+/// Resolves a PEP 440 version range such as `>=3.10,<3.13` to the `(major, minor)` of its lower
+/// bound. We don't enumerate available python-build-standalone releases here to pick the highest
+/// minor satisfying the whole range, so the lower bound -- the version a project actually declares
+/// it needs -- is what we provision. A range can list more than one lower-bound specifier (e.g.
+/// merged from several sources as `>=3.9,>=3.12,<3.13`), so we take the tightest (highest) one
+/// rather than whichever comes first; `>` is treated the same as `>=` at our major.minor
+/// granularity, so an exclusive bound like `>3.9.5` still resolves to `3.9` rather than `3.10`.
+fn python_version_range_lower_bound(range: &str) -> anyhow::Result<(u8, u8)> {
+    let specifiers = VersionSpecifiers::from_str(range).map_err(|err| {
+        format_err!(
+            "'{}' is not a valid version or version range: {}",
+            range,
+            err
+        )
+    })?;
+    let lower_bound = specifiers
+        .iter()
+        .filter(|specifier| {
+            matches!(
+                specifier.operator(),
+                Operator::GreaterThanEqual
+                    | Operator::GreaterThan
+                    | Operator::Equal
+                    | Operator::EqualStar
+                    | Operator::TildeEqual
+            )
+        })
+        .max_by(|a, b| a.version().cmp(b.version()))
+        .with_context(|| {
+            format!(
+                "Version range '{}' has no lower bound (e.g. '>=3.10')",
+                range
+            )
+        })?;
+    major_minor_from_dotted(&lower_bound.version().to_string())
+}
+
+/// There are four possible sources of a python version, checked in this order of priority:
 ///  - explicitly as cli argument
-///  - as +x.y in the python args
+///  - as +x.y (or +pypyx.y) in the python args
 ///  - through MONOTRAIL_PYTHON_VERSION, as forwarding through calling our python hook (TODO: give
 ///    version info to the python hook, maybe with /usr/bin/env, but i don't know how)
-/// We ensure that only one is set a time  
+///  - a `.python-version` file in `base_dir` (the current directory, or the `--root`/`--directory`
+///    project dir if one was given) or any of its parents, lowest priority so it never fights an
+///    explicit source
+/// We ensure that only one of the first three is set a time. The first three may also pin an exact
+/// patch (e.g. `+3.11.7`); a `.python-version` file can't, so provisioning picks the newest matching
+/// patch for that last source instead
 pub fn determine_python_version(
     python_args: &[String],
     python_version: Option<&str>,
-) -> anyhow::Result<(Vec<String>, (u8, u8))> {
+    base_dir: Option<&Path>,
+) -> anyhow::Result<(Vec<String>, Implementation, (u8, u8), Option<u8>)> {
     let (args, python_version_plus) = parse_plus_arg(&python_args)?;
-    let python_version_arg = python_version.map(parse_major_minor).transpose()?;
+    let python_version_arg = python_version
+        .map(parse_implementation_version)
+        .transpose()?;
     let env_var = format!("{}_PYTHON_VERSION", env!("CARGO_PKG_NAME").to_uppercase());
     let python_version_env = env::var_os(&env_var)
-        .map(|x| parse_major_minor(x.to_string_lossy().as_ref()))
+        .map(|x| parse_implementation_version(x.to_string_lossy().as_ref()))
         .transpose()
         .with_context(|| format!("Couldn't parse {}", env_var))?;
     trace!(
@@ -247,11 +1143,11 @@ pub fn determine_python_version(
         env_var,
         python_version_env
     );
-    let python_version = match (python_version_plus, python_version_arg, python_version_env) {
-        (None, None, None) => DEFAULT_PYTHON_VERSION,
-        (Some(python_version_plus), None, None) => python_version_plus,
-        (None, Some(python_version_arg), None) => python_version_arg,
-        (None, None, Some(python_version_env)) => python_version_env,
+    let explicit = match (python_version_plus, python_version_arg, python_version_env) {
+        (None, None, None) => None,
+        (Some(version), None, None) => Some(version),
+        (None, Some(version), None) => Some(version),
+        (None, None, Some(version)) => Some(version),
         (python_version_plus, python_version_arg, python_version_env) => {
             bail!(
                 "Conflicting python versions: as argument {:?}, with plus: {:?}, with {}: {:?}",
@@ -262,32 +1158,333 @@ pub fn determine_python_version(
             );
         }
     };
-    Ok((args, python_version))
+    let version_file = python_version_file_near(base_dir);
+    let (implementation, python_version, patch) =
+        if let Some((implementation, major, minor, patch)) = explicit {
+            // An explicit source always wins, but a differing pin is likely a mistake, so we at
+            // least surface it instead of silently ignoring the file
+            if let Some(version_file) = &version_file {
+                if let Ok(file_version) = read_python_version_file(version_file) {
+                    if file_version != (major, minor) {
+                        warn!(
+                            "Using python {}.{}, overriding {}.{} pinned in {}",
+                            major,
+                            minor,
+                            file_version.0,
+                            file_version.1,
+                            version_file.display()
+                        );
+                    }
+                }
+            }
+            debug!("Using python {}.{} from an explicit argument", major, minor);
+            (implementation, (major, minor), patch)
+        } else if let Some(version_file) = version_file {
+            debug!("Using python version from {}", version_file.display());
+            (
+                Implementation::CPython,
+                read_python_version_file(&version_file)?,
+                // `.python-version` files are a major.minor(.patch) pin, but `read_python_version_file`
+                // already discards the patch component (and version ranges have no single patch at
+                // all), so there's nothing to pass on here -- provisioning falls back to the newest
+                // matching patch, same as before patch pinning existed
+                None,
+            )
+        } else {
+            debug!(
+                "No explicit python version or .python-version file found, defaulting to {}.{}",
+                DEFAULT_PYTHON_VERSION.0, DEFAULT_PYTHON_VERSION.1
+            );
+            (Implementation::CPython, DEFAULT_PYTHON_VERSION, None)
+        };
+    Ok((args, implementation, python_version, patch))
+}
+
+/// Resolves the `.python-version`/`.python-versions` file to use for `base_dir` (or the current
+/// directory if not given), shared by every discovery entry point in this module
+fn python_version_file_near(base_dir: Option<&Path>) -> Option<PathBuf> {
+    match base_dir {
+        Some(base_dir) => find_python_version_file(base_dir),
+        None => env::current_dir()
+            .ok()
+            .as_deref()
+            .and_then(find_python_version_file),
+    }
+}
+
+/// The same `.python-version`/`.python-versions` discovery as [`determine_python_version`], for
+/// entry points such as `ppipx` that don't go through the python-shim arg parsing and so never
+/// have an "explicit" source to consider: an explicit `--python-version` always wins there, and
+/// only an unset one falls back to this
+pub(crate) fn default_python_version() -> anyhow::Result<(u8, u8)> {
+    match python_version_file_near(None) {
+        Some(version_file) => {
+            debug!("Using python version from {}", version_file.display());
+            read_python_version_file(&version_file)
+        }
+        None => {
+            debug!(
+                "No .python-version file found, defaulting to {}.{}",
+                DEFAULT_PYTHON_VERSION.0, DEFAULT_PYTHON_VERSION.1
+            );
+            Ok(DEFAULT_PYTHON_VERSION)
+        }
+    }
+}
+
+/// Every version declared in a `.python-version`/`.python-versions` file near `base_dir` (or the
+/// current directory), as raw strings ready to feed back into `-p`/`--python-version`, alongside
+/// the path that was matched (for tracing). Unlike [`read_python_version_file`] (which only needs
+/// the highest-priority single version), this keeps every line of a plural `.python-versions`
+/// file, so [`crate::cli::run_cli`] can run each one in turn, tox-style, the same as if they had
+/// all been passed as repeated `-p` flags
+pub(crate) fn discover_python_versions(base_dir: Option<&Path>) -> Option<(PathBuf, Vec<String>)> {
+    let path = python_version_file_near(base_dir)?;
+    let content = fs::read_to_string(&path).ok()?;
+    let versions: Vec<String> = content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect();
+    if versions.is_empty() {
+        None
+    } else {
+        Some((path, versions))
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::inject_and_run::naive_python_arg_parser;
+    use crate::inject_and_run::{
+        build_inject_command, discover_python_versions, find_python_version_file,
+        naive_python_arg_parser, parse_implementation_version, parse_major_minor_patch,
+        python_invocation_argv, read_python_version_file, PythonInvocation, PythonRunTarget,
+    };
+    use crate::monotrail::Implementation;
+    use fs_err as fs;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_parse_implementation_version() {
+        let cases = [
+            ("3.10", (Implementation::CPython, 3, 10, None)),
+            ("+3.10", (Implementation::CPython, 3, 10, None)),
+            ("pypy3.9", (Implementation::PyPy, 3, 9, None)),
+            ("+pypy3.9", (Implementation::PyPy, 3, 9, None)),
+            ("3.11.7", (Implementation::CPython, 3, 11, Some(7))),
+            ("+3.11.7", (Implementation::CPython, 3, 11, Some(7))),
+            ("pypy3.9.16", (Implementation::PyPy, 3, 9, Some(16))),
+        ];
+        for (version, expected) in cases {
+            assert_eq!(parse_implementation_version(version).unwrap(), expected);
+        }
+        assert!(parse_implementation_version("jython2.7").is_err());
+        assert!(parse_implementation_version("3.11.abc").is_err());
+    }
+
+    #[test]
+    fn test_parse_major_minor_patch() {
+        assert_eq!(parse_major_minor_patch("3.11").unwrap(), (3, 11, None));
+        assert_eq!(parse_major_minor_patch("3.11.7").unwrap(), (3, 11, Some(7)));
+        assert_eq!(
+            parse_major_minor_patch("+3.11.7").unwrap(),
+            (3, 11, Some(7))
+        );
+        let err = parse_major_minor_patch("3.11.abc").unwrap_err().to_string();
+        assert_eq!(err, "Could not parse value of version_patch");
+        assert!(parse_major_minor_patch("3").is_err());
+    }
 
     #[test]
     fn test_naive_python_arg_parser() {
         let cases: &[(&[&str], _)] = &[
             (
                 &["-v", "-m", "mymod", "--first_arg", "second_arg"],
-                Ok(None),
+                Ok(PythonRunTarget::Module("mymod".to_string())),
             ),
             (
                 &["-v", "my_script.py", "--first_arg", "second_arg"],
-                Ok(Some("my_script.py".to_string())),
+                Ok(PythonRunTarget::Script(PathBuf::from("my_script.py"))),
+            ),
+            (
+                &["-c", "print(1)", "second_arg"],
+                Ok(PythonRunTarget::Command("print(1)".to_string())),
             ),
-            (&["-v"], Ok(None)),
-            (&[], Ok(None)),
+            (&["-"], Ok(PythonRunTarget::Stdin)),
+            (&["-", "second_arg"], Ok(PythonRunTarget::Stdin)),
+            (&["-v"], Ok(PythonRunTarget::Repl)),
+            (&[], Ok(PythonRunTarget::Repl)),
             (&["-m"], Err("Missing argument for -m".to_string())),
+            (&["-c"], Err("Missing argument for -c".to_string())),
+            // clustered short flags (`-vI`) and a repeated one (`-OO`) are recognized instead of
+            // being mistaken for the script filename
+            (
+                &["-vI", "my_script.py"],
+                Ok(PythonRunTarget::Script(PathBuf::from("my_script.py"))),
+            ),
+            (
+                &["-OO", "my_script.py"],
+                Ok(PythonRunTarget::Script(PathBuf::from("my_script.py"))),
+            ),
         ];
         for (args, parsing) in cases {
             assert_eq!(&naive_python_arg_parser(args), parsing);
         }
     }
+
+    #[test]
+    fn test_python_invocation_argv() {
+        let cases = [
+            (
+                PythonInvocation::Command("print(1)".to_string(), vec!["arg".to_string()]),
+                vec!["-c".to_string(), "arg".to_string()],
+            ),
+            (
+                PythonInvocation::Module("mymod".to_string(), vec!["arg".to_string()]),
+                vec!["mymod".to_string(), "arg".to_string()],
+            ),
+            (
+                PythonInvocation::File("script.py".to_string(), vec!["arg".to_string()]),
+                vec!["script.py".to_string(), "arg".to_string()],
+            ),
+            (
+                PythonInvocation::Stdin(vec!["arg".to_string()]),
+                vec!["-".to_string(), "arg".to_string()],
+            ),
+            (PythonInvocation::Interactive, vec![String::new()]),
+        ];
+        for (invocation, expected) in cases {
+            assert_eq!(python_invocation_argv(&invocation), expected);
+        }
+    }
+
+    #[test]
+    fn test_build_inject_command_escapes_single_quotes() {
+        let finder_data = r#"{"path": "C:\\Users\\it's a path"}"#;
+        let command = build_inject_command(finder_data);
+        // the raw string literal the finder data is embedded in must not be closed early by a
+        // literal `'` coming from inside `finder_data` itself
+        let literal = command
+            .split("finder_data_str=r'")
+            .nth(1)
+            .unwrap()
+            .split("'\n")
+            .next()
+            .unwrap();
+        assert!(!literal.contains('\''));
+        assert!(literal.contains(r"it\u0027s a path"));
+    }
+
+    #[test]
+    fn test_build_inject_command_round_trips_finder_data() {
+        let finder_data = r#"{"key": "value"}"#;
+        let command = build_inject_command(finder_data);
+        assert!(command.contains(&format!("finder_data_str=r'{}'", finder_data)));
+        assert!(command.contains("finder_data = FinderData.from_json(finder_data_str)"));
+        assert!(
+            command.contains("MonotrailFinder.get_singleton().update_and_activate(finder_data)")
+        );
+    }
+
+    #[test]
+    fn test_python_version_file_walks_up_and_tolerates_patch() {
+        let root = tempfile::tempdir().unwrap();
+        let nested = root.path().join("a").join("b");
+        fs::create_dir_all(&nested).unwrap();
+        fs::write(root.path().join(".python-version"), "# pinned\n3.10.4\n").unwrap();
+
+        let found = find_python_version_file(&nested).unwrap();
+        assert_eq!(found, root.path().join(".python-version"));
+        assert_eq!(read_python_version_file(&found).unwrap(), (3, 10));
+    }
+
+    #[test]
+    fn test_no_python_version_file() {
+        let root = tempfile::tempdir().unwrap();
+        assert!(find_python_version_file(root.path()).is_none());
+    }
+
+    #[test]
+    fn test_python_versions_plural_file_is_found_too() {
+        let root = tempfile::tempdir().unwrap();
+        let nested = root.path().join("a").join("b");
+        fs::create_dir_all(&nested).unwrap();
+        fs::write(root.path().join(".python-versions"), "3.9.1\n3.8.12\n").unwrap();
+
+        let found = find_python_version_file(&nested).unwrap();
+        assert_eq!(found, root.path().join(".python-versions"));
+        assert_eq!(read_python_version_file(&found).unwrap(), (3, 9));
+    }
+
+    #[test]
+    fn test_discover_python_versions_keeps_every_line_of_the_plural_file() {
+        let root = tempfile::tempdir().unwrap();
+        let nested = root.path().join("a").join("b");
+        fs::create_dir_all(&nested).unwrap();
+        fs::write(
+            root.path().join(".python-versions"),
+            "# tox-style\n3.9.1\n\n3.8.12\n",
+        )
+        .unwrap();
+
+        let (path, versions) = discover_python_versions(Some(&nested)).unwrap();
+        assert_eq!(path, root.path().join(".python-versions"));
+        assert_eq!(versions, vec!["3.9.1", "3.8.12"]);
+    }
+
+    #[test]
+    fn test_discover_python_versions_none_without_a_file() {
+        let root = tempfile::tempdir().unwrap();
+        assert!(discover_python_versions(Some(root.path())).is_none());
+    }
+
+    #[test]
+    fn test_python_version_file_accepts_a_range() {
+        let root = tempfile::tempdir().unwrap();
+        fs::write(root.path().join(".python-version"), ">=3.10,<3.13\n").unwrap();
+
+        let found = find_python_version_file(root.path()).unwrap();
+        assert_eq!(read_python_version_file(&found).unwrap(), (3, 10));
+    }
+
+    #[test]
+    fn test_python_version_file_range_picks_tightest_lower_bound() {
+        let root = tempfile::tempdir().unwrap();
+        fs::write(root.path().join(".python-version"), ">=3.9,>=3.12,<3.13\n").unwrap();
+
+        let found = find_python_version_file(root.path()).unwrap();
+        assert_eq!(read_python_version_file(&found).unwrap(), (3, 12));
+    }
+
+    #[test]
+    fn test_inject_and_run_python_rejects_non_38_cpython() {
+        // The version check runs before anything touches the filesystem or dlopens a library, so
+        // a nonexistent `python_home` doesn't get in the way of exercising it in isolation.
+        let err = super::inject_and_run_python(
+            &PathBuf::from("/does/not/exist"),
+            (3, 11),
+            Implementation::CPython,
+            &[],
+            "",
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("Only CPython 3.8 is supported"));
+    }
+
+    #[test]
+    fn test_inject_and_run_python_rejects_pypy() {
+        // PyPy never matches the hand-rolled CPython `PyConfig` layout, regardless of version.
+        let err = super::inject_and_run_python(
+            &PathBuf::from("/does/not/exist"),
+            (3, 8),
+            Implementation::PyPy,
+            &[],
+            "",
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("Only CPython 3.8 is supported"));
+    }
 }
 
 /// Extends PATH with a directory containing all the scripts we found. This is because many tools
@@ -311,8 +1508,9 @@ pub fn prepare_execve_environment(
         }
         #[cfg(windows)]
         {
-            os::windows::fs::symlink_file(&script_path, path_dir.join(script_name))
-                .context("Failed to create symlink for scripts PATH")?;
+            // Symlinks require elevated privileges on windows by default, so we copy instead
+            fs::copy(&script_path, path_dir.join(script_name))
+                .context("Failed to create script shim for scripts PATH")?;
         }
     }
 
@@ -335,10 +1533,26 @@ pub fn prepare_execve_environment(
         }
     }
 
+    #[cfg(windows)]
+    {
+        // Same moonlighting trick as on unix (see above), except there's no execve on windows,
+        // so these are there for tools that look up `python[3[.x]].exe` on PATH rather than
+        // exec'ing it directly. `.exe` is required for windows' PATH lookup to find them
+        let pythons = [
+            "python.exe".to_string(),
+            format!("python{}.exe", python_version.0),
+            format!("python{}.{}.exe", python_version.0, python_version.1),
+        ];
+        for python in pythons {
+            fs::copy(env::current_exe()?, path_dir.join(python))
+                .context("Failed to create shim for current exe")?;
+        }
+    }
+
     // venv/bin/activate also puts venv scripts first. Our python launcher we have to put first
     // anyway to overwrite system python
     let mut path = path_dir.into_os_string();
-    path.push(":");
+    path.push(if cfg!(windows) { ";" } else { ":" });
     path.push(env::var_os("PATH").unwrap_or_default());
     env::set_var("PATH", path);
 
@@ -356,3 +1570,37 @@ pub fn prepare_execve_environment(
 
     Ok(())
 }
+
+/// Launches `executable` with `args` as argv, replacing the current process on unix. There's no
+/// equivalent of `execve` on windows, so there we instead spawn `executable` as a child and wait
+/// for it, forwarding our (current) environment the same way `execve` would have inherited it --
+/// in particular whatever [`prepare_execve_environment`] put on `PATH` -- since [`Command`]
+/// inherits the calling process' environment by default.
+#[cfg(unix)]
+pub fn exec_or_spawn(executable: &Path, args: &[CString]) -> anyhow::Result<i32> {
+    let executable_c_str = CString::new(executable.to_string_lossy().as_bytes())
+        .context("Failed to convert executable path")?;
+    // We replace the current process with the new process, it's like actually just running the
+    // real thing. Note that this may launch a python script, a native binary or anything else
+    nix::unistd::execv(&executable_c_str, args)
+        .with_context(|| format!("Failed to launch {}", executable.display()))?;
+    unreachable!("execv only returns on error, which we already handled above")
+}
+
+/// Launches `executable` with `args` as argv, replacing the current process on unix. There's no
+/// equivalent of `execve` on windows, so there we instead spawn `executable` as a child and wait
+/// for it, forwarding our (current) environment the same way `execve` would have inherited it --
+/// in particular whatever [`prepare_execve_environment`] put on `PATH` -- since [`Command`]
+/// inherits the calling process' environment by default.
+#[cfg(windows)]
+pub fn exec_or_spawn(executable: &Path, args: &[CString]) -> anyhow::Result<i32> {
+    let args: Vec<String> = args
+        .iter()
+        .map(|arg| arg.to_string_lossy().to_string())
+        .collect();
+    let status = Command::new(executable)
+        .args(args)
+        .status()
+        .with_context(|| format!("Failed to launch {}", executable.display()))?;
+    Ok(status.code().unwrap_or(1))
+}
@@ -1,20 +1,34 @@
 //! Filter and install python packages with install-wheel-rs
 
-use crate::cli::download_distribution_cached;
+use crate::cli::{artifact_cache_path, download_distribution_cached};
+use crate::lock_export::LockedSource;
 use crate::monotrail::filter_installed_monotrail;
+use crate::package_index::{download_distributions, DownloadRequest};
+use crate::site_packages::{Satisfaction, SitePackagesIndex};
 use crate::source_distribution::build_source_distribution_to_wheel_cached;
-use crate::spec::{DistributionType, FileOrUrl, RequestedSpec};
+use crate::spec::{is_sdist_filename, DistributionType, FileOrUrl, RequestedSpec};
+use crate::venv_parser::VirtualEnvironment;
 use anyhow::{bail, Context};
+use flate2::read::GzDecoder;
 use fs_err as fs;
 use fs_err::{DirEntry, File};
 use git2::{Direction, Repository};
 use indicatif::{ProgressBar, ProgressStyle};
-use install_wheel_rs::{install_wheel, parse_key_value_file, InstallLocation, LockedDir};
+use install_wheel_rs::{
+    install_wheel, parse_key_value_file, read_record_file, InstallLocation, LockedDir,
+    WheelFilename,
+};
+use pep440_rs::{Version, VersionSpecifiers};
+use pep508_rs::Requirement;
 use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
 use std::io;
+use std::io::Read;
 use std::ops::Deref;
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use std::sync::{Arc, Mutex};
 use std::thread::sleep;
 use std::time::{Duration, Instant};
@@ -23,7 +37,7 @@ use tracing::{debug, info, trace, warn};
 
 /// what we communicate back to python
 #[cfg(not(feature = "python_bindings"))]
-#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub struct InstalledPackage {
     pub name: String,
     pub python_version: String,
@@ -36,7 +50,7 @@ pub struct InstalledPackage {
 /// TODO: write a pyo3 bug report to parse through cfg attr
 #[cfg(feature = "python_bindings")]
 #[pyo3::pyclass]
-#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub struct InstalledPackage {
     #[pyo3(get)]
     pub name: String,
@@ -66,10 +80,56 @@ impl InstalledPackage {
         _python_version: (u8, u8),
     ) -> PathBuf {
         self.monotrail_location(sprawl_root)
-            .join("lib")
-            .join("python")
-            .join("site-packages")
+            .join(site_packages_suffix())
     }
+
+    /// Like [`Self::monotrail_site_packages`], but relative to `sprawl_root` instead of joined
+    /// onto it, so it can be recorded in a manifest that doesn't know the sprawl root it will
+    /// eventually be installed under (see [`crate::lock_export::LockedPackage`])
+    pub fn relative_site_packages(&self) -> PathBuf {
+        PathBuf::from(&self.name)
+            .join(&self.unique_version)
+            .join(&self.tag)
+            .join(site_packages_suffix())
+    }
+}
+
+/// `lib/python/site-packages`, the subdirectory of a package's sprawl location that's added to
+/// `sys.path`
+fn site_packages_suffix() -> PathBuf {
+    PathBuf::from("lib").join("python").join("site-packages")
+}
+
+/// Parses the `Name`/`Version` a dist-info's `METADATA` file declares, the authoritative source
+/// per the core metadata spec. Falls back to `None` (letting the caller use the dist-info
+/// directory name instead) on any read/parse failure, the same permissive fallback this codebase
+/// already uses for optional-but-preferred metadata sources
+fn name_version_from_metadata(dist_info: &Path) -> Option<(String, String)> {
+    let metadata = parse_key_value_file(
+        &mut File::open(dist_info.join("METADATA")).ok()?,
+        "METADATA",
+    )
+    .ok()?;
+    let name = metadata
+        .get("Name")?
+        .first()?
+        .to_lowercase()
+        .replace('-', "_");
+    let version = metadata.get("Version")?.first()?.clone();
+    Some((name, version))
+}
+
+/// Whether `dist_info`'s `RECORD` is present and parses, the same file
+/// [`crate::verify_installation::verify_package`] relies on to know what the package put on disk.
+/// Without it we can't trust the install is complete (e.g. an interrupted install_wheel), so we'd
+/// rather treat it as not installed and let the caller reinstall over it
+fn has_valid_record(dist_info: &Path) -> bool {
+    (|| -> anyhow::Result<_> {
+        let mut record = File::open(dist_info.join("RECORD"))?;
+        read_record_file(&mut record)?;
+        Ok(())
+    })()
+    .is_ok()
 }
 
 /// Reads the installed packages through .dist-info/WHEEL files, returns the set that is installed
@@ -77,75 +137,132 @@ impl InstalledPackage {
 pub fn filter_installed_venv(
     specs: &[RequestedSpec],
     venv_base: &Path,
-    python_version: (u8, u8),
 ) -> anyhow::Result<(Vec<RequestedSpec>, Vec<InstalledPackage>)> {
-    let site_packages = venv_base
-        .join("lib")
-        .join(format!("python{}.{}", python_version.0, python_version.1))
-        .join("site-packages");
-    let entries: Vec<DirEntry> = match fs::read_dir(site_packages) {
-        Ok(entries) => entries.collect::<io::Result<Vec<DirEntry>>>()?,
-        Err(err) if err.kind() == io::ErrorKind::NotFound => Vec::new(),
-        Err(err) => return Err(err.into()),
-    };
-    let venv_packages: Vec<InstalledPackage> = entries
-        .iter()
-        .filter_map(|entry| {
-            let filename = entry.file_name().to_string_lossy().to_string();
-            let (name, version) = filename.strip_suffix(".dist-info")?.split_once('-')?;
-            let name = name.to_lowercase().replace('-', "_");
-            Some((entry, name, version.to_string()))
-        })
-        .map(|(entry, name, version)| {
-            let wheel_data =
-                parse_key_value_file(&mut File::open(entry.path().join("WHEEL"))?, "WHEEL")?;
-            let tag = wheel_data
-                .get("Tag")
-                .map(|tags| tags.join("."))
-                .unwrap_or_default();
+    // When `include-system-site-packages` is set, the venv also searches the base interpreter's
+    // site-packages, so a package installed there counts as installed here too -- scan every
+    // directory Python itself would, not just the venv's own
+    let virtual_env = VirtualEnvironment::from_venv(venv_base)?;
+    let site_packages_dirs = virtual_env.site_packages_dirs(venv_base);
 
-            Ok(InstalledPackage {
-                name,
-                python_version: version.clone(),
-                unique_version: version,
-                tag,
+    let mut venv_packages: Vec<InstalledPackage> = Vec::new();
+    for site_packages in &site_packages_dirs {
+        let entries: Vec<DirEntry> = match fs::read_dir(site_packages) {
+            Ok(entries) => entries.collect::<io::Result<Vec<DirEntry>>>()?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(err) => return Err(err.into()),
+        };
+        let mut found: Vec<InstalledPackage> = entries
+            .iter()
+            .filter_map(|entry| {
+                let filename = entry.file_name().to_string_lossy().to_string();
+                if !filename.ends_with(".dist-info") {
+                    return None;
+                }
+                let dist_info = entry.path();
+                if !has_valid_record(&dist_info) {
+                    debug!(
+                        "Ignoring {} as installed: no valid RECORD, treating as not installed",
+                        dist_info.display()
+                    );
+                    return None;
+                }
+                let (name, version) = name_version_from_metadata(&dist_info).or_else(|| {
+                    let (name, version) = filename.strip_suffix(".dist-info")?.split_once('-')?;
+                    Some((name.to_lowercase().replace('-', "_"), version.to_string()))
+                })?;
+                Some((entry, name, version))
             })
-        })
-        .collect::<anyhow::Result<_>>()?;
+            .map(|(entry, name, version)| {
+                let wheel_data =
+                    parse_key_value_file(&mut File::open(entry.path().join("WHEEL"))?, "WHEEL")?;
+                let tag = wheel_data
+                    .get("Tag")
+                    .map(|tags| tags.join("."))
+                    .unwrap_or_default();
+
+                Ok(InstalledPackage {
+                    name,
+                    python_version: version.clone(),
+                    unique_version: version,
+                    tag,
+                })
+            })
+            .collect::<anyhow::Result<_>>()?;
+        venv_packages.append(&mut found);
+    }
+    let site_packages_index = SitePackagesIndex::from_dirs(&site_packages_dirs)?;
 
     let mut installed = Vec::new();
     let mut not_installed = Vec::new();
     for spec in specs {
+        let spec_name = spec.normalized_name();
         let matching_package = venv_packages.iter().find(|package| {
             if let Some(spec_version) = &spec.python_version {
-                // TODO: use PEP440
-                package.name == spec.name && &package.python_version == spec_version
+                package.name == spec_name
+                    && version_satisfies(spec_version, &package.python_version)
             } else {
-                package.name == spec.name
+                package.name == spec_name
             }
         });
-        if let Some(package) = matching_package {
+        // A name/version match alone isn't enough if the spec also requests extras: the base
+        // environment might have the package itself but not the extra's transitive dependencies
+        let extras_satisfied = spec.extras.is_empty()
+            || Requirement::from_str(&spec.requested)
+                .map(|requirement| {
+                    site_packages_index.satisfies(&requirement) == Satisfaction::Satisfied
+                })
+                .unwrap_or(false);
+        if let Some(package) = matching_package.filter(|_| extras_satisfied) {
             installed.push(package.clone());
         } else {
+            if let Some(stale) = venv_packages
+                .iter()
+                .find(|package| package.name == spec_name)
+            {
+                debug!(
+                    "{} {} is stale (installed: {}), reinstalling",
+                    spec.name, spec.requested, stale.python_version
+                );
+            } else {
+                debug!("{} {} isn't installed yet", spec.name, spec.requested);
+            }
             not_installed.push(spec.clone())
         }
     }
     Ok((not_installed, installed))
 }
 
+/// Whether `installed_version` (e.g. a `.dist-info` version) satisfies `spec_version`, which can
+/// either be a PEP 440 version specifier set (`>=1.20,<2.0`, `==1.22.*`) or, as is the common case
+/// for an already-resolved spec, a bare pinned version (`1.22.4`). Falls back to raw string
+/// equality if `installed_version` doesn't parse as PEP 440 at all (e.g. a non-standard local
+/// build tag); if `installed_version` parses but `spec_version` doesn't match either PEP 440 form,
+/// they're treated as unequal rather than falling back to a (near-certain to fail) string compare
+pub(crate) fn version_satisfies(spec_version: &str, installed_version: &str) -> bool {
+    let Ok(installed) = Version::from_str(installed_version) else {
+        return spec_version == installed_version;
+    };
+    if let Ok(specifiers) = VersionSpecifiers::from_str(spec_version) {
+        return specifiers.contains(&installed);
+    }
+    if let Ok(pinned) = Version::from_str(spec_version) {
+        return pinned == installed;
+    }
+    false
+}
+
 pub fn filter_installed(
     location: &InstallLocation<impl Deref<Target = Path>>,
     specs: &[RequestedSpec],
     compatible_tags: &[(String, String, String)],
 ) -> anyhow::Result<(Vec<RequestedSpec>, Vec<InstalledPackage>)> {
     match location {
-        InstallLocation::Venv {
-            venv_base,
-            python_version,
-        } => filter_installed_venv(specs, venv_base, *python_version).context(format!(
-            "Failed to filter packages installed in the venv at {}",
-            venv_base.display()
-        )),
+        InstallLocation::Venv { venv_base, .. } => {
+            filter_installed_venv(specs, venv_base).context(format!(
+                "Failed to filter packages installed in the venv at {}",
+                venv_base.display()
+            ))
+        }
         InstallLocation::Monotrail { monotrail_root, .. } => {
             filter_installed_monotrail(specs, monotrail_root, &compatible_tags)
                 .context("Failed to filter installed packages")
@@ -258,6 +375,86 @@ pub fn install_all(
     }
 }
 
+/// Adds `specs` into an already-populated `location` without re-resolving or touching anything
+/// already there, the way a plugin gets added into an existing tool environment: reuses
+/// [`filter_installed`] to skip whatever's already satisfied, takes the location's lock, installs
+/// only what's missing, and reports the packages it added plus any console-script entry points
+/// that came with them.
+pub fn inject(
+    location: &InstallLocation<PathBuf>,
+    specs: &[RequestedSpec],
+    compatible_tags: &[(String, String, String)],
+) -> anyhow::Result<(Vec<InstalledPackage>, BTreeMap<String, PathBuf>)> {
+    let (to_install, _already_installed) = filter_installed(location, specs, compatible_tags)?;
+    let location = location.acquire_lock()?;
+    let installed = install_all(&to_install, &location, compatible_tags, false, false)?;
+    let scripts = injected_scripts(&location, &installed)?;
+    Ok((installed, scripts))
+}
+
+/// The console-script entry points that installing `installed` into `location` made available
+fn injected_scripts(
+    location: &InstallLocation<LockedDir>,
+    installed: &[InstalledPackage],
+) -> anyhow::Result<BTreeMap<String, PathBuf>> {
+    match location {
+        InstallLocation::Venv {
+            venv_base,
+            python_version,
+            ..
+        } => venv_scripts(venv_base, *python_version, installed),
+        InstallLocation::Monotrail { monotrail_root, .. } => {
+            crate::monotrail::find_scripts(installed, monotrail_root)
+        }
+    }
+}
+
+/// In a venv, `install_wheel` itself writes every console script straight into `venv_base`'s
+/// `bin`/`Scripts` directory as part of installation, unlike monotrail's per-package sprawl
+/// directories which [`crate::monotrail::find_scripts`] has to walk -- so reporting what `inject`
+/// added here just means reading back the entry points its packages declared and checking which
+/// of them now exist in that shared directory
+fn venv_scripts(
+    venv_base: &Path,
+    python_version: (u8, u8),
+    installed: &[InstalledPackage],
+) -> anyhow::Result<BTreeMap<String, PathBuf>> {
+    let site_packages = VirtualEnvironment::site_packages_below(venv_base, python_version);
+    let bin_dir = if cfg!(windows) {
+        venv_base.join("Scripts")
+    } else {
+        venv_base.join("bin")
+    };
+
+    let mut scripts = BTreeMap::new();
+    for package in installed {
+        let dist_info = site_packages.join(format!(
+            "{}-{}.dist-info",
+            package.name, package.unique_version
+        ));
+        let entry_points_file = dist_info.join("entry_points.txt");
+        if !entry_points_file.is_file() {
+            continue;
+        }
+        let entry_points = fs::read_to_string(&entry_points_file)
+            .with_context(|| format!("Failed to read {}", entry_points_file.display()))?;
+        for (name, _) in crate::monotrail::parse_console_scripts(&entry_points) {
+            // install_wheel writes console scripts as a plain launcher on unix, but as a compiled
+            // `<name>.exe` on windows
+            let filename = if cfg!(windows) {
+                format!("{}.exe", name)
+            } else {
+                name.clone()
+            };
+            let script_path = bin_dir.join(&filename);
+            if script_path.is_file() {
+                scripts.insert(name, script_path);
+            }
+        }
+    }
+    Ok(scripts)
+}
+
 /// <https://stackoverflow.com/a/67240436/3549270>
 fn checkout_revision(revision: &str, repo: Repository) -> Result<(), git2::Error> {
     let (object, reference) = repo.revparse_ext(revision)?;
@@ -338,6 +535,321 @@ pub fn repo_at_revision(url: &str, revision: &str, repo_dir: &Path) -> anyhow::R
     Ok(())
 }
 
+/// Reads the `Name`/`Version` headers out of a source distribution's `PKG-INFO`, which lives at
+/// the top level of the single directory a `.tar.gz` sdist unpacks into
+fn read_sdist_pkg_info(sdist: &Path) -> anyhow::Result<(String, String)> {
+    let tar_gz = File::open(sdist)?;
+    let mut archive = tar::Archive::new(GzDecoder::new(tar_gz));
+    let pkg_info_entry = archive
+        .entries()?
+        .find_map(|entry| {
+            let entry = entry.ok()?;
+            let path = entry.path().ok()?.to_path_buf();
+            (path.file_name()?.to_str()? == "PKG-INFO").then_some(entry)
+        })
+        .with_context(|| format!("{} doesn't contain a PKG-INFO file", sdist.display()))?;
+
+    let mut pkg_info = String::new();
+    pkg_info_entry
+        .take(10 * 1_048_576)
+        .read_to_string(&mut pkg_info)
+        .context("PKG-INFO is not valid utf8")?;
+
+    let name = pkg_info
+        .lines()
+        .find_map(|line| line.strip_prefix("Name: "))
+        .with_context(|| format!("PKG-INFO in {} has no Name field", sdist.display()))?
+        .to_string();
+    let version = pkg_info
+        .lines()
+        .find_map(|line| line.strip_prefix("Version: "))
+        .with_context(|| format!("PKG-INFO in {} has no Version field", sdist.display()))?
+        .to_string();
+    Ok((name, version))
+}
+
+/// A downloaded direct-reference/url dependency might not actually be what the lockfile says it
+/// is (the url could point to a different release, or the server could have served something
+/// else entirely), so we check name and version against what's in the wheel filename or sdist
+/// `PKG-INFO` before installing it
+fn check_direct_url_matches_spec(
+    artifact: &Path,
+    distribution_type: &DistributionType,
+    expected_name: &str,
+    expected_version: &str,
+) -> anyhow::Result<()> {
+    let (actual_name, actual_version) = match distribution_type {
+        DistributionType::Wheel => {
+            let filename = artifact
+                .file_name()
+                .and_then(|filename| filename.to_str())
+                .with_context(|| format!("Invalid wheel filename: {}", artifact.display()))?;
+            let wheel_filename = WheelFilename::from_str(filename)?;
+            (wheel_filename.distribution, wheel_filename.version)
+        }
+        DistributionType::SourceDistribution => read_sdist_pkg_info(artifact)?,
+    };
+
+    let normalize = |name: &str| name.to_lowercase().replace(['-', '.'], "_");
+    if normalize(&actual_name) != normalize(expected_name) || actual_version != expected_version {
+        bail!(
+            "{} is declared as {} {} in the lockfile, but the downloaded artifact is actually {} {}",
+            artifact.display(),
+            expected_name,
+            expected_version,
+            actual_name,
+            actual_version
+        );
+    }
+    Ok(())
+}
+
+/// Checks a downloaded artifact against a pinned `sha256:<hex digest>` -- from a poetry/pdm/Pipfile
+/// lockfile entry, or the digest the index itself advertised for it -- rejecting the install if the
+/// server served something other than what was expected
+pub(crate) fn check_file_hash(artifact: &Path, expected_hash: &str) -> anyhow::Result<()> {
+    let expected_digest = expected_hash.strip_prefix("sha256:").with_context(|| {
+        format!(
+            "Unsupported hash algorithm (only sha256 is supported): {}",
+            expected_hash
+        )
+    })?;
+
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(artifact)?, &mut hasher)
+        .with_context(|| format!("Failed to read {} for hashing", artifact.display()))?;
+    let actual_digest = format!("{:x}", hasher.finalize());
+
+    if actual_digest != expected_digest {
+        bail!(
+            "Checksum mismatch for {}: expected sha256:{} but the download hashes to sha256:{}",
+            artifact.display(),
+            expected_digest,
+            actual_digest
+        );
+    }
+    Ok(())
+}
+
+/// Like [`check_file_hash`], but for a requirements.txt `--hash`-pinned spec, which allows more
+/// than one acceptable digest (pip does the same, e.g. when a requirement could resolve to either
+/// a wheel or an sdist built from the same release); accepts the artifact as long as any one of
+/// `allowed_hashes` matches
+pub(crate) fn check_file_hashes(artifact: &Path, allowed_hashes: &[String]) -> anyhow::Result<()> {
+    let sha256_digests: Vec<&str> = allowed_hashes
+        .iter()
+        .filter_map(|expected_hash| expected_hash.strip_prefix("sha256:"))
+        .collect();
+    if sha256_digests.is_empty() {
+        bail!(
+            "Unsupported hash algorithm (only sha256 is supported): {}",
+            allowed_hashes.join(", ")
+        );
+    }
+
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(artifact)?, &mut hasher)
+        .with_context(|| format!("Failed to read {} for hashing", artifact.display()))?;
+    let actual_digest = format!("{:x}", hasher.finalize());
+
+    if !sha256_digests.contains(&actual_digest.as_str()) {
+        bail!(
+            "Hash mismatch for {}: none of the pinned hashes ({}) match the download's \
+             sha256:{}",
+            artifact.display(),
+            allowed_hashes.join(", "),
+            actual_digest
+        );
+    }
+    Ok(())
+}
+
+/// Whether `package` is already laid out under `location`'s monotrail root, shared between
+/// [`install_from_lock`]'s download-prefetch pass and its main install loop so both agree on
+/// which packages still need work
+fn already_installed(
+    package: &crate::lock_export::LockedPackage,
+    location: &InstallLocation<LockedDir>,
+) -> bool {
+    match location {
+        InstallLocation::Monotrail { monotrail_root, .. } => monotrail_root
+            .join(&package.name)
+            .join(&package.unique_version)
+            .join(&package.tag)
+            .is_dir(),
+        _ => false,
+    }
+}
+
+/// Installs straight from a [`crate::lock_export::LockManifest`]'s pinned packages, skipping
+/// resolution and the pypi search entirely: a [`LockedSource::Registry`] package's `url` is
+/// downloaded (through the same cache [`download_distribution_cached`] uses elsewhere) and
+/// checked against its recorded `sha256` before being unpacked, exactly like a lockfile-pinned
+/// dependency already is in [`download_and_install`]; only wheels are supported there - a locked
+/// sdist means the manifest was exported before monotrail had built (and could pin) a wheel for
+/// it. A [`LockedSource::Git`] package is rebuilt from its pinned `resolved_reference` instead,
+/// the same way [`download_and_install`] handles a `FileOrUrl::Git` spec.
+pub fn install_from_lock(
+    packages: &[crate::lock_export::LockedPackage],
+    location: &InstallLocation<LockedDir>,
+    sys_executable: &Path,
+) -> anyhow::Result<Vec<InstalledPackage>> {
+    // Prefetch every not-yet-cached registry download in one concurrent batch instead of one
+    // `ureq::get` at a time in the loop below, so a lockfile of dozens of packages doesn't
+    // serialize every HTTP round-trip. Git-sourced packages are rebuilt from source further down
+    // and aren't part of this batch; a filename that isn't a wheel is left for the main loop
+    // below to report with full context instead of being diagnosed here
+    let pending_downloads: Vec<(&crate::lock_export::LockedPackage, &str, PathBuf, String)> =
+        packages
+            .iter()
+            .filter(|package| !already_installed(package, location))
+            .filter_map(|package| match &package.source {
+                LockedSource::Registry { url, sha256 } => {
+                    let filename = url.rsplit('/').next()?;
+                    if !filename.ends_with(".whl") {
+                        return None;
+                    }
+                    let target_file =
+                        artifact_cache_path(&package.name, &package.unique_version, filename)
+                            .ok()?;
+                    (!target_file.is_file()).then_some((
+                        package,
+                        url.as_str(),
+                        target_file,
+                        format!("sha256:{}", sha256),
+                    ))
+                }
+                LockedSource::Git { .. } => None,
+            })
+            .collect();
+    let download_requests: Vec<DownloadRequest> = pending_downloads
+        .iter()
+        .map(|(_, url, target_file, expected_hash)| DownloadRequest {
+            url,
+            target_dir: target_file
+                .parent()
+                .expect("artifact_cache_path always has a parent"),
+            target_file,
+            credentials: None,
+            expected_hash: Some(expected_hash),
+        })
+        .collect();
+    // A failed prefetch doesn't abort the whole install: it just leaves that package's
+    // `target_file` missing, so the per-package loop below falls through to
+    // `download_distribution_cached`'s normal synchronous download path and reports the same
+    // error with full context at the point that package is actually installed, the same as if
+    // prefetching had never happened for it. That way one package's broken url doesn't cost the
+    // packages around it their already-successful downloads and installs
+    for ((package, url, _, _), result) in pending_downloads
+        .iter()
+        .zip(download_distributions(&download_requests))
+    {
+        if let Err(err) = result {
+            debug!(
+                "Prefetch failed for {} from {}, will retry during install: {}",
+                package.name, url, err
+            );
+        }
+    }
+
+    packages
+        .iter()
+        .map(|package| {
+            if already_installed(package, location) {
+                debug!("{} {} already installed", package.name, package.unique_version);
+                return Ok(InstalledPackage {
+                    name: package.name.clone(),
+                    python_version: package.unique_version.clone(),
+                    unique_version: package.unique_version.clone(),
+                    tag: package.tag.clone(),
+                });
+            }
+
+            let wheel_path = match &package.source {
+                LockedSource::Registry { url, sha256 } => {
+                    let filename = url
+                        .rsplit('/')
+                        .next()
+                        .with_context(|| format!("Invalid url: {}", url))?;
+                    if !filename.ends_with(".whl") {
+                        bail!(
+                            "{} {} is locked as a source distribution ({}), which \
+                             monotrail_from_lock can't build; install it once through the normal \
+                             resolver first so the manifest can pin its built wheel instead",
+                            package.name,
+                            package.unique_version,
+                            filename
+                        );
+                    }
+
+                    let expected_hash = format!("sha256:{}", sha256);
+                    let wheel_path = download_distribution_cached(
+                        &package.name,
+                        &package.unique_version,
+                        filename,
+                        url,
+                        None,
+                        Some(&expected_hash),
+                    )
+                    .with_context(|| format!("Failed to download {} from {}", package.name, url))?;
+                    wheel_path
+                }
+                LockedSource::Git {
+                    url,
+                    resolved_reference,
+                } => {
+                    let compatible_tags = match package.tag.split('-').collect::<Vec<_>>()[..] {
+                        [python_tag, abi_tag, platform_tag] => vec![(
+                            python_tag.to_string(),
+                            abi_tag.to_string(),
+                            platform_tag.to_string(),
+                        )],
+                        _ => bail!("Invalid tag {} for {}", package.tag, package.name),
+                    };
+                    let temp_dir = TempDir::new()?;
+                    let repo_dir = temp_dir.path().join(&package.name);
+                    // `resolved_reference` is an exact commit id, so successfully checking it out
+                    // is already the integrity check a sha256 would otherwise give us
+                    repo_at_revision(url, resolved_reference, &repo_dir).with_context(|| {
+                        format!("Failed to check out {} at {}", url, resolved_reference)
+                    })?;
+                    build_source_distribution_to_wheel_cached(
+                        &package.name,
+                        &package.unique_version,
+                        &repo_dir,
+                        &compatible_tags,
+                        sys_executable,
+                    )
+                    .with_context(|| {
+                        format!(
+                            "Failed to build wheel from source for {} (repository: {} revision: {})",
+                            package.name, url, resolved_reference
+                        )
+                    })?
+                }
+            };
+
+            debug!("Installing {} {}", package.name, package.unique_version);
+            let tag = install_wheel(
+                location,
+                &wheel_path,
+                true,
+                &[],
+                &package.unique_version,
+                sys_executable,
+            )
+            .with_context(|| format!("Failed to install {}", package.name))?;
+
+            Ok(InstalledPackage {
+                name: package.name.clone(),
+                python_version: package.unique_version.clone(),
+                unique_version: package.unique_version.clone(),
+                tag,
+            })
+        })
+        .collect()
+}
+
 /// Returns the python version, unique version
 fn download_and_install(
     requested_spec: &RequestedSpec,
@@ -346,30 +858,56 @@ fn download_and_install(
     no_compile: bool,
     sys_executable: &Path,
 ) -> anyhow::Result<(String, String, String)> {
-    let spec = requested_spec.resolve(compatible_tags)?;
+    let spec = requested_spec.resolve(location.get_python_version(), compatible_tags)?;
     trace!("requested: {:?}, resolved: {:?}", requested_spec, spec);
 
     let (wheel_path, distribution_type) = match spec.location.clone() {
         FileOrUrl::File(file_path) => {
-            if file_path.as_os_str().to_string_lossy().ends_with(".whl") {
+            if file_path.is_dir() {
+                // An unbuilt local source directory (a requirements.txt bare path or `-e`
+                // editable entry); built into a wheel below, same as a git checkout
+                (file_path, DistributionType::SourceDistribution)
+            } else if file_path.as_os_str().to_string_lossy().ends_with(".whl") {
                 (file_path, DistributionType::Wheel)
-            } else if file_path.as_os_str().to_string_lossy().ends_with(".tar.gz") {
+            } else if is_sdist_filename(&file_path.as_os_str().to_string_lossy()) {
                 (file_path, DistributionType::SourceDistribution)
             } else {
                 bail!(
-                    "Unknown filetype (neither .whl not .tar.gz): {}",
+                    "Unknown filetype (neither .whl, sdist archive nor directory): {}",
                     file_path.display()
                 )
             }
         }
-        FileOrUrl::Url { url, filename } => {
-            let wheel_path =
-                download_distribution_cached(&spec.name, &spec.unique_version, &filename, &url)
-                    .with_context(|| format!("Failed to download {} from pypi", spec.requested))?;
+        FileOrUrl::Url {
+            url,
+            filename,
+            credentials,
+        } => {
+            let wheel_path = download_distribution_cached(
+                &spec.name,
+                &spec.unique_version,
+                &filename,
+                &url,
+                credentials.as_ref(),
+                spec.file_hash.as_deref(),
+            )
+            .with_context(|| format!("Failed to download {} from pypi", spec.requested))?;
+
+            check_direct_url_matches_spec(
+                &wheel_path,
+                &spec.distribution_type,
+                &spec.name,
+                &spec.unique_version,
+            )
+            .with_context(|| format!("{} didn't match what the lockfile expects", url))?;
 
             (wheel_path, spec.distribution_type.clone())
         }
-        FileOrUrl::Git { url, revision } => {
+        FileOrUrl::Git {
+            url,
+            revision,
+            reference,
+        } => {
             let temp_dir = TempDir::new()?;
             let repo_dir = temp_dir.path().join(&spec.name);
             repo_at_revision(&url, &revision, &repo_dir)?;
@@ -384,18 +922,28 @@ fn download_and_install(
                 &spec.unique_version,
                 &repo_dir,
                 compatible_tags,
+                sys_executable,
             )
-            .with_context(|| {
-                format!(
+            .with_context(|| match &reference {
+                Some(reference) if reference != &revision => format!(
+                    "Failed to build wheel from source for {} (repository: {} revision: {} ({}))",
+                    spec.name, url, revision, reference
+                ),
+                _ => format!(
                     "Failed to build wheel from source for {} (repository: {} revision: {})",
                     spec.name, url, revision
-                )
+                ),
             })?;
 
             (wheel_path, DistributionType::Wheel)
         }
     };
 
+    if !spec.hashes.is_empty() {
+        check_file_hashes(&wheel_path, &spec.hashes)
+            .with_context(|| format!("{} failed hash verification", spec.requested))?;
+    }
+
     let wheel_path = if distribution_type == DistributionType::Wheel {
         wheel_path
     } else {
@@ -409,6 +957,7 @@ fn download_and_install(
             &spec.unique_version,
             &wheel_path,
             compatible_tags,
+            sys_executable,
         )
         .with_context(|| {
             format!(
@@ -1,16 +1,19 @@
 //! Multiplexing between venv install and monotrail install
 
-use crate::install::InstalledPackage;
+use crate::install::{version_satisfies, InstalledPackage};
 use crate::monotrail::filter_installed_monotrail;
 use crate::spec::RequestedSpec;
+use crate::venv_parser::VirtualEnvironment;
 use crate::wheel::parse_key_value_file;
 use anyhow::Context;
 use fs2::FileExt;
 use fs_err as fs;
 use fs_err::{DirEntry, File};
 use std::io;
+use std::io::{Seek, SeekFrom, Write};
 use std::ops::Deref;
 use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 use tracing::{error, warn};
 
 const MONOTRAIL_LOCKFILE: &str = "monotrail.lock";
@@ -24,12 +27,50 @@ pub struct LockedDir {
 }
 
 impl LockedDir {
-    /// Tries to lock the directory, returns Ok(None) if it is already locked
+    /// Tries to lock the directory itself, returns Ok(None) if it is already locked
     pub fn try_acquire(path: &Path) -> io::Result<Option<Self>> {
-        let lockfile = File::create(path.join(MONOTRAIL_LOCKFILE))?;
+        Self::try_acquire_at(path, &path.join(MONOTRAIL_LOCKFILE))
+    }
+
+    /// Locks the directory itself, if necessary blocking until the lock becomes free
+    pub fn acquire(path: &Path) -> io::Result<Self> {
+        Self::acquire_at(path, &path.join(MONOTRAIL_LOCKFILE))
+    }
+
+    /// Like [`Self::acquire`], but gives up with a [`io::ErrorKind::TimedOut`] error instead of
+    /// blocking forever, so a caller that would rather fail fast than wait on a wedged lock holder
+    /// can detect that and bail
+    pub fn acquire_with_timeout(path: &Path, timeout: Duration) -> io::Result<Self> {
+        let lockfile_path = path.join(MONOTRAIL_LOCKFILE);
+        let lockfile = open_lockfile(&lockfile_path)?;
+
+        let deadline = Instant::now() + timeout;
+        while lockfile.file().try_lock_exclusive().is_err() {
+            if Instant::now() >= deadline {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!(
+                        "Timed out waiting for the lock on {} held by pid {}",
+                        lockfile_path.display(),
+                        read_pid(&lockfile).unwrap_or_else(|| "<unknown>".to_string()),
+                    ),
+                ));
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        }
+        write_pid(&lockfile)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            lockfile,
+        })
+    }
+
+    fn try_acquire_at(root: &Path, lockfile_path: &Path) -> io::Result<Option<Self>> {
+        let lockfile = open_lockfile(lockfile_path)?;
         if lockfile.file().try_lock_exclusive().is_ok() {
+            write_pid(&lockfile)?;
             Ok(Some(Self {
-                path: path.to_path_buf(),
+                path: root.to_path_buf(),
                 lockfile,
             }))
         } else {
@@ -37,17 +78,51 @@ impl LockedDir {
         }
     }
 
-    /// Locks the directory, if necessary blocking until the lock becomes free
-    pub fn acquire(path: &Path) -> io::Result<Self> {
-        let lockfile = File::create(path.join(MONOTRAIL_LOCKFILE))?;
-        lockfile.file().lock_exclusive()?;
+    fn acquire_at(root: &Path, lockfile_path: &Path) -> io::Result<Self> {
+        let lockfile = open_lockfile(lockfile_path)?;
+        if lockfile.file().try_lock_exclusive().is_err() {
+            warn!(
+                "Waiting for the lock on {} held by pid {}",
+                lockfile_path.display(),
+                read_pid(&lockfile).unwrap_or_else(|| "<unknown>".to_string()),
+            );
+            lockfile.file().lock_exclusive()?;
+        }
+        write_pid(&lockfile)?;
         Ok(Self {
-            path: path.to_path_buf(),
+            path: root.to_path_buf(),
             lockfile,
         })
     }
 }
 
+/// Opens (creating if necessary) a lockfile for reading and writing without truncating it, so an
+/// already-running holder's recorded pid survives a second process merely opening the same path
+fn open_lockfile(lockfile_path: &Path) -> io::Result<File> {
+    fs::OpenOptions::new()
+        .create(true)
+        .read(true)
+        .write(true)
+        .open(lockfile_path)
+}
+
+/// Records the current process' pid in an already-locked lockfile, so a process waiting on the
+/// same lock can report who's holding it
+fn write_pid(lockfile: &File) -> io::Result<()> {
+    let mut file = lockfile.file();
+    file.set_len(0)?;
+    file.seek(SeekFrom::Start(0))?;
+    write!(file, "{}", std::process::id())
+}
+
+/// Reads back whatever pid [`write_pid`] last recorded, tolerating a lockfile that's empty (just
+/// created) or from a version of this crate that didn't record one
+fn read_pid(lockfile: &File) -> Option<String> {
+    fs::read_to_string(lockfile.path())
+        .ok()
+        .filter(|pid| !pid.is_empty())
+}
+
 impl Drop for LockedDir {
     fn drop(&mut self) {
         if let Err(err) = self.lockfile.file().unlock() {
@@ -100,7 +175,11 @@ impl<T: Deref<Target = Path>> InstallLocation<T> {
         match self {
             InstallLocation::Venv { venv_base, .. } => {
                 // canonicalize on python would resolve the symlink
-                venv_base.join("bin").join("python")
+                if cfg!(windows) {
+                    venv_base.join("Scripts").join("python.exe")
+                } else {
+                    venv_base.join("bin").join("python")
+                }
             }
             // TODO: For monotrail use the monotrail launcher
             InstallLocation::Monotrail { python, .. } => python.clone(),
@@ -137,12 +216,13 @@ impl<T: Deref<Target = Path>> InstallLocation<T> {
             InstallLocation::Venv {
                 venv_base,
                 python_version,
-            } => venv_base
-                .join("lib")
-                .join(format!("python{}.{}", python_version.0, python_version.1))
-                .join("site-packages")
-                .join(format!("{}-{}.dist-info", normalized_name, version))
-                .is_dir(),
+            } => {
+                let site_packages =
+                    VirtualEnvironment::site_packages_below(venv_base, *python_version);
+                site_packages
+                    .join(format!("{}-{}.dist-info", normalized_name, version))
+                    .is_dir()
+            }
             InstallLocation::Monotrail { monotrail_root, .. } => monotrail_root
                 .join(format!("{}-{}", normalized_name, version))
                 .is_dir(),
@@ -151,6 +231,8 @@ impl<T: Deref<Target = Path>> InstallLocation<T> {
 }
 
 impl InstallLocation<PathBuf> {
+    /// Takes the coarse, whole-root exclusive lock, serializing all installs into this location
+    /// regardless of whether they touch disjoint packages.
     pub fn acquire_lock(&self) -> io::Result<InstallLocation<LockedDir>> {
         let root = match self {
             Self::Venv { venv_base, .. } => venv_base,
@@ -195,12 +277,8 @@ pub fn filter_installed_venv(
     venv_base: &Path,
     python_version: (u8, u8),
 ) -> anyhow::Result<(Vec<RequestedSpec>, Vec<InstalledPackage>)> {
-    let entries: Vec<DirEntry> = match fs::read_dir(
-        venv_base
-            .join("lib")
-            .join(format!("python{}.{}", python_version.0, python_version.1))
-            .join("site-packages"),
-    ) {
+    let site_packages = VirtualEnvironment::site_packages_below(venv_base, python_version);
+    let entries: Vec<DirEntry> = match fs::read_dir(site_packages) {
         Ok(entries) => entries.collect::<io::Result<Vec<DirEntry>>>()?,
         Err(err) if err.kind() == io::ErrorKind::NotFound => Vec::new(),
         Err(err) => return Err(err.into()),
@@ -233,12 +311,14 @@ pub fn filter_installed_venv(
     let mut installed = Vec::new();
     let mut not_installed = Vec::new();
     for spec in specs {
+        let spec_name = spec.normalized_name();
         let matching_package = venv_packages.iter().find(|package| {
-            if let Some(spec_version) = &spec.python_version {
-                // TODO: use PEP440
-                package.name == spec.name && &package.python_version == spec_version
-            } else {
-                package.name == spec.name
+            if package.name != spec_name {
+                return false;
+            }
+            match &spec.python_version {
+                Some(spec_version) => version_satisfies(spec_version, &package.python_version),
+                None => true,
             }
         });
         if let Some(package) = matching_package {
@@ -0,0 +1,84 @@
+//! Locates already-installed python interpreters on `PATH`, so
+//! [`crate::standalone_python::provision_python`] can reuse one instead of downloading a standalone
+//! build.
+
+use crate::inject_and_run::{probe_interpreter_info, InterpreterInfo};
+use fs_err as fs;
+use std::collections::HashSet;
+use std::env;
+use std::path::PathBuf;
+use tracing::debug;
+
+/// Whether `file_name` (no directory component) looks like a python interpreter: `python`,
+/// `python3`, or a minor-versioned `python3.X`, with a `.exe` suffix on windows
+fn is_candidate_name(file_name: &str) -> bool {
+    let stem = if cfg!(windows) {
+        match file_name.strip_suffix(".exe") {
+            Some(stem) => stem,
+            None => return false,
+        }
+    } else {
+        file_name
+    };
+    stem == "python"
+        || stem == "python3"
+        || stem
+            .strip_prefix("python3.")
+            .map(|minor| !minor.is_empty() && minor.bytes().all(|byte| byte.is_ascii_digit()))
+            .unwrap_or(false)
+    // TODO: the windows `py` launcher and its registry-based interpreter list are a separate
+    // discovery mechanism (no `PATH` binary to find) and aren't covered here yet
+}
+
+/// Scans every directory in `PATH` for `python`/`python3`/`python3.X` binaries, resolves symlinks
+/// and dedups by canonical real path, then probes each survivor. Returns one entry per unique
+/// interpreter found (keeping the full probed info so callers don't need to probe it again), in no
+/// particular order; unprobeable candidates (broken symlinks, non-python executables that happen to
+/// match the name) are skipped rather than failing the whole scan.
+pub(crate) fn locate_interpreters() -> Vec<(PathBuf, InterpreterInfo)> {
+    let Some(path_var) = env::var_os("PATH") else {
+        return Vec::new();
+    };
+
+    let mut seen = HashSet::new();
+    let mut found = Vec::new();
+    for dir in env::split_paths(&path_var) {
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            if !is_candidate_name(&file_name) {
+                continue;
+            }
+            let candidate = entry.path();
+            let canonical = match fs::canonicalize(&candidate) {
+                Ok(canonical) => canonical,
+                Err(_) => continue,
+            };
+            if !seen.insert(canonical) {
+                continue;
+            }
+            match probe_interpreter_info(&candidate) {
+                Ok(info) => found.push((candidate, info)),
+                Err(err) => debug!("Skipping {}: {}", candidate.display(), err),
+            }
+        }
+    }
+    found
+}
+
+/// Picks the best interpreter matching `python_version` among `candidates`, preferring the
+/// shortest executable path -- an unversioned system `/usr/bin/python3` over a deeply nested venv
+/// shim -- mirroring how native locators (e.g. pyenv, `py`) disambiguate duplicates
+pub(crate) fn select_interpreter(
+    candidates: &[(PathBuf, InterpreterInfo)],
+    python_version: (u8, u8),
+) -> Option<(PathBuf, InterpreterInfo)> {
+    candidates
+        .iter()
+        .filter(|(_, info)| info.python_version == python_version)
+        .min_by_key(|(path, _)| path.as_os_str().len())
+        .cloned()
+}
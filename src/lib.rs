@@ -16,31 +16,53 @@
 //!    installations.
 
 pub use crate::markers::Pep508Environment;
-pub use cli::{run_cli, Cli};
+pub use cli::{run_cli, Args, Cli};
 pub use inject_and_run::{parse_major_minor, run_python_args};
+pub use poetry_integration::lock::ResolutionMode;
 use poetry_integration::read_dependencies::read_poetry_specs;
 #[doc(hidden)]
 pub use utils::assert_cli_error;
 
+mod audit;
 mod cli;
+mod env_registry;
+mod gc;
 mod inject_and_run;
 mod install;
+mod interpreter_locator;
+mod lock_export;
 mod markers;
+mod metadata_inspect;
 mod monotrail;
+mod nix_export;
 mod package_index;
+mod pdm_lock;
+mod pipfile_lock;
 mod poetry_integration;
 mod ppipx;
+mod publish;
 #[cfg(feature = "python_bindings")]
 mod python_bindings;
 mod requirements_txt;
+mod site_packages;
 mod source_distribution;
 mod spec;
 mod standalone_python;
+mod sync;
 mod utils;
 mod venv_parser;
 mod verify_installation;
 
 /// The python script to return the PEP 508 metadata as json string
 pub(crate) static PEP508_QUERY_ENV: &str = include_str!("get_pep508_env.py");
+/// The python script that reports the `sysconfig`/`sys` values we need to locate libpython
+pub(crate) static INTERPRETER_INFO_QUERY: &str = include_str!("get_interpreter_info.py");
+/// The PEP 517 frontend script that calls a build backend's `build_wheel` hook directly
+pub(crate) static PEP517_BUILD_WHEEL: &str = include_str!("pep517_build_wheel.py");
+/// The PEP 517 frontend script that calls a build backend's `prepare_metadata_for_build_wheel`
+/// hook directly, without building a full wheel
+pub(crate) static PEP517_PREPARE_METADATA: &str = include_str!("pep517_prepare_metadata.py");
+/// The PEP 517 frontend script that calls a build backend's `get_requires_for_build_wheel` hook
+pub(crate) static PEP517_GET_REQUIRES: &str = include_str!("pep517_get_requires.py");
 /// Python 3.8
 pub(crate) const DEFAULT_PYTHON_VERSION: (u8, u8) = (3, 8);
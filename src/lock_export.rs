@@ -0,0 +1,110 @@
+//! Pins a resolved [`FinderData`] down into a small, self-contained JSON manifest: every
+//! installed package's exact download url and sha256 (or, for a git dependency, its pinned
+//! `resolved_reference`), plus the marker environment it was resolved for. `monotrail_from_lock`
+//! can later install straight from that manifest without ever invoking poetry or its resolver
+//! again, only re-downloading (and re-checking) the pinned urls.
+//! This is the same idea [`crate::nix_export`] applies to Nix derivations, just kept in
+//! monotrail's own sprawl format instead of requiring Nix to build it.
+
+use crate::install::InstalledPackage;
+use crate::markers::Pep508Environment;
+use crate::monotrail::FinderData;
+use crate::nix_export::resolve_source;
+use crate::poetry_integration::poetry_lock::PoetryLock;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// Where to fetch a pinned package's artifact from, and how to check it came out right
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum LockedSource {
+    /// A plain pypi (or other index) download, checked against its recorded hash
+    Registry { url: String, sha256: String },
+    /// Built from a pinned git commit instead of a published release; there's no hash to check
+    /// the rebuilt wheel against, but `resolved_reference` is itself a content-addressed commit
+    /// id, so checking it out is the verification
+    Git {
+        url: String,
+        resolved_reference: String,
+    },
+}
+
+/// One package pinned down to an exact, directly fetchable artifact
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LockedPackage {
+    pub name: String,
+    pub unique_version: String,
+    /// The compatibility tag the installed wheel was resolved to, e.g.
+    /// "cp38-cp38-manylinux_2_17_x86_64"
+    pub tag: String,
+    pub source: LockedSource,
+    /// Where under `monotrail_root` this package's site-packages directory will end up, relative
+    /// to `monotrail_root` itself (see [`InstalledPackage::relative_site_packages`]), so a
+    /// consumer of the manifest can lay out `PYTHONPATH` without re-deriving it from
+    /// `name`/`unique_version`/`tag`
+    pub monotrail_site_packages: PathBuf,
+}
+
+/// A fully pinned, offline-installable snapshot of a resolved [`FinderData`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LockManifest {
+    /// The marker environment this manifest was resolved for, so `monotrail_from_lock` can warn
+    /// (or refuse) if it's about to be installed into a different one
+    pub marker_environment: Pep508Environment,
+    pub packages: Vec<LockedPackage>,
+}
+
+/// Builds a [`LockManifest`] from an already-resolved `finder_data`, looking up each installed
+/// package's pypi url and sha256 the same way [`crate::nix_export::export_nix`] does, except for
+/// git-sourced packages, which are pinned by `resolved_reference` instead (see [`locked_source`])
+pub fn export_lock(
+    finder_data: &FinderData,
+    marker_environment: &Pep508Environment,
+) -> Result<LockManifest> {
+    let lockfile = PoetryLock::from_str(&finder_data.lockfile)
+        .context("Failed to parse the embedded lockfile")?;
+
+    let packages = finder_data
+        .sprawl_packages
+        .iter()
+        .map(|package| {
+            Ok(LockedPackage {
+                name: package.name.clone(),
+                unique_version: package.unique_version.clone(),
+                tag: package.tag.clone(),
+                source: locked_source(package, &lockfile)?,
+                monotrail_site_packages: package.relative_site_packages(),
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(LockManifest {
+        marker_environment: marker_environment.clone(),
+        packages,
+    })
+}
+
+/// Pins `package` down to a [`LockedSource`], taking the git branch if the lockfile says this
+/// package came from a git dependency instead of a published release
+fn locked_source(package: &InstalledPackage, lockfile: &PoetryLock) -> Result<LockedSource> {
+    let locked = lockfile
+        .package
+        .iter()
+        .find(|candidate| candidate.name == package.name)
+        .with_context(|| format!("{} isn't in the lockfile", package.name))?;
+
+    if let Some(source) = &locked.source {
+        if source.source_type == "git" {
+            return Ok(LockedSource::Git {
+                url: source.url.clone(),
+                resolved_reference: source.resolved_reference.clone(),
+            });
+        }
+    }
+
+    let source = resolve_source(package, lockfile)?;
+    Ok(LockedSource::Registry {
+        url: source.url,
+        sha256: source.sha256,
+    })
+}
@@ -2,7 +2,7 @@
 
 use anyhow::Context;
 use clap::Parser;
-use monotrail::{parse_major_minor, run_cli, run_python_args, Cli};
+use monotrail::{parse_major_minor, run_cli, run_python_args, Args, ResolutionMode};
 use std::env;
 use std::env::args;
 use std::path::{Path, PathBuf};
@@ -53,10 +53,12 @@ fn run() -> anyhow::Result<Option<i32>> {
             python_version,
             root.as_deref(),
             &[],
+            ResolutionMode::Highest,
         )?))
     } else {
         debug!("START: monotrail as '{}': `{}`", name, args.join(" "));
-        run_cli(Cli::parse(), None)
+        let args = Args::parse();
+        run_cli(args.command, None, args.directory.as_deref())
     }
 }
 
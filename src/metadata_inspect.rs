@@ -0,0 +1,266 @@
+//! Figures out the name, version and runtime dependencies of a source tree or sdist that has
+//! neither a published wheel nor a poetry lockfile to tell us that information upfront, e.g. a
+//! git checkout of a plain setuptools/flit/hatch project.
+//!
+//! We try, in order of increasing cost: an already-present `PKG-INFO`/`*.egg-info/PKG-INFO`,
+//! then the PEP 517 `prepare_metadata_for_build_wheel` hook (which most backends implement
+//! without doing a full build), and only as a last resort building the wheel and reading its
+//! `.dist-info/METADATA`.
+
+use crate::source_distribution::{build_to_wheel, provision_build_requirements, read_build_system};
+use crate::PEP517_PREPARE_METADATA;
+use anyhow::{Context, Result};
+use flate2::read::GzDecoder;
+use fs_err as fs;
+use fs_err::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tempfile::TempDir;
+use tracing::debug;
+
+/// What [`inspect_metadata`] found out about a source tree or sdist
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct InspectedMetadata {
+    pub name: String,
+    pub version: String,
+    /// Unconditional (no environment marker) runtime dependencies, as close to PEP 508 syntax as
+    /// the source metadata allows; like [`crate::poetry_integration::resolve`], we skip
+    /// marker-gated requirements (extras, platform-specific deps, ...) rather than evaluate them
+    pub requires_dist: Vec<String>,
+}
+
+/// Extracts name, version and dependencies from a source tree (a checked out repository) or a
+/// `.tar.gz`/`.zip` sdist
+pub fn inspect_metadata(
+    source: &Path,
+    sys_executable: &Path,
+) -> Result<InspectedMetadata> {
+    // Keep the tempdir alive for the rest of the function if we had to unpack anything
+    let _unpacked;
+    let source_dir = if source.is_dir() {
+        source.to_path_buf()
+    } else {
+        _unpacked = unpack_sdist(source)?;
+        find_single_subdir(_unpacked.path())?
+    };
+
+    if let Some(metadata) = read_existing_metadata(&source_dir)? {
+        return Ok(metadata);
+    }
+
+    debug!(
+        "No PKG-INFO/egg-info in {}, trying the PEP 517 metadata hook",
+        source_dir.display()
+    );
+    if let Some(metadata) = prepare_metadata_hook(&source_dir, sys_executable)? {
+        return Ok(metadata);
+    }
+
+    debug!(
+        "{} has no usable metadata hook, building a wheel to read its METADATA",
+        source_dir.display()
+    );
+    build_and_read_metadata(&source_dir, sys_executable)
+}
+
+/// Cheap, python-free peek at a local `.tar.gz`/`.zip` sdist's `PKG-INFO`/`*.egg-info/PKG-INFO`,
+/// without falling back to the PEP 517 hook or a full build. Returns `None` if the sdist has
+/// neither (legacy setup.py-only packages that generate their metadata at build time), in which
+/// case callers that can spare an interpreter should use [`inspect_metadata`] instead
+pub fn read_sdist_metadata(sdist: &Path) -> Result<Option<InspectedMetadata>> {
+    let unpacked = unpack_sdist(sdist)?;
+    let source_dir = find_single_subdir(unpacked.path())?;
+    read_existing_metadata(&source_dir)
+}
+
+/// Unpacks a `.tar.gz` or `.zip` sdist into a fresh temp dir
+fn unpack_sdist(sdist: &Path) -> Result<TempDir> {
+    let target = TempDir::new()?;
+    let filename = sdist.to_string_lossy();
+    if filename.ends_with(".tar.gz") || filename.ends_with(".tgz") {
+        let tar_gz = File::open(sdist)?;
+        let mut archive = tar::Archive::new(GzDecoder::new(tar_gz));
+        archive
+            .unpack(target.path())
+            .with_context(|| format!("Failed to unpack {}", sdist.display()))?;
+    } else if filename.ends_with(".zip") {
+        let file = File::open(sdist)?;
+        let mut archive = zip::ZipArchive::new(file)
+            .with_context(|| format!("{} is not a valid zip", sdist.display()))?;
+        archive
+            .extract(target.path())
+            .with_context(|| format!("Failed to unpack {}", sdist.display()))?;
+    } else {
+        anyhow::bail!(
+            "Don't know how to unpack {} (neither .tar.gz nor .zip)",
+            sdist.display()
+        );
+    }
+    Ok(target)
+}
+
+/// A sdist is a single top-level directory (`<name>-<version>/...`); if there's exactly one
+/// subdirectory we descend into it, otherwise we assume the archive already is the project root
+fn find_single_subdir(unpacked: &Path) -> Result<PathBuf> {
+    let entries: Vec<_> = fs::read_dir(unpacked)?.collect::<std::io::Result<_>>()?;
+    match entries.as_slice() {
+        [entry] if entry.path().is_dir() => Ok(entry.path()),
+        _ => Ok(unpacked.to_path_buf()),
+    }
+}
+
+/// Looks for a `PKG-INFO` at the project root or inside a single `*.egg-info` directory, which
+/// setuptools sdists and checkouts that have already been built once tend to carry around
+fn read_existing_metadata(source_dir: &Path) -> Result<Option<InspectedMetadata>> {
+    let top_level_pkg_info = source_dir.join("PKG-INFO");
+    if top_level_pkg_info.is_file() {
+        return Ok(Some(parse_metadata(&fs::read_to_string(
+            &top_level_pkg_info,
+        )?)?));
+    }
+
+    let egg_info_pkg_info = fs::read_dir(source_dir)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .find(|path| {
+            path.is_dir()
+                && path
+                    .extension()
+                    .map(|extension| extension == "egg-info")
+                    .unwrap_or(false)
+        })
+        .map(|egg_info| egg_info.join("PKG-INFO"));
+    if let Some(egg_info_pkg_info) = egg_info_pkg_info {
+        if egg_info_pkg_info.is_file() {
+            return Ok(Some(parse_metadata(&fs::read_to_string(
+                &egg_info_pkg_info,
+            )?)?));
+        }
+    }
+    Ok(None)
+}
+
+/// What [`PEP517_PREPARE_METADATA`] reports on stdout: the `.dist-info` dir name it created
+/// relative to the metadata directory we gave it, or `null` if the hook isn't implemented
+type PrepareMetadataOutput = Option<String>;
+
+/// Calls the build backend's (optional) `prepare_metadata_for_build_wheel` hook, which is
+/// usually much cheaper than building the whole wheel just to read its METADATA
+fn prepare_metadata_hook(
+    source_dir: &Path,
+    sys_executable: &Path,
+) -> Result<Option<InspectedMetadata>> {
+    let build_system = read_build_system(source_dir)?;
+    let isolated_env = provision_build_requirements(&build_system.requires, sys_executable)?;
+    let metadata_dir = TempDir::new()?;
+
+    let mut python_path = vec![isolated_env.into_os_string()];
+    if let Some(existing) = std::env::var_os("PYTHONPATH") {
+        python_path.push(existing);
+    }
+
+    let output = Command::new(sys_executable)
+        .current_dir(source_dir)
+        .env("PYTHONPATH", std::env::join_paths(&python_path)?)
+        .args(["-S", "-c", PEP517_PREPARE_METADATA])
+        .arg(&build_system.build_backend)
+        .arg(metadata_dir.path())
+        .output()
+        .context("Failed to invoke the build backend's metadata hook")?;
+    if !output.status.success() {
+        debug!(
+            "{} doesn't support the metadata hook: {}\n---stdout:\n{}---stderr:\n{}",
+            build_system.build_backend,
+            output.status,
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        );
+        return Ok(None);
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let last_line = stdout
+        .lines()
+        .last()
+        .with_context(|| format!("{} didn't print anything", build_system.build_backend))?;
+    let dist_info_dir: PrepareMetadataOutput = serde_json::from_str(last_line).with_context(|| {
+        format!(
+            "Invalid output from the {} build backend: {}",
+            build_system.build_backend, last_line
+        )
+    })?;
+    let dist_info_dir = match dist_info_dir {
+        Some(dist_info_dir) => dist_info_dir,
+        None => return Ok(None),
+    };
+
+    let metadata_path = metadata_dir.path().join(&dist_info_dir).join("METADATA");
+    let metadata = fs::read_to_string(&metadata_path)
+        .with_context(|| format!("{} didn't create a METADATA file", dist_info_dir))?;
+    Ok(Some(parse_metadata(&metadata)?))
+}
+
+/// Last resort: build the full wheel and read its `.dist-info/METADATA`
+fn build_and_read_metadata(source_dir: &Path, sys_executable: &Path) -> Result<InspectedMetadata> {
+    let build_dir = TempDir::new()?;
+    // We don't know the compatible tags here (we're only after the metadata, not installing),
+    // so we accept whatever the backend built
+    let wheel_path = build_to_wheel(source_dir, build_dir.path(), &[], sys_executable)
+        .context("Failed to build a wheel to read its metadata")?;
+
+    let file = File::open(&wheel_path)?;
+    let mut archive = zip::ZipArchive::new(file)
+        .with_context(|| format!("{} is not a valid zip", wheel_path.display()))?;
+    let metadata_name = (0..archive.len())
+        .map(|i| archive.by_index(i).map(|entry| entry.name().to_string()))
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter()
+        .find(|name| name.ends_with(".dist-info/METADATA"))
+        .with_context(|| format!("No METADATA in {}", wheel_path.display()))?;
+    let mut metadata_file = archive.by_name(&metadata_name)?;
+    let mut contents = String::new();
+    metadata_file.read_to_string(&mut contents)?;
+    parse_metadata(&contents)
+}
+
+/// Parses `Name`/`Version`/`Requires-Dist` out of a `PKG-INFO` or wheel `METADATA` file, both of
+/// which share the same RFC 822-ish format
+fn parse_metadata(metadata: &str) -> Result<InspectedMetadata> {
+    let name = metadata
+        .lines()
+        .find_map(|line| line.strip_prefix("Name: "))
+        .context("Metadata has no Name field")?
+        .to_string();
+    let version = metadata
+        .lines()
+        .find_map(|line| line.strip_prefix("Version: "))
+        .context("Metadata has no Version field")?
+        .to_string();
+    let requires_dist = metadata
+        .lines()
+        .filter_map(|line| line.strip_prefix("Requires-Dist: "))
+        .filter(|requirement| !requirement.contains(';'))
+        .filter_map(|requirement| normalize_requires_dist(requirement))
+        .collect();
+    Ok(InspectedMetadata {
+        name,
+        version,
+        requires_dist,
+    })
+}
+
+/// `Requires-Dist` entries are usually already valid PEP 508, except some older tools emit the
+/// version constraint in parentheses (e.g. `requests (>=2.0)`) instead of directly after the name
+fn normalize_requires_dist(requirement: &str) -> Option<String> {
+    let requirement = requirement.trim();
+    if requirement.is_empty() {
+        return None;
+    }
+    Some(match requirement.split_once('(') {
+        Some((name, constraint)) => {
+            format!("{}{}", name.trim(), constraint.trim_end_matches(')').trim())
+        }
+        None => requirement.to_string(),
+    })
+}
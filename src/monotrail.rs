@@ -1,21 +1,33 @@
-use crate::inject_and_run::{inject_and_run_python, prepare_execve_environment};
+use crate::gc;
+use crate::inject_and_run::{exec_or_spawn, inject_and_run_python, prepare_execve_environment};
 use crate::install::{install_all, InstalledPackage};
+use crate::lock_export::LockManifest;
 use crate::markers::Pep508Environment;
-use crate::poetry_integration::lock::poetry_resolve;
+use crate::metadata_inspect::inspect_metadata;
+use crate::pdm_lock::read_pdm_lock_specs;
+use crate::pipfile_lock::read_pipfile_lock_specs;
+use crate::poetry_integration::lock::{load_cached_matrix_lock, poetry_resolve, ResolutionMode};
 use crate::poetry_integration::read_dependencies::poetry_spec_from_dir;
+use crate::poetry_integration::resolve;
 use crate::read_poetry_specs;
-use crate::requirements_txt::parse_requirements_txt;
+use crate::requirements_txt::{RequirementEntry, RequirementOrUrl, RequirementsTxt};
 use crate::spec::RequestedSpec;
-use crate::utils::{cache_dir, get_dir_content};
+use crate::utils::{cache_dir, did_you_mean, get_dir_content};
 use anyhow::{bail, Context};
 use fs_err as fs;
 use fs_err::{DirEntry, File};
-use install_wheel_rs::{compatible_tags, Arch, InstallLocation, Os, MONOTRAIL_SCRIPT_SHEBANG};
-use nix::unistd;
-use serde::Serialize;
+use install_wheel_rs::{
+    compatible_tags, Arch, InstallLocation, InterpreterKind, Os, MONOTRAIL_SCRIPT_SHEBANG,
+};
+use pep508_rs::VersionOrUrl;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
 use std::collections::{BTreeMap, HashMap};
+use std::env;
 use std::env::current_dir;
 use std::ffi::CString;
+use std::fmt;
+use std::hash::{Hash, Hasher};
 use std::io::Read;
 use std::path::{Path, PathBuf};
 use std::{env, io};
@@ -25,6 +37,8 @@ use tracing::{debug, trace, warn};
 enum LockfileType {
     PyprojectToml,
     RequirementsTxt,
+    PipfileLock,
+    PdmLock,
 }
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
@@ -35,20 +49,90 @@ pub enum LaunchType {
     Binary,
 }
 
+/// The interpreter implementation we're embedding, as reported by `sys.implementation.name`.
+/// PyPy has a different shared-library naming convention and C-API shim than CPython, so we need
+/// to know which one we're dealing with when provisioning and injecting
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Implementation {
+    CPython,
+    PyPy,
+}
+
+impl Implementation {
+    /// Parses `sys.implementation.name`
+    pub fn from_sys_implementation_name(name: &str) -> anyhow::Result<Self> {
+        match name {
+            "cpython" => Ok(Implementation::CPython),
+            "pypy" => Ok(Implementation::PyPy),
+            other => bail!("Unsupported python implementation: {}", other),
+        }
+    }
+
+    /// Maps to the matching `install_wheel_rs` wheel-tag variant, used to compute which
+    /// `compatible_tags` a wheel filename's `python_tag`/`abi_tag` needs to match
+    pub fn interpreter_kind(&self) -> InterpreterKind {
+        match self {
+            Implementation::CPython => InterpreterKind::CPython,
+            Implementation::PyPy => InterpreterKind::PyPy,
+        }
+    }
+}
+
+impl fmt::Display for Implementation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Implementation::CPython => write!(f, "cpython"),
+            Implementation::PyPy => write!(f, "pypy"),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct PythonContext {
     pub sys_executable: PathBuf,
     pub version: (u8, u8),
+    /// CPython or PyPy, used to pick the right libpython name and `PYTHONHOME` handling
+    pub implementation: Implementation,
     pub pep508_env: Pep508Environment,
     pub launch_type: LaunchType,
+    /// Compatible wheel platform tags for this interpreter (manylinux/musllinux/macosx/linux),
+    /// most specific first, as probed from the interpreter rather than assumed from the host
+    pub platform_tags: Vec<String>,
 }
 
 /// Name of the import -> (`__init__.py`, submodule import dirs)
 pub type SpecPaths = HashMap<String, (PathBuf, Vec<PathBuf>)>;
 
+/// A top-level package name that multiple installed packages ship an `__init__.py` for, where
+/// those `__init__.py` actually differ (see [`spec_paths`]), so one of them had to be picked
+/// arbitrarily as the spec's main file
+#[cfg(not(feature = "python_bindings"))]
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct NamespaceConflict {
+    /// The shared top-level package name, e.g. `poetry`
+    pub name: String,
+    /// The `(name, unique_version)` of the package whose `__init__.py` was used
+    pub chosen: (String, String),
+    /// The `(name, unique_version)` of the packages whose differing `__init__.py` was discarded
+    pub discarded: Vec<(String, String)>,
+}
+
+/// See the `#[cfg(not(feature = "python_bindings"))]` [`NamespaceConflict`]
+#[cfg(feature = "python_bindings")]
+#[pyo3::pyclass(dict)]
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct NamespaceConflict {
+    #[pyo3(get)]
+    pub name: String,
+    #[pyo3(get)]
+    pub chosen: (String, String),
+    #[pyo3(get)]
+    pub discarded: Vec<(String, String)>,
+}
+
 /// The packaging and import data that is resolved by the rust part and deployed by the finder
 #[cfg(not(feature = "python_bindings"))]
-#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub struct FinderData {
     /// The location where all packages are installed
     pub sprawl_root: String,
@@ -67,6 +151,10 @@ pub struct FinderData {
     /// The installed scripts indexed by name. They are in the bin folder of each project, coming
     /// from entry_points.txt or data folder scripts
     pub scripts: BTreeMap<String, String>,
+    /// Top-level package names multiple installed packages contribute a differing `__init__.py`
+    /// for, so callers can surface the ambiguity instead of it just manifesting as a confusing
+    /// import error at runtime
+    pub namespace_conflicts: Vec<NamespaceConflict>,
 }
 
 /// The packaging and import data that is resolved by the rust part and deployed by the finder
@@ -74,7 +162,7 @@ pub struct FinderData {
 /// TODO: write a pyo3 bug report to parse through cfg attr
 #[cfg(feature = "python_bindings")]
 #[pyo3::pyclass(dict)]
-#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub struct FinderData {
     #[pyo3(get)]
     pub sprawl_root: String,
@@ -90,6 +178,8 @@ pub struct FinderData {
     pub lockfile: String,
     #[pyo3(get)]
     pub scripts: BTreeMap<String, String>,
+    #[pyo3(get)]
+    pub namespace_conflicts: Vec<NamespaceConflict>,
 }
 
 #[cfg_attr(feature = "python_bindings", pyo3::pymethods)]
@@ -110,20 +200,52 @@ pub fn monotrail_root() -> anyhow::Result<PathBuf> {
     }
 }
 
-/// Walks the directory tree up to find a pyproject.toml or a requirements.txt and returns
-/// the dir (poetry) or the file (requirements.txt)
-fn find_dep_file(dir_running: &Path) -> Option<(PathBuf, LockfileType)> {
+/// Walks the directory tree up to find a pyproject.toml, requirements.txt, Pipfile.lock or
+/// pdm.lock, and returns the dir (poetry) or the file (everything else).
+///
+/// Follows the [heroku buildpack's rule](https://devcenter.heroku.com/articles/python-dependencies-via-pip)
+/// of refusing a directory that mixes more than one of these: silently picking one, as we used
+/// to, hides that the project's dependencies may have drifted between whichever files its
+/// package managers left behind.
+fn find_dep_file(dir_running: &Path) -> anyhow::Result<Option<(PathBuf, LockfileType)>> {
     let mut parent = Some(dir_running.to_path_buf());
     while let Some(dir) = parent {
-        if dir.join("pyproject.toml").exists() {
-            return Some((dir, LockfileType::PyprojectToml));
-        }
-        if dir.join("requirements.txt").exists() {
-            return Some((dir.join("requirements.txt"), LockfileType::RequirementsTxt));
+        let present: Vec<(PathBuf, LockfileType)> = [
+            (dir.join("pyproject.toml"), LockfileType::PyprojectToml),
+            (dir.join("requirements.txt"), LockfileType::RequirementsTxt),
+            (dir.join("Pipfile.lock"), LockfileType::PipfileLock),
+            (dir.join("pdm.lock"), LockfileType::PdmLock),
+        ]
+        .into_iter()
+        .filter(|(path, _)| path.exists())
+        .collect();
+
+        match present.len() {
+            0 => {}
+            1 => {
+                let (path, lockfile_type) = present.into_iter().next().unwrap();
+                let location = match lockfile_type {
+                    LockfileType::PyprojectToml => dir,
+                    _ => path,
+                };
+                return Ok(Some((location, lockfile_type)));
+            }
+            _ => {
+                bail!(
+                    "Found {} next to each other in {}, refusing to guess which one to use; \
+                     keep only one package manager's file",
+                    present
+                        .iter()
+                        .map(|(path, _)| path.file_name().unwrap().to_string_lossy())
+                        .collect::<Vec<_>>()
+                        .join(" and "),
+                    dir.display()
+                );
+            }
         }
         parent = dir.parent().map(|path| path.to_path_buf());
     }
-    None
+    Ok(None)
 }
 
 /// Returns the list of installed packages, optionally filtering for compatible tags.
@@ -144,10 +266,17 @@ pub fn list_installed(
                 let is_compatible = match tag.split('-').collect::<Vec<_>>()[..] {
                     [python_tag, abi_tag, platform_tag] => {
                         if let Some(compatible_tags) = compatible_tags {
+                            // `compatible_tags` is already the fully enumerated set of tags this
+                            // host's interpreter and libc (manylinux/musllinux, PEP 600/656)
+                            // support, each entry an exact, complete tag string. So membership is
+                            // exact string equality, not a substring check: `str::contains` here
+                            // used to both over-match (any stored tag that happens to contain a
+                            // shorter valid tag as a substring, e.g. "manylinux_2_17" inside
+                            // "manylinux_2_175") and under-match real-world cases.
                             compatible_tags.iter().any(|ok_tag| {
-                                python_tag.contains(&ok_tag.0)
-                                    && abi_tag.contains(&ok_tag.1)
-                                    && platform_tag.contains(&ok_tag.2)
+                                python_tag == ok_tag.0
+                                    && abi_tag == ok_tag.1
+                                    && platform_tag == ok_tag.2
                             })
                         } else {
                             true
@@ -176,6 +305,41 @@ pub fn list_installed(
     Ok(compatible)
 }
 
+/// Feeds an unpinned spec's already-installed versions to the PubGrub-style resolver in
+/// [`crate::poetry_integration::resolve`], so picking a version for it goes through the same
+/// unit-propagation/conflict-driven-backtracking machinery as a fresh resolution instead of
+/// grabbing whichever version `list_installed` happened to enumerate first.
+///
+/// We don't know the dependencies of an already-installed package (that metadata isn't kept in
+/// the sprawl store), so `dependencies` always reports none; this makes the resolver degrade to
+/// "pick the newest candidate version", which is still strictly better than an arbitrary one and
+/// leaves room to plug in real dependency data later without changing callers.
+struct InstalledVersionProvider<'a> {
+    /// `unique_version` strings installed for the one package this provider was built for
+    candidates: &'a [String],
+}
+
+impl resolve::DependencyProvider for InstalledVersionProvider<'_> {
+    fn versions(&self, _package: &str) -> anyhow::Result<Vec<resolve::Version>> {
+        let mut versions: Vec<resolve::Version> = self
+            .candidates
+            .iter()
+            .filter_map(|version| resolve::parse_version(version).ok())
+            .collect();
+        versions.sort();
+        versions.reverse();
+        Ok(versions)
+    }
+
+    fn dependencies(
+        &self,
+        _package: &str,
+        _version: &resolve::Version,
+    ) -> anyhow::Result<Vec<(String, resolve::Range)>> {
+        Ok(Vec::new())
+    }
+}
+
 /// Splits the given spec set into installed and to-be-installed
 pub fn filter_installed_monotrail(
     specs: &[RequestedSpec],
@@ -212,21 +376,39 @@ pub fn filter_installed_monotrail(
                 not_installed.push(spec.clone());
             }
         } else {
-            // For now we just take any version there is
-            // This would take proper version resolution to make sense
-            if let Some((name, unique_version, _path)) = compatible
+            // Spec carries no version of its own, so any installed version technically
+            // satisfies it; resolve through the shared PubGrub engine anyway so that among
+            // several installed versions we deterministically prefer the newest rather than
+            // whichever one `list_installed` happened to enumerate first
+            let name = spec.normalized_name();
+            let candidate_versions: Vec<String> = compatible
                 .iter()
-                .find(|(name, _version, _path)| name == &spec.normalized_name())
-            {
+                .filter(|(candidate_name, _version, _tag)| candidate_name == &name)
+                .map(|(_name, version, _tag)| version.clone())
+                .collect();
+            let provider = InstalledVersionProvider {
+                candidates: &candidate_versions,
+            };
+            let resolution = resolve::resolve(&provider, &[(name.clone(), resolve::Range::full())]);
+            let picked = resolution.ok().and_then(|resolution| {
+                let version = resolution.get(&name)?.to_string();
+                compatible
+                    .iter()
+                    .find(|(candidate_name, candidate_version, _tag)| {
+                        candidate_name == &name && candidate_version == &version
+                    })
+                    .cloned()
+            });
+            if let Some((name, unique_version, tag)) = picked {
                 installed.push(InstalledPackage {
                     // already normalized
-                    name: name.clone(),
+                    name,
                     python_version: spec
                         .python_version
                         .clone()
                         .context("TODO: needs python version")?,
-                    unique_version: unique_version.to_string(),
-                    tag: "".to_string(),
+                    unique_version,
+                    tag,
                 });
             } else {
                 not_installed.push(spec.clone());
@@ -237,6 +419,176 @@ pub fn filter_installed_monotrail(
     Ok((not_installed, installed))
 }
 
+/// An explicit `(Os, Arch)` to resolve and install for, overriding what [`install_missing`] and
+/// [`spec_paths`] would otherwise detect through [`Os::current`]/[`Arch::current`]. Lets a user
+/// on e.g. Linux populate a `monotrail_root` for Windows or macOS ahead of time, the same way
+/// `pip download --platform` targets a platform it isn't running on.
+#[derive(Debug, Clone)]
+pub struct CrossTarget {
+    pub os: Os,
+    pub arch: Arch,
+}
+
+impl CrossTarget {
+    /// Parses one of pip's `--platform`-style platform tag names (`manylinux1_x86_64`,
+    /// `manylinux_2_17_aarch64`, `musllinux_1_1_x86_64`, `win32`, `win_amd64`, `macosx_10_9_x86_64`,
+    /// or a bare OS family like `darwin`/`linux`/`windows`) into a [`CrossTarget`], the way
+    /// pyflow's own `Os` enum maps the same strings -- just keeping the architecture apart
+    /// instead of collapsing everything down to one generic OS.
+    pub fn from_platform_tag(platform_tag: &str) -> anyhow::Result<Self> {
+        let (os, arch) = parse_target_platform(platform_tag)?;
+        Ok(Self { os, arch })
+    }
+
+    /// Synthesizes a [`Pep508Environment`] for this target without running an interpreter on it,
+    /// so markers (`sys_platform == "linux"`, `platform_machine == "aarch64"`, ...) can be
+    /// evaluated for a platform we aren't currently on -- e.g. building a container's install set
+    /// from the host, or a remote target that has no interpreter to probe in the first place.
+    ///
+    /// Only covers the `Os`/`Arch` variants [`parse_target_platform`] itself produces; anything
+    /// else is rejected rather than guessed at, same as that function. `python_full_version` is
+    /// approximated as `<major>.<minor>.0` and `platform_release`/`platform_version` are left
+    /// empty, since neither is derivable for a target that isn't actually running -- both are
+    /// rarely, if ever, checked by real-world markers.
+    pub fn to_pep508_environment(
+        &self,
+        python_version: (u8, u8),
+        implementation: Implementation,
+    ) -> anyhow::Result<Pep508Environment> {
+        let (sys_platform, os_name, platform_system) = match &self.os {
+            Os::Manylinux { .. } | Os::Musllinux { .. } => ("linux", "posix", "Linux"),
+            Os::Macos { .. } => ("darwin", "posix", "Darwin"),
+            Os::Windows => ("win32", "nt", "Windows"),
+            other => bail!(
+                "Don't know how to synthesize a target environment for {}",
+                other
+            ),
+        };
+        let platform_machine = match (&self.os, &self.arch) {
+            (Os::Windows, Arch::X86_64) => "AMD64",
+            (Os::Windows, Arch::Aarch64) => "ARM64",
+            (Os::Windows, Arch::X86) => "x86",
+            (Os::Macos { .. }, Arch::X86_64) => "x86_64",
+            (Os::Macos { .. }, Arch::Aarch64) => "arm64",
+            (Os::Manylinux { .. } | Os::Musllinux { .. }, Arch::X86_64) => "x86_64",
+            (Os::Manylinux { .. } | Os::Musllinux { .. }, Arch::Aarch64) => "aarch64",
+            (Os::Manylinux { .. } | Os::Musllinux { .. }, Arch::Armv7L) => "armv7l",
+            (Os::Manylinux { .. } | Os::Musllinux { .. }, Arch::Powerpc64Le) => "ppc64le",
+            (Os::Manylinux { .. } | Os::Musllinux { .. }, Arch::Powerpc64) => "ppc64",
+            (Os::Manylinux { .. } | Os::Musllinux { .. }, Arch::S390X) => "s390x",
+            (Os::Manylinux { .. } | Os::Musllinux { .. }, Arch::X86) => "i686",
+            (os, arch) => bail!(
+                "Don't know the platform.machine() value for {} on {}",
+                arch,
+                os
+            ),
+        };
+
+        let (major, minor) = python_version;
+        Ok(Pep508Environment {
+            implementation_name: implementation.to_string(),
+            implementation_version: format!("{}.{}.0", major, minor),
+            os_name: os_name.to_string(),
+            platform_machine: platform_machine.to_string(),
+            platform_python_implementation: match implementation {
+                Implementation::CPython => "CPython".to_string(),
+                Implementation::PyPy => "PyPy".to_string(),
+            },
+            platform_release: String::new(),
+            platform_system: platform_system.to_string(),
+            platform_version: String::new(),
+            python_full_version: format!("{}.{}.0", major, minor),
+            python_version: format!("{}.{}", major, minor),
+            sys_platform: sys_platform.to_string(),
+        })
+    }
+}
+
+/// Architecture suffixes recognized in a platform tag, most specific first so e.g. `_x86_64`
+/// isn't shadowed by a shorter match
+const TARGET_ARCH_SUFFIXES: &[(&str, Arch)] = &[
+    ("_x86_64", Arch::X86_64),
+    ("_amd64", Arch::X86_64),
+    ("_aarch64", Arch::Aarch64),
+    ("_arm64", Arch::Aarch64),
+    ("_armv7l", Arch::Armv7L),
+    ("_ppc64le", Arch::Powerpc64Le),
+    ("_ppc64", Arch::Powerpc64),
+    ("_s390x", Arch::S390X),
+    ("_i686", Arch::X86),
+];
+
+/// Parses the `<major>_<minor>` suffix of a `manylinux_`/`musllinux_`/`macosx_` platform tag
+fn parse_major_minor_suffix(rest: &str) -> anyhow::Result<(u16, u16)> {
+    let (major, minor) = rest
+        .split_once('_')
+        .with_context(|| format!("Expected <major>_<minor>_<arch>, got: {}", rest))?;
+    Ok((
+        major
+            .parse()
+            .with_context(|| format!("Invalid major version: {}", major))?,
+        minor
+            .parse()
+            .with_context(|| format!("Invalid minor version: {}", minor))?,
+    ))
+}
+
+/// Maps a pip `--platform`-style platform tag name into the `(Os, Arch)` pair [`compatible_tags`]
+/// needs. A bare OS family with no architecture suffix (`darwin`, `linux`, `windows`) keeps
+/// [`Arch::current`], since switching OS family while staying on the same CPU is the common case
+/// and the tag alone carries no arch to cross to.
+fn parse_target_platform(platform: &str) -> anyhow::Result<(Os, Arch)> {
+    let arch = TARGET_ARCH_SUFFIXES
+        .iter()
+        .find_map(|(suffix, arch)| platform.ends_with(suffix).then_some(*arch));
+
+    let os = if platform.starts_with("manylinux1_") {
+        Os::Manylinux { major: 2, minor: 5 }
+    } else if platform.starts_with("manylinux2010_") {
+        Os::Manylinux {
+            major: 2,
+            minor: 12,
+        }
+    } else if platform.starts_with("manylinux2014_") {
+        Os::Manylinux {
+            major: 2,
+            minor: 17,
+        }
+    } else if let Some(rest) = platform.strip_prefix("manylinux_") {
+        let (major, minor) = parse_major_minor_suffix(rest)?;
+        Os::Manylinux { major, minor }
+    } else if let Some(rest) = platform.strip_prefix("musllinux_") {
+        let (major, minor) = parse_major_minor_suffix(rest)?;
+        Os::Musllinux { major, minor }
+    } else if platform == "win32" || platform.starts_with("win_") || platform == "windows" {
+        Os::Windows
+    } else if let Some(rest) = platform.strip_prefix("macosx_") {
+        let (major, minor) = parse_major_minor_suffix(rest)?;
+        Os::Macos { major, minor }
+    } else if platform == "darwin" {
+        // No version encoded in the bare name, so assume the oldest macOS this crate still
+        // supports, same fallback `Os::for_target_triple` uses for a bare target triple
+        Os::Macos {
+            major: 11,
+            minor: 0,
+        }
+    } else if platform == "linux" {
+        Os::Manylinux {
+            major: 2,
+            minor: 17,
+        }
+    } else {
+        bail!("Unrecognized platform tag: {}", platform);
+    };
+
+    let arch = match arch {
+        Some(arch) => arch,
+        None if platform == "win32" => Arch::X86,
+        None => Arch::current()?,
+    };
+    Ok((os, arch))
+}
+
 /// script can be a manually set working directory or the python script we're running.
 /// Returns a list name, python version, unique version
 #[cfg_attr(not(feature = "python_bindings"), allow(dead_code))]
@@ -244,9 +596,42 @@ pub fn install_missing(
     specs: &[RequestedSpec],
     python: &Path,
     python_version: (u8, u8),
+    implementation: Implementation,
+    platform_tags: &[String],
+    target: Option<&CrossTarget>,
 ) -> anyhow::Result<(String, Vec<InstalledPackage>)> {
     let monotrail_root = monotrail_root()?;
-    let compatible_tags = compatible_tags(python_version, &Os::current()?, &Arch::current()?)?;
+    let (os, arch) = match target {
+        Some(target) => (target.os.clone(), target.arch.clone()),
+        None => (Os::current()?, Arch::current()?),
+    };
+    let mut compatible_tags = compatible_tags(
+        python_version,
+        &os,
+        &arch,
+        &implementation.interpreter_kind(),
+    )?;
+    // The tags above assume the host monotrail itself runs on matches the target interpreter's
+    // libc and arch, which isn't always true (e.g. a standalone python built against a different
+    // glibc, or musl on Alpine). Augment them with whatever manylinux/musllinux platform tags the
+    // interpreter reported about itself, under the same python/abi tags already computed above,
+    // so a wheel that's only compatible with the interpreter (not the host) still gets picked up.
+    // None of this applies to an explicit cross `target` though: there's no local interpreter to
+    // probe for a foreign platform, so its `compatible_tags` are taken as-is.
+    if target.is_none() {
+        let python_abi_tags: Vec<(String, String)> = compatible_tags
+            .iter()
+            .map(|(python_tag, abi_tag, _)| (python_tag.clone(), abi_tag.clone()))
+            .collect();
+        for (python_tag, abi_tag) in python_abi_tags {
+            for platform_tag in platform_tags {
+                let tag = (python_tag.clone(), abi_tag.clone(), platform_tag.clone());
+                if !compatible_tags.contains(&tag) {
+                    compatible_tags.push(tag);
+                }
+            }
+        }
+    }
 
     let (to_install_specs, installed_done) =
         filter_installed_monotrail(specs, Path::new(&monotrail_root), &compatible_tags)?;
@@ -259,7 +644,9 @@ pub fn install_missing(
             python_version,
         },
         &compatible_tags,
-        false,
+        // A foreign target's interpreter can't run on this host, so there's nothing to invoke to
+        // byte-compile its installed modules with
+        target.is_some(),
         true,
     )?;
 
@@ -285,6 +672,37 @@ pub fn install_missing(
     Ok((monotrail_location_string, installed))
 }
 
+/// Resolves a poetry project's `poetry.lock` against an explicit `target` instead of the host's
+/// own (live, locally probed) interpreter, returning both the resulting specs and the
+/// `compatible_tags` they were selected for. Combines [`CrossTarget::to_pep508_environment`] (for
+/// marker evaluation) with [`compatible_tags`] (for wheel-tag selection) the same way
+/// [`install_missing`] does for an explicit `target`, then walks the lockfile with
+/// [`poetry_spec_from_dir`] -- the same walk used for the host's own interpreter, just fed a
+/// synthetic environment instead.
+///
+/// The result is a deterministic, per-target install set: useful for building a container image or
+/// provisioning a remote machine for `target` from a host that may not have a matching interpreter
+/// (or any interpreter at all) to probe.
+#[cfg_attr(not(feature = "python_bindings"), allow(dead_code))]
+pub fn poetry_specs_for_target(
+    dep_file_location: &Path,
+    extras: &[String],
+    python_version: (u8, u8),
+    implementation: Implementation,
+    target: &CrossTarget,
+) -> anyhow::Result<(Vec<RequestedSpec>, Vec<(String, String, String)>)> {
+    let pep508_env = target.to_pep508_environment(python_version, implementation)?;
+    let compatible_tags = compatible_tags(
+        python_version,
+        &target.os,
+        &target.arch,
+        &implementation.interpreter_kind(),
+    )?;
+    let (specs, _scripts, _lockfile) =
+        poetry_spec_from_dir(dep_file_location, extras, &pep508_env)?;
+    Ok((specs, compatible_tags))
+}
+
 /// When python installs packages, it just unpacks zips into the venv. If multiples packages
 /// contain the same directory, they are simply silently merged, and files are overwritten.
 /// This means that packages can ship modules of a different nam, e.g. pillow containing PIL,
@@ -300,14 +718,28 @@ pub fn install_missing(
 ///
 /// <https://docs.python.org/3/library/importlib.html#importlib.machinery.ModuleSpec>
 ///
-/// Returns the name, the main file to import for the spec and the submodule_search_locations
-/// as well as a list of .pth files that need to be executed
+/// Returns the name, the main file to import for the spec and the submodule_search_locations,
+/// a list of .pth files that need to be executed, and the set of namespace conflicts that were
+/// found and had to be resolved by picking one package's `__init__.py` over the others'
 #[cfg_attr(not(feature = "python_bindings"), allow(dead_code))]
 pub fn spec_paths(
     sprawl_root: &Path,
     sprawl_packages: &[InstalledPackage],
     python_version: (u8, u8),
-) -> anyhow::Result<(SpecPaths, Vec<PathBuf>)> {
+    implementation: Implementation,
+    target: Option<&CrossTarget>,
+) -> anyhow::Result<(SpecPaths, Vec<PathBuf>, Vec<NamespaceConflict>)> {
+    let (os, arch) = match target {
+        Some(target) => (target.os.clone(), target.arch.clone()),
+        None => (Os::current()?, Arch::current()?),
+    };
+    let compatible_tags = compatible_tags(
+        python_version,
+        &os,
+        &arch,
+        &implementation.interpreter_kind(),
+    )?;
+
     let mut dir_modules: HashMap<String, Vec<InstalledPackage>> = HashMap::new();
     let mut file_modules: HashMap<String, (InstalledPackage, PathBuf)> = HashMap::new();
     let mut pth_files: Vec<PathBuf> = Vec::new();
@@ -341,10 +773,11 @@ pub fn spec_paths(
                         file_modules
                             .insert(stem.to_string(), (sprawl_package.clone(), entry.path()));
                     }
-                    [stem, _tag, "so"] => {
-                        // TODO: Check compatibility of so tag
-                        file_modules
-                            .insert(stem.to_string(), (sprawl_package.clone(), entry.path()));
+                    [stem, so_tag, "so"] => {
+                        if so_tag_compatible(so_tag, &compatible_tags) {
+                            file_modules
+                                .insert(stem.to_string(), (sprawl_package.clone(), entry.path()));
+                        }
                     }
                     [.., "pth"] => pth_files.push(entry.path()),
                     _ => continue,
@@ -370,8 +803,9 @@ pub fn spec_paths(
         spec_bases.insert(name, (filename, Vec::new()));
     }
 
+    let mut namespace_conflicts = Vec::new();
     for (name, packages) in dir_modules {
-        let submodule_search_locations = packages
+        let submodule_search_locations: Vec<PathBuf> = packages
             .iter()
             .map(|package| {
                 package
@@ -379,17 +813,71 @@ pub fn spec_paths(
                     .join(&name)
             })
             .collect();
-        // This is effectively a random pick, if someone is relying on different __init__.py
-        // contents all is already cursed anyway.
-        // TODO: Should we check __init__.py contents that they're all equal?
-        let first_init_py = packages[0]
-            .monotrail_site_packages(sprawl_root.to_path_buf(), python_version)
-            .join(&name)
-            .join("__init__.py");
-        spec_bases.insert(name, (first_init_py, submodule_search_locations));
+        // Packages are sorted deterministically (see above), so this is always the same pick for
+        // a given set of installed packages, but it's still arbitrary: if the contributing
+        // `__init__.py` actually differ, submodules of the discarded packages are still found
+        // through `submodule_search_locations`, but the top-level package's own code (constants,
+        // `__all__`, re-exports, ...) silently comes from whichever package sorts first.
+        let chosen_init_py = submodule_search_locations[0].join("__init__.py");
+        let discarded = packages[1..]
+            .iter()
+            .zip(submodule_search_locations[1..].iter())
+            .filter(|(_package, location)| {
+                !init_py_equivalent(&chosen_init_py, &location.join("__init__.py"))
+            })
+            .map(|(package, _location)| (package.name.clone(), package.unique_version.clone()))
+            .collect::<Vec<_>>();
+        if !discarded.is_empty() {
+            warn!(
+                "{} is shipped by multiple packages with differing __init__.py contents: picked \
+                 {} {}, discarded {:?}. Imports of `{}` itself (as opposed to its submodules) \
+                 will only see the picked package's code.",
+                name, packages[0].name, packages[0].unique_version, discarded, name
+            );
+            namespace_conflicts.push(NamespaceConflict {
+                name: name.clone(),
+                chosen: (packages[0].name.clone(), packages[0].unique_version.clone()),
+                discarded,
+            });
+        }
+        spec_bases.insert(name, (chosen_init_py, submodule_search_locations));
     }
 
-    Ok((spec_bases, pth_files))
+    Ok((spec_bases, pth_files, namespace_conflicts))
+}
+
+/// Whether a tagged extension module's `SOABI` middle component (e.g. `cpython-310-x86_64-linux-gnu`
+/// in `_foo.cpython-310-x86_64-linux-gnu.so`) was built for one of `compatible_tags`, instead of
+/// [`spec_paths`] blindly accepting any `.so` regardless of which interpreter built it -- loading
+/// one compiled against a different Python would crash the interpreter on import rather than
+/// failing cleanly.
+fn so_tag_compatible(so_tag: &str, compatible_tags: &[(String, String, String)]) -> bool {
+    compatible_tags
+        .iter()
+        .any(|(python_tag, _abi_tag, _platform_tag)| {
+            // `python_tag` is the abbreviated form (e.g. `cp310`), while the `SOABI` naming
+            // distutils/setuptools still use spells it out as `cpython-310`
+            python_tag
+                .strip_prefix("cp")
+                .map(|version| so_tag.contains(&format!("cpython-{}", version)))
+                .unwrap_or(false)
+        })
+}
+
+/// Whether two `__init__.py` files are the same module for our purposes, i.e. identical once
+/// whitespace differences (line endings, trailing whitespace, reformatting) are ignored
+fn init_py_equivalent(first: &Path, second: &Path) -> bool {
+    let hash = |path: &Path| -> u64 {
+        let content = fs::read(path).unwrap_or_default();
+        let normalized: Vec<u8> = content
+            .into_iter()
+            .filter(|byte| !byte.is_ascii_whitespace())
+            .collect();
+        let mut hasher = DefaultHasher::new();
+        normalized.hash(&mut hasher);
+        hasher.finish()
+    };
+    hash(first) == hash(second)
 }
 
 /// Goes up the script path until a pyproject.toml/poetry.lock or a requirements.txt is
@@ -397,14 +885,22 @@ pub fn spec_paths(
 /// set and returns it. `script` can be a file or a directory or will default to the current
 /// working directory.
 ///
-/// Returns the specs and the entrypoints of the root package (if poetry, empty for
-/// requirements.txt)
+/// Returns the specs, the entrypoints of the root package (if poetry, empty for
+/// requirements.txt), the lockfile, and, for a requirements.txt with an `-e`/`--editable` local
+/// entry, that project's directory, so the caller can register it for live-source-tree imports
+/// instead of a copied install
 #[cfg_attr(not(feature = "python_bindings"), allow(dead_code))]
 pub fn get_specs(
     script: Option<&Path>,
     extras: &[String],
+    resolution_mode: ResolutionMode,
     python_context: &PythonContext,
-) -> anyhow::Result<(Vec<RequestedSpec>, BTreeMap<String, String>, String)> {
+) -> anyhow::Result<(
+    Vec<RequestedSpec>,
+    BTreeMap<String, String>,
+    String,
+    Option<PathBuf>,
+)> {
     let dir_running = match script {
         None => current_dir().context("Couldn't get current directory ಠ_ಠ")?,
         Some(file) if file.is_file() => {
@@ -439,9 +935,10 @@ pub fn get_specs(
     };
     debug!("python project dir: {}", dir_running.display());
 
-    let (dep_file_location, lockfile_type) = find_dep_file(&dir_running).with_context(|| {
+    let (dep_file_location, lockfile_type) = find_dep_file(&dir_running)?.with_context(|| {
         format!(
-            "pyproject.toml not found next to {} nor in any parent directory",
+            "pyproject.toml, requirements.txt, Pipfile.lock or pdm.lock not found next to {} \
+             nor in any parent directory",
             script.map_or_else(
                 || "current directory".to_string(),
                 |file_running| file_running.display().to_string()
@@ -450,63 +947,186 @@ pub fn get_specs(
     })?;
     match lockfile_type {
         LockfileType::PyprojectToml => {
-            poetry_spec_from_dir(&dep_file_location, extras, &python_context.pep508_env)
+            let (specs, scripts, lockfile) =
+                poetry_spec_from_dir(&dep_file_location, extras, &python_context.pep508_env)?;
+            Ok((specs, scripts, lockfile, None))
         }
         LockfileType::RequirementsTxt => {
-            let (specs, lockfile) = specs_from_requirements_txt_resolved(
+            let (specs, lockfile, project_dir) = specs_from_requirements_txt_resolved(
                 &dep_file_location,
                 extras,
                 None,
+                resolution_mode,
                 python_context,
             )?;
-            Ok((specs, BTreeMap::new(), lockfile))
+            Ok((specs, BTreeMap::new(), lockfile, project_dir))
+        }
+        LockfileType::PipfileLock => {
+            let specs = read_pipfile_lock_specs(&dep_file_location, extras)?;
+            Ok((specs, BTreeMap::new(), String::new(), None))
+        }
+        LockfileType::PdmLock => {
+            let specs = read_pdm_lock_specs(&dep_file_location, extras)?;
+            Ok((specs, BTreeMap::new(), String::new(), None))
         }
     }
 }
 
-/// Reads the requirements.txt, calls poetry to resolve them and returns the resolved specs and the
-/// lockfile
+/// Reads the requirements.txt, calls poetry to resolve whatever it can express, and returns the
+/// resolved specs together with the lockfile.
+///
+/// Pinned wheel/sdist urls, local paths and `-e`/`--editable` entries can't be expressed as a
+/// poetry dependency without a declared name (or, for `-e`, without poetry copying the project
+/// instead of linking to it live), so [`RequirementsTxt::split_direct_requirements`] pulls those
+/// out first and [`direct_requirement_spec`] turns each into a [`RequestedSpec`] directly,
+/// bypassing poetry resolution entirely for them. If one of them is an editable local directory,
+/// its path is also returned so the caller can register it for live-source-tree imports (see
+/// `repo_dir` on [`FinderData`]); only the first such directory is returned, matching `repo_dir`'s
+/// existing single-project convention.
 pub fn specs_from_requirements_txt_resolved(
     requirements_txt: &Path,
     extras: &[String],
     lockfile: Option<&str>,
+    resolution_mode: ResolutionMode,
     python_context: &PythonContext,
-) -> anyhow::Result<(Vec<RequestedSpec>, String)> {
-    let requirements =
-        parse_requirements_txt(&fs::read_to_string(&requirements_txt)?, &requirements_txt)?;
+) -> anyhow::Result<(Vec<RequestedSpec>, String, Option<PathBuf>)> {
+    // Note that all relative paths and includes in the requirements.txt are resolved against its
+    // own directory here, since that's the only sensible default a library entry point has
+    let working_dir = requirements_txt
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| PathBuf::from("."));
+    let mut requirements = RequirementsTxt::parse(requirements_txt, &working_dir)
+        .with_context(|| format!("Failed to parse {}", requirements_txt.display()))?;
+    let direct_entries = requirements.split_direct_requirements();
+
+    let mut project_dir = None;
+    let mut direct_specs = Vec::with_capacity(direct_entries.len());
+    for entry in direct_entries {
+        let (spec, editable_dir) = direct_requirement_spec(entry, python_context)?;
+        project_dir = project_dir.or(editable_dir);
+        direct_specs.push(spec);
+    }
+
+    // If the caller didn't pin a lockfile, try a matrix lock cached for this exact interpreter
+    // before falling back to a fresh resolution
+    let lockfile = match lockfile {
+        Some(lockfile) => Some(lockfile.to_string()),
+        None => load_cached_matrix_lock(python_context.version)?,
+    };
     // We don't know whether the requirements.txt is from `pip freeze` or just a list of
     // version, so we let it go through poetry resolve either way. For a frozen file
     // there will just be no change
-    let (poetry_section, poetry_lock, lockfile) =
-        poetry_resolve(&requirements, lockfile, python_context)
-            .context("Failed to resolve dependencies with poetry")?;
-    let specs = read_poetry_specs(
+    let poetry_dependencies = requirements.into_poetry(requirements_txt)?;
+    let (poetry_section, poetry_lock, lockfile) = poetry_resolve(
+        &poetry_dependencies,
+        lockfile.as_deref(),
+        resolution_mode,
+        python_context.version,
+        python_context,
+    )
+    .context("Failed to resolve dependencies with poetry")?;
+    let mut specs = read_poetry_specs(
         &poetry_section,
         poetry_lock,
         false,
         extras,
         &python_context.pep508_env,
     )?;
-    Ok((specs, lockfile))
+    specs.extend(direct_specs);
+    Ok((specs, lockfile, project_dir))
+}
+
+/// Turns a single direct/editable `requirements.txt` entry into a [`RequestedSpec`] without going
+/// through poetry, returning its path too if it's an editable local directory (so the caller can
+/// register it for live-source-tree imports instead of a copied install)
+fn direct_requirement_spec(
+    entry: RequirementEntry,
+    python_context: &PythonContext,
+) -> anyhow::Result<(RequestedSpec, Option<PathBuf>)> {
+    let (name, target, extras) = match entry.requirement {
+        RequirementOrUrl::NamedRequirement(requirement) => {
+            let target = match requirement.version_or_url {
+                Some(VersionOrUrl::Url(url)) => url.to_string(),
+                _ if entry.editable => bail!(
+                    "-e/--editable requires a local path or url, not a version constraint: {}",
+                    requirement.name
+                ),
+                _ => bail!(
+                    "{} was split out as a direct requirement, but has no url to install from",
+                    requirement.name
+                ),
+            };
+            (
+                Some(requirement.name),
+                target,
+                requirement.extras.unwrap_or_default(),
+            )
+        }
+        RequirementOrUrl::Url(url_requirement) => (None, url_requirement.url, Vec::new()),
+    };
+
+    // A `git+`-prefixed VCS url or a remote archive url is fetched/resolved the same way a PEP 508
+    // direct reference given on the CLI is
+    if target.starts_with("git+") || target.contains("://") {
+        let spec = match &name {
+            Some(name) => {
+                let requested = format!("{} @ {}", name, target);
+                RequestedSpec::from_direct_reference(&requested, name, &target, &extras)?
+            }
+            None => RequestedSpec::from_requested(&target, &extras)?,
+        };
+        return Ok((spec, None));
+    }
+
+    // Anything else is a local path; `RequirementsTxt::parse`'s working_dir already resolved it
+    // to an absolute path
+    let path = PathBuf::from(&target);
+    if path.is_dir() {
+        let metadata = inspect_metadata(&path, &python_context.sys_executable)
+            .with_context(|| format!("Failed to read project metadata from {}", path.display()))?;
+        let spec = RequestedSpec::from_source_directory(
+            path.clone(),
+            metadata.name,
+            metadata.version,
+            extras,
+        );
+        let project_dir = entry.editable.then_some(path);
+        Ok((spec, project_dir))
+    } else {
+        // A local wheel or sdist archive; its filename already gives us the name and version
+        let spec = RequestedSpec::from_requested(&target, &extras)?;
+        Ok((spec, None))
+    }
 }
 
 /// Convenience wrapper around `install_requested` and `spec_paths`
+///
+/// `target` overrides the platform `specs` are installed for, letting a caller populate a
+/// `monotrail_root` for a platform other than the one monotrail itself is running on (see
+/// [`CrossTarget`]); `None` installs for the host, as before.
 pub fn install(
     specs: &[RequestedSpec],
     scripts: BTreeMap<String, String>,
     lockfile: String,
     repo_dir: Option<PathBuf>,
     python_context: &PythonContext,
+    target: Option<&CrossTarget>,
 ) -> anyhow::Result<FinderData> {
     let (sprawl_root, sprawl_packages) = install_missing(
         specs,
         &python_context.sys_executable,
         python_context.version,
+        python_context.implementation,
+        &python_context.platform_tags,
+        target,
     )?;
-    let (spec_paths, pth_files) = spec_paths(
+    let (spec_paths, pth_files, namespace_conflicts) = spec_paths(
         sprawl_root.as_ref(),
         &sprawl_packages,
         python_context.version,
+        python_context.implementation,
+        target,
     )?;
 
     // ugly hack: jupyter otherwise tries to locate its kernel.json relative to the python
@@ -534,6 +1154,19 @@ pub fn install(
         env::set_var("JUPYTER_PATH", jupyter_path);
     }
 
+    // Record which packages this project's lockfile resolved to, so `gc` can later tell its
+    // installs are still in use without needing the lockfile passed in explicitly
+    if let Some(repo_dir) = &repo_dir {
+        let lockfile_path = repo_dir.join("poetry.lock");
+        if lockfile_path.is_file() {
+            if let Err(err) =
+                gc::record_project(&monotrail_root()?, &lockfile_path, &sprawl_packages)
+            {
+                warn!("Failed to record project for gc tracking: {:#}", err);
+            }
+        }
+    }
+
     let finder_data = FinderData {
         sprawl_root,
         sprawl_packages,
@@ -542,11 +1175,58 @@ pub fn install(
         pth_files,
         lockfile,
         scripts,
+        namespace_conflicts,
     };
 
     Ok(finder_data)
 }
 
+/// Installs a [`LockManifest`] exactly as pinned, with no poetry invocation and no resolution:
+/// every package's url is fetched (or reused from the monotrail install dir/download cache if
+/// already present) and checked against its recorded sha256 before being unpacked, or, for a
+/// package pinned to a git commit instead of a release, rebuilt from that commit. This is the
+/// counterpart to [`crate::lock_export::export_lock`] - a `monotrail_from_requested`/
+/// `monotrail_from_git` resolution pinned once, reproduced offline from then on.
+pub fn install_from_lock(
+    manifest: &LockManifest,
+    python_context: &PythonContext,
+) -> anyhow::Result<FinderData> {
+    let monotrail_root = monotrail_root()?;
+    let location = InstallLocation::Monotrail {
+        monotrail_root: monotrail_root.clone(),
+        python: python_context.sys_executable.clone(),
+        python_version: python_context.version,
+    };
+    let sprawl_packages = crate::install::install_from_lock(
+        &manifest.packages,
+        &location,
+        &python_context.sys_executable,
+    )?;
+    let sprawl_root = monotrail_root
+        .to_str()
+        .with_context(|| format!("{} path is cursed", env!("CARGO_PKG_NAME")))?
+        .to_string();
+
+    let (spec_paths, pth_files, namespace_conflicts) = spec_paths(
+        sprawl_root.as_ref(),
+        &sprawl_packages,
+        python_context.version,
+        python_context.implementation,
+        None,
+    )?;
+
+    Ok(FinderData {
+        sprawl_root,
+        sprawl_packages,
+        spec_paths,
+        repo_dir: None,
+        pth_files,
+        lockfile: String::new(),
+        scripts: BTreeMap::new(),
+        namespace_conflicts,
+    })
+}
+
 /// In a venv, we would have all scripts collected into .venv/bin/ (on linux and mac). Here,
 /// we not to collect them ourselves
 pub fn find_scripts(
@@ -567,10 +1247,19 @@ pub fn find_scripts(
                 continue;
             }
 
-            scripts.insert(
-                entry.file_name().to_string_lossy().to_string(),
-                entry.path(),
-            );
+            let filename = entry.file_name().to_string_lossy().to_string();
+            // A windows gui_scripts entry point pairs a `<name>.exe` GUI-subsystem stub with a
+            // `<name>-script.pyw` file holding the actual python code; registering the latter
+            // under the plain command name (instead of its on-disk filename) is what lets
+            // `run_command_finder_data` recognize the command as a GUI script and launch it with
+            // `pythonw` instead of `python`. A bare `.pyw` file (no `.exe` stub) is registered the
+            // same way, stripping just the extension.
+            let script_name = filename
+                .strip_suffix("-script.pyw")
+                .or_else(|| filename.strip_suffix(".pyw"))
+                .map(str::to_string)
+                .unwrap_or(filename);
+            scripts.insert(script_name, entry.path());
         }
     }
     trace!(
@@ -581,6 +1270,238 @@ pub fn find_scripts(
     Ok(scripts)
 }
 
+/// Reads each package's `entry_points.txt` (if any) and synthesizes a launcher script for every
+/// `[console_scripts]` entry, so wheels whose scripts were never materialized into `bin`/`Scripts`
+/// (e.g. installed without running a wheel-install script-generation step) are still runnable
+/// through `monotrail command`. Writes the synthesized scripts into `scripts_dir` and returns them
+/// indexed by command name, same shape as [`find_scripts`] -- callers merging the two should let
+/// [`find_scripts`]'s filesystem entries win, since a real installed script can do things (custom
+/// shebangs, compiled launchers) a synthesized shim can't.
+pub fn synthesize_entry_point_scripts(
+    packages: &[InstalledPackage],
+    sprawl_root: &Path,
+    python_version: (u8, u8),
+    scripts_dir: &Path,
+) -> anyhow::Result<BTreeMap<String, PathBuf>> {
+    // A synthesized shim is just a python source file; windows console/gui scripts are compiled
+    // .exe launchers we have no way to produce here, so for now we only synthesize on unix
+    if cfg!(windows) {
+        return Ok(BTreeMap::new());
+    }
+
+    let mut scripts = BTreeMap::new();
+    for package in packages {
+        let site_packages =
+            package.monotrail_site_packages(sprawl_root.to_path_buf(), python_version);
+        let dist_info = match get_dir_content(&site_packages) {
+            Ok(entries) => entries
+                .into_iter()
+                .find(|entry| entry.file_name().to_string_lossy().ends_with(".dist-info")),
+            Err(_) => None,
+        };
+        let Some(dist_info) = dist_info else {
+            continue;
+        };
+
+        let entry_points_file = dist_info.path().join("entry_points.txt");
+        if !entry_points_file.is_file() {
+            continue;
+        }
+        let entry_points = fs::read_to_string(&entry_points_file)
+            .with_context(|| format!("Failed to read {}", entry_points_file.display()))?;
+
+        for (name, (module, attr)) in parse_console_scripts(&entry_points) {
+            // Command names come straight from the wheel's entry_points.txt, so a malicious or
+            // corrupted one could try to escape scripts_dir with e.g. a `..` or an absolute path
+            if !matches!(
+                Path::new(&name).components().collect::<Vec<_>>().as_slice(),
+                [std::path::Component::Normal(_)]
+            ) {
+                warn!(
+                    "Skipping entry point with unsafe script name {:?} in {}",
+                    name,
+                    entry_points_file.display()
+                );
+                continue;
+            }
+
+            // `attr` may be a dotted attribute chain (`Class.method`), which the entry_points
+            // spec allows but `from module import Class.method` can't express; importing the
+            // module itself and accessing `attr` through plain attribute access handles both
+            let shim = format!(
+                "{}\nimport sys; import {} as _monotrail_entry_point_module; sys.exit(_monotrail_entry_point_module.{}())\n",
+                MONOTRAIL_SCRIPT_SHEBANG, module, attr
+            );
+            let script_path = scripts_dir.join(&name);
+            fs::write(&script_path, shim)
+                .with_context(|| format!("Failed to write synthesized script for {}", name))?;
+            scripts.insert(name, script_path);
+        }
+    }
+    Ok(scripts)
+}
+
+/// Parses the `[console_scripts]` section of an `entry_points.txt` file (other sections such as
+/// `[gui_scripts]` aren't commands `monotrail command` should list), returning each entry's
+/// `name -> (module, attribute)`
+pub(crate) fn parse_console_scripts(entry_points_txt: &str) -> BTreeMap<String, (String, String)> {
+    let mut console_scripts = BTreeMap::new();
+    let mut in_console_scripts = false;
+    for line in entry_points_txt.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        if let Some(section) = line
+            .strip_prefix('[')
+            .and_then(|rest| rest.strip_suffix(']'))
+        {
+            in_console_scripts = section == "console_scripts";
+            continue;
+        }
+        if !in_console_scripts {
+            continue;
+        }
+        let Some((name, target)) = line.split_once('=') else {
+            continue;
+        };
+        // Entry points declared with extras (`name = module:attr [extra1,extra2]`) are registered
+        // unconditionally; we don't have enough context here to evaluate the extras marker
+        let target = target.split('[').next().unwrap_or(target).trim();
+        let Some((module, attr)) = target.split_once(':') else {
+            continue;
+        };
+        console_scripts.insert(
+            name.trim().to_string(),
+            (module.trim().to_string(), attr.trim().to_string()),
+        );
+    }
+    console_scripts
+}
+
+/// Materializes a conventional `.venv` layout (`.venv/bin` plus a single, flat site-packages) for
+/// an already resolved `FinderData`, by symlinking instead of copying, so external tools that
+/// expect a real venv (IDEs, some test runners) keep working without monotrail abandoning its
+/// per-package sprawl store.
+///
+/// For module names multiple packages contribute to (see [`spec_paths`]), a plain symlink can
+/// only point at one of them, so we symlink the first contributor as usual and then write a
+/// `<name>-nspkg.pth` that extends `<name>.__path__` with the remaining locations at site
+/// startup, the same trick setuptools' own namespace package `.pth` files use.
+pub fn export_venv(
+    finder_data: &FinderData,
+    python_version: (u8, u8),
+    venv_dir: &Path,
+) -> anyhow::Result<()> {
+    let sprawl_root = Path::new(&finder_data.sprawl_root);
+
+    let bin_dir = venv_dir.join("bin");
+    fs::create_dir_all(&bin_dir)?;
+    for (script_name, script_path) in find_scripts(&finder_data.sprawl_packages, sprawl_root)? {
+        symlink_or_copy(&script_path, &bin_dir.join(&script_name))
+            .with_context(|| format!("Failed to export script {}", script_name))?;
+    }
+
+    let site_packages = venv_dir
+        .join("lib")
+        .join(format!("python{}.{}", python_version.0, python_version.1))
+        .join("site-packages");
+    fs::create_dir_all(&site_packages)?;
+
+    for (name, (module_file, submodule_search_locations)) in &finder_data.spec_paths {
+        if submodule_search_locations.is_empty() {
+            // Single file module, e.g. `six.py`; the real extension is already part of the path
+            let filename = module_file
+                .file_name()
+                .with_context(|| format!("Invalid module path for {}", name))?;
+            symlink_or_copy(module_file, &site_packages.join(filename))
+                .with_context(|| format!("Failed to export module {}", name))?;
+            continue;
+        }
+
+        let primary = &submodule_search_locations[0];
+        symlink_or_copy(primary, &site_packages.join(name))
+            .with_context(|| format!("Failed to export package {}", name))?;
+
+        if let [_, extra @ ..] = submodule_search_locations.as_slice() {
+            if !extra.is_empty() {
+                let extra_locations = extra
+                    .iter()
+                    .map(|location| format!("{:?}", location))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                fs::write(
+                    site_packages.join(format!("{}-nspkg.pth", name)),
+                    format!(
+                        "import {name}; {name}.__path__ = list({name}.__path__) + [{extra_locations}]\n",
+                        name = name,
+                        extra_locations = extra_locations,
+                    ),
+                )?;
+            }
+        }
+    }
+
+    // Replay the .pth files we collected while walking the sprawl store, so their side effects
+    // (e.g. easy-install.pth-style extra search paths) still apply in the exported venv
+    for pth_file in &finder_data.pth_files {
+        let filename = pth_file
+            .file_name()
+            .with_context(|| format!("Invalid .pth path {}", pth_file.display()))?;
+        symlink_or_copy(pth_file, &site_packages.join(filename))
+            .with_context(|| format!("Failed to replay {}", pth_file.display()))?;
+    }
+
+    Ok(())
+}
+
+/// Symlinks `target` at `link`, falling back to a recursive copy on windows, where creating
+/// symlinks requires elevated privileges by default
+fn symlink_or_copy(target: &Path, link: &Path) -> anyhow::Result<()> {
+    #[cfg(unix)]
+    {
+        fs_err::os::unix::fs::symlink(target, link)?;
+    }
+    #[cfg(windows)]
+    {
+        if target.is_dir() {
+            fs::create_dir_all(link)?;
+            for entry in get_dir_content(target)? {
+                symlink_or_copy(&entry.path(), &link.join(entry.file_name()))?;
+            }
+        } else {
+            fs::copy(target, link)?;
+        }
+    }
+    Ok(())
+}
+
+/// Whether `path` is a GUI script (a bare `.pyw` file, or the windows `<name>-script.pyw` wrapper
+/// [`find_scripts`] registers under the plain command name) that should be launched with
+/// `pythonw` instead of `python` so it doesn't pop up a console window
+fn is_gui_script(path: &Path) -> bool {
+    path.extension()
+        .map(|ext| ext.eq_ignore_ascii_case("pyw"))
+        .unwrap_or(false)
+}
+
+/// The windows GUI-subsystem sibling of `sys_executable` (`pythonw.exe`/`pythonw`) that doesn't
+/// open a console window, falling back to `sys_executable` itself if it's missing -- e.g. on
+/// unix, where there is no such distinction
+fn pythonw_executable(sys_executable: &Path) -> PathBuf {
+    let pythonw_name = if cfg!(windows) {
+        "pythonw.exe"
+    } else {
+        "pythonw"
+    };
+    let pythonw = sys_executable.with_file_name(pythonw_name);
+    if pythonw.is_file() {
+        pythonw
+    } else {
+        sys_executable.to_path_buf()
+    }
+}
+
 pub fn is_python_script(executable: &Path) -> anyhow::Result<bool> {
     // Check whether we're launching a monotrail python script
     let mut executable_file = File::open(&executable)
@@ -594,18 +1515,49 @@ pub fn is_python_script(executable: &Path) -> anyhow::Result<bool> {
 }
 
 pub fn run_command_finder_data(
-    script: &str,
+    script: Option<&str>,
     args: &[String],
     python_context: &PythonContext,
     python_home: &Path,
     root: &Path,
     finder_data: &FinderData,
+    cwd: Option<&Path>,
+    extra_env: &[(String, String)],
+    exec_into: Option<&[String]>,
 ) -> anyhow::Result<i32> {
-    let scripts = find_scripts(
+    let mut scripts = find_scripts(
         &finder_data.sprawl_packages,
         Path::new(&finder_data.sprawl_root),
     )
     .context("Failed to collect scripts")?;
+
+    let entry_point_scripts_tmp = TempDir::new().context("Failed to create tempdir")?;
+    let synthesized_scripts = synthesize_entry_point_scripts(
+        &finder_data.sprawl_packages,
+        Path::new(&finder_data.sprawl_root),
+        python_context.version,
+        entry_point_scripts_tmp.path(),
+    )
+    .context("Failed to synthesize entry point scripts")?;
+    for (name, path) in synthesized_scripts {
+        scripts.entry(name).or_insert(path);
+    }
+
+    let script = match script {
+        Some(script) => script,
+        None => {
+            for name in scripts.keys() {
+                let kind = if is_python_script(&scripts[name])? {
+                    "python"
+                } else {
+                    "native"
+                };
+                println!("{} ({})", name, kind);
+            }
+            return Ok(0);
+        }
+    };
+
     let scripts_tmp = TempDir::new().context("Failed to create tempdir")?;
     let sys_executable = prepare_execve_environment(
         &scripts,
@@ -614,17 +1566,42 @@ pub fn run_command_finder_data(
         python_context.version,
     )?;
 
+    // Applied on top of what `prepare_execve_environment` set up, just before we hand off to
+    // the script, so package-specific launcher config (working dir, extra env vars) doesn't have
+    // to be exported globally by the user
+    if let Some(cwd) = cwd {
+        env::set_current_dir(cwd)
+            .with_context(|| format!("Failed to chdir to {}", cwd.display()))?;
+    }
+    for (key, value) in extra_env {
+        env::set_var(key, value);
+    }
+
     let script_path = scripts.get(&script.to_string()).with_context(|| {
-        format_err!(
-            "Couldn't find command {} in installed packages.\nInstalled scripts: {:?}",
-            script,
-            scripts.keys()
-        )
+        if let Some(suggestion) = did_you_mean(script, scripts.keys()) {
+            format_err!(
+                "Couldn't find command {} in installed packages, did you mean `{}`?",
+                script,
+                suggestion
+            )
+        } else {
+            format_err!(
+                "Couldn't find command {} in installed packages.\nInstalled scripts: {:?}",
+                script,
+                scripts.keys()
+            )
+        }
     })?;
-    let exit_code = if is_python_script(&script_path)? {
+    let is_gui = is_gui_script(&script_path);
+    let exit_code = if is_python_script(&script_path)? || is_gui {
         debug!("launching (python) {}", script_path.display());
+        let launch_executable = if is_gui {
+            pythonw_executable(&python_context.sys_executable)
+        } else {
+            python_context.sys_executable.clone()
+        };
         let args: Vec<String> = [
-            python_context.sys_executable.to_string_lossy().to_string(),
+            launch_executable.to_string_lossy().to_string(),
             script_path.to_string_lossy().to_string(),
         ]
         .iter()
@@ -634,6 +1611,7 @@ pub fn run_command_finder_data(
         let exit_code = inject_and_run_python(
             &python_home,
             python_context.version,
+            python_context.implementation,
             &sys_executable,
             &args,
             &serde_json::to_string(&finder_data).unwrap(),
@@ -642,8 +1620,6 @@ pub fn run_command_finder_data(
     } else {
         // Sorry for the to_string_lossy all over the place
         // https://stackoverflow.com/a/38948854/3549270
-        let executable_c_str = CString::new(script_path.to_string_lossy().as_bytes())
-            .context("Failed to convert executable path")?;
         let args_c_string = args
             .iter()
             .map(|arg| {
@@ -651,14 +1627,31 @@ pub fn run_command_finder_data(
             })
             .collect::<anyhow::Result<Vec<CString>>>()?;
 
-        debug!("launching (execv) {}", script_path.display());
-        // We replace the current process with the new process is it's like actually just running
-        // the real thing.
-        // Note the that this may launch a python script, a native binary or anything else
-        unistd::execv(&executable_c_str, &args_c_string).context("Failed to launch process")?;
-        unreachable!()
+        debug!("launching {}", script_path.display());
+        // Note that this may launch a python script, a native binary or anything else. On unix
+        // this replaces the current process, on windows (which has no execve) we spawn a child
+        // and wait for it instead, see `exec_or_spawn`
+        exec_or_spawn(&script_path, &args_c_string)?
+    };
+
+    // execline-style chaining: the script we just ran set up its part of the environment for the
+    // next stage of the pipeline, so on success we exec into that stage's argv instead of
+    // returning, preserving the `sys_executable` shim and `PATH` from `prepare_execve_environment`
+    let exit_code = match exec_into {
+        Some([prog, rest @ ..]) if exit_code == 0 => {
+            let args_c_string = [prog].iter().chain(rest).map(|arg| {
+                CString::new(arg.as_bytes()).context("Failed to convert exec-into argument")
+            })
+            .collect::<anyhow::Result<Vec<CString>>>()?;
+            debug!("exec-into {}", prog);
+            exec_or_spawn(Path::new(prog), &args_c_string)
+                .with_context(|| format!("Failed to exec into {}", prog))?
+        }
+        _ => exit_code,
     };
-    // just to assert it lives until here
+
+    // just to assert they live until here
     drop(scripts_tmp);
+    drop(entry_point_scripts_tmp);
     Ok(exit_code)
 }
@@ -0,0 +1,145 @@
+//! Exports a resolved [`FinderData`] as a set of fixed-output Nix derivations, so a monotrail
+//! environment can be pinned into an offline-buildable, reproducible artifact. This reuses
+//! monotrail's own resolution (the embedded `poetry.lock` and the packages it actually installed)
+//! instead of making poetry2nix-style tooling re-parse the lockfile itself.
+
+use crate::install::InstalledPackage;
+use crate::monotrail::FinderData;
+use crate::package_index::search_release;
+use crate::poetry_integration::poetry_lock::PoetryLock;
+use anyhow::{bail, Context, Result};
+
+/// What we need to build a single package's fixed-output `fetchurl` derivation
+///
+/// Also reused by [`crate::lock_export`], which pins the same name/url/sha256 down into a
+/// monotrail-native manifest instead of a Nix expression
+pub(crate) struct NixSource {
+    pub(crate) name: String,
+    pub(crate) unique_version: String,
+    pub(crate) tag: String,
+    pub(crate) url: String,
+    pub(crate) sha256: String,
+}
+
+/// Looks up the pypi download url for `package`'s exact tag and the matching hash recorded in
+/// `lockfile`
+pub(crate) fn resolve_source(
+    package: &InstalledPackage,
+    lockfile: &PoetryLock,
+) -> Result<NixSource> {
+    let compatible_tags = match package.tag.split('-').collect::<Vec<_>>()[..] {
+        [python_tag, abi_tag, platform_tag] => {
+            vec![(
+                python_tag.to_string(),
+                abi_tag.to_string(),
+                platform_tag.to_string(),
+            )]
+        }
+        _ => bail!("Invalid tag {} for {}", package.tag, package.name),
+    };
+    let (release, _distribution_type, _version, _credentials) = search_release(
+        &package.name,
+        Some(package.unique_version.clone()),
+        &compatible_tags,
+        // Pinned to an exact, already-resolved version, so there's no "pick the newest
+        // compatible with the running interpreter" decision left to make here
+        None,
+    )
+    .with_context(|| {
+        format!(
+            "Failed to find a pypi release for {} {} {}",
+            package.name, package.unique_version, package.tag
+        )
+    })?;
+
+    let hashed_files = lockfile
+        .get_filenames(&package.name)
+        .with_context(|| format!("{} isn't in the lockfile", package.name))?;
+    let sha256 = hashed_files
+        .iter()
+        .find(|hashed_file| hashed_file.file == release.filename)
+        .with_context(|| format!("No hash recorded for {} in the lockfile", release.filename))?
+        .hash
+        .strip_prefix("sha256:")
+        .context("Only sha256 lockfile hashes are supported")?
+        .to_string();
+
+    Ok(NixSource {
+        name: package.name.clone(),
+        unique_version: package.unique_version.clone(),
+        tag: package.tag.clone(),
+        url: release.url,
+        sha256,
+    })
+}
+
+/// Nix identifiers can't contain most of the characters that show up in versions or wheel tags
+fn attr_name(source: &NixSource) -> String {
+    let sanitize = |s: &str| {
+        s.to_lowercase()
+            .replace(|char: char| !char.is_alphanumeric(), "_")
+    };
+    format!(
+        "{}_{}_{}",
+        sanitize(&source.name),
+        sanitize(&source.unique_version),
+        sanitize(&source.tag)
+    )
+}
+
+/// Turns `finder_data`'s `sprawl_packages` and embedded `lockfile` into a Nix expression
+/// containing one fixed-output derivation per package plus a top-level derivation that assembles
+/// them into the same `name/unique_version/tag` sprawl layout monotrail expects at runtime (see
+/// [`InstalledPackage::monotrail_location`])
+pub fn export_nix(finder_data: &FinderData) -> Result<String> {
+    let lockfile = PoetryLock::from_str(&finder_data.lockfile)
+        .context("Failed to parse the embedded lockfile")?;
+
+    let sources = finder_data
+        .sprawl_packages
+        .iter()
+        .map(|package| resolve_source(package, &lockfile))
+        .collect::<Result<Vec<NixSource>>>()?;
+
+    let mut nix = String::new();
+    nix.push_str("# Generated by monotrail's nix exporter, do not edit by hand\n");
+    nix.push_str("{ pkgs ? import <nixpkgs> { } }:\n\nlet\n  packages = {\n");
+    for source in &sources {
+        nix.push_str(&format!(
+            "    {attr} = pkgs.fetchurl {{ url = \"{url}\"; sha256 = \"{sha256}\"; }};\n",
+            attr = attr_name(source),
+            url = source.url,
+            sha256 = source.sha256,
+        ));
+    }
+    nix.push_str("  };\nin\npkgs.runCommand \"monotrail-sprawl\" { } ''\n");
+    for source in &sources {
+        let unpack_command = if source.url.ends_with(".whl") {
+            format!(
+                "unzip -q \"${{packages.{attr}}}\" -d $out/{name}/{version}/{tag}",
+                attr = attr_name(source),
+                name = source.name,
+                version = source.unique_version,
+                tag = source.tag,
+            )
+        } else {
+            format!(
+                "tar xzf \"${{packages.{attr}}}\" -C $out/{name}/{version}/{tag}",
+                attr = attr_name(source),
+                name = source.name,
+                version = source.unique_version,
+                tag = source.tag,
+            )
+        };
+        nix.push_str(&format!(
+            "  mkdir -p $out/{name}/{version}/{tag}\n  {unpack_command}\n",
+            name = source.name,
+            version = source.unique_version,
+            tag = source.tag,
+            unpack_command = unpack_command,
+        ));
+    }
+    nix.push_str("''\n");
+
+    Ok(nix)
+}
@@ -1,12 +1,19 @@
 //! Basic downloading from pypi
 
-use crate::spec::DistributionType;
+use crate::spec::{is_sdist_filename, version_from_sdist_filename, DistributionType};
 use crate::wheel_tags::WheelFilename;
 use crate::WheelInstallerError;
 use anyhow::{bail, Context, Result};
+use data_encoding::BASE64;
 use fs_err as fs;
+use pep440_rs::{Version as Pep440Version, VersionSpecifiers};
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use regex::Regex;
 use serde::Deserialize;
+use sha2::{Digest, Sha256};
 use std::collections::HashMap;
+use std::env;
+use std::io::Read;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::{io, result};
@@ -27,6 +34,9 @@ pub struct PypiRelease {
     pub python_version: String,
     pub size: usize,
     pub url: String,
+    /// e.g. `{"md5": "...", "sha256": "..."}`
+    #[serde(default)]
+    pub digests: HashMap<String, String>,
 }
 
 /// https://github.com/pypa/warehouse/blob/4d4c7940063db51e8ee03de78afdff6d4e9140ae/warehouse/filters.py#L33-L41
@@ -43,51 +53,625 @@ pub enum PackageType {
     Sdist,
 }
 
-fn matching_package_for_version(
-    _name: &str,
+/// A configured package index (PyPI itself or a private/internal registry), speaking the PEP
+/// 503/691 Simple Repository API that both warehouse and third-party indexes (devpi, Artifactory,
+/// GitLab) implement, with the basic-auth credentials to use against it, if any
+#[derive(Debug, Clone)]
+pub(crate) struct PackageIndex {
+    name: String,
+    /// Base url, no trailing slash, already including the `/simple` (or equivalent) path prefix,
+    /// so `{url}/{normalized name}/` is the project's Simple API page
+    url: String,
+    credentials: Option<(String, String)>,
+}
+
+/// The name the implicit default PyPI index is known by, both as the `[indexes.pypi]` table name
+/// in `indexes.toml` and for `MONOTRAIL_HTTP_BASIC_PYPI_USERNAME`/`_PASSWORD`
+const DEFAULT_INDEX_NAME: &str = "pypi";
+
+/// `<data_local_dir>/indexes.toml`'s `[indexes.<name>]` tables
+///
+/// ```toml
+/// [indexes.my-internal]
+/// url = "https://example.com/simple"
+/// priority = 0
+/// ```
+#[derive(Deserialize, Debug, Default)]
+struct IndexesConfig {
+    #[serde(default)]
+    indexes: HashMap<String, IndexConfig>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct IndexConfig {
+    url: String,
+    /// Indexes are tried lowest-priority-first; the order among indexes with equal priority is
+    /// not defined. The implicit default pypi index is tried last unless explicitly listed
+    #[serde(default)]
+    priority: i32,
+    username: Option<String>,
+    password: Option<String>,
+}
+
+/// The real PyPI Simple API, except in tests, where it's swapped for the local mockito server so
+/// `search_release`'s tests don't hit the network
+fn default_index_url() -> String {
+    #[cfg(not(test))]
+    let url = "https://pypi.org/simple".to_string();
+    // keeps the `/simple/<name>/` path tests already mock
+    #[cfg(test)]
+    let url = format!("{}/simple", mockito::server_url());
+    url
+}
+
+/// Uppercases `name` and replaces `-` with `_`, so e.g. `test-pypi` becomes the
+/// `MONOTRAIL_HTTP_BASIC_TEST_PYPI_USERNAME` environment variable family
+fn env_var_name(index: &str) -> String {
+    index.to_uppercase().replace('-', "_")
+}
+
+/// Resolves the basic-auth credentials for `name`: `MONOTRAIL_HTTP_BASIC_<NAME>_USERNAME`/
+/// `_PASSWORD` take priority over whatever `indexes.toml` configured for it
+fn resolve_credentials(
+    name: &str,
+    username: Option<String>,
+    password: Option<String>,
+) -> Result<Option<(String, String)>> {
+    let env_name = env_var_name(name);
+    let username = env::var(format!("MONOTRAIL_HTTP_BASIC_{}_USERNAME", env_name))
+        .ok()
+        .or(username);
+    let password = env::var(format!("MONOTRAIL_HTTP_BASIC_{}_PASSWORD", env_name))
+        .ok()
+        .or(password);
+    match (username, password) {
+        (Some(username), Some(password)) => Ok(Some((username, password))),
+        (None, None) => Ok(None),
+        (username, password) => bail!(
+            "Index \"{}\" has a username without a password or vice versa ({:?}/{:?})",
+            name,
+            username,
+            password
+        ),
+    }
+}
+
+/// Reads `indexes.toml` (if any) and returns the configured indexes in priority order, lowest
+/// first, with the implicit default pypi index appended last unless `indexes.toml` already
+/// declares one named `pypi`
+pub(crate) fn configured_indexes() -> Result<Vec<PackageIndex>> {
+    let config_path = crate::utils::data_local_dir()?.join("indexes.toml");
+    let configured = if config_path.is_file() {
+        let contents = fs::read_to_string(&config_path)
+            .with_context(|| format!("Failed to read {}", config_path.display()))?;
+        let config: IndexesConfig = toml::from_str(&contents)
+            .with_context(|| format!("Invalid {}", config_path.display()))?;
+        config.indexes
+    } else {
+        HashMap::new()
+    };
+
+    let mut indexes = configured
+        .into_iter()
+        .map(|(name, config)| {
+            let credentials = resolve_credentials(&name, config.username, config.password)?;
+            Ok((
+                config.priority,
+                PackageIndex {
+                    name: name.clone(),
+                    url: config.url.trim_end_matches('/').to_string(),
+                    credentials,
+                },
+            ))
+        })
+        .collect::<Result<Vec<(i32, PackageIndex)>>>()?;
+
+    if !indexes
+        .iter()
+        .any(|(_, index)| index.name == DEFAULT_INDEX_NAME)
+    {
+        let credentials = resolve_credentials(DEFAULT_INDEX_NAME, None, None)?;
+        indexes.push((
+            i32::MAX,
+            PackageIndex {
+                name: DEFAULT_INDEX_NAME.to_string(),
+                url: default_index_url(),
+                credentials,
+            },
+        ));
+    }
+
+    indexes.sort_by_key(|(priority, _)| *priority);
+    Ok(indexes.into_iter().map(|(_, index)| index).collect())
+}
+
+/// A single file listed for a project on a Simple Repository API index: one `files[]` entry of
+/// a PEP 691 JSON response, or one `<a>` of a PEP 503 HTML response
+#[derive(Deserialize, Clone, Debug)]
+#[allow(dead_code)]
+struct SimpleFile {
+    filename: String,
+    url: String,
+    #[serde(default)]
+    hashes: HashMap<String, String>,
+    /// The PEP 440 version specifier the project requires, if the index declared one. Checked by
+    /// [`satisfies_requires_python`] against the running interpreter when picking the newest
+    /// release; an exact, explicitly-pinned version skips this check the same way it skips the
+    /// yanked check, per the same PEP 592 reasoning
+    #[serde(default, rename = "requires-python")]
+    requires_python: Option<String>,
+    #[serde(default)]
+    yanked: Yanked,
+}
+
+/// PEP 691's `yanked` is either absent/`false` (not yanked) or `true`/a string reason (yanked);
+/// PEP 503's HTML form only ever has the `data-yanked` attribute present or absent, which we map
+/// onto the same `Bool` variant
+#[derive(Deserialize, Clone, Debug)]
+#[serde(untagged)]
+enum Yanked {
+    Bool(bool),
+    Reason(String),
+}
+
+impl Default for Yanked {
+    fn default() -> Self {
+        Yanked::Bool(false)
+    }
+}
+
+impl Yanked {
+    fn is_yanked(&self) -> bool {
+        match self {
+            Yanked::Bool(yanked) => *yanked,
+            Yanked::Reason(_) => true,
+        }
+    }
+}
+
+/// The `application/vnd.pypi.simple.v1+json` (PEP 691) response body, stripped down to what we
+/// use
+#[derive(Deserialize, Clone, Debug)]
+struct SimpleIndexJson {
+    files: Vec<SimpleFile>,
+}
+
+/// PEP 503 name normalization: lowercase, with any run of `-`, `_` or `.` collapsed to a single
+/// `-`, so e.g. `Foo__Bar.Baz` and `foo-bar-baz` resolve to the same index page
+fn normalize_name(name: &str) -> String {
+    let lowercase = name.to_lowercase();
+    let mut normalized = String::with_capacity(lowercase.len());
+    let mut last_was_separator = false;
+    for char in lowercase.chars() {
+        if matches!(char, '-' | '_' | '.') {
+            if !last_was_separator {
+                normalized.push('-');
+            }
+            last_was_separator = true;
+        } else {
+            normalized.push(char);
+            last_was_separator = false;
+        }
+    }
+    normalized
+}
+
+/// Whether [`fetch_simple_project`] should have the caller fall through to the next configured
+/// index, or give up entirely
+enum IndexQueryError {
+    /// The index doesn't have this project at all (HTTP 404 on its Simple API page)
+    NotFound,
+    Other(anyhow::Error),
+}
+
+/// Queries a single index's Simple Repository API (PEP 503/691) page for `name`, preferring the
+/// PEP 691 JSON form but falling back to parsing the PEP 503 HTML form for indexes that only
+/// serve that
+fn fetch_simple_project(
+    index: &PackageIndex,
+    name: &str,
+) -> result::Result<Vec<SimpleFile>, IndexQueryError> {
+    let url = format!("{}/{}/", index.url, normalize_name(name));
+    let mut request = ureq::get(&url)
+        .set("User-Agent", "virtual-sprawl (konstin@mailbox.org)")
+        .set(
+            "Accept",
+            "application/vnd.pypi.simple.v1+json, text/html;q=0.01",
+        );
+    if let Some((username, password)) = &index.credentials {
+        let encoded = BASE64.encode(format!("{}:{}", username, password).as_bytes());
+        request = request.set("Authorization", &format!("Basic {}", encoded));
+    }
+    match request.call() {
+        Ok(response) => {
+            let content_type = response
+                .header("content-type")
+                .unwrap_or_default()
+                .to_lowercase();
+            let body = response.into_string().map_err(|err| {
+                IndexQueryError::Other(
+                    anyhow::Error::new(err).context("Invalid api response from pypi"),
+                )
+            })?;
+            // Sniff the body itself rather than trusting the content-type header: some indexes
+            // sit behind proxies that serve the right PEP 691 JSON (or PEP 503 HTML) body under a
+            // generic or missing content-type, and we'd rather parse that correctly than hard-fail
+            // a working index over a header mismatch
+            let is_json = content_type.contains("json") || body.trim_start().starts_with('{');
+            let files = if is_json {
+                serde_json::from_str::<SimpleIndexJson>(&body)
+                    .map(|index_json| index_json.files)
+                    .map_err(|err| {
+                        IndexQueryError::Other(
+                            anyhow::Error::new(err).context("Invalid api response from pypi"),
+                        )
+                    })?
+            } else {
+                parse_simple_html(&body)
+            };
+            // PEP 503/691 both allow hrefs relative to the page url, not just the absolute urls
+            // pypi.org happens to always return
+            Ok(files
+                .into_iter()
+                .map(|file| SimpleFile {
+                    url: resolve_simple_url(&url, &file.url),
+                    ..file
+                })
+                .collect())
+        }
+        Err(ureq::Error::Status(404, _)) => Err(IndexQueryError::NotFound),
+        Err(err) => Err(IndexQueryError::Other(anyhow::Error::new(err).context(
+            "Failed to contact pypi. Is your internet connection working?",
+        ))),
+    }
+}
+
+/// Resolves a Simple API file's `href`/`url` against the project page's own url, since PEP 503/691
+/// both explicitly allow a relative reference there, not just the absolute urls pypi.org returns.
+/// Covers the schemes actually seen in the wild (absolute, protocol-relative, root-relative, and
+/// plain relative paths with `.`/`..` segments) rather than implementing RFC 3986 in full
+fn resolve_simple_url(page_url: &str, href: &str) -> String {
+    if href.contains("://") {
+        return href.to_string();
+    }
+    let (scheme, after_scheme) = page_url.split_once("://").unwrap_or(("https", page_url));
+    let authority = after_scheme.split('/').next().unwrap_or(after_scheme);
+    if let Some(rest) = href.strip_prefix("//") {
+        return format!("{}://{}", scheme, rest);
+    }
+    if let Some(rest) = href.strip_prefix('/') {
+        return format!("{}://{}/{}", scheme, authority, rest);
+    }
+    // Plain relative path: resolve against the page's own directory. The page url always ends in
+    // `/` (it's `{index.url}/{normalized name}/`), so trimming that trailing slash leaves the path
+    // segments already in the right starting state for `..` to pop the page's own name off first
+    let mut segments: Vec<&str> = page_url.trim_end_matches('/').split('/').collect();
+    for part in href.split('/') {
+        match part {
+            "." | "" => {}
+            ".." => {
+                segments.pop();
+            }
+            _ => segments.push(part),
+        }
+    }
+    segments.join("/")
+}
+
+/// Parses the PEP 503 HTML form: one `<a href="...">filename</a>` per file, optionally carrying
+/// `data-requires-python` and `data-yanked` attributes. We don't parse `data-dist-info-metadata`
+/// since nothing downstream needs the separately-hosted metadata file yet
+fn parse_simple_html(html: &str) -> Vec<SimpleFile> {
+    // The attribute group matches a whole quoted string as one alternative before falling back to
+    // "any non->" char, so a yanked reason or other attribute value containing a literal `>`
+    // doesn't truncate the match early
+    let anchor = Regex::new(r#"(?is)<a\s+((?:"[^"]*"|'[^']*'|[^>])*)>(.*?)</a>"#).unwrap();
+    // Compiled once per page instead of once per attribute per anchor, since a popular package's
+    // Simple API page can list hundreds of files
+    let href_re = attr_regex("href");
+    let requires_python_re = attr_regex("data-requires-python");
+    let yanked_re = attr_regex("data-yanked");
+    anchor
+        .captures_iter(html)
+        .filter_map(|capture| {
+            let attrs = &capture[1];
+            let url = html_attr(&href_re, attrs)?;
+            let (url, hashes) = split_url_fragment_hash(&html_unescape(&url));
+            Some(SimpleFile {
+                filename: html_unescape(capture[2].trim()),
+                url,
+                hashes,
+                requires_python: html_attr(&requires_python_re, attrs)
+                    .map(|value| html_unescape(&value)),
+                yanked: Yanked::Bool(html_attr(&yanked_re, attrs).is_some()),
+            })
+        })
+        .collect()
+}
+
+/// PEP 503 carries a file's hash as a `#<algorithm>=<hex digest>` fragment on its href instead of a
+/// dedicated field, e.g. `.../cffi-1.15.0.tar.gz#sha256=abcd...`. Splits that fragment off into a
+/// `hashes` map in the same shape the PEP 691 JSON form uses, so downstream code doesn't need to
+/// care which form of the Simple API an index served
+fn split_url_fragment_hash(url: &str) -> (String, HashMap<String, String>) {
+    match url.split_once('#') {
+        Some((base, fragment)) => {
+            let mut hashes = HashMap::new();
+            if let Some((algorithm, digest)) = fragment.split_once('=') {
+                hashes.insert(algorithm.to_string(), digest.to_string());
+            }
+            (base.to_string(), hashes)
+        }
+        None => (url.to_string(), HashMap::new()),
+    }
+}
+
+/// Builds the regex [`html_attr`] uses to pull `name="value"` (or `name='value'`) out of a tag's
+/// attribute string
+fn attr_regex(name: &str) -> Regex {
+    Regex::new(&format!(
+        r#"(?i){}\s*=\s*"([^"]*)"|{}\s*=\s*'([^']*)'"#,
+        name, name
+    ))
+    .unwrap()
+}
+
+/// Pulls the value `re` (built by [`attr_regex`]) matches out of a tag's attribute string
+fn html_attr(re: &Regex, attrs: &str) -> Option<String> {
+    let capture = re.captures(attrs)?;
+    Some(
+        capture
+            .get(1)
+            .or_else(|| capture.get(2))?
+            .as_str()
+            .to_string(),
+    )
+}
+
+/// Unescapes the handful of HTML entities PEP 503 listings actually use
+fn html_unescape(text: &str) -> String {
+    // A single left-to-right pass so a literal `&amp;lt;` (an author's own escaped `&lt;`) decodes
+    // to `&lt;`, not `<` -- chaining sequential `.replace()` calls would feed the output of the
+    // `&amp;` pass back into the `&lt;` pass and double-unescape it
+    const ENTITIES: &[(&str, &str)] = &[
+        ("&amp;", "&"),
+        ("&lt;", "<"),
+        ("&gt;", ">"),
+        ("&quot;", "\""),
+        ("&#39;", "'"),
+    ];
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(amp_pos) = rest.find('&') {
+        result.push_str(&rest[..amp_pos]);
+        rest = &rest[amp_pos..];
+        match ENTITIES.iter().find(|(entity, _)| rest.starts_with(entity)) {
+            Some((entity, replacement)) => {
+                result.push_str(replacement);
+                rest = &rest[entity.len()..];
+            }
+            None => {
+                result.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+/// Converts a matched [`SimpleFile`] into the [`PypiRelease`] shape the rest of the crate already
+/// consumes, so callers of [`search_release`] didn't need to change when the index abstraction
+/// switched protocols
+fn simple_file_to_release(file: SimpleFile, distribution_type: &DistributionType) -> PypiRelease {
+    PypiRelease {
+        filename: file.filename,
+        packagetype: match distribution_type {
+            DistributionType::Wheel => PackageType::BdistWheel,
+            DistributionType::SourceDistribution => PackageType::Sdist,
+        },
+        python_version: String::new(),
+        size: 0,
+        url: file.url,
+        digests: file.hashes,
+    }
+}
+
+/// Picks a compatible release for `version` (or, if `None`, the newest version with one) out of
+/// a single index's Simple API file listing. `None` means this index has files but nothing that
+/// matches, not that something went wrong. Files whose filename we can't make sense of (neither a
+/// `.whl` nor a recognized sdist extension) are silently skipped instead of erroring the whole
+/// lookup, since real-world Simple API pages routinely list stray non-distribution files.
+///
+/// `python_version`, the running interpreter's `(major, minor)`, is used to filter out files
+/// whose `requires-python` marker excludes it; callers that don't have a running interpreter in
+/// scope (e.g. looking up a release that's already pinned to an exact version elsewhere) can pass
+/// `None` to skip that filter entirely.
+fn pick_simple_release(
+    files: &[SimpleFile],
+    version: Option<&str>,
+    compatible_tags: &[(String, String, String)],
+    python_version: Option<(u8, u8)>,
+) -> Option<(SimpleFile, DistributionType, String)> {
+    let mut by_version: HashMap<String, Vec<&SimpleFile>> = HashMap::new();
+    for file in files {
+        let file_version = if file.filename.ends_with(".whl") {
+            WheelFilename::from_str(&file.filename)
+                .ok()
+                .map(|wheel| wheel.version)
+        } else if is_sdist_filename(&file.filename) {
+            version_from_sdist_filename(&file.filename)
+        } else {
+            None
+        };
+        if let Some(file_version) = file_version {
+            by_version.entry(file_version).or_default().push(file);
+        }
+    }
+
+    if let Some(version) = version {
+        // Per PEP 592, an exact, explicitly-pinned version is still installable when yanked --
+        // only the "pick the newest" path below skips yanked releases
+        matching_simple_file_for_version(compatible_tags, version, by_version.get(version)?)
+    } else {
+        let interpreter = python_version
+            .and_then(|(major, minor)| Pep440Version::from_str(&format!("{major}.{minor}")).ok());
+
+        // Highest version first; unparsable versions (not valid PEP 440) are dropped instead of
+        // erroring the whole lookup, same spirit as the filename-parsing skip above
+        let mut versions: Vec<(&String, Pep440Version)> = by_version
+            .keys()
+            .filter_map(|version| Pep440Version::from_str(version).ok().map(|v| (version, v)))
+            .collect();
+        versions.sort_by(|(_, a), (_, b)| b.cmp(a));
+
+        let candidates_for = |version: &str| -> Vec<&SimpleFile> {
+            by_version[version]
+                .iter()
+                .filter(|file| !file.yanked.is_yanked())
+                .filter(|file| {
+                    satisfies_requires_python(file.requires_python.as_deref(), interpreter.as_ref())
+                })
+                .copied()
+                .collect()
+        };
+
+        // Final releases take priority over pre-releases; a pre-release is only picked if no
+        // final release has a compatible, non-yanked, requires-python-satisfying file at all. A
+        // user who actually wants a pre-release pins its exact version instead, which takes the
+        // `Some(version)` branch above and never reaches this fallback.
+        let (finals, prereleases): (Vec<_>, Vec<_>) =
+            versions.iter().partition(|(_, v)| !v.is_prerelease());
+        finals
+            .into_iter()
+            .chain(prereleases)
+            .find_map(|(version, _)| {
+                matching_simple_file_for_version(compatible_tags, version, &candidates_for(version))
+            })
+    }
+}
+
+/// Whether a Simple API file's `requires-python` marker (a PEP 440 version specifier set, e.g.
+/// `">=3.7,<4"`) allows `interpreter`. A missing marker, or not having an interpreter version to
+/// check against in the first place, is treated as satisfied; a marker we fail to parse is also
+/// treated as satisfied rather than wrongly dropping an otherwise-good release over a parsing gap
+fn satisfies_requires_python(
+    requires_python: Option<&str>,
+    interpreter: Option<&Pep440Version>,
+) -> bool {
+    let (requires_python, interpreter) = match (requires_python, interpreter) {
+        (Some(requires_python), Some(interpreter)) => (requires_python, interpreter),
+        _ => return true,
+    };
+    match VersionSpecifiers::from_str(requires_python) {
+        Ok(specifiers) => specifiers.contains(interpreter),
+        Err(_) => true,
+    }
+}
+
+fn matching_simple_file_for_version(
     compatible_tags: &[(String, String, String)],
     version: &str,
-    pypi_releases: &[PypiRelease],
-) -> Result<Option<(PypiRelease, DistributionType, String)>> {
-    let wheel_releases = pypi_releases
+    candidates: &[&SimpleFile],
+) -> Option<(SimpleFile, DistributionType, String)> {
+    let wheel_candidates: Vec<(WheelFilename, &SimpleFile)> = candidates
         .iter()
-        .filter(|release| release.packagetype == PackageType::BdistWheel)
-        .map(|release| Ok((WheelFilename::from_str(&release.filename)?, release)))
-        .collect::<Result<Vec<(WheelFilename, &PypiRelease)>, WheelInstallerError>>()?;
-    if let Some((_, picked_wheel)) = wheel_releases
+        .filter_map(|file| {
+            WheelFilename::from_str(&file.filename)
+                .ok()
+                .map(|wheel| (wheel, *file))
+        })
+        .collect();
+    if let Some((_, picked)) = wheel_candidates
         .iter()
-        .find(|(filename, _)| filename.is_compatible(compatible_tags))
+        .find(|(wheel, _)| wheel.is_compatible(compatible_tags))
     {
-        return Ok(Some((
-            (*picked_wheel).clone(),
+        return Some((
+            (*picked).clone(),
             DistributionType::Wheel,
             version.to_string(),
-        )));
+        ));
     }
 
-    if let Some(sdist_release) = pypi_releases
+    candidates
         .iter()
-        .find(|release| release.packagetype == PackageType::Sdist)
-    {
-        Ok(Some((
-            sdist_release.clone(),
-            DistributionType::SourceDistribution,
-            version.to_string(),
-        )))
-    } else {
-        Ok(None)
-    }
+        .find(|file| is_sdist_filename(&file.filename))
+        .map(|file| {
+            (
+                (*file).clone(),
+                DistributionType::SourceDistribution,
+                version.to_string(),
+            )
+        })
 }
 
-/// Finds a matching wheel from pages like https://pypi.org/pypi/tqdm/json
+/// Finds a matching wheel, trying each [`configured_indexes`] entry in priority order and
+/// falling through to the next whenever one doesn't have the project at all (404) or doesn't
+/// have anything that matches. Returns the credentials of whichever index the release came from,
+/// alongside it, so the caller can use them to download the file too
 ///
-/// https://warehouse.pypa.io/api-reference/json.html
+/// `python_version`, the running interpreter's `(major, minor)`, is forwarded to
+/// [`pick_simple_release`] to filter out releases whose `requires-python` marker excludes it when
+/// no exact `version` was requested; pass `None` when there's no running interpreter to check
+/// against (e.g. a lookup that's already pinned to an exact version).
+///
+/// https://packaging.python.org/en/latest/specifications/simple-repository-api/
 pub fn search_release(
     name: &str,
     version: Option<String>,
     compatible_tags: &[(String, String, String)],
-) -> Result<(PypiRelease, DistributionType, String)> {
+    python_version: Option<(u8, u8)>,
+) -> Result<(
+    PypiRelease,
+    DistributionType,
+    String,
+    Option<(String, String)>,
+)> {
     debug!("Getting Releases");
+    let indexes = configured_indexes()?;
+    for index in &indexes {
+        let files = match fetch_simple_project(index, name) {
+            Ok(files) => files,
+            Err(IndexQueryError::NotFound) => {
+                debug!(
+                    "{} not found on index {}, trying the next one",
+                    name, index.name
+                );
+                continue;
+            }
+            Err(IndexQueryError::Other(err)) => return Err(err),
+        };
+        if let Some((file, distribution_type, version)) =
+            pick_simple_release(&files, version.as_deref(), compatible_tags, python_version)
+        {
+            let release = simple_file_to_release(file, &distribution_type);
+            return Ok((
+                release,
+                distribution_type,
+                version,
+                index.credentials.clone(),
+            ));
+        }
+        debug!(
+            "{} has no compatible release on index {}, trying the next one",
+            name, index.name
+        );
+    }
+    bail!(
+        "No matching release for {} found on any configured index ({})",
+        name,
+        indexes
+            .iter()
+            .map(|index| index.name.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+}
+
+/// Raw `releases` map from https://pypi.org/pypi/{name}/json, for callers that want to look at
+/// every version themselves instead of picking one compatible release (e.g. the native resolver
+/// in [`crate::poetry_integration::resolve`])
+pub(crate) fn list_releases(name: &str) -> Result<HashMap<String, Vec<PypiRelease>>> {
     let url = format!("https://pypi.org/pypi/{}/json", name);
     let pypi_project: PypiProject = ureq::get(&url)
         .set("User-Agent", "virtual-sprawl (konstin@mailbox.org)")
@@ -95,53 +679,368 @@ pub fn search_release(
         .context("Failed to contact pypi. Is your internet connection working?")?
         .into_json()
         .context("Invalid api response from pypi")?;
-    if let Some(version) = version {
-        let pypi_releases = pypi_project
-            .releases
-            .get(&version)
-            .with_context(|| format!("{} {} not found on pypi", name, version))?;
-
-        matching_package_for_version(name, compatible_tags, &version, pypi_releases)?
-            .with_context(|| format!("Couldn't find compatible release for {} {}", name, version))
-    } else {
-        let mut releases = pypi_project.releases.iter().collect::<Vec<_>>();
-        // TODO: Actually parse versions
-        releases.sort_by_key(|&(key, _)| key);
-        releases.reverse();
-        for (version, release) in releases {
-            if let Some(matching_package) =
-                matching_package_for_version(name, compatible_tags, version, release)?
-            {
-                return Ok(matching_package);
-            }
-        }
-        bail!("No matching version found for {}", name);
-    }
+    Ok(pypi_project.releases)
 }
 
-/// Just wraps ureq
+/// Wraps ureq. `credentials`, if given, are sent as an HTTP basic auth header, for downloading
+/// from the same private/internal index `url` was resolved from. If `expected_hash` (a
+/// `sha256:<hex digest>` string, the same format [`crate::install::check_file_hash`] uses) is
+/// given, the download is hashed as it streams to the temp file and checked against it before the
+/// temp file is persisted to `target_file`, so a corrupted mirror or compromised index can't get a
+/// mismatched file into the cache in the first place
 pub(crate) fn download_distribution(
     url: &str,
     target_dir: &Path,
     target_file: &Path,
+    credentials: Option<&(String, String)>,
+    expected_hash: Option<&str>,
 ) -> Result<()> {
     debug!("Downloading wheel to {}", target_file.display());
     fs::create_dir_all(&target_dir).context("Couldn't create cache dir")?;
     // temp file so we don't clash with other processes running in parallel
     let mut temp_file = tempfile::NamedTempFile::new_in(&target_dir)
         .context("Couldn't create file for download")?;
-    let request_for_file = ureq::get(url)
-        .set("User-Agent", "virtual-sprawl (konstin@mailbox.org)")
-        .call()
-        .context("Error during pypi request")?;
-    io::copy(&mut request_for_file.into_reader(), &mut temp_file)
-        .context("Failed to download wheel from pypi")?;
+    let mut request = ureq::get(url).set("User-Agent", "virtual-sprawl (konstin@mailbox.org)");
+    if let Some((username, password)) = credentials {
+        let encoded = BASE64.encode(format!("{}:{}", username, password).as_bytes());
+        request = request.set("Authorization", &format!("Basic {}", encoded));
+    }
+    let request_for_file = request.call().context("Error during pypi request")?;
+    let mut hasher = Sha256::new();
+    io::copy(
+        &mut request_for_file.into_reader(),
+        &mut HashingWriter {
+            inner: &mut temp_file,
+            hasher: &mut hasher,
+        },
+    )
+    .context("Failed to download wheel from pypi")?;
+    if let Some(expected_hash) = expected_hash {
+        let expected_digest = expected_hash.strip_prefix("sha256:").with_context(|| {
+            format!(
+                "Unsupported hash algorithm (only sha256 is supported): {}",
+                expected_hash
+            )
+        })?;
+        let actual_digest = format!("{:x}", hasher.finalize());
+        if actual_digest != expected_digest {
+            bail!(
+                "Checksum mismatch downloading {}: expected sha256:{} but the download hashes to \
+                 sha256:{}",
+                url,
+                expected_digest,
+                actual_digest
+            );
+        }
+    }
     temp_file
         .persist(&target_file)
         .context("Failed to moved wheel to target position")?;
     Ok(())
 }
 
+/// Forwards every write to `inner` while also feeding the same bytes to `hasher`, so
+/// [`download_distribution`] can verify a download's integrity as it streams to disk instead of
+/// reading the whole file back afterwards
+struct HashingWriter<'a, W> {
+    inner: &'a mut W,
+    hasher: &'a mut Sha256,
+}
+
+impl<W: io::Write> io::Write for HashingWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// One artifact to fetch in a [`download_distributions`] batch
+pub(crate) struct DownloadRequest<'a> {
+    pub(crate) url: &'a str,
+    pub(crate) target_dir: &'a Path,
+    pub(crate) target_file: &'a Path,
+    pub(crate) credentials: Option<&'a (String, String)>,
+    /// See [`download_distribution`]'s `expected_hash`
+    pub(crate) expected_hash: Option<&'a str>,
+}
+
+/// Downloads every request in `requests` concurrently, bounded by rayon's global thread pool (the
+/// same fixed-worker-pool approach [`crate::install::download_and_install`] already uses to
+/// parallelize installs), instead of a hand-rolled thread-per-url pool or unbounded async
+/// concurrency, which the pixi project found can exhaust the connection pool and deadlock once
+/// enough packages download at once. Each request still goes through [`download_distribution`]'s
+/// temp-file-then-persist dance, so a download that fails partway through never leaves a
+/// corrupted file at `target_file`. Returns one result per request, in the same order as
+/// `requests`, so a single failed download doesn't abort the rest of the batch
+pub(crate) fn download_distributions(requests: &[DownloadRequest]) -> Vec<Result<()>> {
+    requests
+        .par_iter()
+        .map(|request| {
+            download_distribution(
+                request.url,
+                request.target_dir,
+                request.target_file,
+                request.credentials,
+                request.expected_hash,
+            )
+        })
+        .collect()
+}
+
+/// End of central directory record signature, `PK\x05\x06`
+const EOCD_SIGNATURE: [u8; 4] = [0x50, 0x4b, 0x05, 0x06];
+/// Central directory file header signature, `PK\x01\x02`
+const CENTRAL_DIRECTORY_SIGNATURE: [u8; 4] = [0x50, 0x4b, 0x01, 0x02];
+/// Zip64 end of central directory locator signature, `PK\x06\x07`
+const ZIP64_EOCD_LOCATOR_SIGNATURE: [u8; 4] = [0x50, 0x4b, 0x06, 0x07];
+/// How many trailing bytes we fetch up front, hoping it contains the whole central directory.
+/// Wheel central directories are tiny (one entry per file) compared to the compiled payload, so
+/// this is normally enough in a single request
+const EOCD_SEARCH_WINDOW: u64 = 64 * 1024;
+
+/// A located but not yet downloaded `*.dist-info/METADATA` zip entry
+struct MetadataEntry {
+    compression_method: u16,
+    compressed_size: u64,
+    local_header_offset: u64,
+}
+
+/// Fetches a wheel's `*.dist-info/METADATA` as cheaply as possible: first tries the PEP 658/714
+/// standalone `{url}.metadata` file an index may publish alongside the wheel (a plain small
+/// download, cheaper than even the range-request dance below), then falls back to
+/// [`fetch_metadata_lazy`]'s range requests against the wheel itself. Same `Ok(None)` contract as
+/// that function: the caller should fall back to [`download_distribution`] and read METADATA from
+/// disk
+pub(crate) fn fetch_metadata(url: &str) -> Result<Option<String>> {
+    if let Some(metadata) = fetch_standalone_metadata(url) {
+        return Ok(Some(metadata));
+    }
+    fetch_metadata_lazy(url)
+}
+
+/// Tries the PEP 658/714 standalone metadata file a wheel's url may have a sibling of. Indexes
+/// that don't publish one (most of them, as of writing) answer 404; a flaky or misbehaving one
+/// might time out or 500. Either way we fall back to [`fetch_metadata_lazy`] rather than hard-fail
+/// the whole resolution over a sidecar file that was never required in the first place
+fn fetch_standalone_metadata(url: &str) -> Option<String> {
+    let metadata_url = format!("{}.metadata", url);
+    let response = match ureq::get(&metadata_url)
+        .set("User-Agent", "virtual-sprawl (konstin@mailbox.org)")
+        .call()
+    {
+        Ok(response) => response,
+        Err(err) => {
+            debug!("No standalone metadata at {}: {}", metadata_url, err);
+            return None;
+        }
+    };
+    match response.into_string() {
+        Ok(metadata) => Some(metadata),
+        Err(err) => {
+            debug!(
+                "Invalid standalone metadata response from {}: {}",
+                metadata_url, err
+            );
+            None
+        }
+    }
+}
+
+/// Fetches just the `*.dist-info/METADATA` member of a wheel over HTTP range requests, instead
+/// of downloading the whole (possibly large) wheel. Returns `Ok(None)` if the server doesn't
+/// support range requests or the wheel uses zip64 (we don't implement that), in which case the
+/// caller should fall back to [`download_distribution`] and read METADATA from disk
+pub(crate) fn fetch_metadata_lazy(url: &str) -> Result<Option<String>> {
+    let content_length = match content_length(url)? {
+        Some(content_length) => content_length,
+        None => return Ok(None),
+    };
+
+    let tail_start = content_length.saturating_sub(EOCD_SEARCH_WINDOW);
+    let tail = match range_request(url, tail_start, content_length - 1)? {
+        Some(tail) => tail,
+        None => return Ok(None),
+    };
+
+    let eocd_offset = match tail
+        .windows(EOCD_SIGNATURE.len())
+        .rposition(|window| window == EOCD_SIGNATURE)
+    {
+        Some(eocd_offset) => eocd_offset,
+        None => bail!(
+            "Could not find the end-of-central-directory record in the last {} bytes of {}",
+            tail.len(),
+            url
+        ),
+    };
+    if eocd_offset >= 20 && tail[eocd_offset - 20..eocd_offset - 16] == ZIP64_EOCD_LOCATOR_SIGNATURE
+    {
+        debug!("{} is zip64, falling back to a full download", url);
+        return Ok(None);
+    }
+
+    let central_directory_size = read_u32(&tail, eocd_offset + 12)? as u64;
+    let central_directory_offset = read_u32(&tail, eocd_offset + 16)? as u64;
+    if central_directory_size == u32::MAX as u64 || central_directory_offset == u32::MAX as u64 {
+        debug!("{} has zip64 central directory sizes, falling back", url);
+        return Ok(None);
+    }
+
+    let central_directory = if central_directory_offset >= tail_start {
+        let start = (central_directory_offset - tail_start) as usize;
+        let end = start + central_directory_size as usize;
+        tail.get(start..end)
+            .context("Central directory offset out of bounds of the fetched tail")?
+            .to_vec()
+    } else {
+        match range_request(
+            url,
+            central_directory_offset,
+            central_directory_offset + central_directory_size - 1,
+        )? {
+            Some(central_directory) => central_directory,
+            None => return Ok(None),
+        }
+    };
+
+    let entry = match find_metadata_entry(&central_directory)? {
+        Some(entry) => entry,
+        None => bail!(
+            "No *.dist-info/METADATA entry found in the wheel at {}",
+            url
+        ),
+    };
+
+    // The local header copy of the name/extra field lengths can differ slightly from the
+    // central directory's, so read the fixed part of the local header first to get the real
+    // data offset before fetching the (possibly large) compressed payload
+    let local_header = match range_request(
+        url,
+        entry.local_header_offset,
+        entry.local_header_offset + 29,
+    )? {
+        Some(local_header) => local_header,
+        None => return Ok(None),
+    };
+    let name_len = read_u16(&local_header, 26)? as u64;
+    let extra_len = read_u16(&local_header, 28)? as u64;
+    let data_offset = entry.local_header_offset + 30 + name_len + extra_len;
+
+    let compressed = match range_request(url, data_offset, data_offset + entry.compressed_size - 1)?
+    {
+        Some(compressed) => compressed,
+        None => return Ok(None),
+    };
+
+    let raw = match entry.compression_method {
+        0 => compressed,
+        8 => {
+            let mut decoder = flate2::read::DeflateDecoder::new(&compressed[..]);
+            let mut raw = Vec::new();
+            decoder
+                .read_to_end(&mut raw)
+                .context("Failed to inflate METADATA")?;
+            raw
+        }
+        other => bail!("Unsupported zip compression method {} for METADATA", other),
+    };
+    Ok(Some(
+        String::from_utf8(raw).context("METADATA is not valid utf8")?,
+    ))
+}
+
+/// Scans a central directory for the (single) `*.dist-info/METADATA` entry
+fn find_metadata_entry(central_directory: &[u8]) -> Result<Option<MetadataEntry>> {
+    let mut offset = 0;
+    while offset + 46 <= central_directory.len() {
+        if central_directory[offset..offset + 4] != CENTRAL_DIRECTORY_SIGNATURE {
+            break;
+        }
+        let compression_method = read_u16(central_directory, offset + 10)?;
+        let compressed_size = read_u32(central_directory, offset + 20)? as u64;
+        let name_len = read_u16(central_directory, offset + 28)? as usize;
+        let extra_len = read_u16(central_directory, offset + 30)? as usize;
+        let comment_len = read_u16(central_directory, offset + 32)? as usize;
+        let local_header_offset = read_u32(central_directory, offset + 42)? as u64;
+        let name_start = offset + 46;
+        let name = central_directory
+            .get(name_start..name_start + name_len)
+            .context("Truncated central directory entry")?;
+
+        if std::str::from_utf8(name)
+            .map(|name| name.ends_with(".dist-info/METADATA"))
+            .unwrap_or(false)
+        {
+            return Ok(Some(MetadataEntry {
+                compression_method,
+                compressed_size,
+                local_header_offset,
+            }));
+        }
+
+        offset = name_start + name_len + extra_len + comment_len;
+    }
+    Ok(None)
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> Result<u16> {
+    Ok(u16::from_le_bytes(
+        bytes
+            .get(offset..offset + 2)
+            .context("Truncated zip record")?
+            .try_into()
+            .unwrap(),
+    ))
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Result<u32> {
+    Ok(u32::from_le_bytes(
+        bytes
+            .get(offset..offset + 4)
+            .context("Truncated zip record")?
+            .try_into()
+            .unwrap(),
+    ))
+}
+
+/// HEAD request to learn the wheel's size and whether the server supports range requests
+fn content_length(url: &str) -> Result<Option<u64>> {
+    let response = ureq::head(url)
+        .set("User-Agent", "virtual-sprawl (konstin@mailbox.org)")
+        .call()
+        .context("HEAD request failed")?;
+    if response.header("accept-ranges") != Some("bytes") {
+        return Ok(None);
+    }
+    Ok(response
+        .header("content-length")
+        .and_then(|len| len.parse::<u64>().ok()))
+}
+
+/// Issues a byte-range request, returning `Ok(None)` if the server answered with a full `200`
+/// instead of a partial `206` (i.e. it ignored the range)
+fn range_request(url: &str, start: u64, end_inclusive: u64) -> Result<Option<Vec<u8>>> {
+    let response = ureq::get(url)
+        .set("User-Agent", "virtual-sprawl (konstin@mailbox.org)")
+        .set("Range", &format!("bytes={}-{}", start, end_inclusive))
+        .call()
+        .context("Range request failed")?;
+    if response.status() != 206 {
+        return Ok(None);
+    }
+    let mut bytes = Vec::new();
+    response
+        .into_reader()
+        .read_to_end(&mut bytes)
+        .context("Failed to read range response body")?;
+    Ok(Some(bytes))
+}
+
 /// `~/.cache/virtual-sprawl`
 pub(crate) fn cache_dir() -> result::Result<PathBuf, WheelInstallerError> {
     Ok(dirs::cache_dir()
@@ -0,0 +1,81 @@
+//! Reads `pdm.lock`, pdm's TOML equivalent of `poetry.lock`: every package pdm resolved to is
+//! already listed flat with an exact version and its download hashes, tagged with the dependency
+//! group(s) (`default`, or a named `[tool.pdm.dev-dependencies]` group) it belongs to, so there's
+//! no dependency tree to walk here either -- same idea as [`crate::pipfile_lock`], just read from
+//! TOML instead of JSON.
+
+use crate::spec::RequestedSpec;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::path::Path;
+
+/// One `[[package]]` entry in `pdm.lock`
+#[derive(Deserialize, Debug, Clone)]
+struct PdmLockedPackage {
+    name: String,
+    version: String,
+    /// The dependency group(s) this package was locked for; older pdm.lock files (pre 2.0) don't
+    /// carry this field at all, in which case every package is treated as reachable
+    #[serde(default)]
+    groups: Vec<String>,
+    #[serde(default)]
+    files: Vec<PdmLockedFile>,
+}
+
+/// One of a locked package's downloadable artifacts
+#[derive(Deserialize, Debug, Clone)]
+struct PdmLockedFile {
+    hash: String,
+}
+
+/// The subset of `pdm.lock` we need: its flat, already-resolved `[[package]]` list
+#[derive(Deserialize, Debug, Clone)]
+pub struct PdmLock {
+    #[serde(default)]
+    package: Vec<PdmLockedPackage>,
+}
+
+impl PdmLock {
+    /// Parses a `pdm.lock`'s TOML contents
+    pub fn from_str(data: &str) -> Result<Self> {
+        toml::from_str(data).context("Invalid pdm.lock")
+    }
+}
+
+/// Reads `pdm_lock`'s pinned packages into [`RequestedSpec`]s, keeping only those locked for the
+/// `"default"` group plus whichever of `extras` name one of pdm's own dev-dependency groups --
+/// mirroring how a poetry dependency group is selected through the same `extras` mechanism (see
+/// [`crate::poetry_integration::read_dependencies::read_poetry_specs`]). A package with no
+/// `groups` at all (a pre-2.0 `pdm.lock`) is always kept, since that lockfile format predates
+/// group tagging entirely.
+pub fn read_pdm_lock_specs(pdm_lock: &Path, extras: &[String]) -> Result<Vec<RequestedSpec>> {
+    let lock = PdmLock::from_str(
+        &fs_err::read_to_string(pdm_lock)
+            .with_context(|| format!("Failed to read {}", pdm_lock.display()))?,
+    )?;
+
+    lock.package
+        .into_iter()
+        .filter(|package| {
+            package.groups.is_empty()
+                || package.groups.iter().any(|group| group == "default")
+                || package
+                    .groups
+                    .iter()
+                    .any(|group| extras.iter().any(|extra| extra == group))
+        })
+        .map(|package| {
+            Ok(RequestedSpec {
+                requested: format!("{}=={}", package.name, package.version),
+                name: package.name,
+                python_version: Some(package.version),
+                source: None,
+                extras: Vec::new(),
+                file_path: None,
+                url: None,
+                file_hash: package.files.into_iter().next().map(|file| file.hash),
+                hashes: vec![],
+            })
+        })
+        .collect()
+}
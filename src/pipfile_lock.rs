@@ -0,0 +1,83 @@
+//! Reads `Pipfile.lock`, pipenv's equivalent of `poetry.lock`: unlike a plain `pyproject.toml`,
+//! it's already a flat, fully-pinned closure (direct and transitive dependencies alike, each with
+//! its hashes), so there's no dependency tree to walk or resolver to invoke -- we just translate
+//! its `default`/`develop` tables into [`RequestedSpec`]s directly, the same way
+//! [`crate::monotrail::specs_from_requirements_txt_resolved`] treats an already-frozen
+//! `requirements.txt`.
+
+use crate::spec::RequestedSpec;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// One package entry in `Pipfile.lock`'s `default`/`develop` tables
+#[derive(Deserialize, Debug, Clone)]
+struct PipfileLockedPackage {
+    /// A pinned `==x.y.z` specifier, as pipenv always writes it; anything else (a path or url
+    /// dependency pipenv also allows) isn't supported here
+    version: Option<String>,
+    /// `sha256:<hex digest>` hashes pip can verify a download against, in the same format
+    /// [`RequestedSpec::file_hash`] expects
+    #[serde(default)]
+    hashes: Vec<String>,
+}
+
+/// The subset of `Pipfile.lock` we need: its `default` (`[packages]`) and `develop`
+/// (`[dev-packages]`) pinned package tables
+#[derive(Deserialize, Debug, Clone)]
+pub struct PipfileLock {
+    #[serde(default)]
+    default: HashMap<String, PipfileLockedPackage>,
+    #[serde(default)]
+    develop: HashMap<String, PipfileLockedPackage>,
+}
+
+impl PipfileLock {
+    /// Parses a `Pipfile.lock`'s JSON contents
+    pub fn from_str(data: &str) -> Result<Self> {
+        serde_json::from_str(data).context("Invalid Pipfile.lock")
+    }
+}
+
+/// Reads `pipfile_lock`'s pinned `[packages]` into [`RequestedSpec`]s, also pulling in
+/// `[dev-packages]` if `"dev"` is among `extras` -- mirroring how a poetry dependency group is
+/// selected through the same `extras` mechanism (see
+/// [`crate::poetry_integration::read_dependencies::read_poetry_specs`])
+pub fn read_pipfile_lock_specs(
+    pipfile_lock: &Path,
+    extras: &[String],
+) -> Result<Vec<RequestedSpec>> {
+    let lock = PipfileLock::from_str(
+        &fs_err::read_to_string(pipfile_lock)
+            .with_context(|| format!("Failed to read {}", pipfile_lock.display()))?,
+    )?;
+
+    let mut packages = lock.default;
+    if extras.iter().any(|extra| extra == "dev") {
+        packages.extend(lock.develop);
+    }
+
+    packages
+        .into_iter()
+        .map(|(name, package)| {
+            let version = package
+                .version
+                .as_deref()
+                .and_then(|version| version.strip_prefix("=="))
+                .with_context(|| format!("{} in Pipfile.lock isn't pinned to a version", name))?
+                .to_string();
+            Ok(RequestedSpec {
+                requested: format!("{}=={}", name, version),
+                name,
+                python_version: Some(version),
+                source: None,
+                extras: Vec::new(),
+                file_path: None,
+                url: None,
+                file_hash: package.hashes.into_iter().next(),
+                hashes: vec![],
+            })
+        })
+        .collect()
+}
@@ -11,18 +11,189 @@ use anyhow::{bail, format_err, Context};
 use fs_err as fs;
 use std::collections::BTreeMap;
 use std::default::Default;
+use std::path::PathBuf;
 use std::process::Command;
 use std::time::Instant;
 use std::{env, io};
 use tempfile::{tempdir, TempDir};
 use tracing::{debug, span, Level};
 
+/// Which end of each dependency's version range poetry should resolve to
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ResolutionMode {
+    /// The default: resolve each dependency to the highest version matching its constraints
+    Highest,
+    /// Resolve each direct dependency to the lowest version matching its constraints, to catch
+    /// code that actually requires a newer API than the declared floor
+    LowestDirect,
+}
+
+impl Default for ResolutionMode {
+    fn default() -> Self {
+        ResolutionMode::Highest
+    }
+}
+
+/// Rewrites every non-python dependency to `==<lower-bound>`, taking the lower bound out of the
+/// `>=`/`>`/`~=`/`^` operand or the exact `==` version. Dependencies with no lower bound (e.g. a
+/// bare `<2.0` or `*`) are left untouched so poetry still resolves them freely.
+fn pin_to_lowest_direct(
+    dependencies: &BTreeMap<String, poetry_toml::Dependency>,
+) -> BTreeMap<String, poetry_toml::Dependency> {
+    dependencies
+        .iter()
+        .map(|(name, dependency)| {
+            if name == "python" {
+                return (name.clone(), dependency.clone());
+            }
+            let pinned = match dependency {
+                poetry_toml::Dependency::Compact(constraint) => lowest_bound(constraint)
+                    .map(|lower| poetry_toml::Dependency::Compact(format!("=={}", lower)))
+                    .unwrap_or_else(|| dependency.clone()),
+                poetry_toml::Dependency::Expanded {
+                    version,
+                    optional,
+                    extras,
+                    git,
+                    branch,
+                    tag,
+                    rev,
+                    url,
+                    path,
+                    develop,
+                    subdirectory,
+                    markers,
+                    python,
+                    source,
+                } => poetry_toml::Dependency::Expanded {
+                    version: version
+                        .as_deref()
+                        .and_then(lowest_bound)
+                        .map(|lower| format!("=={}", lower))
+                        .or_else(|| version.clone()),
+                    optional: *optional,
+                    extras: extras.clone(),
+                    git: git.clone(),
+                    branch: branch.clone(),
+                    tag: tag.clone(),
+                    rev: rev.clone(),
+                    url: url.clone(),
+                    path: path.clone(),
+                    develop: *develop,
+                    subdirectory: subdirectory.clone(),
+                    markers: markers.clone(),
+                    python: python.clone(),
+                    source: source.clone(),
+                },
+                // Each alternative is pinned independently; they stay mutually exclusive since
+                // pinning doesn't touch their `python`/`markers` gates
+                poetry_toml::Dependency::Multiple(alternatives) => poetry_toml::Dependency::Multiple(
+                    pin_to_lowest_direct(
+                        &alternatives
+                            .iter()
+                            .enumerate()
+                            .map(|(i, dependency)| (i.to_string(), dependency.clone()))
+                            .collect(),
+                    )
+                    .into_values()
+                    .collect(),
+                ),
+            };
+            (name.clone(), pinned)
+        })
+        .collect()
+}
+
+/// Extracts the lower bound operand out of a poetry version constraint, e.g. `1.2` from
+/// `>=1.2,<2.0` or `1.2.3` from `^1.2.3`. Returns `None` if the constraint has no lower bound (e.g. `<2.0`).
+fn lowest_bound(constraint: &str) -> Option<String> {
+    constraint.split(',').find_map(|part| {
+        let part = part.trim();
+        ["~=", ">=", ">", "^", "=="]
+            .iter()
+            .find_map(|op| part.strip_prefix(op).map(|rest| rest.trim().to_string()))
+    })
+}
+
+/// Parses a `major.minor.patch` version, defaulting missing components to `0`, e.g. `"0.2"` to
+/// `(0, 2, 0)`.
+fn parse_semver(version: &str) -> anyhow::Result<(u32, u32, u32)> {
+    let mut parts = version.trim().splitn(3, '.');
+    let major = parts
+        .next()
+        .context("Empty version")?
+        .parse()
+        .context("Could not parse major version")?;
+    let minor = parts
+        .next()
+        .unwrap_or("0")
+        .parse()
+        .context("Could not parse minor version")?;
+    let patch = parts
+        .next()
+        .unwrap_or("0")
+        .parse()
+        .context("Could not parse patch version")?;
+    Ok((major, minor, patch))
+}
+
+/// Checks a single `<op><version>` constraint (e.g. `>=0.2.1`) against `actual`
+fn check_constraint_part(part: &str, actual: (u32, u32, u32)) -> anyhow::Result<bool> {
+    let part = part.trim();
+    for op in ["~=", ">=", "<=", "==", ">", "<", "^"] {
+        if let Some(required) = part.strip_prefix(op) {
+            let required = parse_semver(required.trim())?;
+            // `~=`/`^` both mean "compatible with", i.e. at least the given version but not a
+            // new major release
+            return Ok(match op {
+                ">=" => actual >= required,
+                "~=" | "^" => actual >= required && actual.0 == required.0,
+                "<=" => actual <= required,
+                "==" => actual == required,
+                ">" => actual > required,
+                "<" => actual < required,
+                _ => unreachable!(),
+            });
+        }
+    }
+    bail!("Unsupported version constraint operator in '{}'", part);
+}
+
+/// Checks the optional `[tool.poetry.self]` `version` constraint against the running monotrail's
+/// own version, bailing with a helpful error if the project requires a newer resolver than this
+/// one. Called from [`crate::poetry_integration::read_dependencies::read_toml_files`] before
+/// resolution starts.
+pub fn check_self_version_constraint(poetry_section: &PoetrySection) -> anyhow::Result<()> {
+    let constraint = match &poetry_section.self_ {
+        Some(self_section) => &self_section.version,
+        None => return Ok(()),
+    };
+    let actual = parse_semver(env!("CARGO_PKG_VERSION"))?;
+    for part in constraint.split(',') {
+        if !check_constraint_part(part, actual)? {
+            bail!(
+                "{} {} does not satisfy the version constraint '{}' declared in \
+                 [tool.poetry.self] of {}",
+                env!("CARGO_PKG_NAME"),
+                env!("CARGO_PKG_VERSION"),
+                constraint,
+                poetry_section.name,
+            );
+        }
+    }
+    Ok(())
+}
+
 /// Minimal dummy pyproject.toml with the user requested deps for poetry to resolve
 pub fn dummy_poetry_pyproject_toml(
     dependencies: &BTreeMap<String, poetry_toml::Dependency>,
     python_version: (u8, u8),
+    resolution_mode: ResolutionMode,
 ) -> PoetryPyprojectToml {
-    let mut dependencies = dependencies.clone();
+    let mut dependencies = match resolution_mode {
+        ResolutionMode::Highest => dependencies.clone(),
+        ResolutionMode::LowestDirect => pin_to_lowest_direct(dependencies),
+    };
     // Add python entry with current version; resolving will otherwise fail with complaints
     dependencies.insert(
         "python".to_string(),
@@ -43,12 +214,16 @@ pub fn dummy_poetry_pyproject_toml(
                 description: "monotrail generated this dummy pyproject.toml to call poetry and let it do the dependency resolution".to_string(),
                 authors: vec!["konstin <konstin@mailbox.org>".to_string()],
                 dependencies,
-                dev_dependencies: None,
+                dev_dependencies: BTreeMap::new(),
+                group: BTreeMap::new(),
                 extras: Some(BTreeMap::new()),
                 scripts: None,
+                self_: None,
             }),
+            monotrail: None,
         }),
-        build_system: Default::default()
+        build_system: Default::default(),
+        project: None,
     }
 }
 
@@ -57,11 +232,14 @@ pub fn dummy_poetry_pyproject_toml(
 pub fn poetry_resolve(
     dependencies: &BTreeMap<String, poetry_toml::Dependency>,
     lockfile: Option<&str>,
+    resolution_mode: ResolutionMode,
+    target_python_version: (u8, u8),
     python_context: &PythonContext,
 ) -> anyhow::Result<(PoetrySection, PoetryLock, String)> {
     // Write a dummy poetry pyproject.toml with the requested dependencies
     let resolve_dir = tempdir()?;
-    let pyproject_toml_content = dummy_poetry_pyproject_toml(dependencies, python_context.version);
+    let pyproject_toml_content =
+        dummy_poetry_pyproject_toml(dependencies, target_python_version, resolution_mode);
     let pyproject_toml_path = resolve_dir.path().join("pyproject.toml");
     fs::write(
         &pyproject_toml_path,
@@ -91,6 +269,68 @@ pub fn poetry_resolve(
     Ok((poetry_section, poetry_lock, lockfile))
 }
 
+/// Directory under `cache_dir()` holding one resolved `poetry.lock` per target python version,
+/// keyed by `<major>.<minor>`
+fn poetry_matrix_cache_dir() -> anyhow::Result<PathBuf> {
+    Ok(cache_dir()?.join("poetry_matrix_lock"))
+}
+
+/// Looks up a lock previously cached by [`poetry_resolve_matrix`] for `python_version`, if any
+pub fn load_cached_matrix_lock(python_version: (u8, u8)) -> anyhow::Result<Option<String>> {
+    let lock_path = poetry_matrix_cache_dir()?
+        .join(format!("{}.{}", python_version.0, python_version.1))
+        .join("poetry.lock");
+    if lock_path.is_file() {
+        Ok(Some(fs::read_to_string(lock_path)?))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Resolves `dependencies` against each of `python_versions` in turn, seeding every resolution
+/// with the previous version's lock (the same reuse-previous-lockfile trick `poetry_resolve`
+/// does for a single version) to minimize resolver churn between adjacent interpreters.
+///
+/// Each resulting lock is cached under `cache_dir()` keyed by version, so that a later run
+/// picking a different interpreter (e.g. through `determine_python_version`) can load a
+/// precomputed lock through [`load_cached_matrix_lock`] instead of re-invoking poetry.
+pub fn poetry_resolve_matrix(
+    dependencies: &BTreeMap<String, poetry_toml::Dependency>,
+    python_versions: &[(u8, u8)],
+    resolution_mode: ResolutionMode,
+    python_context: &PythonContext,
+) -> anyhow::Result<BTreeMap<(u8, u8), (PoetrySection, PoetryLock, String)>> {
+    let cache_dir = poetry_matrix_cache_dir()?;
+    fs::create_dir_all(&cache_dir)?;
+
+    let mut previous_lockfile: Option<String> = None;
+    let mut results = BTreeMap::new();
+    for &target_python_version in python_versions {
+        debug!(
+            "Resolving poetry.lock for python {}.{}",
+            target_python_version.0, target_python_version.1
+        );
+        let (poetry_section, poetry_lock, lockfile) = poetry_resolve(
+            dependencies,
+            previous_lockfile.as_deref(),
+            resolution_mode,
+            target_python_version,
+            python_context,
+        )?;
+
+        let version_cache_dir = cache_dir.join(format!(
+            "{}.{}",
+            target_python_version.0, target_python_version.1
+        ));
+        fs::create_dir_all(&version_cache_dir)?;
+        fs::write(version_cache_dir.join("poetry.lock"), &lockfile)?;
+
+        previous_lockfile = Some(lockfile.clone());
+        results.insert(target_python_version, (poetry_section, poetry_lock, lockfile));
+    }
+    Ok(results)
+}
+
 /// Runs `poetry lock --no-update` in the given tempdir, which needs to contain a pyproject.toml
 /// and optionally a poetry.lock
 pub fn poetry_resolve_from_dir(
@@ -129,6 +369,9 @@ pub fn poetry_resolve_from_dir(
         &specs,
         &python_context.sys_executable,
         python_context.version,
+        python_context.implementation,
+        &python_context.platform_tags,
+        None,
     )
     .context("Failed to bootstrap poetry")?;
     drop(bootstrapping_span);
@@ -4,4 +4,5 @@ pub mod lock;
 pub mod poetry_lock;
 pub mod poetry_toml;
 pub mod read_dependencies;
+pub mod resolve;
 pub mod run;
@@ -1,11 +1,16 @@
 //! Types for poetry.lock
 
-use anyhow::bail;
+use anyhow::{bail, Context};
 use pep508_rs::{MarkerEnvironment, MarkerTree};
-use regex::Regex;
 use serde::Deserialize;
+use sha2::{Digest, Sha256};
 use std::collections::{HashMap, HashSet};
 use std::str::FromStr;
+use tracing::warn;
+
+/// The highest `metadata.lock-version` major we understand; we accept any `^MAJOR.0` lockfile,
+/// warning if the minor is newer than we've seen, and refuse anything from a newer major
+const SUPPORTED_LOCK_VERSION_MAJOR: u32 = 2;
 
 #[derive(Deserialize, Debug, Clone)]
 #[serde(rename_all = "kebab-case")]
@@ -17,11 +22,29 @@ pub struct PoetryLock {
 impl PoetryLock {
     pub fn from_str(data: &str) -> anyhow::Result<Self> {
         let lockfile: Self = toml::from_str(data)?;
-        if lockfile.metadata.lock_version != "1.1" && lockfile.metadata.lock_version != "2.0" {
-            bail!(
-                "Unsupported poetry.lock version {}",
-                lockfile.metadata.lock_version
-            )
+        // 1.1 is the legacy format predating poetry 1.3's `^MAJOR.0` scheme, handled specially
+        // throughout (see `get_filenames`)
+        if lockfile.metadata.lock_version != "1.1" {
+            let (major, minor) = parse_lock_version(&lockfile.metadata.lock_version)?;
+            if major > SUPPORTED_LOCK_VERSION_MAJOR {
+                bail!(
+                    "This poetry.lock was written with lock-version {}, which is newer than the \
+                     highest version monotrail supports (^{}.0). Please upgrade monotrail.",
+                    lockfile.metadata.lock_version,
+                    SUPPORTED_LOCK_VERSION_MAJOR
+                );
+            } else if major < SUPPORTED_LOCK_VERSION_MAJOR {
+                bail!(
+                    "Unsupported poetry.lock version {}",
+                    lockfile.metadata.lock_version
+                );
+            } else if minor > 0 {
+                warn!(
+                    "This poetry.lock was written with lock-version {}, we might not understand \
+                     all its fields, proceeding anyway",
+                    lockfile.metadata.lock_version
+                );
+            }
         }
         Ok(lockfile)
     }
@@ -42,6 +65,103 @@ impl PoetryLock {
         // outdated lockfile, to be handled downstream
         None
     }
+
+    /// Whether `metadata.lock-version` is 2.1 or newer, i.e. whether each package carries
+    /// [`Package::markers`], the solver's own fully-resolved transitive marker. When true,
+    /// `read_poetry_specs` can evaluate that marker directly instead of walking
+    /// `package.dependencies` to rebuild reachability itself.
+    pub fn has_locked_markers(&self) -> bool {
+        match parse_lock_version(&self.metadata.lock_version) {
+            Ok((major, minor)) => major > 2 || (major == 2 && minor >= 1),
+            // 1.1 (or anything else we failed to parse) predates locked markers
+            Err(_) => false,
+        }
+    }
+
+    /// Recomputes poetry's `metadata.content-hash` from the raw `[tool.poetry]` table and checks
+    /// it against the value recorded in this lockfile, so a stale lockfile is caught immediately
+    /// instead of surfacing as a confusing "missing package" error deep in the dependency walk.
+    ///
+    /// Mirrors poetry's own algorithm: take only the dependency-relevant keys of `[tool.poetry]`
+    /// (`dependencies`, `dev-dependencies`, `group.*.dependencies`, `source`, `extras`), serialize
+    /// them as JSON with lexicographically sorted keys and no extra whitespace, then hash with
+    /// SHA-256. The hashed keys are read straight from the raw toml rather than through
+    /// `PoetrySection` so the hash isn't perturbed by fields our model doesn't round-trip exactly.
+    pub fn verify_up_to_date(&self, raw_pyproject_toml: &str) -> anyhow::Result<()> {
+        // The content-hash algorithm changed together with the lock-version 2.0 format (poetry
+        // 1.3); we don't know how to recompute the legacy one, so skip gracefully instead of
+        // false-alarming
+        if self.metadata.lock_version == "1.1" {
+            warn!("poetry.lock has lock-version 1.1, skipping content-hash check");
+            return Ok(());
+        }
+
+        let pyproject_toml: toml::Value = toml::from_str(raw_pyproject_toml)?;
+        let poetry_table = pyproject_toml
+            .get("tool")
+            .and_then(|tool| tool.get("poetry"))
+            .and_then(|poetry| poetry.as_table())
+            .context("Missing [tool.poetry] while computing content-hash")?;
+
+        const RELEVANT_KEYS: &[&str] = &[
+            "dependencies",
+            "dev-dependencies",
+            "group",
+            "source",
+            "extras",
+        ];
+        let mut relevant = serde_json::Map::new();
+        for key in RELEVANT_KEYS {
+            if let Some(value) = poetry_table.get(*key) {
+                relevant.insert((*key).to_string(), toml_value_to_json(value));
+            }
+        }
+        let serialized = serde_json::to_string(&serde_json::Value::Object(relevant))
+            .context("Failed to serialize [tool.poetry] for content-hash computation")?;
+        let computed = format!("{:x}", Sha256::digest(serialized.as_bytes()));
+
+        if computed != self.metadata.content_hash {
+            bail!(
+                "poetry.lock is out of date with pyproject.toml (content-hash mismatch), run \
+                 `poetry update` to refresh it"
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Converts a parsed toml value into the equivalent JSON value, recursing into tables and arrays
+fn toml_value_to_json(value: &toml::Value) -> serde_json::Value {
+    match value {
+        toml::Value::String(value) => serde_json::Value::String(value.clone()),
+        toml::Value::Integer(value) => serde_json::Value::from(*value),
+        toml::Value::Float(value) => serde_json::Value::from(*value),
+        toml::Value::Boolean(value) => serde_json::Value::Bool(*value),
+        toml::Value::Datetime(value) => serde_json::Value::String(value.to_string()),
+        toml::Value::Array(array) => {
+            serde_json::Value::Array(array.iter().map(toml_value_to_json).collect())
+        }
+        toml::Value::Table(table) => serde_json::Value::Object(
+            table
+                .iter()
+                .map(|(key, value)| (key.clone(), toml_value_to_json(value)))
+                .collect(),
+        ),
+    }
+}
+
+/// Splits a `metadata.lock-version` such as `"2.0"` into its `(major, minor)` components
+fn parse_lock_version(lock_version: &str) -> anyhow::Result<(u32, u32)> {
+    let (major, minor) = lock_version
+        .split_once('.')
+        .with_context(|| format!("Invalid poetry.lock lock-version {}", lock_version))?;
+    let major = major
+        .parse()
+        .with_context(|| format!("Invalid poetry.lock lock-version {}", lock_version))?;
+    let minor = minor
+        .parse()
+        .with_context(|| format!("Invalid poetry.lock lock-version {}", lock_version))?;
+    Ok((major, minor))
 }
 
 /// `[[package]]`
@@ -63,6 +183,10 @@ pub struct Package {
     pub source: Option<Source>,
     // Only in lock file format 2.0/poetry 1.3 or newer
     pub files: Option<Vec<HashedFile>>,
+    // Only in lock file format 2.1/poetry 1.6 or newer: the solver's fully-resolved transitive
+    // marker for this package, letting us check whether it's needed in the current environment
+    // without re-walking `dependencies` ourselves (see `PoetryLock::has_locked_markers`)
+    pub markers: Option<String>,
 }
 
 /// e.g. `{version = ">=1.21.0", markers = "python_version >= \"3.10\""}`
@@ -105,7 +229,6 @@ impl Dependency {
         environment: &MarkerEnvironment,
         self_extras: &HashSet<String>,
     ) -> Result<Option<(String, Vec<String>)>, String> {
-        let extra_re = Regex::new(r#"^extra == "([\w\d_-]+)"$"#).unwrap();
         let self_extras_vec: Vec<&str> = self_extras.iter().map(|str| str.as_str()).collect();
 
         Ok(match self {
@@ -116,13 +239,7 @@ impl Dependency {
                 extras,
             }) => {
                 if let Some(markers) = markers {
-                    if let Some(captures) = extra_re.captures(markers) {
-                        if self_extras.contains(&captures[1].to_string()) {
-                            Some((version.to_string(), extras.clone().unwrap_or_default()))
-                        } else {
-                            None
-                        }
-                    } else if MarkerTree::from_str(markers)
+                    if MarkerTree::from_str(markers)
                         .unwrap()
                         .evaluate(environment, &self_extras_vec)
                     {
@@ -135,35 +252,34 @@ impl Dependency {
                 }
             }
             Dependency::List(options) => {
-                for option in options {
-                    if let Some(markers) = &option.markers {
-                        if let Some(captures) = extra_re.captures(markers) {
-                            if self_extras.contains(&captures[1].to_string()) {
-                                return Ok(Some((
-                                    option.version.to_string(),
-                                    option.extras.clone().unwrap_or_default(),
-                                )));
-                            } else {
-                                continue;
-                            };
-                        }
-                        if MarkerTree::from_str(markers)
+                // Each option is normally gated on a different, mutually exclusive marker, so
+                // exactly one should match the current environment; more than one matching
+                // (which includes several options that carry no marker at all) means we can't
+                // tell which version constraint is the right one, and is reported as an error
+                let matching: Vec<&DependencyExpanded> = options
+                    .iter()
+                    .filter(|option| match &option.markers {
+                        Some(markers) => MarkerTree::from_str(markers)
                             .unwrap()
-                            .evaluate(environment, &self_extras_vec)
-                        {
-                            return Ok(Some((
-                                option.version.to_string(),
-                                option.extras.clone().unwrap_or_default(),
-                            )));
-                        }
-                    } else {
-                        return Ok(Some((
-                            option.version.to_string(),
-                            option.extras.clone().unwrap_or_default(),
-                        )));
+                            .evaluate(environment, &self_extras_vec),
+                        None => true,
+                    })
+                    .collect();
+                match matching.as_slice() {
+                    [] => None,
+                    [option] => Some((
+                        option.version.to_string(),
+                        option.extras.clone().unwrap_or_default(),
+                    )),
+                    _ => {
+                        return Err(format!(
+                            "Lockfile has ambiguous overlapping markers for this dependency: {} \
+                             of its {} alternatives all match the current environment",
+                            matching.len(),
+                            options.len()
+                        ))
                     }
                 }
-                None
             }
         })
     }
@@ -178,6 +294,17 @@ pub struct Source {
     pub url: String,
     pub reference: String,
     pub resolved_reference: String,
+    /// Set instead of a bare `reference` when the `[tool.poetry.dependencies]` entry pinned this
+    /// git dependency with `branch = "..."` rather than `tag`/`rev`. Not written by every poetry
+    /// version, so defaults to `None` rather than failing to parse when absent
+    #[serde(default)]
+    pub branch: Option<String>,
+    /// Set instead of `reference` when pinned with `tag = "..."`
+    #[serde(default)]
+    pub tag: Option<String>,
+    /// Set instead of `reference` when pinned with `rev = "..."`
+    #[serde(default)]
+    pub rev: Option<String>,
 }
 
 /// `[metadata]`
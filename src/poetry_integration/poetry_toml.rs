@@ -1,7 +1,11 @@
 //! Types for poetry.toml
 
+use crate::markers::Pep508Environment;
+use anyhow::Context;
+use pep508_rs::{MarkerTree, Requirement};
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
+use std::str::FromStr;
 
 /// ```toml
 /// [build-system]
@@ -33,6 +37,37 @@ impl Default for BuildSystem {
 pub struct PoetryPyprojectToml {
     pub tool: Option<ToolSection>,
     pub build_system: Option<BuildSystem>,
+    /// The standard PEP 621 project metadata, used by projects that declare dependencies without
+    /// a `[tool.poetry]` section (poetry-core only as the build backend)
+    pub project: Option<ProjectSection>,
+}
+
+/// ```toml
+/// [project]
+/// name = "..."
+/// version = "..."
+/// dependencies = ["foo>=1.2", "bar[extra]"]
+/// requires-python = ">=3.8"
+///
+/// [project.optional-dependencies]
+/// extra1 = ["baz"]
+/// ```
+///
+/// <https://packaging.python.org/en/latest/specifications/pyproject-toml/>
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+#[allow(dead_code)]
+pub struct ProjectSection {
+    pub name: String,
+    pub version: Option<String>,
+    /// PEP 508 requirement strings, e.g. `"foo>=1.2"` or `"bar[extra]; python_version>=\"3.8\""`
+    #[serde(default)]
+    pub dependencies: Vec<String>,
+    /// Each group is itself a list of PEP 508 requirement strings, mirroring
+    /// `[tool.poetry.extras]`
+    #[serde(default)]
+    pub optional_dependencies: BTreeMap<String, Vec<String>>,
+    pub requires_python: Option<String>,
 }
 
 /// ```toml
@@ -42,12 +77,43 @@ pub struct PoetryPyprojectToml {
 #[serde(rename_all = "kebab-case")]
 pub struct ToolSection {
     pub poetry: Option<PoetrySection>,
+    /// Monotrail-specific settings that don't belong under `[tool.poetry]`
+    pub monotrail: Option<MonotrailSection>,
+}
+
+/// ```toml
+/// [tool.monotrail]
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub struct MonotrailSection {
+    pub workspace: Option<WorkspaceSection>,
+}
+
+/// Declares this project as the root of a monorepo workspace: each member listed here has its own
+/// `pyproject.toml`/`poetry.lock`, and [`crate::poetry_integration::read_dependencies::poetry_spec_from_dir`]
+/// merges all of their specs into one closure when resolving the root.
+///
+/// ```toml
+/// [tool.monotrail.workspace]
+/// members = ["packages/*", "tools/my-cli"]
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub struct WorkspaceSection {
+    /// Member project directories, relative to the workspace root. A trailing `/*` segment
+    /// expands to every subdirectory of that prefix, mirroring cargo's own workspace globs
+    pub members: Vec<String>,
 }
 
 /// ```toml
 /// [tool.poetry.dependencies]
 /// dep1 = "1.2.3"
 /// dep2 = { version = "4.5.6", optional = true }
+/// dep3 = [
+///     { version = "1.0", python = "<3.9" },
+///     { version = "2.0", python = ">=3.9" },
+/// ]
 /// ```
 #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
 #[serde(untagged, rename_all = "kebab-case")]
@@ -60,7 +126,52 @@ pub enum Dependency {
         extras: Option<Vec<String>>,
         git: Option<String>,
         branch: Option<String>,
+        /// ```toml
+        /// dep = { git = "...", tag = "1.0" }
+        /// ```
+        tag: Option<String>,
+        /// ```toml
+        /// dep = { git = "...", rev = "9cf87a2" }
+        /// ```
+        rev: Option<String>,
+        /// ```toml
+        /// dep = { url = "https://example.com/dep-1.0-py3-none-any.whl" }
+        /// ```
+        url: Option<String>,
+        /// ```toml
+        /// dep = { path = "../dep", develop = true }
+        /// ```
+        path: Option<String>,
+        develop: Option<bool>,
+        /// ```toml
+        /// dep = { git = "...", subdirectory = "sub/package" }
+        /// ```
+        /// The package lives in a subdirectory of the git repository or local path rather than at
+        /// its root, same as pip's `#subdirectory=` url fragment
+        subdirectory: Option<String>,
+        /// ```toml
+        /// dep = { version = "1.0", markers = "sys_platform == 'win32'" }
+        /// ```
+        /// An already PEP 508-shaped marker expression, ANDed onto `python` (if also given) when
+        /// lowering to a [`Requirement`] in [`Dependency::to_requirements`]
+        markers: Option<String>,
+        /// ```toml
+        /// dep = { version = "1.0", python = "^3.8" }
+        /// ```
+        /// A poetry version constraint (so e.g. `^3.8` is allowed) restricting which Python
+        /// versions this dependency applies to, translated into a `python_version` marker
+        python: Option<String>,
+        /// ```toml
+        /// dep = { version = "1.0", source = "pypi" }
+        /// ```
+        /// Restricts resolution to a named source configured under `[[tool.poetry.source]]`.
+        /// Carried through for round-tripping only: resolution doesn't read it and searches the
+        /// configured package indexes as for any other dependency
+        source: Option<String>,
     },
+    /// A TOML array of tables giving alternative constraints for the same dependency name, each
+    /// one normally gated by a different `python`/`markers` so they're mutually exclusive
+    Multiple(Vec<Dependency>),
 }
 
 impl Dependency {
@@ -68,15 +179,374 @@ impl Dependency {
         match self {
             Dependency::Compact(_) => false,
             Dependency::Expanded { optional, .. } => optional.unwrap_or(false),
+            Dependency::Multiple(alternatives) => alternatives.iter().all(Dependency::is_optional),
+        }
+    }
+
+    pub fn get_extras(&self) -> Vec<String> {
+        match self {
+            Dependency::Compact(_) => Vec::new(),
+            Dependency::Expanded { extras, .. } => extras.clone().unwrap_or_default(),
+            Dependency::Multiple(alternatives) => alternatives
+                .iter()
+                .flat_map(Dependency::get_extras)
+                .collect(),
         }
     }
 
-    pub fn get_extras(&self) -> &[String] {
+    /// Whether this dependency's `markers` and `python` fields (if any) are satisfied by
+    /// `environment`, so root dependencies such as `pywin32 = {version = "*", markers =
+    /// "sys_platform == 'win32'"}` or `dep = {version = "*", python = ">=3.9,<3.11"}` are dropped
+    /// on hosts they don't apply to instead of being queued and failing to resolve against
+    /// poetry.lock. A [`Dependency::Multiple`] matches if any of its alternatives does, since
+    /// they're normally each gated on a different, mutually exclusive environment.
+    pub fn matches_environment(&self, environment: &Pep508Environment) -> bool {
         match self {
-            Dependency::Compact(_) => &[],
-            Dependency::Expanded { extras, .. } => extras.as_deref().unwrap_or_default(),
+            Dependency::Compact(_) => true,
+            Dependency::Expanded {
+                markers, python, ..
+            } => {
+                let markers_match = markers
+                    .as_deref()
+                    .map(|markers| {
+                        MarkerTree::from_str(markers)
+                            .map(|marker| marker.evaluate(environment, &[]))
+                            .unwrap_or(true)
+                    })
+                    .unwrap_or(true);
+                let python_matches = python
+                    .as_deref()
+                    .map(|python| {
+                        poetry_specifier_to_marker(python)
+                            .ok()
+                            .filter(|marker| !marker.is_empty())
+                            .map(|marker| {
+                                MarkerTree::from_str(&marker)
+                                    .map(|marker| marker.evaluate(environment, &[]))
+                                    .unwrap_or(true)
+                            })
+                            .unwrap_or(true)
+                    })
+                    .unwrap_or(true);
+                markers_match && python_matches
+            }
+            Dependency::Multiple(alternatives) => alternatives
+                .iter()
+                .any(|alternative| alternative.matches_environment(environment)),
+        }
+    }
+
+    /// Lowers this poetry dependency into one or more PEP 508 [`Requirement`]s (more than one only
+    /// for the [`Dependency::Multiple`] platform-specific-alternatives form), translating poetry's
+    /// `^`/`~`/bare-version shorthands into PEP 440 specifiers, `python` into a `python_version`
+    /// marker ANDed with any explicit `markers`, and `git`/`url`/`path` into a PEP 508 direct
+    /// reference (`name @ ...`)
+    pub fn to_requirements(&self, name: &str) -> anyhow::Result<Vec<Requirement>> {
+        match self {
+            Dependency::Compact(version) => Ok(vec![dependency_to_requirement(
+                name,
+                Some(version),
+                &[],
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+            )?]),
+            Dependency::Expanded {
+                version,
+                extras,
+                git,
+                branch,
+                tag,
+                rev,
+                url,
+                path,
+                subdirectory,
+                markers,
+                python,
+                ..
+            } => Ok(vec![dependency_to_requirement(
+                name,
+                version.as_deref(),
+                extras.as_deref().unwrap_or_default(),
+                git.as_deref(),
+                branch.as_deref(),
+                tag.as_deref(),
+                rev.as_deref(),
+                url.as_deref(),
+                path.as_deref(),
+                subdirectory.as_deref(),
+                markers.as_deref(),
+                python.as_deref(),
+            )?]),
+            Dependency::Multiple(alternatives) => {
+                let mut requirements = Vec::new();
+                for alternative in alternatives {
+                    requirements.extend(alternative.to_requirements(name)?);
+                }
+                Ok(requirements)
+            }
+        }
+    }
+}
+
+/// Builds a single PEP 508 requirement string and parses it, rather than constructing a
+/// [`Requirement`] field by field, since that's already the established way we round-trip through
+/// this crate (see [`crate::poetry_integration::read_dependencies::pep508_to_poetry_dependency`])
+#[allow(clippy::too_many_arguments)]
+fn dependency_to_requirement(
+    name: &str,
+    version: Option<&str>,
+    extras: &[String],
+    git: Option<&str>,
+    branch: Option<&str>,
+    tag: Option<&str>,
+    rev: Option<&str>,
+    url: Option<&str>,
+    path: Option<&str>,
+    subdirectory: Option<&str>,
+    markers: Option<&str>,
+    python: Option<&str>,
+) -> anyhow::Result<Requirement> {
+    let extras_part = if extras.is_empty() {
+        String::new()
+    } else {
+        format!("[{}]", extras.join(","))
+    };
+
+    let mut marker_parts = Vec::new();
+    if let Some(python) = python {
+        let marker = poetry_specifier_to_marker(python)
+            .with_context(|| format!("Invalid python constraint '{}' for {}", python, name))?;
+        if !marker.is_empty() {
+            marker_parts.push(format!("({})", marker));
+        }
+    }
+    if let Some(markers) = markers {
+        marker_parts.push(format!("({})", markers.trim()));
+    }
+    let marker_part = if marker_parts.is_empty() {
+        String::new()
+    } else {
+        format!(" ; {}", marker_parts.join(" and "))
+    };
+
+    // A git/url/path source takes priority over a version specifier, same as in poetry itself
+    let mut source = if let Some(git) = git {
+        let mut direct_reference = format!("git+{}", git);
+        if let Some(rev) = rev.or(tag).or(branch) {
+            direct_reference.push('@');
+            direct_reference.push_str(rev);
+        }
+        Some(direct_reference)
+    } else if let Some(url) = url {
+        Some(url.to_string())
+    } else {
+        path.map(|path| format!("file://{}", path))
+    };
+    if let (Some(source), Some(subdirectory)) = (&mut source, subdirectory) {
+        source.push_str(&format!("#subdirectory={}", subdirectory));
+    }
+
+    let requirement_string = if let Some(source) = source {
+        format!("{}{} @ {}{}", name, extras_part, source, marker_part)
+    } else {
+        let version_specifier = match version {
+            None | Some("*") => String::new(),
+            Some(version) => poetry_specifier_to_pep440(version).with_context(|| {
+                format!("Invalid version constraint '{}' for {}", version, name)
+            })?,
+        };
+        format!("{}{}{}{}", name, extras_part, version_specifier, marker_part)
+    };
+
+    Requirement::from_str(&requirement_string).with_context(|| {
+        format!(
+            "Failed to build a PEP 508 requirement for {}: `{}`",
+            name, requirement_string
+        )
+    })
+}
+
+/// Translates a poetry version constraint into a comma-separated PEP 440 specifier set. Poetry
+/// adds three things PEP 440 doesn't have: `^1.2.3` (caret, "compatible up to the next breaking
+/// change"), `~1.2.3` (tilde, "compatible up to the next minor/major release") and a bare version
+/// with no operator at all, which poetry treats the same as a caret constraint
+fn poetry_specifier_to_pep440(constraint: &str) -> anyhow::Result<String> {
+    let mut translated = Vec::new();
+    for part in constraint.split(',') {
+        let part = part.trim();
+        if part.is_empty() || part == "*" {
+            continue;
+        }
+        if let Some(version) = part.strip_prefix('^') {
+            translated.push(caret_range(version)?);
+        } else if let Some(version) = part.strip_prefix('~') {
+            // `~=` is already a valid PEP 440 operator (PEP 440 itself), not poetry's tilde
+            if let Some(version) = version.strip_prefix('=') {
+                translated.push(format!("~={}", version.trim()));
+            } else {
+                translated.push(tilde_range(version)?);
+            }
+        } else if part.ends_with(".*") {
+            translated.push(wildcard_range(part)?);
+        } else if PEP440_OPERATORS.iter().any(|op| part.starts_with(op)) {
+            translated.push(part.to_string());
+        } else {
+            // A bare version, e.g. `dep = "1.2.3"`, is poetry's shorthand for `^1.2.3`
+            translated.push(caret_range(part)?);
         }
     }
+    Ok(translated.join(","))
+}
+
+const PEP440_OPERATORS: &[&str] = &["~=", ">=", "<=", "==", "!=", ">", "<"];
+
+/// Same translation as [`poetry_specifier_to_pep440`], but for a `python = "..."` constraint,
+/// which needs to become a `python_version` marker expression instead of a version specifier
+fn poetry_specifier_to_marker(constraint: &str) -> anyhow::Result<String> {
+    let pep440 = poetry_specifier_to_pep440(constraint)?;
+    let clauses = pep440
+        .split(',')
+        .filter(|clause| !clause.is_empty())
+        .map(|clause| {
+            let op = PEP440_OPERATORS
+                .iter()
+                .find(|op| clause.starts_with(*op))
+                .with_context(|| format!("Invalid python constraint clause '{}'", clause))?;
+            let version = &clause[op.len()..];
+            Ok(format!("python_version {} \"{}\"", op, version))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    Ok(clauses.join(" and "))
+}
+
+/// `^1.2.3` -> `>=1.2.3,<2.0.0`; the first non-zero component (major, else minor, else patch)
+/// determines what's bumped for the upper bound, so e.g. `^0.2.3` -> `>=0.2.3,<0.3.0`
+pub(crate) fn caret_range(version: &str) -> anyhow::Result<String> {
+    let mut parts = version.trim().split('.');
+    let major: u64 = parts
+        .next()
+        .context("Empty version")?
+        .parse()
+        .context("Invalid major version")?;
+    let minor: Option<u64> = parts.next().map(str::parse).transpose().context("Invalid minor version")?;
+    let patch: Option<u64> = parts.next().map(str::parse).transpose().context("Invalid patch version")?;
+
+    let upper = if major > 0 {
+        format!("{}.0.0", major + 1)
+    } else if let Some(minor) = minor {
+        if minor > 0 {
+            format!("0.{}.0", minor + 1)
+        } else if let Some(patch) = patch {
+            format!("0.0.{}", patch + 1)
+        } else {
+            "0.1.0".to_string()
+        }
+    } else {
+        "1.0.0".to_string()
+    };
+    Ok(format!(">={},<{}", version.trim(), upper))
+}
+
+/// `~1.2.3` -> `>=1.2.3,<1.3.0`; `~1` -> `>=1,<2.0.0` since there's no minor to pin down to
+fn tilde_range(version: &str) -> anyhow::Result<String> {
+    let mut parts = version.trim().split('.');
+    let major: u64 = parts
+        .next()
+        .context("Empty version")?
+        .parse()
+        .context("Invalid major version")?;
+    let minor: Option<u64> = parts.next().map(str::parse).transpose().context("Invalid minor version")?;
+    let upper = match minor {
+        Some(minor) => format!("{}.{}.0", major, minor + 1),
+        None => format!("{}.0.0", major + 1),
+    };
+    Ok(format!(">={},<{}", version.trim(), upper))
+}
+
+/// `1.2.*` -> `>=1.2,<1.3`; `1.*` -> `>=1,<2`
+fn wildcard_range(version: &str) -> anyhow::Result<String> {
+    let prefix = version.trim_end_matches(".*");
+    let mut components: Vec<u64> = prefix
+        .split('.')
+        .map(|part| part.parse().context("Invalid version number in wildcard constraint"))
+        .collect::<anyhow::Result<_>>()?;
+    let last = components
+        .pop()
+        .context("Wildcard constraint needs at least one version component")?;
+    let lower = prefix.to_string();
+    components.push(last + 1);
+    let upper = components
+        .iter()
+        .map(u64::to_string)
+        .collect::<Vec<_>>()
+        .join(".");
+    Ok(format!(">={},<{}", lower, upper))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_poetry_specifier_to_pep440() {
+        let cases = [
+            ("^1.2.3", ">=1.2.3,<2.0.0"),
+            ("^1.2", ">=1.2,<2.0.0"),
+            ("^1", ">=1,<2.0.0"),
+            ("^0.2.3", ">=0.2.3,<0.3.0"),
+            ("^0.0.3", ">=0.0.3,<0.0.4"),
+            ("^0.0", ">=0.0,<0.1.0"),
+            ("^0", ">=0,<1.0.0"),
+            ("~1.2.3", ">=1.2.3,<1.3.0"),
+            ("~1.2", ">=1.2,<1.3.0"),
+            ("~1", ">=1,<2.0.0"),
+            ("1.2.*", ">=1.2,<1.3"),
+            ("1.*", ">=1,<2"),
+            ("1.2.3", ">=1.2.3,<2.0.0"),
+            (">=1.2,<2.0", ">=1.2,<2.0"),
+            ("*", ""),
+        ];
+        for (constraint, expected) in cases {
+            assert_eq!(poetry_specifier_to_pep440(constraint).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_poetry_specifier_to_marker() {
+        assert_eq!(
+            poetry_specifier_to_marker("^3.8").unwrap(),
+            "python_version >= \"3.8\" and python_version < \"4.0.0\""
+        );
+    }
+
+    #[test]
+    fn test_dependency_to_requirements() {
+        let dependency = Dependency::Expanded {
+            version: Some("^1.2".to_string()),
+            optional: None,
+            extras: Some(vec!["extra".to_string()]),
+            git: None,
+            branch: None,
+            tag: None,
+            rev: None,
+            url: None,
+            path: None,
+            develop: None,
+            subdirectory: None,
+            markers: Some("sys_platform == 'win32'".to_string()),
+            python: Some(">=3.8".to_string()),
+            source: None,
+        };
+        let requirements = dependency.to_requirements("foo").unwrap();
+        assert_eq!(requirements.len(), 1);
+        assert_eq!(requirements[0].name, "foo");
+    }
 }
 
 /// ```toml
@@ -99,6 +569,48 @@ pub struct PoetrySection {
     pub dependencies: BTreeMap<String, Dependency>,
     #[serde(serialize_with = "toml::ser::tables_last")]
     pub dev_dependencies: BTreeMap<String, Dependency>,
+    /// ```toml
+    /// [tool.poetry.group.test.dependencies]
+    /// ```
+    /// Poetry 1.2+'s generalization of `[tool.poetry.dev-dependencies]` to arbitrarily named
+    /// groups, selected via
+    /// [`crate::poetry_integration::read_dependencies::GroupSelection`]
+    #[serde(default)]
+    pub group: BTreeMap<String, DependencyGroup>,
     pub extras: Option<BTreeMap<String, Vec<String>>>,
     pub scripts: Option<BTreeMap<String, String>>,
+    /// ```toml
+    /// [tool.poetry.self]
+    /// ```
+    /// Lets a project pin the minimum monotrail version required to resolve it, checked in
+    /// [`crate::poetry_integration::lock::check_self_version_constraint`]
+    #[serde(rename = "self")]
+    pub self_: Option<PoetrySelfSection>,
+}
+
+/// ```toml
+/// [tool.poetry.group.<name>]
+/// optional = true
+/// dependencies = { pytest = "^7.0" }
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub struct DependencyGroup {
+    #[serde(default, serialize_with = "toml::ser::tables_last")]
+    pub dependencies: BTreeMap<String, Dependency>,
+    /// Same meaning as poetry's own `optional` group flag: a non-optional group (the default) is
+    /// installed unless explicitly excluded, while an optional one is only pulled in by an
+    /// explicit [`crate::poetry_integration::read_dependencies::GroupSelection::with_groups`]
+    #[serde(default)]
+    pub optional: bool,
+}
+
+/// ```toml
+/// [tool.poetry.self]
+/// version = ">=0.2"
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct PoetrySelfSection {
+    pub version: String,
 }
@@ -2,92 +2,279 @@
 
 use crate::install::repo_at_revision;
 use crate::markers::Pep508Environment;
+use crate::metadata_inspect::inspect_metadata;
 use crate::monotrail::{specs_from_requirements_txt_resolved, PythonContext};
+use crate::poetry_integration::lock::{check_self_version_constraint, ResolutionMode};
 use crate::poetry_integration::poetry_lock::PoetryLock;
-use crate::poetry_integration::poetry_toml::{PoetryPyprojectToml, PoetrySection};
+use crate::poetry_integration::poetry_toml::{PoetryPyprojectToml, PoetrySection, ProjectSection};
+use crate::poetry_integration::resolve::{parse_constraint, resolve, PypiDependencyProvider};
 use crate::poetry_integration::run::poetry_run;
 use crate::poetry_integration::{poetry_lock, poetry_toml};
-use crate::spec::{DistributionType, RequestedSpec, SpecSource};
+use crate::spec::{normalize_git_ssh_url, DistributionType, RequestedSpec, SpecSource};
 use crate::utils::cache_dir;
 use anyhow::{bail, Context};
 use fs_err as fs;
-use install_wheel_rs::{WheelFilename, WheelInstallerError};
+use install_wheel_rs::WheelInstallerError;
+use pep440_rs::{Version as Pep440Version, VersionSpecifiers};
+use pep508_rs::{MarkerTree, Requirement, VersionOrUrl};
 use regex::Regex;
 use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use tracing::debug;
 
+/// Parses a single PEP 508 requirement string (as found in `[project.dependencies]` and
+/// `[project.optional-dependencies]`) into the `poetry_toml::Dependency` shape the rest of the
+/// crate already knows how to consume, mirroring
+/// [`crate::requirements_txt::RequirementsTxt::into_poetry`]
+fn pep508_to_poetry_dependency(
+    requirement: &str,
+    optional: bool,
+) -> anyhow::Result<(String, poetry_toml::Dependency)> {
+    let requirement = Requirement::from_str(requirement)
+        .with_context(|| format!("Invalid PEP 508 requirement '{}'", requirement))?;
+    let markers = requirement.marker.as_ref().map(|marker| marker.to_string());
+    let dependency = version_or_url_to_poetry_dependency(
+        requirement.version_or_url,
+        requirement.extras.clone(),
+        optional,
+        false,
+        markers,
+    )?;
+    Ok((requirement.name, dependency))
+}
+
+/// Turns a PEP 508 requirement's `version_or_url` field into the equivalent `poetry_toml::Dependency`,
+/// covering everything poetry itself accepts in `[tool.poetry.dependencies]`: a plain version
+/// specifier, a direct artifact url (`{ url = ... }`), a `file://` local path (`{ path = ...,
+/// develop = ... }`), and a `git+`-prefixed VCS url with an optional `@<ref>` fragment
+/// (`{ git = ..., rev = ... }`). `markers` carries the requirement's PEP 508 environment marker
+/// (if any) through to the same field poetry's own `markers = "..."` dependency syntax uses. Used
+/// by both the `[project]` table fallback and
+/// [`crate::requirements_txt::RequirementsTxt::into_poetry`].
+pub(crate) fn version_or_url_to_poetry_dependency(
+    version_or_url: Option<VersionOrUrl>,
+    extras: Option<Vec<String>>,
+    optional: bool,
+    editable: bool,
+    markers: Option<String>,
+) -> anyhow::Result<poetry_toml::Dependency> {
+    let version_or_url = match version_or_url {
+        None => {
+            return Ok(poetry_toml::Dependency::Expanded {
+                version: Some("*".to_string()),
+                optional: Some(optional),
+                extras,
+                git: None,
+                branch: None,
+                tag: None,
+                rev: None,
+                url: None,
+                path: None,
+                develop: None,
+                subdirectory: None,
+                markers,
+                python: None,
+                source: None,
+            });
+        }
+        Some(VersionOrUrl::VersionSpecifier(specifiers)) => {
+            return Ok(poetry_toml::Dependency::Expanded {
+                version: Some(specifiers.to_string()),
+                optional: Some(optional),
+                extras,
+                git: None,
+                branch: None,
+                tag: None,
+                rev: None,
+                url: None,
+                path: None,
+                develop: None,
+                subdirectory: None,
+                markers,
+                python: None,
+                source: None,
+            });
+        }
+        Some(VersionOrUrl::Url(url)) => url.to_string(),
+    };
+
+    if let Some(path) = version_or_url.strip_prefix("file://") {
+        return Ok(poetry_toml::Dependency::Expanded {
+            version: None,
+            optional: Some(optional),
+            extras,
+            git: None,
+            branch: None,
+            tag: None,
+            rev: None,
+            url: None,
+            path: Some(path.to_string()),
+            develop: Some(editable),
+            subdirectory: None,
+            markers,
+            python: None,
+            source: None,
+        });
+    }
+
+    if let Some(git_url) = version_or_url.strip_prefix("git+") {
+        // pip/poetry's `git+<url>[@<ref>]` convention; the ref can be a branch, tag or commit and
+        // there's no way to tell which from the url alone, so we record it as `rev`, which poetry
+        // accepts for any of the three
+        let (git_url, rev) = match git_url.rsplit_once('@') {
+            Some((git_url, rev)) => (git_url, Some(rev.to_string())),
+            None => (git_url, None),
+        };
+        return Ok(poetry_toml::Dependency::Expanded {
+            version: None,
+            optional: Some(optional),
+            extras,
+            git: Some(git_url.to_string()),
+            branch: None,
+            tag: None,
+            rev,
+            url: None,
+            path: None,
+            develop: None,
+            subdirectory: None,
+            markers,
+            python: None,
+            source: None,
+        });
+    }
+
+    Ok(poetry_toml::Dependency::Expanded {
+        version: None,
+        optional: Some(optional),
+        extras,
+        git: None,
+        branch: None,
+        tag: None,
+        rev: None,
+        url: Some(version_or_url),
+        path: None,
+        develop: None,
+        subdirectory: None,
+        markers,
+        python: None,
+        source: None,
+    })
+}
+
+/// Builds the `PoetrySection`-shaped root dependency map `get_root_info`/`read_poetry_specs`
+/// expect out of a standard PEP 621 `[project]` table, so projects that only declare
+/// dependencies that way (poetry-core used just as the build backend) can be installed too
+fn project_section_to_poetry(project: &ProjectSection) -> anyhow::Result<PoetrySection> {
+    let mut dependencies = BTreeMap::new();
+    // Mirrors how poetry itself expresses the python constraint: as a "python" entry in
+    // `[tool.poetry.dependencies]`, which the rest of the resolver already knows to special-case
+    if let Some(requires_python) = &project.requires_python {
+        dependencies.insert(
+            "python".to_string(),
+            poetry_toml::Dependency::Compact(requires_python.clone()),
+        );
+    }
+    for requirement in &project.dependencies {
+        let (name, dependency) = pep508_to_poetry_dependency(requirement, false)?;
+        dependencies.insert(name, dependency);
+    }
+
+    let mut extras = BTreeMap::new();
+    for (extra_name, requirements) in &project.optional_dependencies {
+        let mut extra_packages = Vec::new();
+        for requirement in requirements {
+            let (name, dependency) = pep508_to_poetry_dependency(requirement, true)?;
+            extra_packages.push(name.clone());
+            dependencies.insert(name, dependency);
+        }
+        extras.insert(extra_name.clone(), extra_packages);
+    }
+
+    Ok(PoetrySection {
+        name: project.name.clone(),
+        version: project
+            .version
+            .clone()
+            .unwrap_or_else(|| "0.0.0".to_string()),
+        description: String::new(),
+        authors: Vec::new(),
+        dependencies,
+        dev_dependencies: BTreeMap::new(),
+        group: BTreeMap::new(),
+        extras: Some(extras),
+        scripts: None,
+        self_: None,
+    })
+}
+
 /// Resolves a single package's filename and url inside a poetry lockfile
 ///
-/// doesn't work because the pypi api wants a different python version than the one in the wheel
-/// filename
+/// This used to hand-build `files.pythonhosted.org` urls from the filename, which doesn't work:
+/// the real path includes a content-addressed blake2 hash directory that can't be derived from
+/// the filename alone. Instead we ask the configured package index (see
+/// [`crate::package_index::search_release`]) for the release at this exact locked version, which
+/// gives us pypi's own canonical url, and cross-check its declared sha256 against the
+/// `HashedFile.hash` recorded in the lockfile so a compromised or stale index can't swap in a
+/// different file under the same name
+///
+/// Not yet wired into [`resolution_to_specs`]'s main path, which still leaves `package.source ==
+/// None` pypi packages to be resolved by [`crate::spec::RequestedSpec::resolve`] at install time
+/// instead; kept around as the building block for doing that resolution eagerly here instead
 #[allow(dead_code)]
 pub fn filename_and_url(
     lockfile: &PoetryLock,
     package: &poetry_lock::Package,
     compatible_tags: &[(String, String, String)],
 ) -> anyhow::Result<(String, DistributionType, String)> {
-    let hashed_files = lockfile
-        .metadata
-        .files
-        .get(&package.name)
-        .context("invalid lockfile (missing file hashes), run `poetry update`")?;
-    let filenames: Vec<_> = hashed_files
-        .iter()
-        .filter(|hashed_file| hashed_file.file.ends_with(".whl"))
-        .map(|hashed_file| {
-            Ok((
-                hashed_file.file.clone(),
-                WheelFilename::from_str(&hashed_file.file).with_context(|| {
-                    format!(
-                        "Couldn't parse wheel filename {} in lockfile",
-                        hashed_file.file
-                    )
-                })?,
-            ))
+    let (release, distribution_type, _version, _credentials) =
+        crate::package_index::search_release(
+            &package.name,
+            Some(package.version.clone()),
+            compatible_tags,
+            // Pinned to an exact, already-locked version, so there's no "pick the newest
+            // compatible with the running interpreter" decision left to make here
+            None,
+        )
+        .with_context(|| {
+            format!(
+                "Failed to find a release for {} {}",
+                package.name, package.version
+            )
+        })?;
+
+    let locked_hash = lockfile
+        .get_filenames(&package.name.replace('-', "_"))
+        .and_then(|hashed_files| {
+            hashed_files
+                .iter()
+                .find(|hashed_file| hashed_file.file == release.filename)
         })
-        .collect::<Result<_, anyhow::Error>>()?;
-    let wheel = filenames
-        .iter()
-        .find(|(_filename, parsed)| parsed.is_compatible(compatible_tags));
-
-    if let Some((filename, parsed_filename)) = wheel {
-        // https://warehouse.pypa.io/api-reference/integration-guide.html#if-you-so-choose
-        let url = format!(
-            "https://files.pythonhosted.org/packages/{}/{}/{}/{}",
-            parsed_filename.python_tag.join("."),
-            package.name.chars().next().unwrap(),
-            package.name,
-            filename,
-        );
-        return Ok((filename.clone(), DistributionType::Wheel, url));
+        .with_context(|| {
+            format!(
+                "invalid lockfile (missing file hash for {}), run `poetry update`",
+                release.filename
+            )
+        })?
+        .hash
+        .clone();
+    let (algorithm, locked_hash) = locked_hash
+        .split_once(':')
+        .unwrap_or(("sha256", locked_hash.as_str()));
+    if let Some(index_hash) = release.digests.get(algorithm) {
+        if index_hash != locked_hash {
+            bail!(
+                "Hash mismatch for {}: poetry.lock says {}:{}, but the index reports {}:{}",
+                release.filename,
+                algorithm,
+                locked_hash,
+                algorithm,
+                index_hash
+            );
+        }
     }
 
-    if let Some(hashed_file) = hashed_files
-        .iter()
-        .find(|hashed_file| hashed_file.file.ends_with(".tar.gz"))
-    {
-        // https://warehouse.pypa.io/api-reference/integration-guide.html#if-you-so-choose
-        let url = format!(
-            "https://files.pythonhosted.org/packages/{}/{}/{}/{}",
-            "source",
-            package.name.chars().next().unwrap(),
-            package.name,
-            hashed_file.file,
-        );
-        Ok((
-            hashed_file.file.clone(),
-            DistributionType::SourceDistribution,
-            url,
-        ))
-    } else {
-        bail!(
-            "No compatible compiled file found for {}. \
-                Why does it have neither a wheel for your operating system/architecture/python version not any sdist?",
-            package.name
-        )
-    }
+    Ok((release.filename, distribution_type, release.url))
 }
 
 /// this isn't actually needed poetry gives us all we need
@@ -123,6 +310,7 @@ fn parse_dep_extra(
 }
 
 fn resolution_to_specs(
+    lockfile: &PoetryLock,
     packages: HashMap<String, poetry_lock::Package>,
     deps_with_extras: HashMap<String, HashSet<String>>,
 ) -> anyhow::Result<Vec<RequestedSpec>> {
@@ -137,42 +325,182 @@ fn resolution_to_specs(
                     dep_name
                 )
             })?;
+        // A `source.type = "url"` entry is a direct artifact link (wheel or sdist), which
+        // `RequestedSpec::resolve` downloads straight from `url` rather than going through
+        // `SpecSource`/pypi or a git checkout
+        let (source, url, file_hash) = match &package.source {
+            Some(source) if source.source_type == "url" => {
+                let filename = source
+                    .url
+                    .rsplit('/')
+                    .next()
+                    .unwrap_or(&source.url)
+                    .to_string();
+                let distribution_type = if filename.ends_with(".whl") {
+                    DistributionType::Wheel
+                } else {
+                    DistributionType::SourceDistribution
+                };
+                // The lockfile records the hash next to the filename alongside the other package
+                // releases, even for a direct-url dependency
+                let file_hash = package
+                    .files
+                    .iter()
+                    .flatten()
+                    .find(|hashed_file| hashed_file.file == filename)
+                    .map(|hashed_file| hashed_file.hash.clone());
+                (
+                    None,
+                    Some((source.url.clone(), filename, distribution_type)),
+                    file_hash,
+                )
+            }
+            Some(source) => (
+                Some(SpecSource {
+                    source_type: source.source_type.clone(),
+                    url: if source.source_type == "git" {
+                        normalize_git_ssh_url(&source.url)
+                    } else {
+                        source.url.clone()
+                    },
+                    reference: source.reference.clone(),
+                    resolved_reference: source.resolved_reference.clone(),
+                    branch: source.branch.clone(),
+                    tag: source.tag.clone(),
+                    rev: source.rev.clone(),
+                }),
+                None,
+                None,
+            ),
+            None => (None, None, None),
+        };
+
+        // A plain pypi package (no `source` at all) isn't resolved to a concrete filename until
+        // `RequestedSpec::resolve` queries the index at install time, so unlike the `url` source
+        // above we can't cross-check a single expected filename here -- instead pass through every
+        // hash the lockfile recorded for this package (one per platform wheel/sdist) and let
+        // `check_file_hashes` accept whichever one matches whatever the index hands back
+        let hashes = if package.source.is_none() {
+            lockfile
+                .get_filenames(&package.name.replace('-', "_"))
+                .map(|hashed_files| {
+                    hashed_files
+                        .iter()
+                        .map(|hashed_file| {
+                            // lock-version 1.1's `metadata.files` entries are bare hex digests
+                            // with no `algorithm:` prefix, unlike 2.0's per-package `files` (see
+                            // the similar normalization in `filename_and_url` above); default to
+                            // sha256, the only algorithm poetry.lock or `check_file_hashes` support
+                            if hashed_file.hash.contains(':') {
+                                hashed_file.hash.clone()
+                            } else {
+                                format!("sha256:{}", hashed_file.hash)
+                            }
+                        })
+                        .collect()
+                })
+                .unwrap_or_default()
+        } else {
+            vec![]
+        };
+
         let spec = RequestedSpec {
             requested: format!("{} {}", package.name, package.version),
             name: package.name.clone(),
             python_version: Some(package.version.clone()),
-            source: package.source.clone().map(|source| SpecSource {
-                source_type: source.source_type,
-                url: source.url,
-                reference: source.reference,
-                resolved_reference: source.resolved_reference,
-            }),
+            source,
             extras: dep_extras.into_iter().collect(),
             file_path: None,
-            url: None,
+            url,
+            file_hash,
+            hashes,
         };
         specs.push(spec);
     }
     Ok(specs)
 }
 
+/// Which `[tool.poetry.group.<name>.dependencies]` groups (poetry 1.2+) feed the resolver queue,
+/// mirroring poetry's own `--with`/`--without`/`--only` CLI flags.
+///
+/// `[tool.poetry.dependencies]` (poetry's implicit "main" group) is always included. The legacy
+/// `[tool.poetry.dev-dependencies]` table is treated as an always-present `dev` group for
+/// backwards compatibility. Any other named `[tool.poetry.group.<name>]` follows poetry's own
+/// rule when selected through [`Self::with_groups`]: included by default, unless it sets
+/// `optional = true`, in which case it's only pulled in by an explicit `--with`. The legacy
+/// `From<bool>` constructor keeps monotrail's old, more restrictive behaviour instead (groups
+/// other than `main`/`dev` are never included) so existing boolean call sites don't suddenly start
+/// installing groups they don't know about.
+#[derive(Debug, Clone)]
+pub struct GroupSelection {
+    with: HashSet<String>,
+    legacy: bool,
+}
+
+impl GroupSelection {
+    /// Only the `main` group, equivalent to poetry's `--without dev` or monotrail's old
+    /// `no_dev: true`
+    pub fn only_main() -> Self {
+        GroupSelection {
+            with: HashSet::new(),
+            legacy: true,
+        }
+    }
+
+    /// `main` plus the given extra groups, e.g. from `--with test,docs`
+    pub fn with_groups(groups: impl IntoIterator<Item = String>) -> Self {
+        GroupSelection {
+            with: groups.into_iter().collect(),
+            legacy: false,
+        }
+    }
+
+    /// Whether the `main` or legacy `dev` group is included: `main` always is, `dev` only when
+    /// explicitly selected (see [`Self::with_groups`]/the `From<bool>` impl)
+    fn includes(&self, group: &str) -> bool {
+        group == "main" || self.with.contains(group)
+    }
+
+    /// Whether a `[tool.poetry.group.<name>]` with poetry's own `optional` flag should be
+    /// installed: always if explicitly requested, otherwise only non-optional groups and only
+    /// outside the legacy boolean path (see the struct docs)
+    fn includes_group(&self, group: &str, optional: bool) -> bool {
+        self.with.contains(group) || (!optional && !self.legacy)
+    }
+}
+
+impl From<bool> for GroupSelection {
+    /// `no_dev == true` selects only `main`; `no_dev == false` selects `main` plus the legacy
+    /// `dev` group, matching monotrail's previous boolean behaviour
+    fn from(no_dev: bool) -> Self {
+        GroupSelection {
+            with: if no_dev {
+                HashSet::new()
+            } else {
+                HashSet::from(["dev".to_string()])
+            },
+            legacy: true,
+        }
+    }
+}
+
 /// Get the root deps from pyproject.toml, already filtered by activated extras.
 /// The is no root package in poetry.lock that we could use so we also need to read pyproject.toml
 fn get_root_info(
     poetry_section: &PoetrySection,
-    no_dev: bool,
+    groups: &GroupSelection,
     extras: &[String],
+    pep508_env: &Pep508Environment,
 ) -> anyhow::Result<HashMap<String, poetry_toml::Dependency>> {
-    let root_deps = if no_dev {
-        poetry_section.dependencies.clone()
-    } else {
-        poetry_section
-            .dependencies
-            .clone()
-            .into_iter()
-            .chain(poetry_section.dev_dependencies.clone())
-            .collect()
-    };
+    let mut root_deps = poetry_section.dependencies.clone();
+    if groups.includes("dev") {
+        root_deps.extend(poetry_section.dev_dependencies.clone());
+    }
+    for (group_name, group) in &poetry_section.group {
+        if groups.includes_group(group_name, group.optional) {
+            root_deps.extend(group.dependencies.clone());
+        }
+    }
 
     let mut root_extra_deps: HashSet<String> = HashSet::new();
     for extra_name in extras {
@@ -195,6 +523,16 @@ fn get_root_info(
             if dep_spec.is_optional() && !root_extra_deps.contains(dep_name) {
                 return false;
             }
+            // Drop platform-/python-version-specific deps whose `markers`/`python` don't apply
+            // here, e.g. `pywin32` gated on `sys_platform == "win32"`, so they don't get queued
+            // and then fail with "Lockfile outdated" on a host poetry.lock never resolved them for
+            if !dep_spec.matches_environment(pep508_env) {
+                debug!(
+                    "Dropping {} ({:?}): its markers/python constraint don't match this environment",
+                    dep_name, dep_spec
+                );
+                return false;
+            }
             true
         })
         .collect();
@@ -218,14 +556,30 @@ fn get_packages_from_lockfile(
 /// Reads pyproject.toml and poetry.lock, also returns poetry.lock as string
 pub fn read_toml_files(dir: &Path) -> anyhow::Result<(PoetrySection, PoetryLock, String)> {
     let path = dir.join("pyproject.toml").canonicalize()?;
-    let poetry_toml: PoetryPyprojectToml = toml::from_str(&fs::read_to_string(&path)?)
+    let raw_pyproject_toml = fs::read_to_string(&path)?;
+    let poetry_toml: PoetryPyprojectToml = toml::from_str(&raw_pyproject_toml)
         .with_context(|| format!("Invalid pyproject.toml in {}", path.display()))?;
-    let poetry_section = poetry_toml
-        .tool
-        .and_then(|tool| tool.poetry)
-        .with_context(|| format!("[tool.poetry] section missing in {}", path.display()))?;
+    // `[tool.poetry]` takes priority when both are present (poetry's own behaviour), falling
+    // back to standard PEP 621 `[project]` metadata for projects that only declare dependencies
+    // that way
+    let poetry_section = match poetry_toml.tool.and_then(|tool| tool.poetry) {
+        Some(poetry_section) => poetry_section,
+        None => {
+            let project = poetry_toml.project.with_context(|| {
+                format!(
+                    "Neither [tool.poetry] nor [project] found in {}",
+                    path.display()
+                )
+            })?;
+            project_section_to_poetry(&project)?
+        }
+    };
+    check_self_version_constraint(&poetry_section)?;
     let lockfile = fs::read_to_string(dir.join("poetry.lock"))?;
-    let poetry_lock = toml::from_str(&lockfile).context("Invalid poetry.lock")?;
+    let poetry_lock = PoetryLock::from_str(&lockfile)?;
+    poetry_lock
+        .verify_up_to_date(&raw_pyproject_toml)
+        .with_context(|| format!("poetry.lock in {} is outdated", dir.display()))?;
     Ok((poetry_section, poetry_lock, lockfile))
 }
 
@@ -233,16 +587,44 @@ pub fn read_toml_files(dir: &Path) -> anyhow::Result<(PoetrySection, PoetryLock,
 pub fn read_poetry_specs(
     poetry_section: &PoetrySection,
     poetry_lock: PoetryLock,
-    no_dev: bool,
+    groups: impl Into<GroupSelection>,
     extras: &[String],
     pep508_env: &Pep508Environment,
 ) -> anyhow::Result<Vec<RequestedSpec>> {
-    // The deps in pyproject.toml which we need to read explicitly since they aren't marked
-    // poetry.lock (raw names)
-    let root_deps = get_root_info(&poetry_section, no_dev, extras)?;
     // All the details info from poetry.lock, indexed by normalized name
     let packages = get_packages_from_lockfile(&poetry_lock)?;
 
+    // lock-version 2.1+ lockfiles carry the solver's own fully-resolved transitive marker on
+    // each package, so we don't need to rebuild reachability by walking `package.dependencies`
+    // ourselves: a package is needed iff its locked marker matches the current environment.
+    //
+    // This skips the per-dependency extras tracking the BFS below does, since locked markers
+    // don't encode which of *our* extras pulled a package in, only which environments it's valid
+    // for; we treat every reachable package as carrying no extra requirements of its own, which
+    // matches how poetry already flattens extras into the lockfile's dependency graph.
+    if poetry_lock.has_locked_markers() {
+        let deps_with_extras: HashMap<String, HashSet<String>> = packages
+            .values()
+            .filter(|package| match &package.markers {
+                Some(markers) => MarkerTree::from_str(markers)
+                    .map(|tree| tree.evaluate(pep508_env, &[]))
+                    .unwrap_or(true),
+                None => true,
+            })
+            .map(|package| {
+                (
+                    package.name.to_lowercase().replace('-', "_"),
+                    HashSet::new(),
+                )
+            })
+            .collect();
+        return resolution_to_specs(&poetry_lock, packages, deps_with_extras);
+    }
+
+    // The deps in pyproject.toml which we need to read explicitly since they aren't marked
+    // poetry.lock (raw names)
+    let root_deps = get_root_info(&poetry_section, &groups.into(), extras, pep508_env)?;
+
     // This is the thing we want to build: a list with all transitive dependencies and
     // all their (transitively activated) features
     let mut deps_with_extras: HashMap<String, HashSet<String>> = HashMap::new();
@@ -277,7 +659,7 @@ pub fn read_poetry_specs(
         for (new_dep_name, new_dep) in package.dependencies.clone().unwrap_or_default() {
             let new_dep_name_norm = new_dep_name.to_lowercase().replace('-', "_");
             // Check the extras selected on the current dep activate the transitive dependency
-            let (_new_dep_version, new_dep_extras) = match new_dep
+            let (new_dep_version, new_dep_extras) = match new_dep
                 .get_version_and_extras(pep508_env, &self_extras)
                 .map_err(WheelInstallerError::InvalidPoetry)?
             {
@@ -285,6 +667,42 @@ pub fn read_poetry_specs(
                 Some((version, new_dep_extras)) => (version, new_dep_extras),
             };
 
+            // The lockfile was solved against this exact constraint, so the version it pinned for
+            // `new_dep_name` had better satisfy it; if it doesn't, poetry.lock and
+            // pyproject.toml/the dependency tree have drifted apart and installing anyway would
+            // silently give the wrong version
+            if new_dep_version != "*" {
+                if let Some(new_dep_package) = packages.get(&new_dep_name_norm) {
+                    let specifiers =
+                        VersionSpecifiers::from_str(&new_dep_version).with_context(|| {
+                            format!(
+                                "Lockfile outdated (run `poetry update`): invalid version \
+                                 constraint \"{}\" from {} on {}",
+                                new_dep_version, dep_name, new_dep_name
+                            )
+                        })?;
+                    let locked_version = Pep440Version::from_str(&new_dep_package.version)
+                        .with_context(|| {
+                            format!(
+                                "Lockfile outdated (run `poetry update`): invalid locked version \
+                                 \"{}\" for {}",
+                                new_dep_package.version, new_dep_name
+                            )
+                        })?;
+                    if !specifiers.contains(&locked_version) {
+                        bail!(
+                            "Lockfile is internally inconsistent: {} requires {} {}, but \
+                             poetry.lock pins {} to {}. Try `poetry lock --no-update`.",
+                            dep_name,
+                            new_dep_name,
+                            new_dep_version,
+                            new_dep_name,
+                            new_dep_package.version
+                        );
+                    }
+                }
+            }
+
             let new_dep_extras: HashSet<String> = new_dep_extras.into_iter().collect();
 
             let new_extras = if let Some(known_extras) = deps_with_extras.get(&new_dep_name) {
@@ -306,12 +724,182 @@ pub fn read_poetry_specs(
         }
     }
 
-    let specs = resolution_to_specs(packages, deps_with_extras)?;
+    let specs = resolution_to_specs(&poetry_lock, packages, deps_with_extras)?;
     Ok(specs)
 }
 
+/// A dependency's final `(name, unique_version)` selection from [`read_poetry_specs_forked`], plus
+/// which of the target `environments` it passed selected it
+#[derive(Debug, Clone)]
+pub struct ForkedSpec {
+    pub spec: RequestedSpec,
+    /// Indices into the `environments` slice passed to [`read_poetry_specs_forked`]
+    pub environments: Vec<usize>,
+}
+
+/// "Fork" mode: resolves `poetry.lock` independently against each of `environments` (e.g.
+/// linux/mac/windows x a couple of Python versions) and merges the results into one platform-tagged
+/// spec set, so a single resolution run can drive installs across every target instead of
+/// re-reading the lockfile once per platform -- the same shape poetry's own "multiple constraints
+/// dependencies" resolution produces, just evaluated here instead of baked into the lockfile.
+///
+/// Packages are merged by `(name, unique_version)`: when two environments pick the same package at
+/// the same locked version, they're recorded as one [`ForkedSpec`] tagging both environment indices,
+/// with `extras` widened to the union of whatever each environment individually activated. A
+/// dependency that resolves to a genuinely different version per environment (e.g. a marker-gated
+/// alternative like `pywin32; sys_platform == "win32"` next to a unix-only package) ends up as
+/// multiple [`ForkedSpec`]s for that name, each tagged with only the environments that picked it.
+pub fn read_poetry_specs_forked(
+    poetry_section: &PoetrySection,
+    poetry_lock: PoetryLock,
+    groups: impl Into<GroupSelection>,
+    extras: &[String],
+    environments: &[Pep508Environment],
+) -> anyhow::Result<Vec<ForkedSpec>> {
+    let groups = groups.into();
+    let mut merged: HashMap<(String, String), ForkedSpec> = HashMap::new();
+    // `read_poetry_specs` takes `poetry_lock` by value even though it only ever reads through a
+    // reference internally, so the last environment can move it in instead of cloning
+    let last_env_index = environments.len().saturating_sub(1);
+    let mut poetry_lock = Some(poetry_lock);
+    for (env_index, environment) in environments.iter().enumerate() {
+        let lock = if env_index == last_env_index {
+            poetry_lock
+                .take()
+                .expect("only taken once, on the last iteration")
+        } else {
+            poetry_lock
+                .clone()
+                .expect("only taken on the last iteration")
+        };
+        let specs = read_poetry_specs(poetry_section, lock, groups.clone(), extras, environment)?;
+        for spec in specs {
+            let key = (
+                spec.normalized_name(),
+                spec.get_unique_version().unwrap_or_default(),
+            );
+            match merged.get_mut(&key) {
+                Some(forked) => {
+                    forked.environments.push(env_index);
+                    for extra in &spec.extras {
+                        if !forked.spec.extras.contains(extra) {
+                            forked.spec.extras.push(extra.clone());
+                        }
+                    }
+                }
+                None => {
+                    merged.insert(
+                        key,
+                        ForkedSpec {
+                            spec,
+                            environments: vec![env_index],
+                        },
+                    );
+                }
+            }
+        }
+    }
+    Ok(merged.into_values().collect())
+}
+
 /// Checkouts the specified revision to the cache dir, if not present
 #[cfg_attr(not(feature = "python_bindings"), allow(dead_code))]
+/// Resolves a `[tool.poetry]` section's direct dependencies with the in-crate
+/// [`crate::poetry_integration::resolve`] resolver instead of shelling out to `poetry lock`
+/// Resolves `[tool.poetry.dependencies]` natively, the same way [`get_root_info`] does for the
+/// lockfile path: optional dependencies are only included if one of `extras` activates them (via
+/// `[tool.poetry.extras]`). Unlike `get_root_info`, this doesn't go on to walk activated extras
+/// transitively into the dependency tree (the native resolver's dependency edges are plain
+/// `(name, Range)` pairs with no extras attached, see `parse_requires_dist`), so an extra that
+/// only exists on a transitive pypi dependency won't be picked up -- a known, narrower scope than
+/// the lockfile-based BFS.
+fn resolve_root_deps_natively(
+    poetry_section: &PoetrySection,
+    extras: &[String],
+) -> anyhow::Result<Vec<RequestedSpec>> {
+    let mut root_extra_deps: HashSet<String> = HashSet::new();
+    for extra_name in extras {
+        let packages = poetry_section
+            .extras
+            .as_ref()
+            .and_then(|extras| extras.get(extra_name).cloned())
+            .with_context(|| format!("No such extra {}", extra_name))?;
+        root_extra_deps.extend(packages);
+    }
+
+    let root_deps = poetry_section
+        .dependencies
+        .iter()
+        .filter(|(name, _)| name.as_str() != "python")
+        .filter(|(name, dependency)| !dependency.is_optional() || root_extra_deps.contains(*name))
+        .map(|(name, dependency)| {
+            let constraint = match dependency {
+                poetry_toml::Dependency::Compact(constraint) => constraint.clone(),
+                poetry_toml::Dependency::Expanded { version, .. } => {
+                    version.clone().unwrap_or_else(|| "*".to_string())
+                }
+                // This experimental native resolver doesn't model per-platform alternatives;
+                // approximating with the first one is consistent with it already ignoring
+                // git/url/path sources on the `Expanded` arm above
+                poetry_toml::Dependency::Multiple(alternatives) => alternatives
+                    .first()
+                    .and_then(|dependency| match dependency {
+                        poetry_toml::Dependency::Compact(constraint) => Some(constraint.clone()),
+                        poetry_toml::Dependency::Expanded { version, .. } => version.clone(),
+                        poetry_toml::Dependency::Multiple(_) => None,
+                    })
+                    .unwrap_or_else(|| "*".to_string()),
+            };
+            Ok((name.clone(), parse_constraint(&constraint)?))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    resolve_deps_natively(&root_deps)
+}
+
+/// Splits a PEP 508-ish requirement string (name directly followed by its version constraint,
+/// e.g. `requests>=2.0`) into name and constraint, the way [`crate::poetry_integration::resolve`]
+/// expects them
+fn split_name_constraint(requirement: &str) -> (&str, &str) {
+    let split_at = requirement
+        .find(|char: char| !(char.is_alphanumeric() || matches!(char, '-' | '_' | '.')))
+        .unwrap_or(requirement.len());
+    let (name, constraint) = requirement.split_at(split_at);
+    (name.trim(), constraint.trim())
+}
+
+/// Resolves a list of root PEP 508-ish requirement strings (e.g. the `Requires-Dist` entries
+/// [`crate::metadata_inspect::inspect_metadata`] found) against pypi with the native resolver
+fn resolve_requires_dist_natively(requires_dist: &[String]) -> anyhow::Result<Vec<RequestedSpec>> {
+    let root_deps = requires_dist
+        .iter()
+        .map(|requirement| {
+            let (name, constraint) = split_name_constraint(requirement);
+            let constraint = if constraint.is_empty() {
+                "*"
+            } else {
+                constraint
+            };
+            Ok((name.to_string(), parse_constraint(constraint)?))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    resolve_deps_natively(&root_deps)
+}
+
+/// Resolves a list of root dependencies against pypi with the native resolver, turning the
+/// resulting pinned versions into [`RequestedSpec`]s the same way published wheels would be
+fn resolve_deps_natively(
+    root_deps: &[(String, crate::poetry_integration::resolve::Range)],
+) -> anyhow::Result<Vec<RequestedSpec>> {
+    let resolution = resolve(&PypiDependencyProvider, root_deps)?;
+    let specs = resolution
+        .into_iter()
+        .map(|(name, version)| RequestedSpec::from_requested(format!("{}=={}", name, version), &[]))
+        .collect::<Result<Vec<RequestedSpec>, WheelInstallerError>>()?;
+    Ok(specs)
+}
+
 pub fn specs_from_git(
     url: String,
     revision: String,
@@ -341,11 +929,21 @@ pub fn specs_from_git(
         let path = repo_dir.join("pyproject.toml");
         let poetry_toml: PoetryPyprojectToml = toml::from_str(&fs::read_to_string(&path)?)
             .with_context(|| format!("Invalid pyproject.toml in {}", path.display()))?;
-        if let Some(_poetry_section) = poetry_toml.tool.and_then(|tool| tool.poetry) {
+        if let Some(poetry_section) = poetry_toml.tool.and_then(|tool| tool.poetry) {
             debug!(
-                "Found {} but no matching lockfile, generating one",
+                "Found {} but no matching lockfile, resolving with the native resolver",
                 repo_dir.join("pyproject.toml").display()
             );
+            match resolve_root_deps_natively(&poetry_section, extras) {
+                Ok(specs) => return Ok((specs, repo_dir, String::new())),
+                Err(err) => {
+                    debug!(
+                        "Native resolution failed ({:#}), falling back to `poetry lock --no-update`",
+                        err
+                    );
+                }
+            }
+
             let python_version =
                 format!("{}.{}", python_context.version.0, python_context.version.1);
             poetry_run(
@@ -369,17 +967,47 @@ pub fn specs_from_git(
             return Ok((specs, repo_dir, lockfile));
         } else {
             debug!(
-                "Found {} but [tool.poetry] section, ignoring",
+                "Found {} but no [tool.poetry] section, inspecting its build metadata instead",
                 repo_dir.join("pyproject.toml").display()
             );
+            let metadata = inspect_metadata(&repo_dir, &python_context.sys_executable)
+                .context("Failed to inspect the package's build metadata")?;
+            debug!(
+                "Resolved {} {} from build metadata, resolving its dependencies natively",
+                metadata.name, metadata.version
+            );
+            let mut specs = resolve_requires_dist_natively(&metadata.requires_dist)?;
+            specs.push(RequestedSpec {
+                requested: format!("{} {}", metadata.name, metadata.version),
+                name: metadata.name,
+                python_version: Some(metadata.version),
+                source: Some(SpecSource {
+                    source_type: "git".to_string(),
+                    url: url.clone(),
+                    reference: revision.clone(),
+                    resolved_reference: revision.clone(),
+                    branch: None,
+                    tag: None,
+                    rev: None,
+                }),
+                extras: extras.to_vec(),
+                file_path: None,
+                url: None,
+                file_hash: None,
+                hashes: vec![],
+            });
+            return Ok((specs, repo_dir, String::new()));
         }
     }
 
     if repo_dir.join("requirements.txt").is_file() {
-        let (specs, lockfile) = specs_from_requirements_txt_resolved(
+        // `repo_dir` is already the project directory this requirements.txt lives in, so an
+        // editable entry's own project dir (if any) isn't separately propagated here
+        let (specs, lockfile, _project_dir) = specs_from_requirements_txt_resolved(
             &repo_dir.join("requirements.txt"),
             extras,
             lockfile,
+            ResolutionMode::Highest,
             python_context,
         )?;
         return Ok((specs, repo_dir, lockfile));
@@ -387,6 +1015,94 @@ pub fn specs_from_git(
     bail!("Neither poetry.lock nor pyproject.toml with [tool.poetry] section nor requirements.txt found");
 }
 
+/// Like [`specs_from_git`], but for a local project directory instead of a git checkout, so
+/// `dep = { path = "../foo" }` entries (and `monotrail_from_path`) can resolve a sibling
+/// directory's own dependencies the same way a git dependency would. With a `poetry.lock` already
+/// there this reads the lockfile without touching the network; a `[tool.poetry]` section without
+/// one is resolved natively against pypi
+pub fn specs_from_path(
+    dir: &Path,
+    extras: &[String],
+    lockfile: Option<&str>,
+    python_context: &PythonContext,
+) -> anyhow::Result<(Vec<RequestedSpec>, String)> {
+    let dir = dir
+        .canonicalize()
+        .with_context(|| format!("Not a directory: {}", dir.display()))?;
+    let source_for = |name: String, version: String| SpecSource {
+        source_type: "directory".to_string(),
+        url: dir.display().to_string(),
+        reference: String::new(),
+        resolved_reference: String::new(),
+        branch: None,
+        tag: None,
+        rev: None,
+    };
+
+    if dir.join("poetry.lock").is_file() {
+        let (poetry_section, poetry_lock, lockfile) = read_toml_files(&dir)
+            .context("Failed to read pyproject.toml/poetry.lock from the project directory")?;
+        let specs = read_poetry_specs(
+            &poetry_section,
+            poetry_lock,
+            true,
+            extras,
+            &python_context.pep508_env,
+        )?;
+        return Ok((specs, lockfile));
+    } else if dir.join("pyproject.toml").is_file() {
+        let path = dir.join("pyproject.toml");
+        let poetry_toml: PoetryPyprojectToml = toml::from_str(&fs::read_to_string(&path)?)
+            .with_context(|| format!("Invalid pyproject.toml in {}", path.display()))?;
+        if let Some(poetry_section) = poetry_toml.tool.and_then(|tool| tool.poetry) {
+            debug!(
+                "Found {} but no matching lockfile, resolving with the native resolver",
+                path.display()
+            );
+            if let Ok(specs) = resolve_root_deps_natively(&poetry_section, extras) {
+                return Ok((specs, String::new()));
+            }
+            debug!(
+                "Native resolution failed, falling back to inspecting the package's build metadata"
+            );
+        }
+        let metadata = inspect_metadata(&dir, &python_context.sys_executable)
+            .context("Failed to inspect the package's build metadata")?;
+        debug!(
+            "Resolved {} {} from build metadata, resolving its dependencies natively",
+            metadata.name, metadata.version
+        );
+        let mut specs = resolve_requires_dist_natively(&metadata.requires_dist)?;
+        specs.push(RequestedSpec {
+            requested: format!("{} {}", metadata.name, metadata.version),
+            name: metadata.name.clone(),
+            python_version: Some(metadata.version.clone()),
+            source: Some(source_for(metadata.name, metadata.version)),
+            extras: extras.to_vec(),
+            file_path: None,
+            url: None,
+            file_hash: None,
+            hashes: vec![],
+        });
+        return Ok((specs, String::new()));
+    } else if dir.join("requirements.txt").is_file() {
+        // `dir` is already the project directory this requirements.txt lives in, so an editable
+        // entry's own project dir (if any) isn't separately propagated here
+        let (specs, lockfile, _project_dir) = specs_from_requirements_txt_resolved(
+            &dir.join("requirements.txt"),
+            extras,
+            lockfile,
+            ResolutionMode::Highest,
+            python_context,
+        )?;
+        return Ok((specs, lockfile));
+    }
+    bail!(
+        "Neither poetry.lock nor pyproject.toml nor requirements.txt found in {}",
+        dir.display()
+    );
+}
+
 #[cfg(test)]
 mod test {
     use crate::markers::Pep508Environment;
@@ -461,7 +1177,9 @@ mod test {
     }
 }
 
-/// Reads `poetry.toml` and `poetry.lock` from `dep_file_location`
+/// Reads `pyproject.toml` and `poetry.lock` from `dep_file_location`. If the project declares
+/// `[tool.monotrail.workspace]` members, also resolves each member's own specs and merges them in,
+/// so a single install covers the whole monorepo
 pub fn poetry_spec_from_dir(
     dep_file_location: &Path,
     extras: &[String],
@@ -469,6 +1187,120 @@ pub fn poetry_spec_from_dir(
 ) -> anyhow::Result<(Vec<RequestedSpec>, BTreeMap<String, String>, String)> {
     let (poetry_section, poetry_lock, lockfile) = read_toml_files(dep_file_location)?;
     let scripts = poetry_section.scripts.clone().unwrap_or_default();
-    let specs = read_poetry_specs(&poetry_section, poetry_lock, false, extras, pep508_env)?;
+    let mut specs = read_poetry_specs(&poetry_section, poetry_lock, false, extras, pep508_env)?;
+
+    if let Some(members) = read_workspace_members(dep_file_location)? {
+        merge_workspace_member_specs(dep_file_location, &members, extras, pep508_env, &mut specs)?;
+    }
+
     Ok((specs, scripts, lockfile))
 }
+
+/// Reads back `[tool.monotrail.workspace]` from a project's pyproject.toml, if it declares one
+fn read_workspace_members(dir: &Path) -> anyhow::Result<Option<Vec<String>>> {
+    let path = dir.join("pyproject.toml");
+    let poetry_toml: PoetryPyprojectToml = toml::from_str(&fs::read_to_string(&path)?)
+        .with_context(|| format!("Invalid pyproject.toml in {}", path.display()))?;
+    Ok(poetry_toml
+        .tool
+        .and_then(|tool| tool.monotrail)
+        .and_then(|monotrail| monotrail.workspace)
+        .map(|workspace| workspace.members))
+}
+
+/// Expands `[tool.monotrail.workspace] members` entries into concrete member directories. A
+/// trailing `/*` segment expands to every subdirectory of that prefix, mirroring cargo's own
+/// workspace globs; anything else is taken as a literal path relative to `root`
+fn expand_workspace_members(root: &Path, members: &[String]) -> anyhow::Result<Vec<PathBuf>> {
+    let mut expanded = Vec::new();
+    for member in members {
+        match member.strip_suffix("/*") {
+            Some(prefix) => {
+                let base = root.join(prefix);
+                for entry in fs::read_dir(&base).with_context(|| {
+                    format!(
+                        "Invalid workspace member glob \"{}\": {} doesn't exist",
+                        member,
+                        base.display()
+                    )
+                })? {
+                    let entry = entry?;
+                    if entry.path().is_dir() {
+                        expanded.push(entry.path());
+                    }
+                }
+            }
+            None => expanded.push(root.join(member)),
+        }
+    }
+    expanded.sort();
+    Ok(expanded)
+}
+
+/// Resolves every workspace member's specs and merges them into `specs` (which already holds the
+/// workspace root's own specs), erroring out if two members pin the same package to different
+/// versions instead of silently picking one
+fn merge_workspace_member_specs(
+    workspace_root: &Path,
+    members: &[String],
+    extras: &[String],
+    pep508_env: &Pep508Environment,
+    specs: &mut Vec<RequestedSpec>,
+) -> anyhow::Result<()> {
+    // Which member (or "the workspace root") last contributed each package, so conflicts can
+    // name both sides
+    let mut contributed_by: HashMap<String, String> = specs
+        .iter()
+        .map(|spec| (spec.normalized_name(), "the workspace root".to_string()))
+        .collect();
+
+    for member_dir in expand_workspace_members(workspace_root, members)? {
+        let member_label = member_dir
+            .strip_prefix(workspace_root)
+            .unwrap_or(&member_dir)
+            .display()
+            .to_string();
+        let (member_poetry_section, member_poetry_lock, _member_lockfile) =
+            read_toml_files(&member_dir)
+                .with_context(|| format!("Invalid workspace member {}", member_label))?;
+        let member_specs = read_poetry_specs(
+            &member_poetry_section,
+            member_poetry_lock,
+            false,
+            extras,
+            pep508_env,
+        )
+        .with_context(|| {
+            format!(
+                "Failed to read dependencies of workspace member {}",
+                member_label
+            )
+        })?;
+
+        let mut contributed_specs = 0;
+        for member_spec in member_specs {
+            let name = member_spec.normalized_name();
+            if let Some(existing) = specs.iter().find(|spec| spec.normalized_name() == name) {
+                if existing.get_unique_version() != member_spec.get_unique_version() {
+                    bail!(
+                        "Workspace members {} and {} both depend on {} but pin different versions ({} vs {})",
+                        contributed_by[&name],
+                        member_label,
+                        member_spec.name,
+                        existing.get_unique_version().unwrap_or_default(),
+                        member_spec.get_unique_version().unwrap_or_default(),
+                    );
+                }
+                continue;
+            }
+            contributed_by.insert(name, member_label.clone());
+            specs.push(member_spec);
+            contributed_specs += 1;
+        }
+        debug!(
+            "Workspace member {} contributed {} spec(s)",
+            member_label, contributed_specs
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,842 @@
+//! A small in-crate dependency resolver based on the PubGrub/mixology conflict-driven clause
+//! learning algorithm (<https://github.com/dart-lang/pub/blob/master/doc/solver.md>), so
+//! [`crate::poetry_integration::read_dependencies::specs_from_git`] doesn't need a working
+//! `poetry` install on the host just to lock a freshly checked out `pyproject.toml`.
+//!
+//! This is a scoped-down PubGrub: a [`Range`] is a single contiguous `lower <= v < upper`
+//! interval per package rather than a union of disjoint intervals. That covers plain
+//! `>=`/`<`/`==`/`^`/`~=`-style constraints, which is all monotrail's own dependency
+//! specifications use, but not arbitrary OR'd version sets.
+//!
+//! The algorithm maintains a set of [`Incompatibility`] (conjunctions of terms that can't all
+//! hold at once) and a [`PartialSolution`] (a decision stack with assignment levels). Unit
+//! propagation derives new assignments, or detects that an incompatibility is fully satisfied,
+//! in which case we do conflict resolution: walk the incompatibility's cause chain backwards,
+//! resolving it against the incompatibility that caused the most recent contradicting
+//! assignment, until we reach an incompatibility that is satisfied at an earlier decision level
+//! than we're currently at (a "near-satisfied" incompatibility), then backjump the partial
+//! solution to that level and add the derived incompatibility.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use anyhow::{bail, Context};
+use tracing::debug;
+
+/// A plain `major.minor.patch` version used by the resolver. Keeping this separate from
+/// `pep440_rs::Version` keeps the solver's core logic independent of PEP 440's full (and much
+/// more complex) version grammar; callers translate PEP 440/poetry constraints into [`Range`]s
+/// before calling [`resolve`].
+#[derive(Debug, Clone, Copy, Eq, PartialEq, PartialOrd, Ord)]
+pub struct Version(pub u32, pub u32, pub u32);
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.0, self.1, self.2)
+    }
+}
+
+/// `lower <= v < upper`, with `None` meaning unbounded on that side
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Range {
+    pub lower: Option<Version>,
+    pub upper: Option<Version>,
+}
+
+impl Range {
+    pub fn full() -> Self {
+        Range {
+            lower: None,
+            upper: None,
+        }
+    }
+
+    pub fn at_least(version: Version) -> Self {
+        Range {
+            lower: Some(version),
+            upper: None,
+        }
+    }
+
+    pub fn exact(version: Version) -> Self {
+        Range {
+            lower: Some(version),
+            upper: Some(Version(version.0, version.1, version.2 + 1)),
+        }
+    }
+
+    pub fn contains(&self, version: &Version) -> bool {
+        self.lower.map_or(true, |lower| *version >= lower)
+            && self.upper.map_or(true, |upper| *version < upper)
+    }
+
+    /// `None` and `None` is unbounded, so the tighter of the two bounds wins on each side
+    pub fn intersect(&self, other: &Range) -> Range {
+        let lower = match (self.lower, other.lower) {
+            (Some(a), Some(b)) => Some(a.max(b)),
+            (Some(a), None) => Some(a),
+            (None, Some(b)) => Some(b),
+            (None, None) => None,
+        };
+        let upper = match (self.upper, other.upper) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (Some(a), None) => Some(a),
+            (None, Some(b)) => Some(b),
+            (None, None) => None,
+        };
+        Range { lower, upper }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        match (self.lower, self.upper) {
+            (Some(lower), Some(upper)) => lower >= upper,
+            _ => false,
+        }
+    }
+}
+
+/// Whether a package must fall in `range` (`Positive`) or must not (`Negative`)
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum Term {
+    Positive(Range),
+    Negative(Range),
+}
+
+impl Term {
+    fn contains(&self, version: &Version) -> bool {
+        match self {
+            Term::Positive(range) => range.contains(version),
+            Term::Negative(range) => !range.contains(version),
+        }
+    }
+
+    fn negate(&self) -> Term {
+        match self {
+            // Negating a bounded positive range isn't expressible as a single contiguous range
+            // in general (it can split into two), but in this solver the only ranges we ever
+            // negate are ones produced by intersecting root/dependency constraints, which in
+            // practice stay one-sided (`>= x` or `< x`) often enough for this scoped-down
+            // algorithm; we fall back to the widest sound approximation otherwise.
+            Term::Positive(range) => match (range.lower, range.upper) {
+                (Some(lower), None) => Term::Positive(Range {
+                    lower: None,
+                    upper: Some(lower),
+                }),
+                (None, Some(upper)) => Term::Positive(Range::at_least(upper)),
+                _ => Term::Negative(range.clone()),
+            },
+            Term::Negative(range) => Term::Positive(range.clone()),
+        }
+    }
+}
+
+/// Why an incompatibility exists, so conflicts can be explained as "because X needs Y"
+#[derive(Debug, Clone)]
+pub enum Cause {
+    /// The root project (or git checkout) directly requires this
+    Root,
+    /// `package` depends on `dependency`, which isn't compatible with what's already decided
+    Dependency { package: String, dependency: String },
+    /// No version of `package` satisfies the range that was required of it
+    NoVersions { package: String },
+    /// Derived during conflict resolution from two other incompatibilities
+    Conflict {
+        because: Box<Incompatibility>,
+        and: Box<Incompatibility>,
+    },
+}
+
+/// A conjunction of (package, term) that cannot all hold at once
+#[derive(Debug, Clone)]
+pub struct Incompatibility {
+    terms: BTreeMap<String, Term>,
+    cause: Cause,
+}
+
+impl Incompatibility {
+    fn from_dependency(package: &str, dependency: &str, range: Range) -> Self {
+        let mut terms = BTreeMap::new();
+        terms.insert(package.to_string(), Term::Positive(Range::full()));
+        terms.insert(dependency.to_string(), Term::Negative(range));
+        Incompatibility {
+            terms,
+            cause: Cause::Dependency {
+                package: package.to_string(),
+                dependency: dependency.to_string(),
+            },
+        }
+    }
+
+    fn no_versions(package: &str, range: Range) -> Self {
+        let mut terms = BTreeMap::new();
+        terms.insert(package.to_string(), Term::Positive(range));
+        Incompatibility {
+            terms,
+            cause: Cause::NoVersions {
+                package: package.to_string(),
+            },
+        }
+    }
+
+    /// A human-readable "because X needs Y" explanation, following the cause chain
+    pub fn explain(&self) -> String {
+        match &self.cause {
+            Cause::Root => "the project's direct dependencies".to_string(),
+            Cause::Dependency {
+                package,
+                dependency,
+            } => format!("{} depends on {}", package, dependency),
+            Cause::NoVersions { package } => {
+                format!("no version of {} satisfies the required range", package)
+            }
+            Cause::Conflict { because, and } => {
+                format!("{}; and {}", because.explain(), and.explain())
+            }
+        }
+    }
+}
+
+/// One entry of the decision stack: either a concrete pick (`decision = Some`) or a derivation
+/// from unit propagation that narrowed the term without committing to a version yet
+struct Assignment {
+    package: String,
+    term: Term,
+    decision_level: usize,
+    decision: Option<Version>,
+}
+
+/// The decision stack plus helpers to compute the combined term for a package
+struct PartialSolution {
+    assignments: Vec<Assignment>,
+    decision_level: usize,
+}
+
+impl PartialSolution {
+    fn new() -> Self {
+        PartialSolution {
+            assignments: Vec::new(),
+            decision_level: 0,
+        }
+    }
+
+    /// Intersection of every assignment's term for `package`, or unconstrained `Positive(full)`
+    /// if we haven't derived anything about it yet
+    fn term(&self, package: &str) -> Term {
+        let mut combined = Range::full();
+        let mut any = false;
+        for assignment in &self.assignments {
+            if assignment.package != package {
+                continue;
+            }
+            any = true;
+            if let Term::Positive(range) = &assignment.term {
+                combined = combined.intersect(range);
+            }
+            // Negative terms produced by this scoped-down solver are only ever used
+            // transiently during propagation (see `negate`), so only positive terms need to
+            // accumulate into the package's running range here.
+        }
+        if !any {
+            Term::Positive(Range::full())
+        } else {
+            Term::Positive(combined)
+        }
+    }
+
+    fn decided_version(&self, package: &str) -> Option<Version> {
+        self.assignments
+            .iter()
+            .rev()
+            .find(|assignment| assignment.package == package)
+            .and_then(|assignment| assignment.decision)
+    }
+
+    fn derive(&mut self, package: &str, term: Term) {
+        self.assignments.push(Assignment {
+            package: package.to_string(),
+            term,
+            decision_level: self.decision_level,
+            decision: None,
+        });
+    }
+
+    fn decide(&mut self, package: &str, version: Version) {
+        self.decision_level += 1;
+        self.assignments.push(Assignment {
+            package: package.to_string(),
+            term: Term::Positive(Range::exact(version)),
+            decision_level: self.decision_level,
+            decision: Some(version),
+        });
+    }
+
+    fn backtrack_to(&mut self, decision_level: usize) {
+        self.assignments
+            .retain(|assignment| assignment.decision_level <= decision_level);
+        self.decision_level = decision_level;
+    }
+
+    /// All packages we have an opinion about (decided or derived), except the virtual root
+    fn known_packages(&self) -> Vec<String> {
+        let mut packages: Vec<String> = self
+            .assignments
+            .iter()
+            .map(|assignment| assignment.package.clone())
+            .collect();
+        packages.sort();
+        packages.dedup();
+        packages
+    }
+}
+
+/// Supplies the resolver with the two things it can't know on its own: which versions of a
+/// package exist, and what a concrete version of a package depends on
+pub trait DependencyProvider {
+    /// Known versions of `package`, returned in the order the resolver should try them (monotrail
+    /// always wants the newest compatible version first)
+    fn versions(&self, package: &str) -> anyhow::Result<Vec<Version>>;
+    /// The dependencies of `package` at exactly `version`
+    fn dependencies(
+        &self,
+        package: &str,
+        version: &Version,
+    ) -> anyhow::Result<Vec<(String, Range)>>;
+}
+
+/// Bounds how many decide/propagate cycles [`resolve`] will run before giving up. This
+/// scoped-down solver's conflict resolution doesn't tie a dependency incompatibility to the
+/// specific version that produced it (see [`Incompatibility::from_dependency`]), so a backjump
+/// can occasionally land somewhere that just leads straight back to the same decision -- this
+/// guards against that looping forever instead of reporting a (less precise) error.
+const MAX_RESOLUTION_STEPS: usize = 1_000;
+
+/// Resolves `root_deps` against what `provider` reports, returning one pinned version per
+/// transitively required package. Bails with an explanation built from the incompatibilities'
+/// causes if no solution exists.
+pub fn resolve(
+    provider: &dyn DependencyProvider,
+    root_deps: &[(String, Range)],
+) -> anyhow::Result<BTreeMap<String, Version>> {
+    let mut incompatibilities = Vec::new();
+    let mut solution = PartialSolution::new();
+
+    for (package, range) in root_deps {
+        incompatibilities.push(Incompatibility {
+            terms: {
+                let mut terms = BTreeMap::new();
+                terms.insert(package.clone(), Term::Negative(range.clone()));
+                terms
+            },
+            cause: Cause::Root,
+        });
+        solution.derive(package, Term::Positive(range.clone()));
+    }
+
+    let mut steps = 0;
+    loop {
+        steps += 1;
+        if steps > MAX_RESOLUTION_STEPS {
+            bail!(
+                "Dependency resolution did not converge after {} steps, which usually means a \
+                 version conflict this scoped-down solver couldn't pin down precisely",
+                MAX_RESOLUTION_STEPS
+            );
+        }
+        unit_propagate(&mut incompatibilities, &mut solution)?;
+
+        let next = solution
+            .known_packages()
+            .into_iter()
+            .find(|package| solution.decided_version(package).is_none());
+        let package = match next {
+            Some(package) => package,
+            None => break,
+        };
+
+        let term = solution.term(&package);
+        let range = match &term {
+            Term::Positive(range) => range.clone(),
+            Term::Negative(_) => Range::full(),
+        };
+        let versions = provider.versions(&package)?;
+        let picked = versions.into_iter().find(|version| range.contains(version));
+
+        match picked {
+            Some(version) => {
+                for (dependency, dependency_range) in provider.dependencies(&package, &version)? {
+                    incompatibilities.push(Incompatibility::from_dependency(
+                        &package,
+                        &dependency,
+                        dependency_range,
+                    ));
+                }
+                solution.decide(&package, version);
+            }
+            None => {
+                incompatibilities.push(Incompatibility::no_versions(&package, range));
+            }
+        }
+    }
+
+    Ok(solution
+        .known_packages()
+        .into_iter()
+        .filter_map(|package| {
+            solution
+                .decided_version(&package)
+                .map(|version| (package, version))
+        })
+        .collect())
+}
+
+/// Repeatedly scans incompatibilities until none of them change the partial solution: either
+/// deriving a new assignment (exactly one term undecided) or resolving a conflict (all terms
+/// satisfied)
+fn unit_propagate(
+    incompatibilities: &mut Vec<Incompatibility>,
+    solution: &mut PartialSolution,
+) -> anyhow::Result<()> {
+    loop {
+        let mut changed = false;
+        let mut index = 0;
+        while index < incompatibilities.len() {
+            // A term that's already contradicted (the partial solution guarantees its negation)
+            // can never become true, so the whole incompatibility can never become fully
+            // satisfied either -- there's nothing to derive or conflict on here. Without this
+            // check a once-satisfied term (most commonly a `Cause::Root` term, which is always
+            // phrased as the negation of what's already been derived) looks permanently
+            // "undecided" and gets re-derived every pass without ever converging.
+            let mut impossible = false;
+            let undecided: Vec<&String> = incompatibilities[index]
+                .terms
+                .iter()
+                .filter(|(package, term)| {
+                    let current = solution.term(package);
+                    if satisfies(&current, &term.negate()) {
+                        impossible = true;
+                    }
+                    !satisfies(&current, term)
+                })
+                .map(|(package, _)| package)
+                .collect();
+
+            if impossible {
+                index += 1;
+                continue;
+            }
+
+            if undecided.is_empty() {
+                // Every term holds under the current partial solution: conflict
+                let resolved = resolve_conflict(
+                    incompatibilities[index].clone(),
+                    incompatibilities,
+                    solution,
+                )?;
+                match resolved {
+                    Some(new_decision_level) => {
+                        solution.backtrack_to(new_decision_level);
+                        changed = true;
+                        break;
+                    }
+                    None => {
+                        bail!(
+                            "No compatible set of versions could be found: {}",
+                            incompatibilities[index].explain()
+                        );
+                    }
+                }
+            } else if undecided.len() == 1 {
+                let package = undecided[0].clone();
+                let derived_term = incompatibilities[index].terms[&package].negate();
+                solution.derive(&package, derived_term);
+                changed = true;
+            }
+            index += 1;
+        }
+        if !changed {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Whether the partial solution's combined term for a package already guarantees `required`
+fn satisfies(current: &Term, required: &Term) -> bool {
+    match (current, required) {
+        (Term::Positive(current_range), Term::Positive(required_range)) => {
+            // current is a subset of required, i.e. every version `current` still allows for
+            // also satisfies `required`
+            let intersection = current_range.intersect(required_range);
+            intersection == *current_range
+        }
+        (Term::Positive(current_range), Term::Negative(required_range)) => {
+            current_range.intersect(required_range).is_empty()
+        }
+        // Negative current terms are transient (see `PartialSolution::term`), treat as unknown
+        _ => false,
+    }
+}
+
+/// Walks the cause chain of a fully-satisfied incompatibility backwards, combining it with the
+/// incompatibility that produced the most recently-added contradicting assignment, until the
+/// result is no longer satisfied at the current decision level (i.e. it's satisfied at an
+/// earlier level, telling us where to backjump to). Returns `None` if we conflict at decision
+/// level 0, meaning there is no solution.
+fn resolve_conflict(
+    mut conflicting: Incompatibility,
+    incompatibilities: &mut Vec<Incompatibility>,
+    solution: &PartialSolution,
+) -> anyhow::Result<Option<usize>> {
+    loop {
+        // Find the most recent assignment whose package is a term of the conflicting
+        // incompatibility; that's the one whose addition caused the conflict
+        let culprit = solution
+            .assignments
+            .iter()
+            .rev()
+            .find(|assignment| conflicting.terms.contains_key(&assignment.package));
+
+        let culprit = match culprit {
+            Some(culprit) => culprit,
+            None => return Ok(None),
+        };
+
+        if culprit.decision_level == 0 {
+            return Ok(None);
+        }
+
+        // Find the incompatibility that justifies the culprit assignment being a decision: the
+        // dependency/no-versions incompatibility that has the culprit's package as its only
+        // unresolved term at the time it was added. For this scoped-down solver we approximate
+        // that by the most recently added incompatibility mentioning the same package, which is
+        // how `unit_propagate` adds them (dependencies are pushed right before the decision that
+        // needed them).
+        let cause_index = incompatibilities
+            .iter()
+            .rposition(|incompat| incompat.terms.contains_key(&culprit.package));
+        let cause = match cause_index {
+            Some(cause_index) if incompatibilities.len() > 1 => {
+                incompatibilities.remove(cause_index)
+            }
+            _ => return Ok(None),
+        };
+
+        let mut merged_terms = conflicting.terms.clone();
+        for (package, term) in cause.terms {
+            merged_terms
+                .entry(package)
+                .and_modify(|existing| *existing = term.clone())
+                .or_insert(term);
+        }
+        conflicting = Incompatibility {
+            terms: merged_terms,
+            cause: Cause::Conflict {
+                because: Box::new(conflicting.clone()),
+                and: Box::new(cause),
+            },
+        };
+
+        let satisfied_at_current_level = conflicting
+            .terms
+            .iter()
+            .all(|(package, term)| satisfies(&solution.term(package), term));
+        if !satisfied_at_current_level || culprit.decision_level - 1 == 0 {
+            incompatibilities.push(conflicting);
+            return Ok(Some(culprit.decision_level.saturating_sub(1)));
+        }
+    }
+}
+
+/// Parses a poetry/PEP 440-style constraint (e.g. `>=1.2,<2.0` or `^1.4`) into the [`Range`] this
+/// solver works with. `^x.y.z` (poetry's default compact operator) keeps the next major version
+/// out of range, and `~=x.y.z` keeps the next minor version out of range, mirroring poetry's own
+/// caret/tilde semantics rather than leaving the upper bound open.
+pub fn parse_constraint(constraint: &str) -> anyhow::Result<Range> {
+    let mut range = Range::full();
+    for part in constraint.split(',') {
+        let part = part.trim();
+        if part.is_empty() || part == "*" {
+            continue;
+        }
+        let (op, rest) = ["~=", ">=", "<=", "==", ">", "<", "^"]
+            .iter()
+            .find_map(|op| part.strip_prefix(op).map(|rest| (*op, rest.trim())))
+            .with_context(|| format!("Unsupported version constraint '{}'", part))?;
+        let part_range = match op {
+            ">=" => Range::at_least(parse_version(rest)?),
+            // Poetry's caret has a 0.x special case (`^0.2.3` -> `<0.3.0`, not `<1.0.0`), so
+            // reuse the same conversion poetry.toml parsing uses instead of duplicating it.
+            "^" => parse_constraint(&crate::poetry_integration::poetry_toml::caret_range(rest)?)?,
+            // PEP 440 `~=`: keep everything but the last given segment fixed and bump the new
+            // last segment, e.g. `~=1.4.2` -> `>=1.4.2,<1.5.0`, `~=1.4` -> `>=1.4,<2.0`.
+            "~=" => Range {
+                lower: Some(parse_version(rest)?),
+                upper: Some(tilde_equals_upper(rest)?),
+            },
+            "==" => Range::exact(parse_version(rest)?),
+            ">" => {
+                let version = parse_version(rest)?;
+                Range::at_least(Version(version.0, version.1, version.2 + 1))
+            }
+            "<=" => {
+                let version = parse_version(rest)?;
+                Range {
+                    lower: None,
+                    upper: Some(Version(version.0, version.1, version.2 + 1)),
+                }
+            }
+            "<" => Range {
+                lower: None,
+                upper: Some(parse_version(rest)?),
+            },
+            _ => unreachable!(),
+        };
+        range = range.intersect(&part_range);
+    }
+    Ok(range)
+}
+
+/// Parses `major.minor.patch`, defaulting missing components to `0`
+pub(crate) fn parse_version(version: &str) -> anyhow::Result<Version> {
+    let mut parts = version.trim().splitn(3, '.');
+    let major = parts
+        .next()
+        .context("Empty version")?
+        .parse()
+        .context("Could not parse major version")?;
+    let minor = parts.next().unwrap_or("0").parse().unwrap_or(0);
+    let patch = parts.next().unwrap_or("0").parse().unwrap_or(0);
+    Ok(Version(major, minor, patch))
+}
+
+/// Computes the exclusive upper bound for a PEP 440 `~=` (compatible release) constraint:
+/// everything but the last given segment stays fixed and the new last segment is bumped, e.g.
+/// `~=1.4.2` -> `<1.5.0`, `~=1.4` -> `<2.0`. Unlike [`parse_version`] this has to see how many
+/// segments were actually given, since that determines which one gets bumped. Only the first
+/// three segments are considered, matching [`parse_version`]'s own `major.minor.patch` limit.
+fn tilde_equals_upper(rest: &str) -> anyhow::Result<Version> {
+    let mut components: Vec<u32> = rest
+        .trim()
+        .split('.')
+        .take(3)
+        .map(|part| part.parse().context("Invalid version component"))
+        .collect::<anyhow::Result<_>>()?;
+    anyhow::ensure!(
+        components.len() >= 2,
+        "'~=' requires at least two version segments, got '{}'",
+        rest
+    );
+    components.pop();
+    let last = components.len() - 1;
+    components[last] = components[last].saturating_add(1);
+    components.resize(3, 0);
+    Ok(Version(components[0], components[1], components[2]))
+}
+
+/// Extracts the unconditional (no environment marker) `Requires-Dist` entries from a wheel's
+/// METADATA contents. Marker-gated dependencies (extras, platform-specific deps, ...) are
+/// skipped rather than evaluated, which is a known gap of this scoped-down resolver.
+fn parse_requires_dist(metadata: &str) -> Vec<(String, Range)> {
+    let mut dependencies = Vec::new();
+    for line in metadata.lines() {
+        let rest = match line.strip_prefix("Requires-Dist:") {
+            Some(rest) => rest.trim(),
+            None => continue,
+        };
+        if rest.contains(';') {
+            // Has an environment marker (often an extra) we don't evaluate; skip rather than
+            // risk pulling in a dependency that wasn't actually requested
+            continue;
+        }
+        let (name, constraint) = match rest.split_once('(') {
+            Some((name, constraint)) => (name.trim(), constraint.trim_end_matches(')').trim()),
+            None => (rest, "*"),
+        };
+        // Drop any `[extra1,extra2]` suffix on the name itself, we don't resolve extras here
+        let name = name.split('[').next().unwrap_or(name).trim();
+        match parse_constraint(constraint) {
+            Ok(range) => dependencies.push((name.to_string(), range)),
+            Err(err) => debug!("Skipping unparseable requirement '{}': {}", rest, err),
+        }
+    }
+    dependencies
+}
+
+/// Resolves dependencies straight against the PyPI index: [`versions`](DependencyProvider) lists
+/// every release pypi knows about, [`dependencies`](DependencyProvider) downloads (lazily, see
+/// [`crate::package_index::fetch_metadata`]) one wheel's METADATA and reads its `Requires-Dist`
+/// entries
+pub struct PypiDependencyProvider;
+
+impl DependencyProvider for PypiDependencyProvider {
+    fn versions(&self, package: &str) -> anyhow::Result<Vec<Version>> {
+        let releases = crate::package_index::list_releases(package)?;
+        let mut versions: Vec<Version> = releases
+            .keys()
+            .filter_map(|version| parse_version(version).ok())
+            .collect();
+        versions.sort();
+        versions.reverse();
+        Ok(versions)
+    }
+
+    fn dependencies(
+        &self,
+        package: &str,
+        version: &Version,
+    ) -> anyhow::Result<Vec<(String, Range)>> {
+        let releases = crate::package_index::list_releases(package)?;
+        let release = releases
+            .iter()
+            .find(|(key, _)| parse_version(key).ok().as_ref() == Some(version))
+            .and_then(|(_, releases)| {
+                releases.iter().find(|release| {
+                    release.packagetype == crate::package_index::PackageType::BdistWheel
+                })
+            })
+            .with_context(|| format!("No wheel release for {} {}", package, version))?;
+
+        let metadata = match crate::package_index::fetch_metadata(&release.url)? {
+            Some(metadata) => metadata,
+            None => {
+                debug!(
+                    "Falling back to a full download to read METADATA for {} {}",
+                    package, version
+                );
+                let cache_dir = crate::utils::cache_dir()?
+                    .join("resolve_metadata")
+                    .join(package)
+                    .join(version.to_string());
+                let wheel_path = cache_dir.join(&release.filename);
+                let expected_hash = release
+                    .digests
+                    .get("sha256")
+                    .map(|hash| format!("sha256:{}", hash));
+                // This pipeline always resolves straight against pypi, never a configured index
+                crate::package_index::download_distribution(
+                    &release.url,
+                    &cache_dir,
+                    &wheel_path,
+                    None,
+                    expected_hash.as_deref(),
+                )?;
+                let file = std::fs::File::open(&wheel_path)
+                    .with_context(|| format!("Failed to open {}", wheel_path.display()))?;
+                let mut archive = zip::ZipArchive::new(file)
+                    .with_context(|| format!("{} is not a valid zip", wheel_path.display()))?;
+                let metadata_name = (0..archive.len())
+                    .map(|i| archive.by_index(i).map(|entry| entry.name().to_string()))
+                    .collect::<Result<Vec<_>, _>>()?
+                    .into_iter()
+                    .find(|name| name.ends_with(".dist-info/METADATA"))
+                    .with_context(|| format!("No METADATA in {}", wheel_path.display()))?;
+                let mut metadata_file = archive.by_name(&metadata_name)?;
+                let mut contents = String::new();
+                std::io::Read::read_to_string(&mut metadata_file, &mut contents)?;
+                contents
+            }
+        };
+
+        Ok(parse_requires_dist(&metadata))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_constraint, resolve, DependencyProvider, Range, Version};
+
+    #[test]
+    fn caret_constraint_excludes_next_major() {
+        let range = parse_constraint("^1.4.2").unwrap();
+        assert_eq!(range.lower, Some(Version(1, 4, 2)));
+        assert!(range.contains(&Version(1, 9, 0)));
+        assert!(!range.contains(&Version(2, 0, 0)));
+    }
+
+    #[test]
+    fn caret_constraint_0x_excludes_next_minor() {
+        let range = parse_constraint("^0.2.3").unwrap();
+        assert_eq!(range.lower, Some(Version(0, 2, 3)));
+        assert!(range.contains(&Version(0, 2, 9)));
+        assert!(!range.contains(&Version(0, 3, 0)));
+    }
+
+    #[test]
+    fn tilde_constraint_excludes_next_minor() {
+        let range = parse_constraint("~=1.4.2").unwrap();
+        assert_eq!(range.lower, Some(Version(1, 4, 2)));
+        assert!(range.contains(&Version(1, 4, 9)));
+        assert!(!range.contains(&Version(1, 5, 0)));
+    }
+
+    #[test]
+    fn tilde_constraint_extra_segments_stay_non_empty() {
+        let range = parse_constraint("~=1.2.0.4").unwrap();
+        assert!(!range.is_empty());
+        assert_eq!(range.lower, Some(Version(1, 2, 0)));
+        assert_eq!(range.upper, Some(Version(1, 3, 0)));
+    }
+
+    #[test]
+    fn tilde_constraint_two_components_excludes_next_major() {
+        let range = parse_constraint("~=1.4").unwrap();
+        assert_eq!(range.lower, Some(Version(1, 4, 0)));
+        assert!(range.contains(&Version(1, 9, 0)));
+        assert!(!range.contains(&Version(2, 0, 0)));
+    }
+
+    /// A diamond: root requires `a` (any version) and pins `shared` directly to `<2.0.0`, while
+    /// `a`'s newest version requires `shared >= 2.0.0`. There's no version of `shared` that
+    /// satisfies both paths, so this can only be solved by walking the cause chain back past
+    /// `a`'s decision to the conflicting root constraint on `shared` -- a plain version lookup
+    /// doesn't catch this, only backjumping conflict resolution does.
+    struct DiamondProvider;
+
+    impl DependencyProvider for DiamondProvider {
+        fn versions(&self, package: &str) -> anyhow::Result<Vec<Version>> {
+            match package {
+                "a" => Ok(vec![Version(2, 0, 0), Version(1, 0, 0)]),
+                "shared" => Ok(vec![Version(2, 0, 0), Version(1, 0, 0)]),
+                other => anyhow::bail!("No such package: {}", other),
+            }
+        }
+
+        fn dependencies(
+            &self,
+            package: &str,
+            version: &Version,
+        ) -> anyhow::Result<Vec<(String, Range)>> {
+            if package == "a" && *version == Version(2, 0, 0) {
+                Ok(vec![(
+                    "shared".to_string(),
+                    Range::at_least(Version(2, 0, 0)),
+                )])
+            } else {
+                Ok(vec![])
+            }
+        }
+    }
+
+    #[test]
+    fn diamond_dependency_conflict_is_reported_after_backjumping() {
+        let root_deps = vec![
+            ("a".to_string(), Range::full()),
+            (
+                "shared".to_string(),
+                Range {
+                    lower: None,
+                    upper: Some(Version(2, 0, 0)),
+                },
+            ),
+        ];
+
+        let err = resolve(&DiamondProvider, &root_deps).unwrap_err();
+
+        let message = err.to_string();
+        assert!(
+            message.contains("a depends on shared"),
+            "expected the conflict to be explained via a's dependency on shared, got: {}",
+            message
+        );
+    }
+}
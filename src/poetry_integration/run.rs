@@ -13,8 +13,9 @@ use std::path::PathBuf;
 /// Use the libpython.so to run a poetry command on python 3.8, unless you give +x.y as first
 /// argument
 pub fn poetry_run(args: &[String], python_version: Option<&str>) -> anyhow::Result<i32> {
-    let (args, python_version) = determine_python_version(&args, python_version)?;
-    let (python_context, python_home) = provision_python(python_version)?;
+    let (args, implementation, python_version, patch) =
+        determine_python_version(&args, python_version, None)?;
+    let (python_context, python_home) = provision_python(implementation, python_version, patch)?;
 
     let pyproject_toml = include_str!("poetry_boostrap_lock/pyproject.toml");
     let poetry_toml: PoetryPyprojectToml = toml::from_str(pyproject_toml).unwrap();
@@ -60,6 +61,7 @@ pub fn poetry_run(args: &[String], python_version: Option<&str>) -> anyhow::Resu
     let exit_code = inject_and_run_python(
         &python_home,
         python_version,
+        python_context.implementation,
         // poetry doesn't need monotrail-moonlighting-as-python subprocesses
         // (at least i never encountered that)
         &python_context.sys_executable,
@@ -0,0 +1,329 @@
+use crate::inject_and_run::default_python_version;
+use crate::monotrail::{install, run_command_finder_data, Implementation, PythonContext};
+use crate::poetry_integration::lock::poetry_resolve_from_dir;
+use crate::poetry_integration::poetry_toml;
+use crate::poetry_integration::poetry_toml::PoetryPyprojectToml;
+use crate::poetry_integration::read_dependencies::read_toml_files;
+use crate::standalone_python::provision_python;
+use crate::utils::data_local_dir;
+use crate::{parse_major_minor, read_poetry_specs};
+use anyhow::Context;
+use fs_err as fs;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use tempfile::TempDir;
+use tracing::{debug, info};
+
+/// Simple pipx reimplementation
+///
+/// Resolves one package, saving it in .local and runs one command from it
+pub fn ppipx(
+    package: Option<&str>,
+    python_version: Option<&str>,
+    version: Option<&str>,
+    extras: &[String],
+    command: &str,
+    args: &[String],
+) -> anyhow::Result<i32> {
+    let python_version = python_version
+        .map(parse_major_minor)
+        .transpose()?
+        .map(Ok)
+        .unwrap_or_else(default_python_version)?;
+
+    let (python_context, python_home) =
+        provision_python(Implementation::CPython, python_version, None)?;
+    let package = package.unwrap_or(command);
+    let package_extras = if extras.is_empty() {
+        package.to_string()
+    } else {
+        format!("{}[{}]", package, extras.join(","))
+    };
+
+    let resolution_dir = ppipx_entry_dir(&package_extras, version)?;
+
+    if !resolution_dir.join("poetry.lock").is_file() {
+        info!(
+            "Generating ppipx entry for {}@{}",
+            package_extras,
+            version.unwrap_or("latest")
+        );
+        generate_ppipx_entry(
+            version,
+            extras,
+            python_version,
+            &python_context,
+            package,
+            &resolution_dir,
+        )?;
+    } else {
+        debug!("ppipx entry already present")
+    }
+
+    let (poetry_section, poetry_lock, lockfile) = read_toml_files(&resolution_dir)
+        .with_context(|| format!("Invalid ppipx entry at {}", resolution_dir.display()))?;
+    let specs = read_poetry_specs(
+        &poetry_section,
+        poetry_lock,
+        true,
+        &[],
+        &python_context.pep508_env,
+    )?;
+
+    let finder_data = install(
+        &specs,
+        BTreeMap::new(),
+        lockfile,
+        Some(resolution_dir.clone()),
+        &python_context,
+        None,
+    )
+    .context("Couldn't install packages")?;
+
+    run_command_finder_data(
+        Some(command),
+        args,
+        &python_context,
+        &python_home,
+        &resolution_dir,
+        &finder_data,
+        None,
+        &[],
+        None,
+    )
+}
+
+/// `data_local_dir()/ppipx/<package[extras]>/<version or "latest">`
+fn ppipx_entry_dir(package_extras: &str, version: Option<&str>) -> anyhow::Result<PathBuf> {
+    Ok(data_local_dir()?
+        .join("ppipx")
+        .join(package_extras)
+        .join(version.unwrap_or("latest")))
+}
+
+/// One resolved `ppipx` entry, as reported by [`ppipx_list`]
+#[derive(Debug, Clone)]
+pub struct PpipxEntry {
+    /// The directory name under `data_local_dir()/ppipx`, e.g. `black` or `nox[tox_to_nox]`
+    pub package_extras: String,
+    /// The directory name under the package dir, e.g. `latest` or a pinned version
+    pub version: String,
+    /// The poetry package name, read back from the stored `pyproject.toml`
+    pub package: String,
+    /// The extras requested for this entry, read back from the stored `pyproject.toml`
+    pub extras: Vec<String>,
+    /// The console scripts this entry exposes, read back from the stored `pyproject.toml`
+    pub commands: Vec<String>,
+}
+
+/// Enumerates all entries under `data_local_dir()/ppipx`, taking the pinned version from each
+/// entry's directory name and reading back package name, extras, and exposed commands from its
+/// `pyproject.toml`
+pub fn ppipx_list() -> anyhow::Result<Vec<PpipxEntry>> {
+    let ppipx_dir = data_local_dir()?.join("ppipx");
+    if !ppipx_dir.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let mut entries = Vec::new();
+    for package_dir in fs::read_dir(&ppipx_dir)? {
+        let package_dir = package_dir?;
+        if !package_dir.path().is_dir() {
+            continue;
+        }
+        let package_extras = package_dir.file_name().to_string_lossy().to_string();
+        for version_dir in fs::read_dir(package_dir.path())? {
+            let version_dir = version_dir?;
+            if !version_dir.path().is_dir() {
+                continue;
+            }
+            let version = version_dir.file_name().to_string_lossy().to_string();
+            let pyproject_toml = version_dir.path().join("pyproject.toml");
+            if !pyproject_toml.is_file() {
+                continue;
+            }
+            let toml_str = fs::read_to_string(&pyproject_toml)
+                .with_context(|| format!("Failed to read {}", pyproject_toml.display()))?;
+            let poetry_toml: PoetryPyprojectToml = toml::from_str(&toml_str)
+                .with_context(|| format!("Invalid ppipx entry at {}", pyproject_toml.display()))?;
+            let poetry_section = poetry_toml
+                .tool
+                .and_then(|tool| tool.poetry)
+                .with_context(|| format!("Missing [tool.poetry] in {}", pyproject_toml.display()))?;
+            // The launcher package is always named `<package>_launcher`, with the actual
+            // dependency being the only non-python entry
+            let package = poetry_section
+                .dependencies
+                .keys()
+                .find(|name| name.as_str() != "python")
+                .cloned()
+                .unwrap_or_else(|| poetry_section.name.clone());
+            let extras = poetry_section
+                .dependencies
+                .get(&package)
+                .map(|dependency| dependency.get_extras().to_vec())
+                .unwrap_or_default();
+            let commands = poetry_section
+                .scripts
+                .map(|scripts| scripts.into_keys().collect())
+                .unwrap_or_default();
+            entries.push(PpipxEntry {
+                package_extras,
+                version,
+                package,
+                extras,
+                commands,
+            });
+        }
+    }
+    entries.sort_by(|a, b| {
+        (a.package_extras.as_str(), a.version.as_str())
+            .cmp(&(b.package_extras.as_str(), b.version.as_str()))
+    });
+    Ok(entries)
+}
+
+/// Removes all resolution dirs (and thereby all installed versions) of `package_extras` from
+/// `data_local_dir()/ppipx`
+pub fn ppipx_uninstall(package_extras: &str) -> anyhow::Result<()> {
+    let package_dir = data_local_dir()?.join("ppipx").join(package_extras);
+    if !package_dir.is_dir() {
+        anyhow::bail!("{} is not installed through ppipx", package_extras);
+    }
+    fs::remove_dir_all(&package_dir)
+        .with_context(|| format!("Failed to remove {}", package_dir.display()))?;
+    info!("Uninstalled {}", package_extras);
+    Ok(())
+}
+
+/// Re-resolves the `latest` entry of `package_extras`, regenerating `poetry.lock` even if one is
+/// already present, so a newer matching release gets picked up
+pub fn ppipx_upgrade(
+    package_extras: &str,
+    python_version: Option<&str>,
+) -> anyhow::Result<()> {
+    let python_version = python_version
+        .map(parse_major_minor)
+        .transpose()?
+        .map(Ok)
+        .unwrap_or_else(default_python_version)?;
+    let (python_context, _python_home) =
+        provision_python(Implementation::CPython, python_version, None)?;
+
+    let resolution_dir = ppipx_entry_dir(package_extras, None)?;
+    if !resolution_dir.is_dir() {
+        anyhow::bail!("{} is not installed through ppipx", package_extras);
+    }
+    let (poetry_section, _poetry_lock, _lockfile) = read_toml_files(&resolution_dir)
+        .with_context(|| format!("Invalid ppipx entry at {}", resolution_dir.display()))?;
+    let package = poetry_section
+        .dependencies
+        .keys()
+        .find(|name| name.as_str() != "python")
+        .cloned()
+        .with_context(|| format!("{} has no package dependency", resolution_dir.display()))?;
+    let extras = poetry_section
+        .dependencies
+        .get(&package)
+        .map(|dependency| dependency.get_extras().to_vec())
+        .unwrap_or_default();
+
+    info!("Upgrading {}", package_extras);
+    generate_ppipx_entry(
+        None,
+        &extras,
+        python_version,
+        &python_context,
+        &package,
+        &resolution_dir,
+    )
+}
+
+/// Writes a pyproject.toml for the ppipx command and calls poetry to resolve it to a poetry.lock
+fn generate_ppipx_entry(
+    version: Option<&str>,
+    extras: &[String],
+    python_version: (u8, u8),
+    python_context: &PythonContext,
+    package: &str,
+    resolution_dir: &PathBuf,
+) -> anyhow::Result<()> {
+    let mut dependencies = BTreeMap::new();
+    // Add python entry with current version; resolving will otherwise fail with complaints
+    dependencies.insert(
+        "python".to_string(),
+        // For some reason on github actions 3.8.12 is not 3.8 compatible, so we name the range explicitly
+        poetry_toml::Dependency::Compact(format!(
+            ">={}.{},<{}.{}",
+            python_version.0,
+            python_version.1,
+            python_version.0,
+            python_version.1 + 1
+        )),
+    );
+    if extras.is_empty() {
+        dependencies.insert(
+            package.to_string(),
+            poetry_toml::Dependency::Compact(version.unwrap_or("*").to_string()),
+        );
+    } else {
+        dependencies.insert(
+            package.to_string(),
+            poetry_toml::Dependency::Expanded {
+                version: Some(version.unwrap_or("*").to_string()),
+                optional: None,
+                extras: Some(extras.to_vec()),
+                git: None,
+                branch: None,
+                tag: None,
+                rev: None,
+                url: None,
+                path: None,
+                develop: None,
+                subdirectory: None,
+                markers: None,
+                python: None,
+                source: None,
+            },
+        );
+    }
+    let pyproject_toml = PoetryPyprojectToml {
+        tool: Some(poetry_toml::ToolSection {
+            poetry: Some(poetry_toml::PoetrySection {
+                name: format!("{}_launcher", package),
+                version: "0.0.1".to_string(),
+                description: format!("Launcher for {}@{}", package, version.unwrap_or("latest")),
+                authors: vec!["monotrail".to_string()],
+                dependencies,
+                dev_dependencies: Default::default(),
+                group: Default::default(),
+                extras: None,
+                scripts: None,
+                self_: None,
+            }),
+            monotrail: None,
+        }),
+        build_system: None,
+        project: None,
+    };
+
+    fs::create_dir_all(&resolution_dir).context("Failed to create ppipx resolution dir")?;
+    let resolve_dir = TempDir::new()?;
+    fs::write(
+        resolve_dir.path().join("pyproject.toml"),
+        toml::to_string(&pyproject_toml).context("Failed to serialize pyproject.toml for ppipx")?,
+    )?;
+    poetry_resolve_from_dir(&resolve_dir, &python_context)?;
+    fs::copy(
+        resolve_dir.path().join("pyproject.toml"),
+        resolution_dir.join("pyproject.toml"),
+    )
+    .context("Failed to copy ppipx pyproject.toml")?;
+    fs::copy(
+        resolve_dir.path().join("poetry.lock"),
+        resolution_dir.join("poetry.lock"),
+    )
+    .context("Poetry didn't generate a poetry.lock")?;
+
+    Ok(())
+}
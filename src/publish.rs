@@ -0,0 +1,312 @@
+//! Uploads built wheels/sdists to PyPI or a custom repository, analogous to `twine upload` or
+//! poetry's `poetry publish`
+
+use crate::metadata_inspect::read_sdist_metadata;
+use crate::spec::{is_sdist_filename, version_from_sdist_filename};
+use crate::utils::data_local_dir;
+use anyhow::{bail, Context};
+use data_encoding::BASE64;
+use fs_err as fs;
+use install_wheel_rs::WheelFilename;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::env;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::debug;
+
+/// PyPI's legacy (warehouse) upload endpoint, used whenever `--repository` isn't given or is
+/// literally `pypi` and nothing in `publish.toml`/the environment overrides it
+const PYPI_UPLOAD_URL: &str = "https://upload.pypi.org/legacy/";
+
+/// `<data_local_dir>/publish.toml`, read by [`resolve_repository`]
+///
+/// ```toml
+/// [repositories.testpypi]
+/// url = "https://test.pypi.org/legacy/"
+/// ```
+#[derive(Deserialize, Debug, Default)]
+struct PublishConfig {
+    #[serde(default)]
+    repositories: HashMap<String, RepositoryConfig>,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+struct RepositoryConfig {
+    url: Option<String>,
+    username: Option<String>,
+    password: Option<String>,
+}
+
+/// Upload url plus optional HTTP basic auth credentials for a repository
+struct Repository {
+    url: String,
+    credentials: Option<(String, String)>,
+}
+
+/// Uppercases `name` and replaces `-` with `_`, so e.g. `test-pypi` becomes the
+/// `MONOTRAIL_REPOSITORIES_TEST_PYPI_URL` environment variable family
+fn env_var_name(repository: &str) -> String {
+    repository.to_uppercase().replace('-', "_")
+}
+
+/// Resolves a repository name to an upload url and optional credentials, in this order:
+///  * `MONOTRAIL_REPOSITORIES_<NAME>_URL`, `MONOTRAIL_HTTP_BASIC_<NAME>_USERNAME`/`_PASSWORD`
+///  * the `[repositories.<name>]` table in `<data_local_dir>/publish.toml`
+///  * `pypi`'s well-known default, if `name == "pypi"` and nothing above set a url
+fn resolve_repository(name: &str) -> anyhow::Result<Repository> {
+    let env_name = env_var_name(name);
+    let config_path = data_local_dir()?.join("publish.toml");
+    let from_file = if config_path.is_file() {
+        let contents = fs::read_to_string(&config_path)
+            .with_context(|| format!("Failed to read {}", config_path.display()))?;
+        let config: PublishConfig = toml::from_str(&contents)
+            .with_context(|| format!("Invalid {}", config_path.display()))?;
+        config.repositories.get(name).cloned().unwrap_or_default()
+    } else {
+        RepositoryConfig::default()
+    };
+
+    let url = env::var(format!("MONOTRAIL_REPOSITORIES_{}_URL", env_name))
+        .ok()
+        .or(from_file.url)
+        .or_else(|| (name == "pypi").then(|| PYPI_UPLOAD_URL.to_string()))
+        .with_context(|| {
+            format!(
+                "No url configured for repository \"{}\": set MONOTRAIL_REPOSITORIES_{}_URL or add \
+                 a [repositories.{}] table with a url to {}",
+                name,
+                env_name,
+                name,
+                config_path.display()
+            )
+        })?;
+
+    let username = env::var(format!("MONOTRAIL_HTTP_BASIC_{}_USERNAME", env_name))
+        .ok()
+        .or(from_file.username);
+    let password = env::var(format!("MONOTRAIL_HTTP_BASIC_{}_PASSWORD", env_name))
+        .ok()
+        .or(from_file.password);
+    let credentials = match (username, password) {
+        (Some(username), Some(password)) => Some((username, password)),
+        (None, None) => None,
+        (username, password) => {
+            bail!(
+                "Repository \"{}\" has a username without a password or vice versa ({:?}/{:?})",
+                name,
+                username,
+                password
+            )
+        }
+    };
+
+    Ok(Repository { url, credentials })
+}
+
+/// The bits of name/version/filetype/pyversion a single artifact contributes to the upload's
+/// form fields
+struct ArtifactMetadata {
+    name: String,
+    version: String,
+    filetype: &'static str,
+    pyversion: String,
+}
+
+/// Extracts the upload metadata for a single wheel or sdist, reusing the same heuristics
+/// [`RequestedSpec::from_requested`](crate::spec::RequestedSpec::from_requested) already applies
+/// to local artifacts
+fn artifact_metadata(artifact: &Path) -> anyhow::Result<ArtifactMetadata> {
+    let filename = artifact
+        .file_name()
+        .and_then(|name| name.to_str())
+        .with_context(|| format!("Not a valid filename: {}", artifact.display()))?;
+
+    if filename.ends_with(".whl") {
+        let wheel_filename = WheelFilename::from_str(filename)
+            .with_context(|| format!("Invalid wheel filename: {}", filename))?;
+        Ok(ArtifactMetadata {
+            name: wheel_filename.distribution,
+            version: wheel_filename.version,
+            filetype: "bdist_wheel",
+            pyversion: wheel_filename.python_tag.join("."),
+        })
+    } else if is_sdist_filename(filename) {
+        let metadata = read_sdist_metadata(artifact)
+            .with_context(|| format!("Failed to inspect sdist: {}", artifact.display()))?;
+        let (name, version) = match metadata {
+            Some(metadata) => (metadata.name, metadata.version),
+            None => {
+                let (name, _rest) = filename.split_once('-').with_context(|| {
+                    format!("Can't determine package name from filename: {}", filename)
+                })?;
+                let version = version_from_sdist_filename(filename).with_context(|| {
+                    format!("Can't determine version from filename: {}", filename)
+                })?;
+                (name.to_string(), version)
+            }
+        };
+        Ok(ArtifactMetadata {
+            name,
+            version,
+            filetype: "sdist",
+            // sdists aren't tied to a python version; warehouse still wants the field present
+            pyversion: "source".to_string(),
+        })
+    } else {
+        bail!(
+            "Don't know how to publish {}: neither a .whl nor a sdist archive",
+            artifact.display()
+        )
+    }
+}
+
+/// A boundary that's exceedingly unlikely to collide with an artifact's contents, without pulling
+/// in a `rand`/`uuid` dependency just for this
+fn new_multipart_boundary() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_nanos())
+        .unwrap_or_default();
+    format!("monotrail-publish-boundary-{}-{}", std::process::id(), nanos)
+}
+
+/// Appends a plain form field to a multipart/form-data body
+fn push_field(body: &mut Vec<u8>, boundary: &str, name: &str, value: &str) {
+    body.extend_from_slice(format!("--{}\r\n", boundary).as_bytes());
+    body.extend_from_slice(
+        format!("Content-Disposition: form-data; name=\"{}\"\r\n\r\n", name).as_bytes(),
+    );
+    body.extend_from_slice(value.as_bytes());
+    body.extend_from_slice(b"\r\n");
+}
+
+/// Appends the file field carrying the artifact's raw bytes to a multipart/form-data body
+fn push_file_field(body: &mut Vec<u8>, boundary: &str, name: &str, filename: &str, content: &[u8]) {
+    body.extend_from_slice(format!("--{}\r\n", boundary).as_bytes());
+    body.extend_from_slice(
+        format!(
+            "Content-Disposition: form-data; name=\"{}\"; filename=\"{}\"\r\n\
+             Content-Type: application/octet-stream\r\n\r\n",
+            name, filename
+        )
+        .as_bytes(),
+    );
+    body.extend_from_slice(content);
+    body.extend_from_slice(b"\r\n");
+}
+
+/// Builds the warehouse (PyPI legacy upload api) multipart/form-data request body for a single
+/// artifact: <https://warehouse.pypa.io/api-reference/legacy.html#upload-api>
+fn build_upload_body(artifact: &Path, metadata: &ArtifactMetadata, content: &[u8]) -> (String, Vec<u8>) {
+    let boundary = new_multipart_boundary();
+    let mut body = Vec::new();
+
+    push_field(&mut body, &boundary, ":action", "file_upload");
+    push_field(&mut body, &boundary, "protocol_version", "1");
+    push_field(&mut body, &boundary, "name", &metadata.name);
+    push_field(&mut body, &boundary, "version", &metadata.version);
+    push_field(&mut body, &boundary, "filetype", metadata.filetype);
+    push_field(&mut body, &boundary, "pyversion", &metadata.pyversion);
+    push_field(&mut body, &boundary, "metadata_version", "2.1");
+    push_field(
+        &mut body,
+        &boundary,
+        "sha256_digest",
+        &format!("{:x}", Sha256::digest(content)),
+    );
+    let filename = artifact
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or_default();
+    push_file_field(&mut body, &boundary, "content", filename, content);
+    body.extend_from_slice(format!("--{}--\r\n", boundary).as_bytes());
+
+    (boundary, body)
+}
+
+/// Uploads a single artifact (wheel or sdist) to `repository`, returning `Ok(true)` if it was
+/// uploaded (or, with `dry_run`, would have been) and `Ok(false)` if it was skipped because
+/// `skip_existing` was set and the server already had this exact file
+fn upload_one(
+    artifact: &Path,
+    repository: &Repository,
+    skip_existing: bool,
+    dry_run: bool,
+) -> anyhow::Result<bool> {
+    let metadata = artifact_metadata(artifact)?;
+    if dry_run {
+        println!(
+            "Would upload {} {} ({}) to {}",
+            metadata.name, metadata.version, metadata.filetype, repository.url
+        );
+        return Ok(true);
+    }
+
+    let content = fs::read(artifact)
+        .with_context(|| format!("Failed to read {}", artifact.display()))?;
+    let (boundary, body) = build_upload_body(artifact, &metadata, &content);
+
+    let mut request = ureq::post(&repository.url)
+        .set("User-Agent", "monotrail (konstin@mailbox.org)")
+        .set(
+            "Content-Type",
+            &format!("multipart/form-data; boundary={}", boundary),
+        );
+    if let Some((username, password)) = &repository.credentials {
+        let encoded = BASE64.encode(format!("{}:{}", username, password).as_bytes());
+        request = request.set("Authorization", &format!("Basic {}", encoded));
+    }
+
+    match request.send_bytes(&body) {
+        Ok(response) => {
+            debug!(
+                "Uploaded {} {}: {} {}",
+                metadata.name,
+                metadata.version,
+                response.status(),
+                response.status_text()
+            );
+            Ok(true)
+        }
+        Err(ureq::Error::Status(409, _)) if skip_existing => {
+            println!(
+                "{} {} already exists on {}, skipping",
+                metadata.name, metadata.version, repository.url
+            );
+            Ok(false)
+        }
+        Err(err) => Err(err).with_context(|| {
+            format!(
+                "Failed to upload {} to {}",
+                artifact.display(),
+                repository.url
+            )
+        }),
+    }
+}
+
+/// Uploads `artifacts` (wheels and/or sdists) to `repository_name`, returning the number uploaded
+/// (i.e. not skipped through `skip_existing`); with `dry_run` nothing is sent and every artifact
+/// counts as uploaded
+pub fn publish(
+    artifacts: &[impl AsRef<Path>],
+    repository_name: &str,
+    skip_existing: bool,
+    dry_run: bool,
+) -> anyhow::Result<usize> {
+    if artifacts.is_empty() {
+        bail!("No artifacts given to publish");
+    }
+    let repository = resolve_repository(repository_name)?;
+    let mut uploaded = 0;
+    for artifact in artifacts {
+        let artifact = artifact.as_ref();
+        if upload_one(artifact, &repository, skip_existing, dry_run)? {
+            uploaded += 1;
+        }
+    }
+    Ok(uploaded)
+}
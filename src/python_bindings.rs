@@ -5,13 +5,17 @@
 //!
 //! TODO: Be consistent with String vs. PathBuf
 
+use crate::inject_and_run::{compatible_platform_tags, probe_interpreter_info};
 use crate::install::InstalledPackage;
+use crate::lock_export;
 use crate::markers::Pep508Environment;
 use crate::monotrail::{
-    find_scripts, install, load_specs, spec_paths, FinderData, LaunchType, PythonContext, SpecPaths,
+    export_venv, find_scripts, install, load_specs, spec_paths, FinderData, Implementation,
+    LaunchType, NamespaceConflict, PythonContext, SpecPaths,
 };
-use crate::poetry_integration::lock::poetry_resolve;
-use crate::poetry_integration::read_dependencies::specs_from_git;
+use crate::poetry_integration::lock::{poetry_resolve, ResolutionMode};
+use crate::poetry_integration::read_dependencies::{specs_from_git, specs_from_path, GroupSelection};
+use crate::standalone_python::provision_python;
 use crate::{inject_and_run, read_poetry_specs, PEP508_QUERY_ENV};
 use anyhow::{bail, Context};
 use install_wheel_rs::Script;
@@ -20,7 +24,7 @@ use pyo3::types::PyModule;
 use pyo3::{pyfunction, pymodule, wrap_pyfunction, Py, PyAny, PyErr, PyResult, Python};
 use std::collections::BTreeMap;
 use std::env;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Once;
 use tracing::{debug, trace};
 
@@ -59,29 +63,70 @@ fn get_python_context(py: Python) -> PyResult<PythonContext> {
     }
     // Would be nicer through https://docs.python.org/3/c-api/init.html#c.Py_GetProgramFullPath
     let sys_executable: String = py.import("sys")?.getattr("executable")?.extract()?;
+    let implementation_name: String = py
+        .import("sys")?
+        .getattr("implementation")?
+        .getattr("name")?
+        .extract()?;
+    let implementation = Implementation::from_sys_implementation_name(&implementation_name)
+        .map_err(format_monotrail_error)?;
+    let platform_tags = probe_interpreter_info(Path::new(&sys_executable))
+        .map(|info| compatible_platform_tags(Path::new(&sys_executable), &info))
+        .unwrap_or_else(|err| {
+            debug!(
+                "Failed to probe platform tags for {}, falling back to none: {}",
+                sys_executable, err
+            );
+            Vec::new()
+        });
     let python_context = PythonContext {
         sys_executable: PathBuf::from(sys_executable),
         version: (py.version_info().major, py.version_info().minor),
+        implementation,
         pep508_env: Pep508Environment::from_json_str(&get_pep508_env(py)?),
         launch_type: LaunchType::PythonBindings,
+        platform_tags,
     };
     debug!("python: {:?}", python_context);
     Ok(python_context)
 }
 
+/// Builds the [`PythonContext`] to resolve and install against: normally the interpreter that's
+/// calling us, but if `python_version` asks for a different `(major, minor)`, that version is
+/// located (or downloaded from python-build-standalone, same as [`provision_python`] does for
+/// `ppipx`) instead, so e.g. a 3.11 monotrail can resolve and build a 3.9 environment
+fn resolve_python_context(py: Python, python_version: Option<(u8, u8)>) -> PyResult<PythonContext> {
+    match python_version {
+        Some(version) if version != (py.version_info().major, py.version_info().minor) => {
+            let (python_context, _python_home) =
+                provision_python(Implementation::CPython, version, None)
+                    .map_err(format_monotrail_error)?;
+            Ok(python_context)
+        }
+        _ => get_python_context(py),
+    }
+}
+
 /// Takes a python invocation, extracts the script dir (if any), installs all required packages
 /// and returns script dir and finder data to python
 #[pyfunction]
 pub fn monotrail_from_args(py: Python, args: Vec<String>) -> PyResult<FinderData> {
     // We parse the python args even if we take MONOTRAIL_CWD as a validation
     // step
-    let script = inject_and_run::naive_python_arg_parser(&args).map_err(PyRuntimeError::new_err)?;
+    let script =
+        match inject_and_run::naive_python_arg_parser(&args).map_err(PyRuntimeError::new_err)? {
+            inject_and_run::PythonRunTarget::Script(path) => Some(path),
+            inject_and_run::PythonRunTarget::Module(_)
+            | inject_and_run::PythonRunTarget::Command(_)
+            | inject_and_run::PythonRunTarget::Stdin
+            | inject_and_run::PythonRunTarget::Repl => None,
+        };
     let script = if let Some(script) =
         env::var_os(&format!("{}_CWD", env!("CARGO_PKG_NAME").to_uppercase()))
     {
         Some(PathBuf::from(script))
     } else {
-        script.map(PathBuf::from)
+        script
     };
     debug!("monotrail_from_args script: {:?}, args: {:?}", script, args);
     let python_context = get_python_context(py)?;
@@ -96,6 +141,7 @@ pub fn monotrail_from_args(py: Python, args: Vec<String>) -> PyResult<FinderData
         lockfile,
         Some(project_dir),
         &python_context,
+        None,
     )
     .map_err(format_monotrail_error)
 }
@@ -106,21 +152,33 @@ pub fn monotrail_from_requested(
     py: Python,
     requested: String,
     lockfile: Option<String>,
+    python_version: Option<(u8, u8)>,
 ) -> PyResult<FinderData> {
     let requested = serde_json::from_str(&requested)
         .map_err(|serde_err| PyRuntimeError::new_err(format!("Invalid dependency format: {}.\n See https://python-poetry.org/docs/dependency-specification/", serde_err)))?;
 
-    let python_context = get_python_context(py)?;
-    let pep508_env = Pep508Environment::from_json_str(&get_pep508_env(py)?);
+    let python_context = resolve_python_context(py, python_version)?;
 
-    let (poetry_section, poetry_lock, lockfile) =
-        poetry_resolve(&requested, lockfile.as_deref(), &python_context)
-            .context("Failed to resolve requested dependencies through poetry")
-            .map_err(format_monotrail_error)?;
-    let specs = read_poetry_specs(&poetry_section, poetry_lock, false, &[], &pep508_env)
-        .map_err(format_monotrail_error)?;
+    let (poetry_section, poetry_lock, lockfile) = poetry_resolve(
+        &requested,
+        lockfile.as_deref(),
+        ResolutionMode::Highest,
+        python_context.version,
+        &python_context,
+    )
+    .context("Failed to resolve requested dependencies through poetry")
+    .map_err(format_monotrail_error)?;
+    let groups = parse_groups().map_err(format_monotrail_error)?;
+    let specs = read_poetry_specs(
+        &poetry_section,
+        poetry_lock,
+        GroupSelection::with_groups(std::iter::once("dev".to_string()).chain(groups)),
+        &[],
+        &python_context.pep508_env,
+    )
+    .map_err(format_monotrail_error)?;
 
-    install(&specs, BTreeMap::new(), lockfile, None, &python_context)
+    install(&specs, BTreeMap::new(), lockfile, None, &python_context, None)
         .map_err(format_monotrail_error)
 }
 
@@ -132,9 +190,10 @@ pub fn monotrail_from_git(
     revision: String,
     extras: Option<Vec<String>>,
     lockfile: Option<String>,
+    python_version: Option<(u8, u8)>,
 ) -> PyResult<FinderData> {
     debug!("monotrail_from_git: {} {}", git_url, revision);
-    let python_context = get_python_context(py)?;
+    let python_context = resolve_python_context(py, python_version)?;
     debug!("extras: {:?}", extras);
 
     let (specs, repo_dir, lockfile) = specs_from_git(
@@ -152,10 +211,40 @@ pub fn monotrail_from_git(
         lockfile,
         Some(repo_dir),
         &python_context,
+        None,
     )
     .map_err(format_monotrail_error)
 }
 
+/// Installs a local project directory the same way `monotrail_from_git` installs a checkout,
+/// letting a project depend on a sibling directory (`dep = { path = "../foo", develop = true }`)
+/// without publishing it anywhere first. Resolves through the project's own `poetry.lock`/native
+/// resolver if present, only falling back to inspecting its PEP 517 build metadata otherwise, so
+/// this never needs a network round-trip for an already-locked dependency.
+#[pyfunction]
+pub fn monotrail_from_path(
+    py: Python,
+    dir: PathBuf,
+    extras: Option<Vec<String>>,
+    lockfile: Option<String>,
+    python_version: Option<(u8, u8)>,
+) -> PyResult<FinderData> {
+    debug!("monotrail_from_path: {}", dir.display());
+    let python_context = resolve_python_context(py, python_version)?;
+    debug!("extras: {:?}", extras);
+
+    let (specs, lockfile) = specs_from_path(
+        &dir,
+        extras.as_deref().unwrap_or_default(),
+        lockfile.as_deref(),
+        &python_context,
+    )
+    .map_err(format_monotrail_error)?;
+
+    install(&specs, BTreeMap::new(), lockfile, Some(dir), &python_context, None)
+        .map_err(format_monotrail_error)
+}
+
 /// Like monotrail_from_args, except you explicitly pass what you want, currently only used for
 /// testing
 #[pyfunction]
@@ -172,6 +261,7 @@ pub fn monotrail_from_dir(py: Python, dir: PathBuf, extras: Vec<String>) -> PyRe
         lockfile,
         Some(project_dir),
         &python_context,
+        None,
     )
     .map_err(format_monotrail_error)
 }
@@ -184,15 +274,28 @@ pub fn monotrail_spec_paths(
     py: Python,
     sprawl_root: PathBuf,
     sprawl_packages: Vec<InstalledPackage>,
-) -> PyResult<(SpecPaths, Vec<PathBuf>)> {
+) -> PyResult<(SpecPaths, Vec<PathBuf>, Vec<NamespaceConflict>)> {
     let python_version = (py.version_info().major, py.version_info().minor);
-    let (modules, pth_files) = spec_paths(&sprawl_root, &sprawl_packages, python_version)
+    let implementation_name: String = py
+        .import("sys")?
+        .getattr("implementation")?
+        .getattr("name")?
+        .extract()?;
+    let implementation = Implementation::from_sys_implementation_name(&implementation_name)
         .map_err(format_monotrail_error)?;
+    let (modules, pth_files, namespace_conflicts) = spec_paths(
+        &sprawl_root,
+        &sprawl_packages,
+        python_version,
+        implementation,
+        None,
+    )
+    .map_err(format_monotrail_error)?;
     trace!(
         "Available modules: {}",
         modules.keys().map(|s| &**s).collect::<Vec<_>>().join(" ")
     );
-    Ok((modules, pth_files))
+    Ok((modules, pth_files, namespace_conflicts))
 }
 
 /// Searches all the bin dirs for scripts
@@ -204,6 +307,46 @@ pub fn monotrail_find_scripts(
     find_scripts(&sprawl_packages, &sprawl_root).map_err(format_monotrail_error)
 }
 
+/// Materializes a conventional `.venv` (a `bin` dir and a flat site-packages) out of a resolved
+/// `FinderData`, for interop with external tools (IDEs, some test runners) that expect one
+#[pyfunction]
+pub fn monotrail_export_venv(
+    py: Python,
+    finder_data: FinderData,
+    venv_dir: PathBuf,
+) -> PyResult<()> {
+    let python_version = (py.version_info().major, py.version_info().minor);
+    export_venv(&finder_data, python_version, &venv_dir).map_err(format_monotrail_error)
+}
+
+/// Pins a resolved `finder_data` down into a self-contained, hash-pinned manifest (see
+/// [`crate::lock_export`]) and writes it to `lock_path` as json, so [`monotrail_from_lock`] can
+/// later install the exact same packages without invoking poetry or its resolver again
+#[pyfunction]
+pub fn monotrail_export_lock(py: Python, finder_data: FinderData, lock_path: PathBuf) -> PyResult<()> {
+    let python_context = get_python_context(py)?;
+    let manifest = lock_export::export_lock(&finder_data, &python_context.pep508_env)
+        .map_err(format_monotrail_error)?;
+    let manifest_json = serde_json::to_string_pretty(&manifest).map_err(format_monotrail_error)?;
+    std::fs::write(&lock_path, manifest_json)
+        .with_context(|| format!("Failed to write {}", lock_path.display()))
+        .map_err(format_monotrail_error)
+}
+
+/// Installs straight from a manifest written by [`monotrail_export_lock`], with no poetry
+/// invocation and no network resolution - only the pinned urls are fetched and checked against
+/// their recorded sha256
+#[pyfunction]
+pub fn monotrail_from_lock(py: Python, lock_path: PathBuf) -> PyResult<FinderData> {
+    let manifest_json = std::fs::read_to_string(&lock_path)
+        .with_context(|| format!("Failed to read {}", lock_path.display()))
+        .map_err(format_monotrail_error)?;
+    let manifest: lock_export::LockManifest =
+        serde_json::from_str(&manifest_json).map_err(format_monotrail_error)?;
+    let python_context = get_python_context(py)?;
+    crate::monotrail::install_from_lock(&manifest, &python_context).map_err(format_monotrail_error)
+}
+
 fn parse_extras() -> anyhow::Result<Vec<String>> {
     let extras_env_var = format!("{}_EXTRAS", env!("CARGO_PKG_NAME").to_uppercase());
     let extras = if let Some(extras) = env::var_os(&extras_env_var) {
@@ -230,6 +373,25 @@ fn parse_extras() -> anyhow::Result<Vec<String>> {
     Ok(extras)
 }
 
+/// Same convention as [`parse_extras`], but for activating named `[tool.poetry.group.<name>]`
+/// dependency groups (e.g. `MONOTRAIL_GROUPS=test,docs`) instead of extras
+fn parse_groups() -> anyhow::Result<Vec<String>> {
+    let groups_env_var = format!("{}_GROUPS", env!("CARGO_PKG_NAME").to_uppercase());
+    let groups = if let Some(groups) = env::var_os(&groups_env_var) {
+        groups
+            .into_string()
+            .ok() // can't use the original OsString
+            .with_context(|| format!("{} must only contain utf-8 characters", groups_env_var))?
+            .split(',')
+            .filter(|group| !group.is_empty())
+            .map(ToString::to_string)
+            .collect()
+    } else {
+        Vec::new()
+    };
+    Ok(groups)
+}
+
 #[pymodule]
 pub fn monotrail(_py: Python, m: &PyModule) -> PyResult<()> {
     // Good enough for now
@@ -247,11 +409,16 @@ pub fn monotrail(_py: Python, m: &PyModule) -> PyResult<()> {
     m.add_function(wrap_pyfunction!(monotrail_from_requested, m)?)?;
     m.add_function(wrap_pyfunction!(monotrail_from_dir, m)?)?;
     m.add_function(wrap_pyfunction!(monotrail_from_git, m)?)?;
+    m.add_function(wrap_pyfunction!(monotrail_from_path, m)?)?;
     m.add_function(wrap_pyfunction!(monotrail_spec_paths, m)?)?;
     m.add_function(wrap_pyfunction!(monotrail_find_scripts, m)?)?;
+    m.add_function(wrap_pyfunction!(monotrail_export_venv, m)?)?;
+    m.add_function(wrap_pyfunction!(monotrail_export_lock, m)?)?;
+    m.add_function(wrap_pyfunction!(monotrail_from_lock, m)?)?;
     m.add("project_name", env!("CARGO_PKG_NAME"))?;
     m.add_class::<InstalledPackage>()?;
     m.add_class::<Script>()?;
     m.add_class::<FinderData>()?;
+    m.add_class::<NamespaceConflict>()?;
     Ok(())
 }
@@ -8,12 +8,16 @@
 //!  * `-c`
 //!  * `--hash` (postfix)
 //!  * `-e`
-//!
-//! Unsupported:
-//!  * `-e <path>`. TBD
-//!  * `<path>`. TBD
-//!  * `<archive_url>`. TBD
-//!  * Options without a requirement, such as `--find-links` or `--index-url`
+//!  * `<path>`, `<archive_url>` and `-e <path>`, i.e. unnamed requirements
+//!  * `-r`/`-c` includes given as an absolute `http`/`https` url, fetched with a blocking client
+//!    behind the `remote_requirements` feature; relative includes found inside such a file
+//!    resolve against that url instead of `working_dir`
+//!  * The long-form spellings `--requirement`, `--constraint` and `--editable`
+//!  * The global, requirement-less options `-i`/`--index-url`, `--extra-index-url`,
+//!    `-f`/`--find-links`, `--no-index`, `--no-binary`, `--only-binary` and `--pre`, collected
+//!    onto [`RequirementsTxt`] instead of being rejected
+//!  * `-r`/`-c` include cycles are detected and rejected instead of recursing until the stack
+//!    overflows
 //!
 //! Grammar as implemented:
 //!
@@ -21,26 +25,36 @@
 //! file = (statement | empty ('#' any*)? '\n')*
 //! empty = whitespace*
 //! statement = constraint_include | requirements_include | editable_requirement | requirement
-//! constraint_include = '-c' ('=' | wrappable_whitespaces) filepath
-//! requirements_include = '-r' ('=' | wrappable_whitespaces) filepath
-//! editable_requirement = '-e' ('=' | wrappable_whitespaces) requirement
+//! constraint_include = ('-c' | '--constraint') ('=' | wrappable_whitespaces) filepath
+//! requirements_include = ('-r' | '--requirement') ('=' | wrappable_whitespaces) filepath
+//! editable_requirement = ('-e' | '--editable') ('=' | wrappable_whitespaces) requirement_or_url
+//! requirement_or_url = url_or_path | requirement
 //! # We check whether the line starts with a letter or a number, in that case we assume it's a
 //! # PEP 508 requirement
 //! # https://packaging.python.org/en/latest/specifications/name-normalization/#valid-non-normalized-names
-//! # This does not (yet?) support plain files or urls, we use a letter or a number as first
-//! # character to assume a PEP 508 requirement
 //! requirement = [a-zA-Z0-9] pep508_grammar_tail wrappable_whitespaces hashes
+//! # Otherwise, if it starts with '.', '/' or a '<scheme>://' (optionally prefixed by a vcs type,
+//! # e.g. 'git+https://'), or contains a bare '/' anywhere else (a PEP 508 name never does), we
+//! # assume it's an unnamed path or url requirement
+//! url_or_path = ('.' | '/' | ([a-zA-Z0-9+-.])+ '://' | any* '/' any*) any* wrappable_whitespaces hashes
 //! hashes = ('--hash' ('=' | wrappable_whitespaces) [a-zA-Z0-9-_]+ ':' [a-zA-Z0-9-_] wrappable_whitespaces+)*
 //! # This should indicate a single backslash before a newline
 //! wrappable_whitespaces = whitespace ('\\\n' | whitespace)*
+//! # A '#' ends the statement as a trailing comment everywhere above: filepaths (after '-r'/'-c'/a
+//! # global option) stop at any '#', while url_or_path/requirement/hashes only treat a '#'
+//! # preceded by whitespace as a comment, since a bare '#' can be part of the token itself, e.g. a
+//! # url fragment such as 'pkg.whl#egg=name'
 //! ```
 
 use crate::poetry_integration::poetry_toml;
-use anyhow::bail;
+use crate::poetry_integration::read_dependencies::version_or_url_to_poetry_dependency;
+use anyhow::{bail, Context};
 use fs_err as fs;
 use pep508_rs::{Pep508Error, Requirement, VersionOrUrl};
+use regex::{Captures, Regex};
 use serde::{Deserialize, Serialize};
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
+use std::env;
 use std::io;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
@@ -50,33 +64,130 @@ use unscanny::{Pattern, Scanner};
 
 /// We emit one of those for each requirements.txt entry
 enum RequirementsTxtStatement {
-    /// `-r` inclusion filename
+    /// `-r`/`--requirement` inclusion filename
     Requirements { filename: String, location: usize },
-    /// `-c` inclusion filename
+    /// `-c`/`--constraint` inclusion filename
     Constraint { filename: String, location: usize },
     /// PEP 508 requirement plus metadata
     RequirementEntry(RequirementEntry),
+    /// A global option with no requirement attached, e.g. `--index-url` or `--pre`
+    GlobalOption(GlobalOption),
+}
+
+/// A pip global option that applies to the whole requirements.txt rather than a single
+/// requirement, collected onto [`RequirementsTxt`] instead of being rejected
+enum GlobalOption {
+    /// `-i`/`--index-url`
+    IndexUrl(String),
+    /// `--extra-index-url`
+    ExtraIndexUrl(String),
+    /// `-f`/`--find-links`
+    FindLinks(String),
+    /// `--no-index`
+    NoIndex,
+    /// `--no-binary`
+    NoBinary(String),
+    /// `--only-binary`
+    OnlyBinary(String),
+    /// `--pre`
+    Pre,
+}
+
+/// How to handle a `${NAME}` reference to a process environment variable that isn't set, the way
+/// pip expands them in requirements files (e.g. credentials embedded in an index url)
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum EnvVarPolicy {
+    /// Leave an unset `${NAME}` in the value untouched
+    #[default]
+    Lenient,
+    /// Fail with [`RequirementsTxtError::UnsetEnvVar`] if `${NAME}` isn't set
+    Strict,
+}
+
+/// Expands `${NAME}` references to process environment variables in `value`. A lone `$NAME`
+/// (without braces) is left untouched, matching pip's own requirements.txt behaviour
+fn expand_env_vars(
+    value: &str,
+    env_var_policy: EnvVarPolicy,
+    requirements_txt: &impl AsRef<Path>,
+    location: usize,
+) -> Result<String, RequirementsTxtError> {
+    let pattern = Regex::new(r"\$\{([A-Za-z_][A-Za-z0-9_]*)\}").unwrap();
+    let mut error = None;
+    let expanded = pattern.replace_all(value, |captures: &Captures| {
+        let name = &captures[1];
+        match env::var(name) {
+            Ok(value) => value,
+            Err(_) if env_var_policy == EnvVarPolicy::Strict => {
+                error.get_or_insert(RequirementsTxtError::UnsetEnvVar {
+                    name: name.to_string(),
+                    file: requirements_txt.as_ref().to_path_buf(),
+                    location,
+                });
+                String::new()
+            }
+            Err(_) => captures[0].to_string(),
+        }
+    });
+    match error {
+        Some(err) => Err(err),
+        None => Ok(expanded.into_owned()),
+    }
 }
 
 /// A [Requirement] with additional metadata from the requirements.txt, currently only hashes but in
 /// the future also editable an similar information
 #[derive(Debug, Deserialize, Clone, Eq, PartialEq, Serialize)]
 pub struct RequirementEntry {
-    /// The actual PEP 508 requirement
-    pub requirement: Requirement,
+    /// The actual requirement, either a normal PEP 508 requirement or an unnamed url/path
+    pub requirement: RequirementOrUrl,
     /// Hashes of the downloadable packages
     pub hashes: Vec<String>,
     /// Editable installation, see e.g. <https://stackoverflow.com/q/35064426/3549270>
     pub editable: bool,
 }
 
+/// Either a normal `name==version` PEP 508 requirement, or a requirement given as a bare path or
+/// url, e.g. `./local/pkg`, `../dist.whl`, `https://example.com/foo-1.0.tar.gz` or
+/// `git+https://github.com/org/repo`. The latter has no declared name - that's only known once the
+/// package is actually fetched and its metadata read
+#[derive(Debug, Deserialize, Clone, Eq, PartialEq, Serialize)]
+pub enum RequirementOrUrl {
+    /// A `name==version` PEP 508 requirement
+    NamedRequirement(Requirement),
+    /// An unnamed path or url requirement
+    Url(UrlRequirement),
+}
+
+/// An unnamed, direct path or url requirement
+#[derive(Debug, Deserialize, Clone, Eq, PartialEq, Serialize)]
+pub struct UrlRequirement {
+    /// The url or path, verbatim except relative paths, which are resolved against `working_dir`
+    pub url: String,
+}
+
 /// Parsed and flattened requirements.txt with requirements and constraints
 #[derive(Debug, Deserialize, Clone, Default, Eq, PartialEq, Serialize)]
 pub struct RequirementsTxt {
     /// The actual requirements with the hashes
     pub requirements: Vec<RequirementEntry>,
-    /// Constraints included with `-c`
+    /// Constraints included with `-c`/`--constraint`
     pub constraints: Vec<Requirement>,
+    /// `-i`/`--index-url`: replaces the default index for resolving requirements that aren't
+    /// pinned to a url of their own
+    pub index_url: Option<String>,
+    /// `--extra-index-url`, may be given multiple times
+    pub extra_index_urls: Vec<String>,
+    /// `-f`/`--find-links`: an additional flat index (a path or url) to look for packages in
+    pub find_links: Vec<String>,
+    /// `--no-index`: don't query the default (or `--index-url`) index at all, only `--find-links`
+    pub no_index: bool,
+    /// `--no-binary`: package names (or `:all:`/`:none:`) to always build from source
+    pub no_binary: Vec<String>,
+    /// `--only-binary`: package names (or `:all:`/`:none:`) to only ever install as a wheel
+    pub only_binary: Vec<String>,
+    /// `--pre`: consider pre-release and development versions
+    pub pre: bool,
 }
 
 impl RequirementsTxt {
@@ -88,53 +199,156 @@ impl RequirementsTxt {
         requirements_txt: impl AsRef<Path>,
         working_dir: impl AsRef<Path>,
     ) -> Result<Self, RequirementsTxtError> {
-        let content = fs::read_to_string(&requirements_txt)?;
-        let mut s = Scanner::new(&content);
+        Self::parse_with_env_var_policy(requirements_txt, working_dir, EnvVarPolicy::default())
+    }
+
+    /// Like [`Self::parse`], but lets the caller decide what happens when a `${NAME}` reference in
+    /// an include filename, url/path requirement or requirement text has no matching environment
+    /// variable, e.g. rejecting the file outright instead of leaving it unexpanded
+    pub fn parse_with_env_var_policy(
+        requirements_txt: impl AsRef<Path>,
+        working_dir: impl AsRef<Path>,
+        env_var_policy: EnvVarPolicy,
+    ) -> Result<Self, RequirementsTxtError> {
+        let mut loader = Loader::default();
+        Self::parse_file(
+            requirements_txt.as_ref(),
+            working_dir.as_ref(),
+            &IncludeBase::Dir(working_dir.as_ref().to_path_buf()),
+            &mut loader,
+            env_var_policy,
+        )
+    }
+
+    /// Reads a local requirements.txt file and parses it, entering it onto `loader`'s include
+    /// chain first so a file that (directly or transitively) includes itself is caught instead of
+    /// recursing until the stack overflows
+    fn parse_file(
+        requirements_txt: &Path,
+        working_dir: &Path,
+        base: &IncludeBase,
+        loader: &mut Loader,
+        env_var_policy: EnvVarPolicy,
+    ) -> Result<Self, RequirementsTxtError> {
+        let content = fs::read_to_string(requirements_txt)?;
+        let key =
+            fs::canonicalize(requirements_txt).unwrap_or_else(|_| requirements_txt.to_path_buf());
+        loader.enter(key)?;
+        let result = Self::parse_content(
+            &content,
+            requirements_txt,
+            working_dir,
+            base,
+            loader,
+            env_var_policy,
+        );
+        loader.exit();
+        result
+    }
+
+    /// Fetches `url` over http(s) and parses it. Relative `-r`/`-c` includes found inside resolve
+    /// against `url` itself rather than `working_dir`, so a remote requirements file can include
+    /// further remote siblings without knowing the local filesystem at all
+    #[cfg(feature = "remote_requirements")]
+    fn parse_remote(
+        url: &str,
+        working_dir: &Path,
+        loader: &mut Loader,
+        env_var_policy: EnvVarPolicy,
+    ) -> Result<Self, RequirementsTxtError> {
+        loader.enter(PathBuf::from(url))?;
+        let response = ureq::get(url)
+            .set("User-Agent", "monotrail (konstin@mailbox.org)")
+            .call()
+            .map_err(|err| RequirementsTxtError::Remote {
+                url: url.to_string(),
+                source: Box::new(err),
+            })?;
+        let content = response.into_string()?;
+        let result = Self::parse_content(
+            &content,
+            Path::new(url),
+            working_dir,
+            &IncludeBase::Url(url.to_string()),
+            loader,
+            env_var_policy,
+        );
+        loader.exit();
+        result
+    }
+
+    /// Shared by [`Self::parse_file`] and [`Self::parse_remote`]: parses already-read `content`,
+    /// resolving any `-r`/`-c` includes it contains against `base`
+    fn parse_content(
+        content: &str,
+        requirements_txt: &Path,
+        working_dir: &Path,
+        base: &IncludeBase,
+        loader: &mut Loader,
+        env_var_policy: EnvVarPolicy,
+    ) -> Result<Self, RequirementsTxtError> {
+        let mut s = Scanner::new(content);
 
         let mut data = Self::default();
-        while let Some(statement) = parse_entry(&mut s, &content, &requirements_txt)? {
+        while let Some(statement) = parse_entry(
+            &mut s,
+            content,
+            &requirements_txt,
+            working_dir,
+            env_var_policy,
+        )? {
             match statement {
                 RequirementsTxtStatement::Requirements { filename, location } => {
-                    let sub_file = working_dir.as_ref().join(filename);
-                    let sub_requirements =
-                        Self::parse(&sub_file, working_dir.as_ref()).map_err(|err| {
-                            RequirementsTxtError::Subfile {
-                                file: requirements_txt.as_ref().to_path_buf(),
-                                source: Box::new(err),
-                                location,
-                            }
+                    let sub_requirements = base
+                        .include(&filename, working_dir, loader, env_var_policy)
+                        .map_err(|err| RequirementsTxtError::Subfile {
+                            file: requirements_txt.to_path_buf(),
+                            source: Box::new(err),
+                            location,
                         })?;
                     // Add each to the correct category
                     data.update_from(sub_requirements);
                 }
                 RequirementsTxtStatement::Constraint { filename, location } => {
-                    let sub_file = working_dir.as_ref().join(filename);
-                    let sub_constraints =
-                        Self::parse(&sub_file, working_dir.as_ref()).map_err(|err| {
-                            RequirementsTxtError::Subfile {
-                                file: requirements_txt.as_ref().to_path_buf(),
-                                source: Box::new(err),
-                                location,
-                            }
+                    let sub_constraints = base
+                        .include(&filename, working_dir, loader, env_var_policy)
+                        .map_err(|err| RequirementsTxtError::Subfile {
+                            file: requirements_txt.to_path_buf(),
+                            source: Box::new(err),
+                            location,
                         })?;
                     // Here we add both to constraints
-                    data.constraints.extend(
-                        sub_constraints
-                            .requirements
-                            .into_iter()
-                            .map(|requirement_entry| requirement_entry.requirement),
-                    );
+                    data.constraints
+                        .extend(sub_constraints.requirements.into_iter().filter_map(
+                            |requirement_entry| match requirement_entry.requirement {
+                                RequirementOrUrl::NamedRequirement(requirement) => {
+                                    Some(requirement)
+                                }
+                                // A constraint has nothing to match a name-less url/path requirement
+                                // against, so it can't meaningfully constrain one
+                                RequirementOrUrl::Url(_) => None,
+                            },
+                        ));
                     data.constraints.extend(sub_constraints.constraints);
                 }
                 RequirementsTxtStatement::RequirementEntry(requirement_entry) => {
                     data.requirements.push(requirement_entry);
                 }
+                RequirementsTxtStatement::GlobalOption(option) => match option {
+                    GlobalOption::IndexUrl(url) => data.index_url = Some(url),
+                    GlobalOption::ExtraIndexUrl(url) => data.extra_index_urls.push(url),
+                    GlobalOption::FindLinks(url) => data.find_links.push(url),
+                    GlobalOption::NoIndex => data.no_index = true,
+                    GlobalOption::NoBinary(value) => data.no_binary.push(value),
+                    GlobalOption::OnlyBinary(value) => data.only_binary.push(value),
+                    GlobalOption::Pre => data.pre = true,
+                },
             }
         }
         if data == Self::default() {
             warn!(
                 "Requirements file {} does not contain any dependencies",
-                requirements_txt.as_ref().display()
+                requirements_txt.display()
             );
         }
         Ok(data)
@@ -144,46 +358,209 @@ impl RequirementsTxt {
     pub fn update_from(&mut self, other: RequirementsTxt) {
         self.requirements.extend(other.requirements);
         self.constraints.extend(other.constraints);
+        if other.index_url.is_some() {
+            self.index_url = other.index_url;
+        }
+        self.extra_index_urls.extend(other.extra_index_urls);
+        self.find_links.extend(other.find_links);
+        self.no_index |= other.no_index;
+        self.no_binary.extend(other.no_binary);
+        self.only_binary.extend(other.only_binary);
+        self.pre |= other.pre;
+    }
+
+    /// Splits off the requirements poetry can't resolve on its own -- unnamed url/path entries,
+    /// anything marked `-e`/`--editable`, and named direct references (`name @ url`, including the
+    /// `git+` VCS form) -- leaving only plain `name`/`name==version` requirements in
+    /// `self.requirements` for [`Self::into_poetry`]. See
+    /// [`crate::monotrail::specs_from_requirements_txt_resolved`], which installs the returned
+    /// entries straight into `monotrail_root` instead of going through poetry
+    pub fn split_direct_requirements(&mut self) -> Vec<RequirementEntry> {
+        let (direct, poetry_bound) = std::mem::take(&mut self.requirements)
+            .into_iter()
+            .partition(|entry| {
+                entry.editable
+                    || match &entry.requirement {
+                        RequirementOrUrl::Url(_) => true,
+                        RequirementOrUrl::NamedRequirement(requirement) => {
+                            matches!(requirement.version_or_url, Some(VersionOrUrl::Url(_)))
+                        }
+                    }
+            });
+        self.requirements = poetry_bound;
+        direct
     }
 
     /// Method to bridge between the new parser and the poetry assumptions of the existing code
+    ///
+    /// Constraints (`-c`) restrict a requirement's version only if that package is otherwise
+    /// required; they never add a dependency on their own, matching pip's own `-c` semantics
     pub fn into_poetry(
         self,
         requirements_txt: &Path,
     ) -> anyhow::Result<BTreeMap<String, poetry_toml::Dependency>> {
-        if !self.constraints.is_empty() {
-            bail!(
-                "Constraints (`-c`) from {} are not supported yet",
-                requirements_txt.display()
-            );
+        // Keyed by normalized name, same as the rest of the crate uses to match up dependencies
+        let mut constraints: HashMap<String, String> = HashMap::new();
+        for constraint in &self.constraints {
+            if let Some(VersionOrUrl::VersionSpecifier(specifiers)) = &constraint.version_or_url {
+                constraints.insert(
+                    constraint.name.to_lowercase().replace('-', "_"),
+                    specifiers.to_string(),
+                );
+            }
         }
+
         let mut poetry_requirements: BTreeMap<String, poetry_toml::Dependency> = BTreeMap::new();
         for requirement_entry in self.requirements {
-            let version = match requirement_entry.requirement.version_or_url {
-                None => "*".to_string(),
-                Some(VersionOrUrl::Url(_)) => {
+            let requirement = match requirement_entry.requirement {
+                RequirementOrUrl::NamedRequirement(requirement) => requirement,
+                RequirementOrUrl::Url(url_requirement) => {
+                    // Poetry's dependency table is keyed by name, which an unnamed requirement
+                    // doesn't have without actually fetching and reading its metadata first
                     bail!(
-                        "Unsupported url requirement in {}: '{}'",
-                        requirements_txt.display(),
-                        requirement_entry.requirement,
-                    )
+                        "Unnamed requirement '{}' can't be bridged to poetry, which requires a \
+                         declared name; give it an explicit name, e.g. `name @ {}`",
+                        url_requirement.url,
+                        url_requirement.url
+                    );
                 }
-                Some(VersionOrUrl::VersionSpecifier(specifiers)) => specifiers.to_string(),
             };
+            let markers = requirement.marker.as_ref().map(|marker| marker.to_string());
+            let mut dep = version_or_url_to_poetry_dependency(
+                requirement.version_or_url,
+                requirement.extras.clone(),
+                false,
+                requirement_entry.editable,
+                markers,
+            )
+            .with_context(|| format!("In {}", requirements_txt.display()))?;
 
-            let dep = poetry_toml::Dependency::Expanded {
-                version: Some(version),
-                optional: Some(false),
-                extras: requirement_entry.requirement.extras.clone(),
-                git: None,
-                branch: None,
-            };
-            poetry_requirements.insert(requirement_entry.requirement.name, dep);
+            let normalized_name = requirement.name.to_lowercase().replace('-', "_");
+            if let Some(constraint) = constraints.get(&normalized_name) {
+                intersect_constraint(&mut dep, constraint);
+            }
+
+            poetry_requirements.insert(requirement.name, dep);
         }
         Ok(poetry_requirements)
     }
 }
 
+/// Intersects a `-c` constraints-file version specifier into an already-built dependency by
+/// appending it to the existing specifier (poetry/PEP 440 specifiers are comma-separated ANDs).
+/// Dependencies pinned to a url/path/git source have no version specifier to constrain, so
+/// constraints on those are silently ignored, matching pip's own behaviour there
+fn intersect_constraint(dependency: &mut poetry_toml::Dependency, constraint: &str) {
+    let version = match dependency {
+        poetry_toml::Dependency::Compact(version) => version,
+        poetry_toml::Dependency::Expanded {
+            version: Some(version),
+            ..
+        } => version,
+        _ => return,
+    };
+    *version = if version == "*" {
+        constraint.to_string()
+    } else {
+        format!("{},{}", version, constraint)
+    };
+}
+
+/// What a `-r`/`-c` include's relative path resolves against: the original `working_dir` while
+/// we're still walking local files, or (behind the `remote_requirements` feature) the url an
+/// already-remote file was itself fetched from
+#[derive(Debug, Clone)]
+enum IncludeBase {
+    Dir(PathBuf),
+    #[cfg(feature = "remote_requirements")]
+    Url(String),
+}
+
+impl IncludeBase {
+    /// Resolves `target` against this base and parses whatever it points to, fetching it over
+    /// http(s) if `target` is itself an absolute url, or if this base itself is a url
+    fn include(
+        &self,
+        target: &str,
+        working_dir: &Path,
+        loader: &mut Loader,
+        env_var_policy: EnvVarPolicy,
+    ) -> Result<RequirementsTxt, RequirementsTxtError> {
+        #[cfg(feature = "remote_requirements")]
+        if is_http_url(target) {
+            return RequirementsTxt::parse_remote(target, working_dir, loader, env_var_policy);
+        }
+        #[cfg(not(feature = "remote_requirements"))]
+        if target.starts_with("http://") || target.starts_with("https://") {
+            return Err(RequirementsTxtError::RemoteDisabled {
+                url: target.to_string(),
+            });
+        }
+        match self {
+            IncludeBase::Dir(dir) => RequirementsTxt::parse_file(
+                &dir.join(target),
+                working_dir,
+                self,
+                loader,
+                env_var_policy,
+            ),
+            #[cfg(feature = "remote_requirements")]
+            IncludeBase::Url(base_url) => RequirementsTxt::parse_remote(
+                &join_remote_url(base_url, target),
+                working_dir,
+                loader,
+                env_var_policy,
+            ),
+        }
+    }
+}
+
+/// Tracks the requirements.txt files and `-r`/`-c` includes on the current include chain, so that
+/// a file which (directly or transitively) includes itself is reported as
+/// [`RequirementsTxtError::Cycle`] instead of recursing until the stack overflows
+#[derive(Debug, Default)]
+struct Loader {
+    /// Canonicalized local paths or raw urls, in the order they were entered; re-entering one of
+    /// these before it's left again means the include graph has a cycle
+    chain: Vec<PathBuf>,
+}
+
+impl Loader {
+    /// Pushes `key` onto the include chain, failing if it's already on it
+    fn enter(&mut self, key: PathBuf) -> Result<(), RequirementsTxtError> {
+        if self.chain.contains(&key) {
+            let mut chain = self.chain.clone();
+            chain.push(key);
+            return Err(RequirementsTxtError::Cycle { chain });
+        }
+        self.chain.push(key);
+        Ok(())
+    }
+
+    /// Pops the most recently entered file off the include chain, once it (and everything it
+    /// transitively included) has finished parsing
+    fn exit(&mut self) {
+        self.chain.pop();
+    }
+}
+
+/// Whether `value` is an absolute `http(s)` url rather than a filesystem path
+#[cfg(feature = "remote_requirements")]
+fn is_http_url(value: &str) -> bool {
+    value.starts_with("http://") || value.starts_with("https://")
+}
+
+/// Resolves a relative `-r`/`-c` include found inside an already-fetched remote file against the
+/// url it came from, the same way a browser resolves a relative link: everything after the last
+/// `/` in `base` is replaced with `target`
+#[cfg(feature = "remote_requirements")]
+fn join_remote_url(base: &str, target: &str) -> String {
+    match base.rsplit_once('/') {
+        Some((parent, _)) => format!("{}/{}", parent, target),
+        None => target.to_string(),
+    }
+}
+
 /// Parse a single entry, that is a requirement, an inclusion or a comment line
 ///
 /// Consumes all preceding trivia (whitespace and comments). If it returns None, we've reached
@@ -192,6 +569,8 @@ fn parse_entry(
     s: &mut Scanner,
     content: &str,
     requirements_txt: &impl AsRef<Path>,
+    working_dir: &Path,
+    env_var_policy: EnvVarPolicy,
 ) -> Result<Option<RequirementsTxtStatement>, RequirementsTxtError> {
     // Eat all preceding whitespace, this may run us to the end of file
     eat_wrappable_whitespace(s);
@@ -201,40 +580,77 @@ fn parse_entry(
         eat_wrappable_whitespace(s);
     }
 
-    Ok(Some(if s.eat_if("-r") {
+    Ok(Some(if s.eat_if("-r") || s.eat_if("--requirement") {
         let location = s.cursor();
         let requirements_file = parse_value(
             s,
             |c: char| !['\n', '\r', '#'].contains(&c),
             &requirements_txt,
         )?;
+        let requirements_file = expand_env_vars(
+            requirements_file,
+            env_var_policy,
+            &requirements_txt,
+            location,
+        )?;
         eat_trailing_line(s, requirements_txt.as_ref())?;
         RequirementsTxtStatement::Requirements {
-            filename: requirements_file.to_string(),
+            filename: requirements_file,
             location,
         }
-    } else if s.eat_if("-c") {
+    } else if s.eat_if("-c") || s.eat_if("--constraint") {
         let location = s.cursor();
         let constraints_file = parse_value(
             s,
             |c: char| !['\n', '\r', '#'].contains(&c),
             &requirements_txt,
         )?;
+        let constraints_file = expand_env_vars(
+            constraints_file,
+            env_var_policy,
+            &requirements_txt,
+            location,
+        )?;
         eat_trailing_line(s, requirements_txt.as_ref())?;
         RequirementsTxtStatement::Constraint {
-            filename: constraints_file.to_string(),
+            filename: constraints_file,
             location,
         }
-    } else if s.eat_if("-e") {
-        let (requirement, hashes) = parse_requirement_and_hashes(s, &content, &requirements_txt)?;
+    } else if s.eat_if("-e") || s.eat_if("--editable") {
+        let (requirement, hashes) =
+            parse_requirement_or_url(s, content, &requirements_txt, working_dir, env_var_policy)?;
         eat_trailing_line(s, requirements_txt.as_ref())?;
         RequirementsTxtStatement::RequirementEntry(RequirementEntry {
             requirement,
             hashes,
             editable: true,
         })
-    } else if s.at(char::is_ascii_alphanumeric) {
-        let (requirement, hashes) = parse_requirement_and_hashes(s, &content, &requirements_txt)?;
+    } else if s.eat_if("-i") || s.eat_if("--index-url") {
+        let value = parse_global_option_value(s, &requirements_txt, env_var_policy)?;
+        RequirementsTxtStatement::GlobalOption(GlobalOption::IndexUrl(value))
+    } else if s.eat_if("--extra-index-url") {
+        let value = parse_global_option_value(s, &requirements_txt, env_var_policy)?;
+        RequirementsTxtStatement::GlobalOption(GlobalOption::ExtraIndexUrl(value))
+    } else if s.eat_if("-f") || s.eat_if("--find-links") {
+        let value = parse_global_option_value(s, &requirements_txt, env_var_policy)?;
+        RequirementsTxtStatement::GlobalOption(GlobalOption::FindLinks(value))
+    } else if s.eat_if("--no-binary") {
+        let value = parse_global_option_value(s, &requirements_txt, env_var_policy)?;
+        RequirementsTxtStatement::GlobalOption(GlobalOption::NoBinary(value))
+    } else if s.eat_if("--only-binary") {
+        let value = parse_global_option_value(s, &requirements_txt, env_var_policy)?;
+        RequirementsTxtStatement::GlobalOption(GlobalOption::OnlyBinary(value))
+    } else if s.eat_if("--no-index") {
+        eat_wrappable_whitespace(s);
+        eat_trailing_line(s, requirements_txt.as_ref())?;
+        RequirementsTxtStatement::GlobalOption(GlobalOption::NoIndex)
+    } else if s.eat_if("--pre") {
+        eat_wrappable_whitespace(s);
+        eat_trailing_line(s, requirements_txt.as_ref())?;
+        RequirementsTxtStatement::GlobalOption(GlobalOption::Pre)
+    } else if s.at(char::is_ascii_alphanumeric) || at_url_or_path(s) {
+        let (requirement, hashes) =
+            parse_requirement_or_url(s, content, &requirements_txt, working_dir, env_var_policy)?;
         eat_trailing_line(s, requirements_txt.as_ref())?;
         RequirementsTxtStatement::RequirementEntry(RequirementEntry {
             requirement,
@@ -244,8 +660,10 @@ fn parse_entry(
     } else if let Some(char) = s.peek() {
         return Err(RequirementsTxtError::Parser {
             message: format!(
-                "Unexpected '{}', expected '-c', '-e', '-r' or the start of a requirement",
-                char
+                "Unexpected '{}', expected '-c', '-e', '-r', a global option such as '--index-url' \
+                 or the start of a requirement, path or url\n{}",
+                char,
+                render_excerpt(content, s.cursor())
             ),
             file: requirements_txt.as_ref().to_path_buf(),
             location: s.cursor(),
@@ -256,6 +674,73 @@ fn parse_entry(
     }))
 }
 
+/// Parses the value of a `-<key>=<value>`/`-<key> <value>` global option, e.g. the url after
+/// `--index-url`
+fn parse_global_option_value(
+    s: &mut Scanner,
+    requirements_txt: &impl AsRef<Path>,
+    env_var_policy: EnvVarPolicy,
+) -> Result<String, RequirementsTxtError> {
+    let location = s.cursor();
+    let value = parse_value(
+        s,
+        |c: char| !['\n', '\r', '#'].contains(&c),
+        &requirements_txt,
+    )?;
+    let value = expand_env_vars(value, env_var_policy, &requirements_txt, location)?;
+    eat_trailing_line(s, requirements_txt.as_ref())?;
+    Ok(value)
+}
+
+/// Checks whether the upcoming token is a bare path or url rather than a `name==version` PEP 508
+/// requirement: a leading `.` or `/` (relative or absolute path), a `<scheme>://` prefix
+/// (optionally preceded by a vcs type such as `git+https://`), or a `/` appearing where a PEP 508
+/// name would be, e.g. `dist/foo-1.0-py3-none-any.whl` -- a PEP 508 name can never contain `/`, so
+/// that's unambiguously a relative path even without a leading `./`
+fn at_url_or_path(s: &Scanner) -> bool {
+    let after = s.after();
+    if after.starts_with('.') || after.starts_with('/') {
+        return true;
+    }
+    let token_end = after
+        .find(|c: char| c.is_whitespace())
+        .unwrap_or(after.len());
+    let token = &after[..token_end];
+    if let Some(scheme_end) = token.find("://") {
+        return token[..scheme_end]
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '-' || c == '.');
+    }
+    // Only inspect the name portion (valid PEP 508 name characters at the start of the token): a
+    // '/' appearing after it -- e.g. in a direct reference (`pkg@url`), a marker
+    // (`pkg;platform=='linux/arm64'`) or an extras/version specifier -- doesn't make the line a
+    // path, since whatever precedes it already looks like a name
+    match token.find(|c: char| !(c.is_ascii_alphanumeric() || ['.', '-', '_'].contains(&c))) {
+        Some(boundary) => token[boundary..].starts_with('/'),
+        None => false,
+    }
+}
+
+/// Parses either a PEP 508 requirement or an unnamed url/path requirement, routing on
+/// [`at_url_or_path`]
+fn parse_requirement_or_url(
+    s: &mut Scanner,
+    content: &str,
+    requirements_txt: &impl AsRef<Path>,
+    working_dir: &Path,
+    env_var_policy: EnvVarPolicy,
+) -> Result<(RequirementOrUrl, Vec<String>), RequirementsTxtError> {
+    if at_url_or_path(s) {
+        let (url_requirement, hashes) =
+            parse_url_requirement(s, content, &requirements_txt, working_dir, env_var_policy)?;
+        Ok((RequirementOrUrl::Url(url_requirement), hashes))
+    } else {
+        let (requirement, hashes) =
+            parse_requirement_and_hashes(s, content, &requirements_txt, env_var_policy)?;
+        Ok((RequirementOrUrl::NamedRequirement(requirement), hashes))
+    }
+}
+
 /// Eat whitespace and ignore newlines escaped with a backslash
 fn eat_wrappable_whitespace<'a>(s: &mut Scanner<'a>) -> &'a str {
     let start = s.cursor();
@@ -278,8 +763,9 @@ fn eat_trailing_line(
 
     if s.eat_if('\r') {
         if !s.eat_if('\n') {
+            let excerpt = render_excerpt(&format!("{}{}", s.before(), s.after()), s.cursor());
             Err(RequirementsTxtError::Parser {
-                message: "Expected \\n after \\n, found {}".to_string(),
+                message: format!("Expected \\n after \\r, found {:?}\n{}", s.peek(), excerpt),
                 file: requirements_txt.as_ref().to_path_buf(),
                 location: s.cursor(),
             })
@@ -298,46 +784,56 @@ fn eat_trailing_line(
     }
 }
 
-/// Parse a PEP 508 requirement with optional trailing hashes
-fn parse_requirement_and_hashes(
-    s: &mut Scanner,
-    content: &&str,
-    requirements_txt: &impl AsRef<Path>,
-) -> Result<(Requirement, Vec<String>), RequirementsTxtError> {
-    // PEP 508 requirement
-    let start = s.cursor();
+/// Scans from the cursor up to the end of line, an escaped newline, a comment or a `--hash`,
+/// without interpreting the scanned token, and returns its end offset
+fn scan_until_hash_or_eol(s: &mut Scanner) -> usize {
     // Termination: s.eat() eventually becomes None
-    let (end, has_hashes) = loop {
+    loop {
         let end = s.cursor();
 
         //  We look for the end of the line ...
         if s.at('\n') || s.at('\r') {
-            break (end, false);
+            return end;
         }
         // ... or`--hash`, an escaped newline or a comment separated by whitespace ...
         if !eat_wrappable_whitespace(s).is_empty() {
-            if s.after().starts_with("--") {
-                break (end, true);
-            } else if s.at('\\') || s.at('#') {
-                break (end, false);
+            if s.after().starts_with("--") || s.at('\\') || s.at('#') {
+                return end;
             } else {
                 continue;
             }
         }
         // ... or the end of the file, which works like the end of line
         if s.eat().is_none() {
-            break (end, false);
+            return end;
         }
-    };
-    let requirement = Requirement::from_str(&content[start..end]).map_err(|err| {
-        RequirementsTxtError::Pep508 {
+    }
+}
+
+/// Parse a PEP 508 requirement with optional trailing hashes
+fn parse_requirement_and_hashes(
+    s: &mut Scanner,
+    content: &str,
+    requirements_txt: &impl AsRef<Path>,
+    env_var_policy: EnvVarPolicy,
+) -> Result<(Requirement, Vec<String>), RequirementsTxtError> {
+    // PEP 508 requirement
+    let start = s.cursor();
+    let end = scan_until_hash_or_eol(s);
+    let expanded = expand_env_vars(
+        &content[start..end],
+        env_var_policy,
+        &requirements_txt,
+        start,
+    )?;
+    let requirement =
+        Requirement::from_str(&expanded).map_err(|err| RequirementsTxtError::Pep508 {
             source: err,
             file: requirements_txt.as_ref().to_path_buf(),
             start,
             end,
-        }
-    })?;
-    let hashes = if has_hashes {
+        })?;
+    let hashes = if s.after().starts_with("--") {
         parse_hashes(s, &requirements_txt)?
     } else {
         Vec::new()
@@ -345,6 +841,34 @@ fn parse_requirement_and_hashes(
     Ok((requirement, hashes))
 }
 
+/// Parse an unnamed path or url requirement with optional trailing hashes, resolving relative
+/// paths against `working_dir`
+fn parse_url_requirement(
+    s: &mut Scanner,
+    content: &str,
+    requirements_txt: &impl AsRef<Path>,
+    working_dir: &Path,
+    env_var_policy: EnvVarPolicy,
+) -> Result<(UrlRequirement, Vec<String>), RequirementsTxtError> {
+    let start = s.cursor();
+    let end = scan_until_hash_or_eol(s);
+    let raw = content[start..end].trim_end();
+    let raw = expand_env_vars(raw, env_var_policy, &requirements_txt, start)?;
+    // Everything without a `<scheme>://` is a path, including a bare `dist/foo.whl` (see
+    // `at_url_or_path`)
+    let url = if !raw.contains("://") {
+        working_dir.join(&raw).to_string_lossy().into_owned()
+    } else {
+        raw
+    };
+    let hashes = if s.after().starts_with("--") {
+        parse_hashes(s, &requirements_txt)?
+    } else {
+        Vec::new()
+    };
+    Ok((UrlRequirement { url }, hashes))
+}
+
 /// Parse `--hash=... --hash ...` after a requirement
 fn parse_hashes(
     s: &mut Scanner,
@@ -352,13 +876,16 @@ fn parse_hashes(
 ) -> Result<Vec<String>, RequirementsTxtError> {
     let mut hashes = Vec::new();
     if s.eat_while("--hash").is_empty() {
+        let location = s.cursor();
+        let source = format!("{}{}", s.before(), s.after());
         return Err(RequirementsTxtError::Parser {
             message: format!(
-                "Expected '--hash', found '{:?}'",
-                s.eat_while(|c: char| !c.is_whitespace())
+                "Expected '--hash', found '{:?}'\n{}",
+                s.eat_while(|c: char| !c.is_whitespace()),
+                render_excerpt(&source, location)
             ),
             file: requirements_txt.as_ref().to_path_buf(),
-            location: s.cursor(),
+            location,
         });
     }
     let hash = parse_value(s, |c: char| !c.is_whitespace(), &requirements_txt)?;
@@ -388,8 +915,13 @@ fn parse_value<'a, T>(
         s.eat_whitespace();
         Ok(s.eat_while(while_pattern).trim_end())
     } else {
+        let excerpt = render_excerpt(&format!("{}{}", s.before(), s.after()), s.cursor());
         Err(RequirementsTxtError::Parser {
-            message: format!("Expected '=' or whitespace, found {:?}", s.peek()),
+            message: format!(
+                "Expected '=' or whitespace, found {:?}\n{}",
+                s.peek(),
+                excerpt
+            ),
             file: requirements_txt.as_ref().to_path_buf(),
             location: s.cursor(),
         })
@@ -420,6 +952,46 @@ pub enum RequirementsTxtError {
         source: Box<RequirementsTxtError>,
         location: usize,
     },
+    /// Fetching a `-r`/`-c` include over http(s) failed
+    #[cfg(feature = "remote_requirements")]
+    #[error("Failed to fetch remote requirements file {url}")]
+    Remote {
+        url: String,
+        source: Box<ureq::Error>,
+    },
+    /// A `-r`/`-c` include pointed at a remote url, but this build wasn't compiled with the
+    /// `remote_requirements` feature that's needed to fetch it
+    #[error(
+        "{url} is a remote url; rebuild with the `remote_requirements` feature to fetch \
+         -r/-c includes over http(s)"
+    )]
+    RemoteDisabled { url: String },
+    /// A `-r`/`-c` include re-entered a file that's already on the current include chain
+    #[error(
+        "Include cycle detected: {}",
+        chain.iter().map(|path| path.display().to_string()).collect::<Vec<_>>().join(" -> ")
+    )]
+    Cycle { chain: Vec<PathBuf> },
+    /// A `${NAME}` reference under [`EnvVarPolicy::Strict`] had no matching environment variable
+    #[error("Environment variable {name} referenced in {} position {location} is not set", file.display())]
+    UnsetEnvVar {
+        name: String,
+        file: PathBuf,
+        location: usize,
+    },
+}
+
+/// Renders the source line containing byte offset `location`, with a caret on the line below
+/// pointing at the exact column, so `Parser` errors read the same way pep508_rs's own parse
+/// errors do (see e.g. [`RequirementsTxtError::Pep508`])
+fn render_excerpt(source: &str, location: usize) -> String {
+    let line_start = source[..location].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = source[location..]
+        .find('\n')
+        .map_or(source.len(), |i| location + i);
+    let line = &source[line_start..line_end];
+    let column = source[line_start..location].chars().count();
+    format!("{line}\n{}^", " ".repeat(column))
 }
 
 #[cfg(test)]
@@ -487,9 +1059,8 @@ mod test {
         }
     }
 
-    /// Pass test only - currently fails due to `-e ./` in pyproject.toml-constrained.in
+    /// Pass test only
     #[test]
-    #[ignore]
     fn test_pydantic() {
         let working_dir = Path::new("test-data").join("requirements-pydantic");
         for basic in fs::read_dir(&working_dir).unwrap() {
@@ -526,6 +1097,21 @@ mod test {
         // The last error message is os specific
     }
 
+    #[test]
+    fn test_include_cycle() {
+        let working_dir = Path::new("test-data").join("requirements-txt");
+        let basic = working_dir.join("cycle-a.txt");
+        let err = RequirementsTxt::parse(&basic, &working_dir).unwrap_err();
+        let errors = anyhow::Error::new(err)
+            .chain()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        assert!(errors
+            .last()
+            .unwrap()
+            .starts_with("Include cycle detected:"));
+    }
+
     #[test]
     fn test_invalid_requirement() {
         let working_dir = Path::new("test-data").join("requirements-txt");
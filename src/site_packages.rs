@@ -0,0 +1,170 @@
+//! Indexes an already-provisioned environment's `site-packages` so that [`crate::install`] can
+//! skip distributions (or a user-supplied `--with` set) that are already present at a compatible
+//! version instead of re-downloading and re-unpacking them.
+
+use crate::utils::get_dir_content;
+use crate::venv_parser::VirtualEnvironment;
+use anyhow::Context;
+use fs_err as fs;
+use pep440_rs::Version;
+use pep508_rs::{Requirement, VersionOrUrl};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// One `*.dist-info` entry found in a `site-packages` directory
+#[derive(Debug, Clone)]
+struct InstalledDistribution {
+    version: Version,
+    /// The unparsed `Requires-Dist` lines from `METADATA`, parsed lazily since most callers only
+    /// care about them when the requirement they're checking actually requests extras
+    requires_dist: Vec<String>,
+}
+
+/// Whether an already-installed distribution satisfies a requested one
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Satisfaction {
+    /// Installed at a version the requirement's specifier accepts, with all requested extras'
+    /// transitive dependencies present too
+    Satisfied,
+    /// Installed, but at a version the requirement's specifier rejects
+    Mismatch,
+    /// Not installed at all, or missing a requested extra's dependencies
+    Missing,
+}
+
+/// An index of the `*.dist-info/METADATA` files in a `site-packages` directory
+#[derive(Debug, Clone, Default)]
+pub struct SitePackagesIndex {
+    /// normalized (PEP 503) name -> what's installed
+    distributions: HashMap<String, InstalledDistribution>,
+}
+
+impl SitePackagesIndex {
+    /// Scans `site_packages` for `*.dist-info` directories and parses each `METADATA`'s
+    /// `Name`/`Version`/`Requires-Dist` fields. A missing `site-packages` (e.g. a base
+    /// interpreter that was never used with monotrail before) is treated as empty, not an error.
+    pub fn from_site_packages(site_packages: &Path) -> anyhow::Result<Self> {
+        let mut distributions = HashMap::new();
+        let dist_info_dirs = match get_dir_content(site_packages) {
+            Ok(entries) => entries,
+            Err(_) => return Ok(Self::default()),
+        };
+        for entry in dist_info_dirs {
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            if !file_name.ends_with(".dist-info") {
+                continue;
+            }
+            let metadata_file = entry.path().join("METADATA");
+            if !metadata_file.is_file() {
+                continue;
+            }
+            let metadata = fs::read_to_string(&metadata_file)
+                .with_context(|| format!("Failed to read {}", metadata_file.display()))?;
+            let name = metadata
+                .lines()
+                .find_map(|line| line.strip_prefix("Name: "))
+                .with_context(|| format!("{} has no Name field", metadata_file.display()))?;
+            let version = metadata
+                .lines()
+                .find_map(|line| line.strip_prefix("Version: "))
+                .with_context(|| format!("{} has no Version field", metadata_file.display()))?;
+            let version = Version::from_str(version).with_context(|| {
+                format!(
+                    "Invalid version in {}: {}",
+                    metadata_file.display(),
+                    version
+                )
+            })?;
+            let requires_dist = metadata
+                .lines()
+                .filter_map(|line| line.strip_prefix("Requires-Dist: "))
+                .map(str::to_string)
+                .collect();
+            distributions.insert(
+                normalize_name(name),
+                InstalledDistribution {
+                    version,
+                    requires_dist,
+                },
+            );
+        }
+        Ok(Self { distributions })
+    }
+
+    /// Indexes `venv_base`'s `site-packages`, using [`VirtualEnvironment::site_packages_dirs`] to
+    /// find every directory that's actually searched -- the venv's own, plus the base
+    /// interpreter's when `include-system-site-packages` is set.
+    pub fn from_venv(venv_base: &Path) -> anyhow::Result<Self> {
+        let virtual_env = VirtualEnvironment::from_venv(venv_base)?;
+        Self::from_dirs(&virtual_env.site_packages_dirs(venv_base))
+    }
+
+    /// Indexes each of `site_packages_dirs`, ordered the way [`VirtualEnvironment::site_packages_dirs`]
+    /// returns them (the venv's own first, then the base interpreter's). Processed in reverse so the
+    /// first-listed directory's entries win on a name collision, matching how Python itself prefers
+    /// the venv's own site-packages earlier on `sys.path`.
+    pub fn from_dirs(site_packages_dirs: &[PathBuf]) -> anyhow::Result<Self> {
+        let mut index = Self::default();
+        for site_packages in site_packages_dirs.iter().rev() {
+            index
+                .distributions
+                .extend(Self::from_site_packages(site_packages)?.distributions);
+        }
+        Ok(index)
+    }
+
+    /// Whether `requirement` is already satisfied by what's indexed, also checking that any
+    /// requested extras' transitive dependencies are present
+    pub fn satisfies(&self, requirement: &Requirement) -> Satisfaction {
+        let Some(installed) = self.distributions.get(&normalize_name(&requirement.name)) else {
+            return Satisfaction::Missing;
+        };
+        if let Some(VersionOrUrl::VersionSpecifier(specifiers)) = &requirement.version_or_url {
+            if !specifiers.contains(&installed.version) {
+                return Satisfaction::Mismatch;
+            }
+        }
+        let requested_extras = requirement.extras.clone().unwrap_or_default();
+        if requested_extras
+            .iter()
+            .all(|extra| self.extra_satisfied(installed, extra))
+        {
+            Satisfaction::Satisfied
+        } else {
+            Satisfaction::Missing
+        }
+    }
+
+    /// Checks that every `Requires-Dist` of `installed` gated on `extra` is itself satisfied,
+    /// recursing through [`Self::satisfies`] so a chain of extras-only dependencies is fully
+    /// verified rather than just the extra's direct deps
+    fn extra_satisfied(&self, installed: &InstalledDistribution, extra: &str) -> bool {
+        installed
+            .requires_dist
+            .iter()
+            .filter_map(|requires_dist| Requirement::from_str(requires_dist).ok())
+            .filter(|dep| requirement_gated_on_extra(dep, extra))
+            .all(|dep| self.satisfies(&dep) == Satisfaction::Satisfied)
+    }
+}
+
+/// Whether `requirement`'s marker expression contains an `extra == "<extra>"` clause, the
+/// convention wheel metadata uses to mark a dependency as belonging to an extra
+fn requirement_gated_on_extra(requirement: &Requirement, extra: &str) -> bool {
+    requirement
+        .marker
+        .as_ref()
+        .map(|marker| {
+            marker
+                .to_string()
+                .contains(&format!("extra == \"{}\"", extra))
+        })
+        .unwrap_or(false)
+}
+
+/// Same normalization as [`crate::spec::RequestedSpec::normalized_name`] and the `.dist-info`
+/// folder names on disk
+fn normalize_name(name: &str) -> String {
+    name.to_lowercase().replace('-', "_")
+}
@@ -1,23 +1,42 @@
-//! Build a wheel from a source distribution
+//! Build a wheel from a source distribution or repo checkout, via PEP 517: read `[build-system]`,
+//! provision the declared backend (plus whatever it reports through `get_requires_for_build_wheel`)
+//! into an isolated directory, then call its `build_wheel` hook directly. There's no `build_sdist`
+//! call anywhere in here -- we only ever need an installable wheel out of this, and PEP 517 lets a
+//! frontend go straight from a source tree to `build_wheel` without round-tripping through an sdist
+//! first. Build requirements are provisioned with a plain `pip install --target` ([`provision_build_requirements`])
+//! rather than [`crate::poetry_integration::run::poetry_run`]'s bundled-lockfile bootstrap: that
+//! pattern only works because poetry itself is one fixed, known-in-advance set of dependencies we
+//! can pin a lockfile to ahead of time, whereas a `[build-system] requires` list is arbitrary and
+//! different for every project we build, so there's nothing to bundle a lockfile for.
 
+use crate::poetry_integration::poetry_toml::{BuildSystem, PoetryPyprojectToml};
 use crate::utils::cache_dir;
+use crate::{PEP517_BUILD_WHEEL, PEP517_GET_REQUIRES};
 use anyhow::{bail, Context, Result};
 use fs_err as fs;
 use install_wheel_rs::{WheelFilename, WheelInstallerError};
+use serde::Deserialize;
 use std::ffi::OsString;
 use std::io;
 use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::str::FromStr;
 use tempfile::TempDir;
+use tracing::debug;
 
 /// Takes a source distribution, checks whether we have already built a matching wheel, and if
-/// not, builds a wheels from the source distribution by invoking `pip wheel --no-deps`
+/// not, builds a wheel from the source distribution by calling its build backend's PEP 517
+/// `build_wheel` hook directly
+///
+/// Cached under `artifacts/<name>/<version>`, keyed on the compatible tag at lookup time; `name`
+/// and `version` already double as the source identity here (the exact pypi sdist for that
+/// version, or the resolved git revision for a checkout), so there's no separate source hash
 pub fn build_source_distribution_to_wheel_cached(
     name: &str,
     version: &str,
     sdist: &Path,
     compatible_tags: &[(String, String, String)],
+    sys_executable: &Path,
 ) -> Result<PathBuf> {
     let target_dir = cache_dir()?.join("artifacts").join(name).join(version);
 
@@ -40,7 +59,7 @@ pub fn build_source_distribution_to_wheel_cached(
     } else {
         let build_dir = TempDir::new()?;
 
-        let wheel = build_to_wheel(sdist, build_dir.path(), compatible_tags)?;
+        let wheel = build_to_wheel(sdist, build_dir.path(), compatible_tags, sys_executable)?;
         fs::create_dir_all(&target_dir)?;
         let wheel_in_cache = target_dir.join(wheel.file_name().unwrap_or(&OsString::new()));
         // rename only work on the same device :/
@@ -49,43 +68,193 @@ pub fn build_source_distribution_to_wheel_cached(
     }
 }
 
-/// Builds a wheel from an source distribution or a repo checkout using `pip wheel --no-deps`
+/// Reads `[build-system]` from `pyproject.toml`. Unlike the poetry-specific parsing elsewhere in
+/// the crate (which defaults to poetry-core, since that's only reached for projects we already
+/// know are poetry projects), a source distribution we're building for its own sake can be
+/// anything, so we fall back to the PEP 517-mandated default of setuptools's legacy backend when
+/// `[build-system]` (or the whole file) is missing
+/// <https://peps.python.org/pep-0517/#source-trees>
+pub(crate) fn read_build_system(sdist_or_dir: &Path) -> Result<BuildSystem> {
+    let pyproject_toml_path = sdist_or_dir.join("pyproject.toml");
+    if !pyproject_toml_path.is_file() {
+        return Ok(setuptools_legacy_build_system());
+    }
+    let pyproject_toml: PoetryPyprojectToml = toml::from_str(&fs::read_to_string(&pyproject_toml_path)?)
+        .with_context(|| format!("Invalid pyproject.toml in {}", pyproject_toml_path.display()))?;
+    Ok(pyproject_toml
+        .build_system
+        .unwrap_or_else(setuptools_legacy_build_system))
+}
+
+/// The implicit build backend PEP 517 mandates for projects that don't declare `[build-system]`
+fn setuptools_legacy_build_system() -> BuildSystem {
+    BuildSystem {
+        requires: vec!["setuptools>=40.8.0".to_string(), "wheel".to_string()],
+        build_backend: "setuptools.build_meta:__legacy__".to_string(),
+    }
+}
+
+/// Installs `requires` into an isolated directory (à la `pip install --target`) that we then put
+/// on `PYTHONPATH` for the build backend invocation, so the build doesn't pick up whatever
+/// happens to be installed in the calling environment.
+///
+/// This still shells out to pip rather than resolving and installing through our own
+/// [`crate::install::install_wheel`]/[`crate::package_index`] machinery: that machinery expects
+/// to install an already-resolved `(name, version)` pin into a wheel's own tag-keyed slot, whereas
+/// `[build-system] requires` is a handful of plain, unresolved PEP 508 requirement strings
+/// (version ranges, occasional extras) for a backend we don't know ahead of time -- resolving that
+/// correctly means exactly the dependency resolution pip already does, for the one part of the
+/// install pipeline where PEP 517 itself assumes a pip-equivalent is available
+pub(crate) fn provision_build_requirements(
+    requires: &[String],
+    sys_executable: &Path,
+) -> Result<PathBuf> {
+    let isolated_env = TempDir::new()?.into_path();
+    if !requires.is_empty() {
+        install_into(&isolated_env, requires, sys_executable)?;
+    }
+    Ok(isolated_env)
+}
+
+/// Installs additional requirements into an already provisioned isolated build environment, e.g.
+/// ones a build backend reported through the `get_requires_for_build_wheel` hook on top of the
+/// statically declared `[build-system] requires`
+fn install_into(isolated_env: &Path, requires: &[String], sys_executable: &Path) -> Result<()> {
+    debug!("Provisioning build requirements {:?}", requires);
+    let output = Command::new(sys_executable)
+        .args(["-m", "pip", "install", "--target"])
+        .arg(isolated_env)
+        .args(requires)
+        .output()
+        .context("Failed to invoke pip to provision build requirements")?;
+    if !output.status.success() {
+        bail!(
+            "Failed to provision build requirements {:?}: {}\n---stdout:\n{}---stderr:\n{}",
+            requires,
+            output.status,
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+    Ok(())
+}
+
+/// Calls the build backend's (optional) `get_requires_for_build_wheel` hook, which reports
+/// additional requirements on top of `[build-system] requires` that only the backend itself
+/// knows about (e.g. because they depend on the project's own setup.py/setup.cfg)
+fn get_requires_for_build_wheel(
+    sdist_or_dir: &Path,
+    build_backend: &str,
+    python_path: &std::ffi::OsStr,
+    sys_executable: &Path,
+) -> Result<Vec<String>> {
+    let output = Command::new(sys_executable)
+        .current_dir(sdist_or_dir)
+        .env("PYTHONPATH", python_path)
+        .args(["-S", "-c", PEP517_GET_REQUIRES])
+        .arg(build_backend)
+        .output()
+        .context("Failed to invoke the build backend's get_requires_for_build_wheel hook")?;
+    if !output.status.success() {
+        bail!(
+            "Failed to determine {}'s build requirements: {}\n---stdout:\n{}---stderr:\n{}",
+            build_backend,
+            output.status,
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let last_line = stdout
+        .lines()
+        .last()
+        .with_context(|| format!("{} didn't print anything", build_backend))?;
+    let requires: Vec<String> = serde_json::from_str(last_line)
+        .with_context(|| format!("Invalid output from {}: {}", build_backend, last_line))?;
+    Ok(requires)
+}
+
+/// What [`PEP517_BUILD_WHEEL`] reports on stdout
+#[derive(Deserialize)]
+struct BuildWheelOutput {
+    wheel_filename: String,
+}
+
+/// Builds a wheel from a source distribution or a repo checkout by calling the declared build
+/// backend's PEP 517 `build_wheel` hook directly (no more shelling out to `pip wheel --no-deps`)
 pub fn build_to_wheel(
     sdist_or_dir: &Path,
     // needs to be passed in or the tempdir will be deleted to early
     build_dir: &Path,
     compatible_tags: &[(String, String, String)],
+    sys_executable: &Path,
 ) -> Result<PathBuf> {
-    let output = Command::new("pip")
-        .current_dir(build_dir)
-        .args(&["wheel", "--no-deps"])
-        .arg(sdist_or_dir)
+    let build_system = read_build_system(sdist_or_dir)?;
+    let isolated_env = provision_build_requirements(&build_system.requires, sys_executable)?;
+
+    let mut python_path = vec![isolated_env.clone().into_os_string()];
+    if let Some(existing) = std::env::var_os("PYTHONPATH") {
+        python_path.push(existing);
+    }
+    let python_path = std::env::join_paths(&python_path)?;
+
+    let extra_requires = get_requires_for_build_wheel(
+        sdist_or_dir,
+        &build_system.build_backend,
+        &python_path,
+        sys_executable,
+    )?;
+    if !extra_requires.is_empty() {
+        debug!(
+            "{} additionally requires {:?} to build",
+            build_system.build_backend, extra_requires
+        );
+        install_into(&isolated_env, &extra_requires, sys_executable)?;
+    }
+
+    let output = Command::new(sys_executable)
+        .current_dir(sdist_or_dir)
+        .env("PYTHONPATH", &python_path)
+        .args(["-S", "-c", PEP517_BUILD_WHEEL])
+        .arg(&build_system.build_backend)
+        .arg(build_dir)
         .output()
-        .context("Failed to invoke pip")?;
+        .context("Failed to invoke the build backend")?;
 
     if !output.status.success() {
         return Err(WheelInstallerError::PythonSubcommandError(io::Error::new(
             io::ErrorKind::Other,
             format!(
-                "Failed to run `pip wheel --no-deps {}`: {}\n---stdout:\n{}---stderr:\n{}",
+                "Failed to build a wheel for {} through its {} build backend: {}\n\
+                 ---stdout:\n{}---stderr:\n{}",
                 sdist_or_dir.display(),
+                build_system.build_backend,
                 output.status,
                 String::from_utf8_lossy(&output.stdout),
                 String::from_utf8_lossy(&output.stderr)
             ),
         ))
         .into());
-    } else {
-        for path in fs::read_dir(build_dir)? {
-            let path = path?;
-            let filename = path.file_name().to_string_lossy().to_string();
-            if filename.ends_with(".whl") {
-                if !WheelFilename::from_str(&filename)?.is_compatible(compatible_tags) {
-                    bail!("pip wrote out an incompatible wheel (this is a bug)")
-                }
-                return Ok(path.path());
-            }
-        }
-        bail!("pip didn't write out a wheel (dubious)")
     }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let last_line = stdout
+        .lines()
+        .last()
+        .with_context(|| format!("{} didn't print anything", build_system.build_backend))?;
+    let reported: BuildWheelOutput = serde_json::from_str(last_line).with_context(|| {
+        format!(
+            "Invalid output from the {} build backend: {}",
+            build_system.build_backend, last_line
+        )
+    })?;
+
+    let wheel_path = build_dir.join(&reported.wheel_filename);
+    if !WheelFilename::from_str(&reported.wheel_filename)?.is_compatible(compatible_tags) {
+        bail!(
+            "{} built an incompatible wheel (this is a bug)",
+            build_system.build_backend
+        )
+    }
+    Ok(wheel_path)
 }
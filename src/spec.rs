@@ -1,8 +1,13 @@
+use crate::metadata_inspect::read_sdist_metadata;
 use crate::package_index::search_release;
 use install_wheel_rs::{WheelFilename, WheelInstallerError};
 use regex::Regex;
-use std::path::PathBuf;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::time::UNIX_EPOCH;
+use walkdir::WalkDir;
 
 /// Additional metadata for the url
 #[derive(Debug, Clone, Eq, PartialEq)]
@@ -18,6 +23,27 @@ pub struct SpecSource {
     pub url: String,
     pub reference: String,
     pub resolved_reference: String,
+    /// See [`crate::poetry_integration::poetry_lock::Source::branch`]
+    pub branch: Option<String>,
+    /// See [`crate::poetry_integration::poetry_lock::Source::tag`]
+    pub tag: Option<String>,
+    /// See [`crate::poetry_integration::poetry_lock::Source::rev`]
+    pub rev: Option<String>,
+}
+
+impl SpecSource {
+    /// The branch/tag/rev the user actually pinned, for error messages and as a fallback
+    /// checkout target when `resolved_reference` hasn't been filled in yet (e.g. a direct
+    /// PEP 508 `git+<url>@<ref>` reference, which is only resolved to a commit once cloned)
+    fn human_ref(&self) -> Option<&str> {
+        if !self.reference.is_empty() {
+            return Some(self.reference.as_str());
+        }
+        self.branch
+            .as_deref()
+            .or(self.tag.as_deref())
+            .or(self.rev.as_deref())
+    }
 }
 
 /// We have four sources of package install requests:
@@ -25,8 +51,6 @@ pub struct SpecSource {
 ///  * User gives a package name and version, needs json api and download
 ///  * User gives a file, which has name and version, doesn't need download
 ///  * Lockfile fives name, version and filename, needs download
-///
-/// TODO: carry hashes/locked files
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct RequestedSpec {
     pub requested: String,
@@ -34,10 +58,20 @@ pub struct RequestedSpec {
     pub python_version: Option<String>,
     pub source: Option<SpecSource>,
     pub extras: Vec<String>,
-    /// TODO: allow sdist filepath
-    pub file_path: Option<(PathBuf, WheelFilename)>,
+    pub file_path: Option<(PathBuf, DistributionType)>,
     /// Url, filename, distribution type
     pub url: Option<(String, String, DistributionType)>,
+    /// The `sha256:<hex digest>` the lockfile expects the downloaded `url` artifact to hash to, if
+    /// the lockfile recorded one for this file; checked against the actual download in
+    /// `download_and_install` and otherwise left unenforced
+    pub file_hash: Option<String>,
+    /// The `sha256:<hex digest>` values a requirements.txt `--hash` annotation allows the
+    /// downloaded artifact to match (pip permits more than one, e.g. when a requirement could
+    /// resolve to either a wheel or an sdist); checked in `download_and_install` against whichever
+    /// artifact was actually downloaded or built. Empty unless the spec came from a requirements.txt
+    /// entry with `--hash` flags or from a poetry.lock package without a `source`, for which every
+    /// file hash the lockfile recorded for that package is passed through
+    pub hashes: Vec<String>,
 }
 
 impl RequestedSpec {
@@ -47,55 +81,124 @@ impl RequestedSpec {
 
     pub fn get_unique_version(&self) -> Option<String> {
         if let Some(source) = &self.source {
-            Some(source.resolved_reference.clone())
+            match source.source_type.as_str() {
+                // Keyed the same way `resolve()` keys a directory source's `unique_version`: the
+                // directory itself has no version of its own, so tell apart successive edits with
+                // a mtime fingerprint instead
+                "directory" => Some(directory_unique_version(
+                    Path::new(&source.url),
+                    self.python_version.as_deref().unwrap_or_default(),
+                )),
+                // A local archive/direct url has no meaningful resolved reference; fall back to
+                // the plain version the same way a pypi-resolved spec does
+                "file" | "url" => self.python_version.clone(),
+                _ => Some(source.resolved_reference.clone()),
+            }
         } else {
             self.python_version.clone()
         }
     }
 
-    /// Parses "package_name", "package_name==version" and "some/path/tqdm-4.62.3-py2.py3-none-any.whl"
+    /// Parses "package_name", "package_name==version", "some/path/tqdm-4.62.3-py2.py3-none-any.whl",
+    /// a PEP 508 direct reference (`package @ https://host/foo-1.2-py3-none-any.whl`, including the
+    /// `git+` VCS form: `package @ git+https://github.com/org/repo@<rev>`), or a bare sdist/wheel
+    /// url without the `name @ ` prefix
     pub fn from_requested(
         requested: impl AsRef<str>,
         extras: &[String],
     ) -> Result<Self, WheelInstallerError> {
-        if requested.as_ref().ends_with(".whl") {
-            let file_path = PathBuf::from(requested.as_ref());
+        let requested_str = requested.as_ref();
+        if requested_str.ends_with(".whl") && !requested_str.contains("://") {
+            let file_path = PathBuf::from(requested_str);
             let filename = file_path
                 .file_name()
                 .ok_or_else(|| WheelInstallerError::InvalidWheel("Expected a file".to_string()))?
                 .to_string_lossy();
             let metadata = WheelFilename::from_str(&filename)?;
             Ok(Self {
-                requested: requested.as_ref().to_string(),
-                name: metadata.distribution.clone(),
-                python_version: Some(metadata.version.clone()),
+                requested: requested_str.to_string(),
+                name: metadata.distribution,
+                python_version: Some(metadata.version),
                 source: None,
                 extras: extras.to_vec(),
-                file_path: Some((file_path, metadata)),
+                file_path: Some((file_path, DistributionType::Wheel)),
                 url: None,
+                file_hash: None,
+                hashes: vec![],
             })
+        } else if is_sdist_filename(requested_str) && !requested_str.contains("://") {
+            let file_path = PathBuf::from(requested_str);
+            let sdist_metadata = read_sdist_metadata(&file_path)
+                .map_err(|err| WheelInstallerError::InvalidWheel(err.to_string()))?;
+            let (name, python_version) = match sdist_metadata {
+                Some(metadata) => (metadata.name, metadata.version),
+                // Legacy setup.py-only sdist with no PKG-INFO/egg-info yet; best effort from the
+                // filename until whoever resolves this spec can spare an interpreter to build it
+                None => {
+                    let filename = file_path
+                        .file_name()
+                        .ok_or_else(|| {
+                            WheelInstallerError::InvalidWheel("Expected a file".to_string())
+                        })?
+                        .to_string_lossy();
+                    let name = filename
+                        .split_once('-')
+                        .map_or(&*filename, |(name, _)| name);
+                    let version = version_from_sdist_filename(&filename)
+                        .unwrap_or_else(|| filename.to_string());
+                    (name.to_string(), version)
+                }
+            };
+            Ok(Self {
+                requested: requested_str.to_string(),
+                name,
+                python_version: Some(python_version),
+                source: None,
+                extras: extras.to_vec(),
+                file_path: Some((file_path, DistributionType::SourceDistribution)),
+                url: None,
+                file_hash: None,
+                hashes: vec![],
+            })
+        } else if let Some((name, target)) = requested_str.split_once('@') {
+            // PEP 508 direct reference, e.g. `tqdm @ https://host/tqdm-4.62.3-py2.py3-none-any.whl`
+            // or `tqdm @ git+https://github.com/tqdm/tqdm@v4.62.3`
+            Self::from_direct_reference(requested_str, name.trim(), target.trim(), extras)
+        } else if requested_str.contains("://") {
+            // A bare sdist/wheel url with no `name @ ` prefix; derive the name from the filename
+            let filename = requested_str
+                .rsplit('/')
+                .next()
+                .filter(|segment| !segment.is_empty())
+                .ok_or(WheelInstallerError::Pep440)?;
+            let name = filename.split_once('-').map_or(filename, |(name, _)| name);
+            Self::from_direct_reference(requested_str, name, requested_str, extras)
         } else {
             // TODO: check actual naming rules
             let valid_name = Regex::new(r"[-_a-zA-Z\d.]+").unwrap();
-            if let Some((name, version)) = requested.as_ref().split_once("==") {
+            if let Some((name, version)) = requested_str.split_once("==") {
                 Ok(Self {
-                    requested: requested.as_ref().to_string(),
+                    requested: requested_str.to_string(),
                     name: name.to_string(),
                     python_version: Some(version.to_string()),
                     source: None,
                     extras: extras.to_vec(),
                     file_path: None,
                     url: None,
+                    file_hash: None,
+                    hashes: vec![],
                 })
-            } else if valid_name.is_match(requested.as_ref()) {
+            } else if valid_name.is_match(requested_str) {
                 Ok(Self {
-                    requested: requested.as_ref().to_string(),
-                    name: requested.as_ref().to_string(),
+                    requested: requested_str.to_string(),
+                    name: requested_str.to_string(),
                     python_version: None,
                     source: None,
                     extras: extras.to_vec(),
                     file_path: None,
                     url: None,
+                    file_hash: None,
+                    hashes: vec![],
                 })
             } else {
                 Err(WheelInstallerError::Pep440)
@@ -103,23 +206,127 @@ impl RequestedSpec {
         }
     }
 
-    /// if required (most cases) it queries the pypi index for the actual url
-    /// (the pypi url shortcut doesn't work)
+    /// Builds a [`RequestedSpec`] for a local, not-yet-built source directory (a requirements.txt
+    /// bare path or `-e`/`--editable` entry) whose name and version aren't known from a filename
+    /// the way an archive's is -- the caller has already read them from the project's PEP 517/621
+    /// metadata (see [`crate::metadata_inspect::inspect_metadata`])
+    pub fn from_source_directory(
+        dir: PathBuf,
+        name: String,
+        version: String,
+        extras: Vec<String>,
+    ) -> Self {
+        Self {
+            requested: dir.display().to_string(),
+            name,
+            python_version: Some(version),
+            source: None,
+            extras,
+            file_path: Some((dir, DistributionType::SourceDistribution)),
+            url: None,
+            file_hash: None,
+            hashes: vec![],
+        }
+    }
+
+    /// Builds a [`RequestedSpec`] for a PEP 508 direct reference or bare URL: `target` is either a
+    /// `git+<repo-url>[@<ref>]` VCS spec or a plain sdist/wheel url
+    pub(crate) fn from_direct_reference(
+        requested: &str,
+        name: &str,
+        target: &str,
+        extras: &[String],
+    ) -> Result<Self, WheelInstallerError> {
+        if let Some(repo_spec) = target.strip_prefix("git+") {
+            let (url, reference) = parse_git_reference(repo_spec);
+            let url = normalize_git_ssh_url(&url);
+            return Ok(Self {
+                requested: requested.to_string(),
+                name: name.to_string(),
+                // python_version isn't a real version for a git source: get_unique_version()
+                // always prefers source.resolved_reference once source is set, so this is just a
+                // placeholder that lets resolve() reach the FileOrUrl::Git branch below
+                python_version: Some(if reference.is_empty() {
+                    "HEAD".to_string()
+                } else {
+                    reference.clone()
+                }),
+                source: Some(SpecSource {
+                    source_type: "git".to_string(),
+                    url,
+                    reference,
+                    // filled in once the repository has actually been cloned and the ref resolved
+                    resolved_reference: String::new(),
+                    // pip/poetry's `git+<url>[@<ref>]` VCS form doesn't distinguish branch, tag
+                    // and commit, so we can't tell which of the three `ref` is
+                    branch: None,
+                    tag: None,
+                    rev: None,
+                }),
+                extras: extras.to_vec(),
+                file_path: None,
+                url: None,
+                file_hash: None,
+                hashes: vec![],
+            });
+        }
+
+        let filename = target
+            .rsplit('/')
+            .next()
+            .filter(|segment| !segment.is_empty())
+            .ok_or(WheelInstallerError::Pep440)?
+            .to_string();
+        let (python_version, distribution_type) = if filename.ends_with(".whl") {
+            let metadata = WheelFilename::from_str(&filename)?;
+            (metadata.version, DistributionType::Wheel)
+        } else {
+            // Best-effort PEP 440 version out of a `{name}-{version}.tar.gz`-style sdist filename;
+            // falling back to the filename itself is no worse than not knowing the version at all
+            let version =
+                version_from_sdist_filename(&filename).unwrap_or_else(|| filename.clone());
+            (version, DistributionType::SourceDistribution)
+        };
+        Ok(Self {
+            requested: requested.to_string(),
+            name: name.to_string(),
+            python_version: Some(python_version),
+            source: None,
+            extras: extras.to_vec(),
+            file_path: None,
+            url: Some((target.to_string(), filename, distribution_type)),
+            file_hash: None,
+            hashes: vec![],
+        })
+    }
+
+    /// Turns this spec into something installable: a local `file_path` or `url` short-circuits
+    /// immediately, a lockfile-pinned `source` (git/url/directory) resolves straight to the
+    /// matching [`FileOrUrl`] variant without touching the index, and otherwise (most cases) it
+    /// queries the pypi index for the actual url (the pypi url shortcut doesn't work)
     pub fn resolve(
         &self,
+        interpreter_python_version: (u8, u8),
         compatible_tags: &[(String, String, String)],
     ) -> anyhow::Result<ResolvedSpec> {
         if let Some(python_version) = self.python_version.clone() {
-            if let Some((file_path, _filename)) = self.file_path.clone() {
+            if let Some((file_path, distribution_type)) = self.file_path.clone() {
+                let unique_version = match path_mtime_fingerprint(&file_path) {
+                    Some(fingerprint) => format!("{}+{}", python_version, fingerprint),
+                    // A plain archive file (unlike a source directory) is immutable once
+                    // downloaded, so its own version is already a stable, unique identifier
+                    None => python_version.clone(),
+                };
                 return Ok(ResolvedSpec {
                     requested: self.requested.clone(),
                     name: self.name.clone(),
                     python_version: python_version.clone(),
-                    // TODO: hash path + last modified into something unique
-                    unique_version: python_version,
+                    unique_version,
                     extras: self.extras.clone(),
                     location: FileOrUrl::File(file_path),
-                    distribution_type: DistributionType::Wheel,
+                    distribution_type,
+                    file_hash: self.file_hash.clone(),
+                    hashes: self.hashes.clone(),
                 });
             } else if let Some((url, filename, distribution_type)) = self.url.clone() {
                 return Ok(ResolvedSpec {
@@ -128,27 +335,141 @@ impl RequestedSpec {
                     python_version: python_version.clone(),
                     unique_version: self.get_unique_version().unwrap_or(python_version),
                     extras: self.extras.clone(),
-                    location: FileOrUrl::Url { url, filename },
+                    // A directly pinned file/lockfile url isn't resolved through a configured
+                    // index, so there's no index credentials to attach here
+                    location: FileOrUrl::Url {
+                        url,
+                        filename,
+                        credentials: None,
+                    },
                     distribution_type,
+                    file_hash: self.file_hash.clone(),
+                    hashes: self.hashes.clone(),
                 });
             } else if let Some(source) = self.source.clone() {
-                return Ok(ResolvedSpec {
-                    requested: self.requested.clone(),
-                    name: self.name.clone(),
-                    python_version,
-                    unique_version: source.resolved_reference.clone(),
-                    extras: self.extras.clone(),
-                    location: FileOrUrl::Git {
-                        url: source.url,
-                        revision: source.resolved_reference,
-                    },
-                    distribution_type: DistributionType::SourceDistribution,
-                });
+                return match source.source_type.as_str() {
+                    // A vendored local path (poetry's `path = "..."` dependency, editable or not):
+                    // `source.url` is the directory itself, built into a wheel the same way a git
+                    // checkout is, with the same mtime fingerprint used to invalidate the build
+                    // cache when it's edited
+                    "directory" => {
+                        let dir = PathBuf::from(&source.url);
+                        let unique_version = directory_unique_version(&dir, &python_version);
+                        Ok(ResolvedSpec {
+                            requested: self.requested.clone(),
+                            name: self.name.clone(),
+                            python_version,
+                            unique_version,
+                            extras: self.extras.clone(),
+                            location: FileOrUrl::File(dir),
+                            distribution_type: DistributionType::SourceDistribution,
+                            file_hash: None,
+                            hashes: vec![],
+                        })
+                    }
+                    // A local sdist/wheel archive (poetry's `path = "..."` pointing at a file
+                    // rather than a directory): the archive is immutable once written, so its own
+                    // version is already a stable, unique identifier, same as a directly pinned file
+                    "file" => {
+                        let file_path = PathBuf::from(&source.url);
+                        let distribution_type = if is_sdist_filename(&source.url) {
+                            DistributionType::SourceDistribution
+                        } else {
+                            DistributionType::Wheel
+                        };
+                        Ok(ResolvedSpec {
+                            requested: self.requested.clone(),
+                            name: self.name.clone(),
+                            python_version: python_version.clone(),
+                            unique_version: python_version,
+                            extras: self.extras.clone(),
+                            location: FileOrUrl::File(file_path),
+                            distribution_type,
+                            file_hash: None,
+                            hashes: vec![],
+                        })
+                    }
+                    // A direct archive url (poetry's `url = "..."` dependency); `resolution_to_specs`
+                    // normally intercepts this source type earlier and routes it through `self.url`
+                    // instead, so this arm only fires for a lockfile source we built more directly
+                    "url" => {
+                        let filename = source
+                            .url
+                            .rsplit('/')
+                            .next()
+                            .filter(|segment| !segment.is_empty())
+                            .unwrap_or(&source.url)
+                            .to_string();
+                        let distribution_type = if is_sdist_filename(&filename) {
+                            DistributionType::SourceDistribution
+                        } else {
+                            DistributionType::Wheel
+                        };
+                        Ok(ResolvedSpec {
+                            requested: self.requested.clone(),
+                            name: self.name.clone(),
+                            python_version: python_version.clone(),
+                            unique_version: python_version,
+                            extras: self.extras.clone(),
+                            location: FileOrUrl::Url {
+                                url: source.url,
+                                filename,
+                                credentials: None,
+                            },
+                            distribution_type,
+                            file_hash: None,
+                            hashes: vec![],
+                        })
+                    }
+                    // "git", and any source type we don't specifically recognize (e.g. a future
+                    // poetry.lock source kind), falls back to the git checkout path that's handled
+                    // every other source type until now
+                    _ => {
+                        // `resolved_reference` (an exact commit) is preferred since it's
+                        // reproducible, but a direct PEP 508 reference hasn't been cloned yet at
+                        // this point and so has no resolved commit -- fall back to the human-given
+                        // branch/tag/rev so there's still something to check out
+                        let human_ref = source.human_ref().map(str::to_string);
+                        let revision = if !source.resolved_reference.is_empty() {
+                            source.resolved_reference.clone()
+                        } else {
+                            human_ref.clone().unwrap_or_default()
+                        };
+                        Ok(ResolvedSpec {
+                            requested: self.requested.clone(),
+                            name: self.name.clone(),
+                            python_version,
+                            unique_version: revision.clone(),
+                            extras: self.extras.clone(),
+                            location: FileOrUrl::Git {
+                                url: source.url,
+                                revision,
+                                reference: human_ref,
+                            },
+                            distribution_type: DistributionType::SourceDistribution,
+                            file_hash: None,
+                            hashes: vec![],
+                        })
+                    }
+                };
             }
         }
 
-        let (picked_release, distribution_type, version) =
-            search_release(&self.name, self.python_version.clone(), compatible_tags)?;
+        let (picked_release, distribution_type, version, credentials) = search_release(
+            &self.name,
+            self.python_version.clone(),
+            compatible_tags,
+            Some(interpreter_python_version),
+        )?;
+        // A hash the spec already pinned (e.g. from a Pipfile.lock/pdm.lock entry) takes priority
+        // over whatever the index itself advertised, since that's the more specific, explicitly
+        // requested pin
+        let file_hash = self.file_hash.clone().or_else(|| {
+            picked_release
+                .digests
+                .get("sha256")
+                .map(|hash| format!("sha256:{}", hash))
+        });
         Ok(ResolvedSpec {
             requested: self.requested.clone(),
             name: self.name.clone(),
@@ -158,17 +479,140 @@ impl RequestedSpec {
             location: FileOrUrl::Url {
                 url: picked_release.url,
                 filename: picked_release.filename,
+                credentials,
             },
             distribution_type,
+            file_hash,
+            hashes: self.hashes.clone(),
         })
     }
 }
 
+/// A short fingerprint of a local source directory's last modification time, used to tell apart
+/// successive edits of the same unbuilt project (which, unlike a pinned archive, keeps the same
+/// name and version across changes) so a stale build doesn't get reused after the source changed.
+/// Walks the whole tree rather than stat'ing just `path` itself, since editing an existing file's
+/// contents in place doesn't bump its parent directory's mtime on most filesystems. Returns `None`
+/// for anything that isn't a directory (an archive file's own version is already a stable, unique
+/// identifier)
+fn path_mtime_fingerprint(path: &Path) -> Option<String> {
+    if !path.is_dir() {
+        return None;
+    }
+    let mut hasher = DefaultHasher::new();
+    let walker = WalkDir::new(path).into_iter().filter_entry(|entry| {
+        // depth 0 is `path` itself, which we always want to walk into even if it happens to be
+        // named e.g. `build` -- only prune artifact directories/files below the root
+        if entry.depth() == 0 {
+            return true;
+        }
+        // A PEP 517 build backend (setuptools in particular) writes its own build artifacts
+        // straight into the source directory, so including them here would make every build
+        // change the fingerprint and invalidate its own wheel cache on the very next run
+        !matches!(
+            entry.file_name().to_str(),
+            Some("build" | "dist" | "__pycache__" | ".git")
+        ) && !entry.file_name().to_string_lossy().ends_with(".egg-info")
+    });
+    for entry in walker.filter_map(|entry| entry.ok()) {
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let modified = match entry.metadata().ok().and_then(|m| m.modified().ok()) {
+            Some(modified) => modified,
+            None => continue,
+        };
+        let since_epoch = modified.duration_since(UNIX_EPOCH).unwrap_or_default();
+        entry.path().hash(&mut hasher);
+        since_epoch.hash(&mut hasher);
+    }
+    Some(format!("{:x}", hasher.finish()))
+}
+
+/// The `unique_version` for a local, unbuilt source directory: `version` with a path-mtime
+/// fingerprint appended so editing the directory invalidates the build cache, matching
+/// [`RequestedSpec::resolve`]'s "directory" source arm. Shared with [`RequestedSpec::get_unique_version`]
+/// and [`crate::gc::reachable_from_lockfile`], which both need to key on the exact same directory
+/// name `resolve()` installs into. Falls back to `version` alone if `dir` no longer exists
+pub(crate) fn directory_unique_version(dir: &Path, version: &str) -> String {
+    match path_mtime_fingerprint(dir) {
+        Some(fingerprint) => format!("{}+{}", version, fingerprint),
+        None => version.to_string(),
+    }
+}
+
+/// Splits a `git+<url>[@<ref>]` spec (with the `git+` prefix already stripped) into the repo url
+/// and the branch/tag/rev, if any. The last `@` after the scheme separator is the ref marker
+/// *unless* it has no `/` before it within that same span -- that shape is the authority's own
+/// `user@host` (`git+ssh://git@host/org/repo`, scp-style `git@host:org/repo.git`, or
+/// either of those with no ref at all), since an actual ref always comes after the repo path and
+/// the repo path always contains at least one `/`
+fn parse_git_reference(repo_spec: &str) -> (String, String) {
+    let scheme_end = repo_spec.find("://").map(|pos| pos + 3).unwrap_or(0);
+    match repo_spec[scheme_end..].rfind('@') {
+        Some(at_pos) if repo_spec[scheme_end..scheme_end + at_pos].contains('/') => {
+            let split_at = scheme_end + at_pos;
+            (
+                repo_spec[..split_at].to_string(),
+                repo_spec[split_at + 1..].to_string(),
+            )
+        }
+        _ => (repo_spec.to_string(), String::new()),
+    }
+}
+
+/// Normalizes a scp-style ssh url (`git@host:org/repo.git`) into the equivalent `ssh://`
+/// form (`ssh://git@host/org/repo.git`) that pip/poetry also accept, so downstream code
+/// only ever has to deal with one shape. Urls that already have a scheme are returned unchanged
+pub(crate) fn normalize_git_ssh_url(url: &str) -> String {
+    if url.contains("://") {
+        return url.to_string();
+    }
+    let expr = Regex::new(r"^([\w.-]+@[\w.-]+):(.+)$").unwrap();
+    match expr.captures(url) {
+        Some(capture) => format!("ssh://{}/{}", &capture[1], &capture[2]),
+        None => url.to_string(),
+    }
+}
+
+/// Extensions sdist archives are published under
+const SDIST_EXTENSIONS: &[&str] = &[".tar.gz", ".tar.bz2", ".tgz", ".zip"];
+
+/// Whether `name` looks like a sdist archive by its extension
+pub(crate) fn is_sdist_filename(name: &str) -> bool {
+    SDIST_EXTENSIONS.iter().any(|ext| name.ends_with(ext))
+}
+
+/// Best-effort PEP 440 version out of a `{name}-{version}.tar.gz`-style sdist filename. Since sdist
+/// filenames don't reliably distinguish where the name ends and the version begins, this just
+/// takes the last `-`-separated segment, which is right for the common case
+pub(crate) fn version_from_sdist_filename(filename: &str) -> Option<String> {
+    let stem = SDIST_EXTENSIONS
+        .iter()
+        .find_map(|ext| filename.strip_suffix(ext))?;
+    stem.rsplit_once('-')
+        .map(|(_name, version)| version.to_string())
+}
+
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub enum FileOrUrl {
     File(PathBuf),
-    Url { url: String, filename: String },
-    Git { url: String, revision: String },
+    Url {
+        url: String,
+        filename: String,
+        /// HTTP basic auth credentials for the index the file was resolved from, if it needed
+        /// any, to be reused for downloading the file itself
+        credentials: Option<(String, String)>,
+    },
+    Git {
+        url: String,
+        /// The revision to actually check out: a resolved commit when known, otherwise the
+        /// human-given branch/tag/rev
+        revision: String,
+        /// The human-given branch/tag/rev, if any, kept alongside `revision` purely for error
+        /// messages -- `revision` is what's actually passed to `repo_at_revision`
+        reference: Option<String>,
+    },
 }
 
 /// An installation request for a specific source, that unlike [RequestedSpec] definitely
@@ -188,14 +632,19 @@ pub struct ResolvedSpec {
     pub extras: Vec<String>,
     pub location: FileOrUrl,
     pub distribution_type: DistributionType,
+    /// The `sha256:<hex digest>` the downloaded artifact has to hash to, either pinned by the
+    /// requested spec or, for a release found on the index, the digest the index advertised;
+    /// checked in `download_and_install` before the artifact is installed
+    pub file_hash: Option<String>,
+    /// See [`RequestedSpec::hashes`]
+    pub hashes: Vec<String>,
 }
 
 #[cfg(test)]
 mod test {
     use crate::spec::{FileOrUrl, ResolvedSpec};
-    use crate::utils::zstd_json_mock;
     use crate::{poetry_spec_from_dir, Pep508Environment};
-    use install_wheel_rs::{compatible_tags, Arch, Os};
+    use install_wheel_rs::{compatible_tags, Arch, InterpreterKind, Os};
     use std::path::Path;
 
     fn manylinux_url(package: &str) -> anyhow::Result<ResolvedSpec> {
@@ -205,7 +654,31 @@ mod test {
         };
         let arch = Arch::X86_64;
         let python_version = (3, 7);
-        let compatible_tags = compatible_tags(python_version, &os, &arch).unwrap();
+        let compatible_tags =
+            compatible_tags(python_version, &os, &arch, &InterpreterKind::CPython).unwrap();
+        let pep508_env = Pep508Environment::from_json_str(
+            r##"{"implementation_name": "cpython", "implementation_version": "3.7.13", "os_name": "posix", "platform_machine": "x86_64", "platform_python_implementation": "CPython", "platform_release": "5.4.188+", "platform_system": "Linux", "platform_version": "#1 SMP Sun Apr 24 10:03:06 PDT 2022", "python_full_version": "3.7.13", "python_version": "3.7", "sys_platform": "linux"}"##,
+        );
+
+        let (specs, _, _) = poetry_spec_from_dir(
+            Path::new("src/poetry_integration/poetry_boostrap_lock"),
+            &[],
+            &pep508_env,
+        )
+        .unwrap();
+        specs
+            .iter()
+            .find(|spec| spec.name == package)
+            .unwrap()
+            .resolve(python_version, &compatible_tags)
+    }
+
+    fn musllinux_url(package: &str) -> anyhow::Result<ResolvedSpec> {
+        let os = Os::Musllinux { major: 1, minor: 2 };
+        let arch = Arch::X86_64;
+        let python_version = (3, 7);
+        let compatible_tags =
+            compatible_tags(python_version, &os, &arch, &InterpreterKind::CPython).unwrap();
         let pep508_env = Pep508Environment::from_json_str(
             r##"{"implementation_name": "cpython", "implementation_version": "3.7.13", "os_name": "posix", "platform_machine": "x86_64", "platform_python_implementation": "CPython", "platform_release": "5.4.188+", "platform_system": "Linux", "platform_version": "#1 SMP Sun Apr 24 10:03:06 PDT 2022", "python_full_version": "3.7.13", "python_version": "3.7", "sys_platform": "linux"}"##,
         );
@@ -220,17 +693,75 @@ mod test {
             .iter()
             .find(|spec| spec.name == package)
             .unwrap()
-            .resolve(&compatible_tags)
+            .resolve(python_version, &compatible_tags)
+    }
+
+    #[test]
+    fn test_musllinux_url() {
+        // Same shape as `test_manylinux_url`, but the only compatible wheel is tagged
+        // `musllinux_1_1` instead of `manylinux`, so this only passes if `compatible_tags` and
+        // `resolve` both recognize musllinux platform tags. Uses a different package name than
+        // `test_manylinux_url`/`test_pypi_no_internet` so the mocks don't race on the same url.
+        let body = r#"{
+            "meta": {"api-version": "1.0"},
+            "name": "cryptography",
+            "files": [
+                {
+                    "filename": "cryptography-38.0.1-cp37-abi3-musllinux_1_1_x86_64.whl",
+                    "url": "https://files.pythonhosted.org/packages/44/6b/5edf93698ef1dc745774e47e26f5995040dd3604562dd63f5959fcd3a49e/cryptography-38.0.1-cp37-abi3-musllinux_1_1_x86_64.whl",
+                    "hashes": {"sha256": "abcd"}
+                },
+                {
+                    "filename": "cryptography-38.0.1-cp37-abi3-win_amd64.whl",
+                    "url": "https://files.pythonhosted.org/packages/00/00/cryptography-38.0.1-cp37-abi3-win_amd64.whl",
+                    "hashes": {"sha256": "efgh"}
+                }
+            ]
+        }"#;
+        let _mock = mockito::mock("GET", "/simple/cryptography/")
+            .with_header("content-type", "application/vnd.pypi.simple.v1+json")
+            .with_body(body)
+            .create();
+        assert_eq!(
+            musllinux_url("cryptography").unwrap().location,
+            FileOrUrl::Url {
+                url: "https://files.pythonhosted.org/packages/44/6b/5edf93698ef1dc745774e47e26f5995040dd3604562dd63f5959fcd3a49e/cryptography-38.0.1-cp37-abi3-musllinux_1_1_x86_64.whl".to_string(),
+                filename: "cryptography-38.0.1-cp37-abi3-musllinux_1_1_x86_64.whl".to_string(),
+                credentials: None,
+            },
+        )
     }
 
     #[test]
     fn test_manylinux_url() {
-        let _mock = zstd_json_mock("/pypi/cffi/json", "test-data/pypi/cffi.json.zstd");
+        // PEP 691 JSON form of the Simple Repository API, with just the one file the assertion
+        // below needs plus a decoy wheel for an incompatible platform
+        let body = r#"{
+            "meta": {"api-version": "1.0"},
+            "name": "cffi",
+            "files": [
+                {
+                    "filename": "cffi-1.15.0-cp37-cp37m-manylinux_2_12_x86_64.manylinux2010_x86_64.whl",
+                    "url": "https://files.pythonhosted.org/packages/44/6b/5edf93698ef1dc745774e47e26f5995040dd3604562dd63f5959fcd3a49e/cffi-1.15.0-cp37-cp37m-manylinux_2_12_x86_64.manylinux2010_x86_64.whl",
+                    "hashes": {"sha256": "abcd"}
+                },
+                {
+                    "filename": "cffi-1.15.0-cp37-cp37m-win_amd64.whl",
+                    "url": "https://files.pythonhosted.org/packages/00/00/cffi-1.15.0-cp37-cp37m-win_amd64.whl",
+                    "hashes": {"sha256": "efgh"}
+                }
+            ]
+        }"#;
+        let _mock = mockito::mock("GET", "/simple/cffi/")
+            .with_header("content-type", "application/vnd.pypi.simple.v1+json")
+            .with_body(body)
+            .create();
         assert_eq!(
             manylinux_url("cffi").unwrap().location,
             FileOrUrl::Url {
                 url: "https://files.pythonhosted.org/packages/44/6b/5edf93698ef1dc745774e47e26f5995040dd3604562dd63f5959fcd3a49e/cffi-1.15.0-cp37-cp37m-manylinux_2_12_x86_64.manylinux2010_x86_64.whl".to_string(),
-                filename: "cffi-1.15.0-cp37-cp37m-manylinux_2_12_x86_64.manylinux2010_x86_64.whl".to_string()
+                filename: "cffi-1.15.0-cp37-cp37m-manylinux_2_12_x86_64.manylinux2010_x86_64.whl".to_string(),
+                credentials: None,
             },
         )
     }
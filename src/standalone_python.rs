@@ -1,21 +1,36 @@
 //! Download and install standalone python builds (PyOxy) from
 //! <https://github.com/indygreg/python-build-standalone>
 
-use crate::monotrail::{LaunchType, PythonContext};
+use crate::inject_and_run::{
+    compatible_platform_tags, parse_major_minor, probe_interpreter_info, InterpreterInfo,
+    PythonConfigFile, PYTHON_CONFIG_FILE_VAR,
+};
+use crate::interpreter_locator::{locate_interpreters, select_interpreter};
+use crate::monotrail::{Implementation, LaunchType, PythonContext};
 use crate::utils::cache_dir;
 use crate::Pep508Environment;
 use anyhow::{bail, Context};
+use flate2::read::GzDecoder;
 use fs2::FileExt;
 use fs_err as fs;
 use fs_err::File;
-use regex::Regex;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::io;
+use std::io::Read;
 use std::path::{Path, PathBuf};
 use tempfile::tempdir_in;
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
 #[cfg_attr(test, allow(dead_code))]
 const GITHUB_API: &str = "https://api.github.com";
 
+/// Env var overriding [`GITHUB_API`] with a mirror that serves the same
+/// `/repos/indygreg/python-build-standalone/releases/...` paths, for air-gapped CI or behind a
+/// corporate proxy that can't reach `api.github.com`
+const PYTHON_MIRROR_VAR: &str = "MONOTRAIL_PYTHON_MIRROR";
+
 const PYTHON_STANDALONE_LATEST_RELEASE: (&str, &str) = (
     // api url
     "/repos/indygreg/python-build-standalone/releases/latest",
@@ -33,6 +48,7 @@ const PYTHON_STANDALONE_KNOWN_GOOD_RELEASE: (&str, &str) = (
 
 #[derive(Deserialize)]
 struct GitHubRelease {
+    tag_name: String,
     assets: Vec<GitHubAsset>,
 }
 
@@ -42,15 +58,284 @@ struct GitHubAsset {
     browser_download_url: String,
 }
 
-/// Returns the url of the matching pgo+lto prebuilt python. We first try to find one in the latest
-/// indygreg/python-build-standalone, then fall back to a known good release in case a more recent
-/// release broke compatibility
-fn find_python(major: u8, minor: u8) -> anyhow::Result<String> {
+/// The tarball url to download plus, if a companion `<name>.sha256` asset was found in the same
+/// release, the digest we expect the downloaded bytes to hash to. `patch` is the exact patch
+/// version the asset name resolved to, `None` only if the name we just picked it by somehow fails
+/// to parse back (it shouldn't, since [`select_asset`] already parsed it once to select it)
+struct PythonDownload {
+    url: String,
+    expected_sha256: Option<String>,
+    patch: Option<u8>,
+}
+
+/// One `python-build-standalone` asset recorded in `versions.json`, keyed by the same
+/// `(major, minor, patch, triple, flavor, optimization)` tuple [`StandaloneAssetName`] parses out of
+/// an asset filename
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct VersionsManifestEntry {
+    major: u8,
+    minor: u8,
+    patch: u8,
+    /// Part of the same `(patch, build_tag)` newest-wins tiebreak [`pick_standalone_build`] uses, so
+    /// two entries that only differ by a release re-cut of the same patch don't depend on
+    /// [`fetch_versions`]'s write order to pick the right one
+    build_tag: u32,
+    triple: String,
+    flavor: String,
+    optimization: Option<String>,
+    /// The python-build-standalone release tag this asset was published under, e.g. `"20220502"`,
+    /// kept around only for provenance when looking at the file, not used for selection
+    tag: String,
+    url: String,
+    sha256: String,
+}
+
+/// Checked-in index of `python-build-standalone` downloads, regenerated by [`fetch_versions`] from
+/// the upstream release listing. Consulting this instead of querying GitHub on every install makes
+/// the set of downloadable builds explicit and integrity-checkable (every entry carries the sha256
+/// we verified when we recorded it) and bumping supported interpreters a data change rather than a
+/// code change
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+struct VersionsManifest {
+    downloads: Vec<VersionsManifestEntry>,
+}
+
+/// `versions.json` lives at the repo root and is embedded at build time rather than read from disk,
+/// the same way e.g. the finder python sources are, so an install never depends on a file being
+/// next to the binary. Starts out (and may remain, in between [`fetch_versions`] runs) without an
+/// entry for every version we can still provision -- [`find_python`] falls back to a live lookup
+/// against indygreg/python-build-standalone whenever this manifest has no match
+const VERSIONS_JSON: &str = include_str!("../versions.json");
+
+fn load_versions_manifest() -> VersionsManifest {
+    serde_json::from_str(VERSIONS_JSON).unwrap_or_else(|err| {
+        warn!("Couldn't parse the checked-in versions.json, ignoring it: {err}");
+        VersionsManifest::default()
+    })
+}
+
+/// Picks the entry with the newest `(patch, build_tag)` matching `(major, minor[, patch])`,
+/// `triple`, `flavor` and `optimization` out of `manifest`, the manifest-backed counterpart to
+/// [`select_asset`]/[`pick_standalone_build`]
+fn find_in_versions_manifest(
+    manifest: &VersionsManifest,
+    major: u8,
+    minor: u8,
+    patch: Option<u8>,
+    triple: &str,
+    flavor: &str,
+    optimization: Option<&str>,
+) -> Option<PythonDownload> {
+    manifest
+        .downloads
+        .iter()
+        .filter(|entry| {
+            matches_selection(
+                entry.major,
+                entry.minor,
+                entry.patch,
+                &entry.triple,
+                &entry.flavor,
+                entry.optimization.as_deref(),
+                major,
+                minor,
+                patch,
+                triple,
+                flavor,
+                optimization,
+            )
+        })
+        .max_by_key(|entry| (entry.patch, entry.build_tag))
+        .map(|entry| PythonDownload {
+            url: entry.url.clone(),
+            expected_sha256: Some(entry.sha256.clone()),
+            patch: Some(entry.patch),
+        })
+}
+
+/// Queries the upstream python-build-standalone release listing (the same latest and known-good
+/// releases [`find_python`] falls back to) and regenerates `versions.json` at `output` from every
+/// asset we can parse and find a sha256 digest for. Run manually when bumping the set of
+/// interpreters we offer (`monotrail fetch-versions`, then commit the resulting diff) -- not part
+/// of the normal install path, which only ever reads the checked-in file
+pub fn fetch_versions(output: &Path) -> anyhow::Result<usize> {
+    let host = env::var(PYTHON_MIRROR_VAR).unwrap_or_else(|_| GITHUB_API.to_string());
+
+    // Keyed the same way `find_in_versions_manifest`/`pick_standalone_build` select on, so if both
+    // releases (or a release re-cut of the same patch) publish an asset for the same key, we keep
+    // only the one with the newest build tag instead of depending on which happened to be seen last
+    let mut by_key: BTreeMap<(u8, u8, u8, String, String, Option<String>), VersionsManifestEntry> =
+        BTreeMap::new();
+    for (api_path, _web_url) in [
+        PYTHON_STANDALONE_LATEST_RELEASE,
+        PYTHON_STANDALONE_KNOWN_GOOD_RELEASE,
+    ] {
+        let release: GitHubRelease = ureq::get(&format!("{}{}", host, api_path))
+            .set("User-Agent", "monotrail (konstin@mailbox.org)")
+            .call()?
+            .into_json()?;
+        // Fetched once per release instead of once per asset -- most assets have no per-file
+        // `.sha256` and fall back to this
+        let sums_cache = fetch_sha256sums(&host, &release.assets);
+        for asset in &release.assets {
+            let parsed = match StandaloneAssetName::parse(&asset.name) {
+                Some(parsed) => parsed,
+                None => continue,
+            };
+            let key = (
+                parsed.python_version.0,
+                parsed.python_version.1,
+                parsed.python_version.2,
+                parsed.triple.clone(),
+                parsed.flavor.clone(),
+                parsed.optimization.clone(),
+            );
+            if by_key
+                .get(&key)
+                .map_or(false, |existing| existing.build_tag >= parsed.build_tag)
+            {
+                continue;
+            }
+            let sha256 = match find_sha256_asset_file(&host, &release.assets, &asset.name)
+                .or_else(|| sums_cache.get(&asset.name).cloned())
+            {
+                Some(sha256) => sha256,
+                None => continue,
+            };
+            by_key.insert(
+                key,
+                VersionsManifestEntry {
+                    major: parsed.python_version.0,
+                    minor: parsed.python_version.1,
+                    patch: parsed.python_version.2,
+                    build_tag: parsed.build_tag,
+                    triple: parsed.triple,
+                    flavor: parsed.flavor,
+                    optimization: parsed.optimization,
+                    tag: release.tag_name.clone(),
+                    url: asset.browser_download_url.clone(),
+                    sha256,
+                },
+            );
+        }
+    }
+
+    let count = by_key.len();
+    let manifest = VersionsManifest {
+        downloads: by_key.into_values().collect(),
+    };
+    fs::write(
+        output,
+        serde_json::to_string_pretty(&manifest).context("Failed to serialize versions.json")?
+            + "\n",
+    )
+    .with_context(|| format!("Failed to write {}", output.display()))?;
+    Ok(count)
+}
+
+/// Looks for a `<asset_name>.sha256` asset among `assets` and, if present, fetches and parses it;
+/// falling back to the release-wide `SHA256SUMS` asset (not every release publishes a per-file
+/// digest). Both are plain `sha256sum`-style text (`"<hex>  <filename>\n"`, one or more lines for
+/// `SHA256SUMS`), so we just look for the line naming `asset_name` and take its first
+/// whitespace-separated token. We treat any failure (missing asset, network error, unparseable
+/// body) as "no digest available" rather than fatal: older releases or a release format change
+/// shouldn't turn a digest we can't find into a hard failure, since the tarball download itself
+/// already worked
+fn find_sha256(host: &str, assets: &[GitHubAsset], asset_name: &str) -> Option<String> {
+    find_sha256_asset_file(host, assets, asset_name)
+        .or_else(|| fetch_sha256sums(host, assets).remove(asset_name))
+}
+
+/// Looks for a `<asset_name>.sha256` asset among `assets` and, if present, fetches and parses it
+fn find_sha256_asset_file(host: &str, assets: &[GitHubAsset], asset_name: &str) -> Option<String> {
+    let digest_asset_name = format!("{}.sha256", asset_name);
+    let digest_asset = assets
+        .iter()
+        .find(|asset| asset.name == digest_asset_name)?;
+    let url = if cfg!(test) {
+        format!("{}/sha256/{}", host, digest_asset_name)
+    } else {
+        digest_asset.browser_download_url.clone()
+    };
+    let body = fetch_text(&url)?;
+    body.split_whitespace().next().map(|hex| hex.to_lowercase())
+}
+
+/// Fetches and parses the release-wide `SHA256SUMS` asset (if present) into a `name -> hex digest`
+/// map once, instead of every caller re-fetching and re-parsing the same body for its own asset --
+/// an empty map on a missing asset or any network error, same as [`find_sha256`]'s existing
+/// "no digest available" handling
+fn fetch_sha256sums(
+    host: &str,
+    assets: &[GitHubAsset],
+) -> std::collections::HashMap<String, String> {
+    let sums_asset = match assets.iter().find(|asset| asset.name == "SHA256SUMS") {
+        Some(sums_asset) => sums_asset,
+        None => return std::collections::HashMap::new(),
+    };
+    let url = if cfg!(test) {
+        format!("{}/sha256/{}", host, sums_asset.name)
+    } else {
+        sums_asset.browser_download_url.clone()
+    };
+    let body = match fetch_text(&url) {
+        Some(body) => body,
+        None => return std::collections::HashMap::new(),
+    };
+    body.lines()
+        .filter_map(|line| {
+            let mut parts = line.split_whitespace();
+            let hex = parts.next()?;
+            let name = parts.next()?.trim_start_matches('*');
+            Some((name.to_string(), hex.to_lowercase()))
+        })
+        .collect()
+}
+
+/// Fetches `url` as a plain text body, returning `None` on any network or status error
+fn fetch_text(url: &str) -> Option<String> {
+    ureq::get(url)
+        .set("User-Agent", "monotrail (konstin@mailbox.org)")
+        .call()
+        .ok()?
+        .into_string()
+        .ok()
+}
+
+/// Returns the url of the newest matching prebuilt python, plus its expected sha256 digest if we
+/// could find one. We first check the checked-in [`VERSIONS_JSON`] manifest, then, on a miss, try
+/// the latest indygreg/python-build-standalone release, then fall back to a known good release in
+/// case a more recent release broke compatibility.
+///
+/// `patch` pins an exact `major.minor.patch`; `None` picks the newest patch/build tag available.
+///
+/// The manifest lookup is skipped under `#[cfg(test)]`: the unit tests below exercise the
+/// release-fetch fallback against fixture data through `mockito`, and a `versions.json` entry that
+/// happens to match would shadow that path without the tests noticing
+fn find_python(major: u8, minor: u8, patch: Option<u8>) -> anyhow::Result<PythonDownload> {
+    let (triple, flavor, optimization) = standalone_variant(minor);
+
+    #[cfg(not(test))]
+    let manifest = load_versions_manifest();
+    #[cfg(test)]
+    let manifest = VersionsManifest::default();
+    if let Some(download) = find_in_versions_manifest(
+        &manifest,
+        major,
+        minor,
+        patch,
+        &triple,
+        flavor,
+        optimization,
+    ) {
+        return Ok(download);
+    }
+
     #[cfg(not(test))]
-    let host = GITHUB_API;
+    let host = env::var(PYTHON_MIRROR_VAR).unwrap_or_else(|_| GITHUB_API.to_string());
 
     #[cfg(test)]
-    let host = &mockito::server_url();
+    let host = mockito::server_url();
 
     let latest_release: GitHubRelease =
         ureq::get(&format!("{}{}", host, PYTHON_STANDALONE_LATEST_RELEASE.0))
@@ -58,14 +343,21 @@ fn find_python(major: u8, minor: u8) -> anyhow::Result<String> {
             .call()?
             .into_json()?;
 
-    let version_re = filename_regex(major, minor);
-    let asset = latest_release.assets.into_iter().find(|asset| {
-        // TODO: Proper name parsing
-        // https://github.com/indygreg/python-build-standalone/issues/127
-        version_re.is_match(&asset.name)
-    });
-    if let Some(asset) = asset {
-        return Ok(asset.browser_download_url);
+    if let Some(asset) = select_asset(
+        &latest_release.assets,
+        major,
+        minor,
+        patch,
+        &triple,
+        flavor,
+        optimization,
+    ) {
+        let expected_sha256 = find_sha256(&host, &latest_release.assets, &asset.name);
+        return Ok(PythonDownload {
+            url: asset.browser_download_url.clone(),
+            expected_sha256,
+            patch: StandaloneAssetName::parse(&asset.name).map(|parsed| parsed.python_version.2),
+        });
     }
 
     let good_release: GitHubRelease = ureq::get(&format!(
@@ -76,44 +368,158 @@ fn find_python(major: u8, minor: u8) -> anyhow::Result<String> {
     .call()?
     .into_json()?;
 
-    let asset = good_release
-        .assets
-        .into_iter()
-        .find(|asset| {
-            // TODO: Proper name parsing
-            // https://github.com/indygreg/python-build-standalone/issues/127
-            version_re.is_match(&asset.name)
-        })
-        .with_context(|| {
+    let asset = select_asset(
+        &good_release.assets,
+        major,
+        minor,
+        patch,
+        &triple,
+        flavor,
+        optimization,
+    )
+    .with_context(|| {
+        let available_patches = if patch.is_some() {
+            available_patch_versions(&latest_release.assets, major, minor, &triple, flavor, optimization)
+                .into_iter()
+                .chain(available_patch_versions(&good_release.assets, major, minor, &triple, flavor, optimization))
+                .collect::<BTreeSet<_>>()
+        } else {
+            BTreeSet::new()
+        };
+        let patches_hint = if available_patches.is_empty() {
+            String::new()
+        } else {
             format!(
-                "Failed to find a matching python-build-standalone download: /{}/. Searched in {} and {}", 
-                version_re,
-                PYTHON_STANDALONE_LATEST_RELEASE.1,
-                PYTHON_STANDALONE_KNOWN_GOOD_RELEASE.1,
+                ". Available patch versions for {major}.{minor}: {}",
+                available_patches
+                    .iter()
+                    .map(|patch| patch.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
             )
-        })?;
-    Ok(asset.browser_download_url)
+        };
+        format!(
+            "Failed to find a matching python-build-standalone download: cpython-{major}.{minor}{patch}+<build tag> for {triple} ({optimization}, {flavor}). Searched in {} and {}{patches_hint}",
+            PYTHON_STANDALONE_LATEST_RELEASE.1,
+            PYTHON_STANDALONE_KNOWN_GOOD_RELEASE.1,
+            major = major,
+            minor = minor,
+            patch = patch.map(|patch| format!(".{}", patch)).unwrap_or_default(),
+            triple = triple,
+            optimization = optimization.unwrap_or("no optimization"),
+            flavor = flavor,
+            patches_hint = patches_hint,
+        )
+    })?;
+    let expected_sha256 = find_sha256(&host, &good_release.assets, &asset.name);
+    Ok(PythonDownload {
+        url: asset.browser_download_url.clone(),
+        expected_sha256,
+        patch: StandaloneAssetName::parse(&asset.name).map(|parsed| parsed.python_version.2),
+    })
+}
+
+/// A [`Read`] wrapper that feeds every byte it reads through a [`Sha256`] hasher, so we can hash
+/// the raw compressed stream as it's being decompressed instead of buffering it twice
+struct HashingReader<R> {
+    inner: R,
+    hasher: Sha256,
+}
+
+impl<R> HashingReader<R> {
+    fn new(inner: R) -> Self {
+        Self {
+            inner,
+            hasher: Sha256::new(),
+        }
+    }
+
+    fn finalize_hex(self) -> String {
+        format!("{:x}", self.hasher.finalize())
+    }
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
 }
 
-/// Download the prebuilt python .tar.zstd and unpacks it into the the target dir
-fn download_and_unpack_python(url: &str, target_dir: &Path) -> anyhow::Result<()> {
+/// Download the prebuilt python .tar.zstd and unpacks it into the the target dir, checking the
+/// downloaded bytes against `expected_sha256` (if we have one) before trusting the unpacked result
+fn download_and_unpack_python(
+    url: &str,
+    expected_sha256: Option<&str>,
+    target_dir: &Path,
+) -> anyhow::Result<()> {
     // TODO: Add MB from API
     info!("Downloading {}", url);
     let tar_zstd = ureq::get(url)
         .set("User-Agent", "monotrail (konstin@mailbox.org)")
         .call()?
         .into_reader();
-    let tar = zstd::Decoder::new(tar_zstd)?;
-    let mut archive = tar::Archive::new(tar);
+    let mut hashing_reader = HashingReader::new(tar_zstd);
+    {
+        let tar = zstd::Decoder::new(&mut hashing_reader)?;
+        let mut archive = tar::Archive::new(tar);
+        fs::create_dir_all(&target_dir)?;
+        archive.unpack(target_dir)?;
+    }
+    if let Some(expected_sha256) = expected_sha256 {
+        let actual_sha256 = hashing_reader.finalize_hex();
+        if &actual_sha256 != expected_sha256 {
+            bail!(
+                "Checksum mismatch for {}: expected {} but downloaded {}",
+                url,
+                expected_sha256,
+                actual_sha256
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Unpacks an already-locally-present `.tar.zst`/`.tar.gz` archive, for [`PYTHON_BOOTSTRAP_DIR_VAR`].
+/// No checksum to verify here (there's no release metadata for a locally staged archive), we trust
+/// whatever put it in the bootstrap dir the same way we trust an already-unpacked
+/// [`PYTHON_STANDALONE_DIR_VAR`] tree
+fn unpack_local_python(archive_path: &Path, target_dir: &Path) -> anyhow::Result<()> {
+    info!("Using bootstrap archive {}", archive_path.display());
+    let file = File::open(archive_path)?;
     fs::create_dir_all(&target_dir)?;
-    archive.unpack(target_dir)?;
+    if archive_path.extension().and_then(|ext| ext.to_str()) == Some("zst") {
+        let tar = zstd::Decoder::new(file)?;
+        tar::Archive::new(tar).unpack(target_dir)?;
+    } else {
+        let tar = GzDecoder::new(file);
+        tar::Archive::new(tar).unpack(target_dir)?;
+    }
     Ok(())
 }
 
-/// Check whether the installed python looks good or broken
-fn check_installed_python(unpack_dir: &Path, python_version: (u8, u8)) -> anyhow::Result<()> {
-    let install_dir = unpack_dir.join("python").join("install");
-    let lib = if cfg!(target_os = "macos") {
+/// Where the actual installed interpreter lives inside an unpacked archive. The `full` variant
+/// nests it under `python/install` (alongside `python/build`, `python/licenses`, ...); the
+/// `install_only` variant skips the build-artifact wrapper and puts it directly under `python`.
+/// We detect which layout we're looking at instead of threading the variant through, so a
+/// pre-existing cache dir from a previous run with the other variant (e.g. after flipping
+/// [`PYTHON_FULL_BUILD_VAR`]) still resolves correctly
+fn python_install_root(unpack_dir: &Path) -> PathBuf {
+    let full_layout = unpack_dir.join("python").join("install");
+    if full_layout.is_dir() {
+        full_layout
+    } else {
+        unpack_dir.join("python")
+    }
+}
+
+/// Where the `libpython`/`python3.dll` shared library lives inside an unpack dir, shared between
+/// [`check_installed_python`] (existence check) and [`check_installed_python_for_use`] (existence
+/// plus [`ensure_host_architecture`]) so the two can't drift apart on where to look
+fn installed_lib_path(unpack_dir: &Path, python_version: (u8, u8)) -> PathBuf {
+    let install_dir = python_install_root(unpack_dir);
+    if cfg!(target_os = "macos") {
         install_dir.join("lib").join(format!(
             "libpython{}.{}.dylib",
             python_version.0, python_version.1
@@ -123,7 +529,12 @@ fn check_installed_python(unpack_dir: &Path, python_version: (u8, u8)) -> anyhow
     } else {
         // Assume generic unix otherwise (tested for linux)
         install_dir.join("lib").join("libpython3.so".to_string())
-    };
+    }
+}
+
+/// Check whether the installed python looks good or broken
+fn check_installed_python(unpack_dir: &Path, python_version: (u8, u8)) -> anyhow::Result<()> {
+    let lib = installed_lib_path(unpack_dir, python_version);
     if !lib.is_file() {
         bail!(
             "broken python installation in {}. \
@@ -136,9 +547,108 @@ fn check_installed_python(unpack_dir: &Path, python_version: (u8, u8)) -> anyhow
     Ok(())
 }
 
+/// [`check_installed_python`] plus [`ensure_host_architecture`], for the handful of call sites in
+/// [`provision_python`] that are about to actually hand the result to `run_python_args`/
+/// `inject_and_run_python`. Deliberately not folded into [`check_installed_python`] itself: that
+/// one is also called once per entry by `monotrail python-list`, which only wants a quick
+/// existence check for its status column, not an ELF parse of every cached `libpython` on disk
+fn check_installed_python_for_use(
+    unpack_dir: &Path,
+    python_version: (u8, u8),
+) -> anyhow::Result<()> {
+    check_installed_python(unpack_dir, python_version)?;
+    let lib = installed_lib_path(unpack_dir, python_version);
+    ensure_host_architecture(&lib).with_context(|| {
+        format!(
+            "python installation in {} doesn't match this machine",
+            unpack_dir.display()
+        )
+    })
+}
+
+/// Reads `path` (`libpython*`) as an ELF file and checks its word size and machine type against
+/// the architecture monotrail itself was compiled for, the same sanity check PyO3's
+/// `ensure_target_architecture` does before linking against a libpython. Catches a corrupted
+/// download, a manually staged [`PYTHON_STANDALONE_DIR_VAR`]/[`PYTHON_BOOTSTRAP_DIR_VAR`] entry
+/// for the wrong machine, or a cache dir synced over from a different host, with a clear message
+/// naming both architectures instead of a cryptic failure once we actually try to `dlopen` it.
+///
+/// Does nothing (not even an error) if `path` can't be parsed as ELF (the macOS `.dylib`/Windows
+/// `.dll` builds this is never called for in practice), if `path` is simply unreadable (the caller
+/// already checked it's a file; a read failure here isn't this check's job to report), or if the
+/// ELF machine type isn't one we recognize -- an architecture we can't name is no evidence of a
+/// mismatch, just of a gap in the match below.
+///
+/// Reads the whole file into memory (the same thing [`crate::inject_and_run::probe_elf_platform`]
+/// already does for platform-tag detection) rather than just the header, trading a bit of I/O for
+/// reusing the proven-correct [`goblin::elf::Elf::parse`] instead of a hand-rolled partial parse;
+/// this runs once per `provision_python` call, right before that same call spawns the interpreter
+/// as a subprocess anyway ([`probe_interpreter_info`]), so it isn't the dominant cost on that path.
+fn ensure_host_architecture(path: &Path) -> anyhow::Result<()> {
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(_) => return Ok(()),
+    };
+    let elf = match goblin::elf::Elf::parse(&bytes) {
+        Ok(elf) => elf,
+        Err(_) => return Ok(()),
+    };
+
+    let found_arch = match elf.header.e_machine {
+        goblin::elf::header::EM_X86_64 => "x86_64",
+        goblin::elf::header::EM_AARCH64 => "aarch64",
+        goblin::elf::header::EM_386 => "x86",
+        goblin::elf::header::EM_ARM => "arm",
+        goblin::elf::header::EM_S390 => "s390x",
+        goblin::elf::header::EM_PPC64 => "powerpc64",
+        _ => return Ok(()),
+    };
+    let found_bits = if elf.is_64 { 64 } else { 32 };
+    let expected_arch = env::consts::ARCH;
+    let expected_bits: u32 = if cfg!(target_pointer_width = "64") {
+        64
+    } else {
+        32
+    };
+
+    if found_arch != expected_arch || found_bits != expected_bits {
+        bail!(
+            "{} is built for {} ({}-bit), but this machine is {} ({}-bit)",
+            path.display(),
+            found_arch,
+            found_bits,
+            expected_arch,
+            expected_bits,
+        );
+    }
+    Ok(())
+}
+
+/// Where [`provision_python`] and the `monotrail python-install`/`python-list` subcommands all
+/// keep their cache of unpacked standalone builds
+fn python_cache_dir() -> anyhow::Result<PathBuf> {
+    Ok(cache_dir()?.join("python-build-standalone"))
+}
+
+/// The unpack dir for `(major, minor[, patch])`, under [`python_cache_dir`]. The cache key has to
+/// include the flavor: install_only and full builds of the same version have different directory
+/// layouts (see [`python_install_root`]), so caching them under the same key would make flipping
+/// [`PYTHON_FULL_BUILD_VAR`] a no-op once the other flavor is cached
+fn unpack_dir_for(major: u8, minor: u8, patch: Option<u8>) -> anyhow::Result<PathBuf> {
+    let patch_suffix = patch.map(|patch| format!(".{}", patch)).unwrap_or_default();
+    Ok(python_cache_dir()?.join(format!(
+        "cpython-{}.{}{}{}",
+        major,
+        minor,
+        patch_suffix,
+        standalone_flavor_suffix()
+    )))
+}
+
 /// Actual download and move into place logic
 fn provision_python_inner(
     python_version: (u8, u8),
+    patch: Option<u8>,
     python_parent_dir: &PathBuf,
     unpack_dir: &PathBuf,
 ) -> anyhow::Result<()> {
@@ -146,44 +656,367 @@ fn provision_python_inner(
         "Installing python {}.{}",
         python_version.0, python_version.1
     );
-    let url = find_python(python_version.0, python_version.1).with_context(|| {
-        format!(
-            "Couldn't find a matching python {}.{} to download",
-            python_version.0, python_version.1,
-        )
-    })?;
     // atomic installation by tempdir & rename
     let temp_dir = tempdir_in(&python_parent_dir)
         .context("Failed to create temporary directory for unpacking")?;
-    download_and_unpack_python(&url, temp_dir.path())?;
+
+    let bootstrap_dir = env::var_os(PYTHON_BOOTSTRAP_DIR_VAR).map(PathBuf::from);
+    let bootstrap_archive = bootstrap_dir.as_deref().and_then(|bootstrap_dir| {
+        let (triple, flavor, optimization) = standalone_variant(python_version.1);
+        match find_bootstrap_archive(
+            bootstrap_dir,
+            python_version.0,
+            python_version.1,
+            patch,
+            &triple,
+            flavor,
+            optimization,
+        ) {
+            Ok(archive) => archive,
+            Err(err) => {
+                warn!(
+                    "Ignoring {}={}: {}",
+                    PYTHON_BOOTSTRAP_DIR_VAR,
+                    bootstrap_dir.display(),
+                    err
+                );
+                None
+            }
+        }
+    });
+    if let Some(archive) = bootstrap_archive {
+        unpack_local_python(&archive, temp_dir.path())?;
+    } else {
+        if env::var_os(NO_DOWNLOAD_VAR).as_deref() == Some(std::ffi::OsStr::new("1")) {
+            match &bootstrap_dir {
+                Some(bootstrap_dir) => bail!(
+                    "No cached python {}.{} in {} and no matching archive in {} ({}=1 forbids downloading one)",
+                    python_version.0,
+                    python_version.1,
+                    unpack_dir.display(),
+                    bootstrap_dir.display(),
+                    NO_DOWNLOAD_VAR
+                ),
+                None => bail!(
+                    "No cached python {}.{} in {} and {}=1 forbids downloading one",
+                    python_version.0,
+                    python_version.1,
+                    unpack_dir.display(),
+                    NO_DOWNLOAD_VAR
+                ),
+            }
+        }
+        let download =
+            find_python(python_version.0, python_version.1, patch).with_context(|| {
+                format!(
+                    "Couldn't find a matching python {}.{}{} to download",
+                    python_version.0,
+                    python_version.1,
+                    patch.map(|patch| format!(".{}", patch)).unwrap_or_default(),
+                )
+            })?;
+        download_and_unpack_python(
+            &download.url,
+            download.expected_sha256.as_deref(),
+            temp_dir.path(),
+        )?;
+    }
     // we can use fs::rename here because we stay in the same directory
     fs::rename(temp_dir, &unpack_dir).context("Failed to move installed python into place")?;
     debug!("Installed python {}.{}", python_version.0, python_version.1);
     Ok(())
 }
 
+/// Env var pointing at a directory of already-unpacked `cpython-<major>.<minor>` trees (the same
+/// layout [`provision_python`] itself produces under its cache dir), for offline/air-gapped setups
+/// that pre-stage interpreters instead of letting monotrail hit the network. Checked first, before
+/// [`python_cache_dir`] and any network access, so it also doubles as the deterministic,
+/// test-friendly equivalent of tools like uv's `UV_BOOTSTRAP_DIR`: point it at a fixture tree and
+/// `run_python_args`/`provision_python` resolve from there without ever touching the real cache or
+/// `api.github.com`
+const PYTHON_STANDALONE_DIR_VAR: &str = "MONOTRAIL_PYTHON_STANDALONE_DIR";
+
+/// Env var pointing at a directory of pre-downloaded `cpython-*.tar.zst`/`.tar.gz` release
+/// archives (not yet unpacked, unlike [`PYTHON_STANDALONE_DIR_VAR`]), for air-gapped setups that
+/// can stage the archives themselves but can't reach `api.github.com` to resolve which one to
+/// download. Checked before [`find_python`] on every install, so a matching archive here always
+/// wins over a network fetch
+const PYTHON_BOOTSTRAP_DIR_VAR: &str = "MONOTRAIL_PYTHON_BOOTSTRAP_DIR";
+
+/// Env var that turns a cache miss into a hard error instead of falling back to downloading, for
+/// CI/air-gapped setups that want to know loudly when the pre-staged interpreter set is incomplete
+const NO_DOWNLOAD_VAR: &str = "MONOTRAIL_NO_DOWNLOAD";
+
+/// Env var that skips downloading a python-build-standalone tarball in favor of an
+/// already-installed interpreter, for offline/corporate setups with no network access to
+/// indygreg's releases. Set to `1` to auto-detect a matching interpreter from `PATH` via
+/// [`locate_interpreters`], or to a path to probe and use that interpreter directly
+const USE_SYSTEM_PYTHON_VAR: &str = "MONOTRAIL_USE_SYSTEM_PYTHON";
+
+/// Builds a [`PythonContext`] and python home straight from a `MONOTRAIL_PYTHON_CONFIG` file
+/// ([`PythonConfigFile`]), with no interpreter ever spawned. `pep508_env` is assembled from
+/// `config` plus the host triple ([`std::env::consts`]) instead of the real `sys`/`platform`
+/// values a live interpreter would report -- `platform_release`/`platform_version` are left empty
+/// since `platform.release()`/`platform.version()` aren't knowable without running one.
+/// `platform_tags` is the bare `linux_<arch>` tag on linux (no manylinux/musllinux detection,
+/// which needs either `sysconfig` values or reading the interpreter's own ELF header -- both
+/// skipped here since this path isn't given an executable to probe, only a shared library) and
+/// empty on macOS/windows, where guessing a tag without probing isn't reliable enough to be worth
+/// it. Good enough to resolve and run against local/sdist-only projects; anything that needs an
+/// exact marker match or a prebuilt wheel won't work as precisely as the probed paths below.
+///
+/// Bails if `config`'s version/implementation don't match what was requested, the same consistency
+/// check [`provision_system_python`] does, and if `libpython` isn't absolute (a relative path would
+/// be resolved against the process's current directory at `dlopen` time, not `config_path`'s).
+fn provision_python_from_config_file(
+    config: PythonConfigFile,
+    implementation: Implementation,
+    python_version: (u8, u8),
+) -> anyhow::Result<(PythonContext, PathBuf)> {
+    let config_version = parse_major_minor(&config.version)?;
+    if config_version != python_version {
+        bail!(
+            "{} is python {}.{}, but {}.{} was requested",
+            config.libpython.display(),
+            config_version.0,
+            config_version.1,
+            python_version.0,
+            python_version.1
+        );
+    }
+    let config_implementation =
+        Implementation::from_sys_implementation_name(&config.implementation)?;
+    if config_implementation != implementation {
+        bail!(
+            "{} is {}, but {} was requested",
+            config.libpython.display(),
+            config_implementation,
+            implementation
+        );
+    }
+    if !config.libpython.is_absolute() {
+        bail!(
+            "`libpython` in {} must be an absolute path, got {}",
+            PYTHON_CONFIG_FILE_VAR,
+            config.libpython.display()
+        );
+    }
+
+    let (os_name, sys_platform, platform_system) = if cfg!(target_os = "windows") {
+        ("nt", "win32", "Windows")
+    } else if cfg!(target_os = "macos") {
+        ("posix", "darwin", "Darwin")
+    } else {
+        ("posix", "linux", "Linux")
+    };
+    let platform_python_implementation = match implementation {
+        Implementation::CPython => "CPython",
+        Implementation::PyPy => "PyPy",
+    };
+    let version_string = format!("{}.{}", python_version.0, python_version.1);
+    let pep508_env = Pep508Environment {
+        implementation_name: config.implementation.to_lowercase(),
+        // Only major.minor is known from the config file, not the real patch release
+        implementation_version: format!("{}.0", version_string),
+        os_name: os_name.to_string(),
+        platform_machine: env::consts::ARCH.to_string(),
+        platform_python_implementation: platform_python_implementation.to_string(),
+        platform_release: String::new(),
+        platform_system: platform_system.to_string(),
+        platform_version: String::new(),
+        python_full_version: format!("{}.0", version_string),
+        python_version: version_string,
+        sys_platform: sys_platform.to_string(),
+    };
+    let sys_executable = if cfg!(target_os = "windows") {
+        config.python_home.join("python.exe")
+    } else {
+        config.python_home.join("bin").join("python3")
+    };
+    // Only a bare linux fallback tag is safe to guess without probing: macosx tags need the real
+    // macOS version (`macosx_10_9_<arch>`) and windows tags don't follow a `<platform>_<arch>`
+    // pattern we can derive (`win32`/`win_amd64`), so on those platforms we'd rather match no wheel
+    // tag at all than emit one that looks plausible but never matches a real wheel.
+    let platform_tags = if cfg!(target_os = "windows") || cfg!(target_os = "macos") {
+        Vec::new()
+    } else {
+        vec![format!("linux_{}", env::consts::ARCH)]
+    };
+    let python_context = PythonContext {
+        sys_executable,
+        version: python_version,
+        implementation,
+        pep508_env,
+        launch_type: LaunchType::Binary,
+        platform_tags,
+    };
+    Ok((python_context, config.python_home))
+}
+
+/// Builds a [`PythonContext`] and python home by probing the interpreter at `python_binary` instead
+/// of downloading a standalone build, for [`USE_SYSTEM_PYTHON_VAR`].
+///
+/// Bails if the interpreter isn't a shared build (`Py_ENABLE_SHARED=0`, mirroring
+/// [`crate::inject_and_run::find_libpython`]'s check, since `inject_and_run_python` later needs to
+/// dlopen it), or if its reported `(major, minor)` doesn't match `python_version`.
+fn provision_system_python(
+    implementation: Implementation,
+    python_binary: &Path,
+    python_version: (u8, u8),
+) -> anyhow::Result<(PythonContext, PathBuf)> {
+    let info = probe_interpreter_info(python_binary).with_context(|| {
+        format!(
+            "Failed to probe system python at {} ({})",
+            python_binary.display(),
+            USE_SYSTEM_PYTHON_VAR
+        )
+    })?;
+    python_context_from_system_python(implementation, python_binary, python_version, info)
+}
+
+/// The checks and [`PythonContext`] assembly shared between [`provision_system_python`] (which
+/// probes `python_binary` itself) and the `PATH` auto-detect path (which already has an
+/// [`InterpreterInfo`] from [`crate::interpreter_locator::locate_interpreters`] and would otherwise
+/// probe the same interpreter twice)
+fn python_context_from_system_python(
+    implementation: Implementation,
+    python_binary: &Path,
+    python_version: (u8, u8),
+    info: InterpreterInfo,
+) -> anyhow::Result<(PythonContext, PathBuf)> {
+    if info.python_version != python_version {
+        bail!(
+            "{} is python {}.{}, but {}.{} was requested",
+            python_binary.display(),
+            info.python_version.0,
+            info.python_version.1,
+            python_version.0,
+            python_version.1
+        );
+    }
+    let probed_implementation =
+        Implementation::from_sys_implementation_name(&info.implementation_name)?;
+    if probed_implementation != implementation {
+        bail!(
+            "{} is {}, but {} was requested",
+            python_binary.display(),
+            probed_implementation,
+            implementation
+        );
+    }
+    if info.py_enable_shared == Some(0) {
+        bail!(
+            "{} was built without a shared libpython (Py_ENABLE_SHARED=0), it can't be embedded",
+            info.base_prefix
+        );
+    }
+    let sys_executable = PathBuf::from(&info.sys_executable);
+    let pep508_env = Pep508Environment::from_python(&sys_executable);
+    let platform_tags = compatible_platform_tags(&sys_executable, &info);
+    let python_context = PythonContext {
+        sys_executable,
+        version: python_version,
+        implementation,
+        pep508_env,
+        launch_type: LaunchType::Binary,
+        platform_tags,
+    };
+    let python_home = PathBuf::from(&info.base_prefix);
+    Ok((python_context, python_home))
+}
+
 /// If a downloaded python version exists, return this, otherwise download and unpack a matching one
 /// from indygreg/python-build-standalone
-pub fn provision_python(python_version: (u8, u8)) -> anyhow::Result<(PythonContext, PathBuf)> {
-    let python_parent_dir = cache_dir()?.join("python-build-standalone");
+///
+/// indygreg/python-build-standalone only ships CPython builds, so asking for `Implementation::PyPy`
+/// here always fails; PyPy is only usable when injecting into an already-installed interpreter, e.g.
+/// through [`USE_SYSTEM_PYTHON_VAR`] below
+///
+/// `patch` pins an exact `major.minor.patch` for reproducible provisioning; `None` picks the
+/// newest patch available, same as before `patch` existed. It only constrains the standalone
+/// download/cache path below: [`USE_SYSTEM_PYTHON_VAR`] probes whatever interpreter it's given and
+/// has no way to check its patch version, so it's ignored there.
+pub fn provision_python(
+    implementation: Implementation,
+    python_version: (u8, u8),
+    patch: Option<u8>,
+) -> anyhow::Result<(PythonContext, PathBuf)> {
+    // Checked first, ahead of every other override below: it's the most explicit one available,
+    // and unlike `USE_SYSTEM_PYTHON_VAR` there's nothing left to fall back to probing if a field
+    // is missing or wrong.
+    if let Some(config) = PythonConfigFile::from_env() {
+        return provision_python_from_config_file(config?, implementation, python_version);
+    }
+    if let Some(system_python) = env::var_os(USE_SYSTEM_PYTHON_VAR) {
+        if system_python == "1" {
+            let candidates = locate_interpreters();
+            let (python_binary, info) = select_interpreter(&candidates, python_version)
+                .with_context(|| {
+                    format!(
+                        "No python {}.{} found on PATH ({}=1)",
+                        python_version.0, python_version.1, USE_SYSTEM_PYTHON_VAR
+                    )
+                })?;
+            return python_context_from_system_python(
+                implementation,
+                &python_binary,
+                python_version,
+                info,
+            );
+        }
+        let python_binary = PathBuf::from(system_python);
+        return provision_system_python(implementation, &python_binary, python_version);
+    }
+
+    if implementation != Implementation::CPython {
+        bail!(
+            "python-build-standalone only provides CPython builds, can't provision {}",
+            implementation
+        );
+    }
+
+    // Pinning a patch version changes the cache/override key: otherwise an already-provisioned
+    // unpinned install (or one pinned to a different patch) would silently satisfy a pinned
+    // request instead of provisioning the exact patch asked for
+    let patch_suffix = patch.map(|patch| format!(".{}", patch)).unwrap_or_default();
+
+    if let Some(override_dir) = env::var_os(PYTHON_STANDALONE_DIR_VAR) {
+        let unpack_dir = PathBuf::from(override_dir).join(format!(
+            "cpython-{}.{}{}",
+            python_version.0, python_version.1, patch_suffix
+        ));
+        check_installed_python_for_use(&unpack_dir, python_version).with_context(|| {
+            format!(
+                "No pre-provisioned python {}.{}{} in {} ({})",
+                python_version.0,
+                python_version.1,
+                patch_suffix,
+                unpack_dir.display(),
+                PYTHON_STANDALONE_DIR_VAR
+            )
+        })?;
+        return Ok(python_context_from_unpack_dir(&unpack_dir, python_version));
+    }
+
+    let python_parent_dir = python_cache_dir()?;
     // We need this here for the locking logic
     fs::create_dir_all(&python_parent_dir).context("Failed to create cache dir")?;
-    let unpack_dir =
-        python_parent_dir.join(format!("cpython-{}.{}", python_version.0, python_version.1));
+    let unpack_dir = unpack_dir_for(python_version.0, python_version.1, patch)?;
 
     if unpack_dir.is_dir() {
-        check_installed_python(&unpack_dir, python_version)?;
+        check_installed_python_for_use(&unpack_dir, python_version)?;
     } else {
         // If two processes are started in parallel that both install python, the second one will fail
         // because it can't move the installed directory because it already exists. To avoid this, only
         // one process at
         let install_lock = python_parent_dir.join(format!(
-            "cpython-{}.{}.install-lock",
-            python_version.0, python_version.1
+            "cpython-{}.{}{}.install-lock",
+            python_version.0, python_version.1, patch_suffix
         ));
         let lockfile = File::create(install_lock)?;
         if lockfile.file().try_lock_exclusive().is_ok() {
-            provision_python_inner(python_version, &python_parent_dir, &unpack_dir)?;
+            provision_python_inner(python_version, patch, &python_parent_dir, &unpack_dir)?;
+            check_installed_python_for_use(&unpack_dir, python_version)?;
         } else {
             info!("Waiting for other process to finish installing");
             lockfile.file().lock_exclusive()?;
@@ -191,10 +1024,11 @@ pub fn provision_python(python_version: (u8, u8)) -> anyhow::Result<(PythonConte
             let result = if unpack_dir.is_dir() {
                 info!("The other process seems to have succeeded");
                 // Check if ok install, ok if true, error if not
-                check_installed_python(&unpack_dir, python_version)
+                check_installed_python_for_use(&unpack_dir, python_version)
             } else {
                 info!("The other process seems to have failed, installing");
-                provision_python_inner(python_version, &python_parent_dir, &unpack_dir)
+                provision_python_inner(python_version, patch, &python_parent_dir, &unpack_dir)
+                    .and_then(|()| check_installed_python_for_use(&unpack_dir, python_version))
             };
             // Make sure we unlock the file before returning. This would be nicer if it would
             // work through drop on a file lock object
@@ -203,37 +1037,183 @@ pub fn provision_python(python_version: (u8, u8)) -> anyhow::Result<(PythonConte
         }
     }
 
+    Ok(python_context_from_unpack_dir(&unpack_dir, python_version))
+}
+
+/// Parses a `monotrail python-install`/`python-list` version argument: `"3.9"` pins just the
+/// minor version (the newest matching patch is installed), `"3.9.12"` additionally pins an exact
+/// patch, mirroring the `patch` parameter [`provision_python`] already takes
+fn parse_version_pin(version: &str) -> anyhow::Result<((u8, u8), Option<u8>)> {
+    let mut parts = version.splitn(3, '.');
+    let major = parts
+        .next()
+        .context("Expected a version like 3.9 or 3.9.12")?
+        .parse()
+        .context("Could not parse major version")?;
+    let minor = parts
+        .next()
+        .context("Expected a version like 3.9 or 3.9.12")?
+        .parse()
+        .context("Could not parse minor version")?;
+    let patch = parts
+        .next()
+        .map(|patch| patch.parse().context("Could not parse patch version"))
+        .transpose()?;
+    Ok(((major, minor), patch))
+}
+
+/// `monotrail python-install <version>`: explicitly provisions a standalone CPython build into
+/// the managed cache that [`provision_python`] already reads from automatically on every
+/// `run`/`ppipx`/`poetry run`, returning the resolved `(major, minor, patch)` that ended up
+/// installed, `patch` being `None` only when an unpinned `x.y` was already satisfied by a
+/// previously-cached install (see below). Useful to pre-warm the cache (e.g. in CI before going
+/// offline) or to pin an exact patch instead of whatever the automatic provisioning picks.
+///
+/// With `force`, wipes any existing cache entry for this `(major, minor[, patch])` first, so a
+/// broken or stale install doesn't get silently reused by [`check_installed_python`]
+pub fn install_python(version: &str, force: bool) -> anyhow::Result<(u8, u8, Option<u8>)> {
+    let ((major, minor), patch) = parse_version_pin(version)?;
+    let unpack_dir = unpack_dir_for(major, minor, patch)?;
+    if force && unpack_dir.is_dir() {
+        fs::remove_dir_all(&unpack_dir).with_context(|| {
+            format!(
+                "Failed to remove existing install at {}",
+                unpack_dir.display()
+            )
+        })?;
+    }
+    // Only worth resolving the exact patch behind an unpinned `x.y` when we're about to hit the
+    // network for it anyway (a cache miss, or `--force`): an already-cached unpinned install has
+    // no patch recorded in its directory name, and re-resolving it just to print a label would
+    // make a no-op re-run of an already-warm `python-install` hit the network (breaking
+    // `MONOTRAIL_NO_DOWNLOAD`) for nothing. `provision_python` below re-resolves independently on
+    // an actual cache miss, so in that case there's a narrow window where a patch published
+    // between the two calls could make the reported number disagree with what actually got
+    // installed; we accept that rather than threading a resolved download through
+    // `provision_python`'s hot, widely-used return type just for this label.
+    let resolved_patch = match patch {
+        Some(patch) => Some(patch),
+        None if force || !unpack_dir.is_dir() => find_python(major, minor, None)?.patch,
+        None => None,
+    };
+    provision_python(Implementation::CPython, (major, minor), patch)?;
+    Ok((major, minor, resolved_patch))
+}
+
+/// One managed CPython build found by [`list_installed`]
+pub struct InstalledPython {
+    pub major: u8,
+    pub minor: u8,
+    /// `None` for an unpinned install: the newest patch available at the time it was
+    /// provisioned, which may no longer be the newest one upstream
+    pub patch: Option<u8>,
+    /// Whether this is the [`PYTHON_FULL_BUILD_VAR`] variant rather than the default
+    /// `install_only` one
+    pub full: bool,
+    /// Whether [`check_installed_python`] found the expected `libpython` in place
+    pub ok: bool,
+}
+
+/// Reverses [`unpack_dir_for`]'s naming scheme, e.g. `cpython-3.9.12-full` back to
+/// `(3, 9, Some(12), true)`
+fn parse_cache_dir_name(name: &str) -> Option<(u8, u8, Option<u8>, bool)> {
+    let body = name.strip_prefix("cpython-")?;
+    let (body, full) = match body.strip_suffix("-full") {
+        Some(body) => (body, true),
+        None => (body, false),
+    };
+    let mut parts = body.splitn(3, '.');
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next()?.parse().ok()?;
+    let patch = parts.next().and_then(|patch| patch.parse().ok());
+    Some((major, minor, patch, full))
+}
+
+/// `monotrail python-list`: the managed CPython builds currently cached under
+/// [`python_cache_dir`], one entry per `(major, minor[, patch])` that [`provision_python`] (or
+/// [`install_python`]) has downloaded so far
+pub fn list_installed() -> anyhow::Result<Vec<InstalledPython>> {
+    let python_parent_dir = python_cache_dir()?;
+    if !python_parent_dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut installed: Vec<InstalledPython> = fs::read_dir(&python_parent_dir)?
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            if !entry.file_type().ok()?.is_dir() {
+                return None;
+            }
+            let (major, minor, patch, full) =
+                parse_cache_dir_name(&entry.file_name().to_string_lossy())?;
+            let ok = check_installed_python(&entry.path(), (major, minor)).is_ok();
+            Some(InstalledPython {
+                major,
+                minor,
+                patch,
+                full,
+                ok,
+            })
+        })
+        .collect();
+    installed.sort_by_key(|python| (python.major, python.minor, python.patch, python.full));
+    Ok(installed)
+}
+
+/// Builds the [`PythonContext`] and python home for an already-unpacked
+/// `cpython-<major>.<minor>` tree, shared between the normal download path and the
+/// `MONOTRAIL_PYTHON_STANDALONE_DIR` override
+fn python_context_from_unpack_dir(
+    unpack_dir: &Path,
+    python_version: (u8, u8),
+) -> (PythonContext, PathBuf) {
+    let install_root = python_install_root(unpack_dir);
     let python_binary = if cfg!(target_os = "windows") {
-        unpack_dir.join("python").join("install").join("python.exe")
+        install_root.join("python.exe")
     } else {
         // Tested for linux and mac
-        unpack_dir
-            .join("python")
-            .join("install")
-            .join("bin")
-            .join("python3")
+        install_root.join("bin").join("python3")
     };
     // TODO: Already init and use libpython here
     let pep508_env = Pep508Environment::from_python(&python_binary);
+    let platform_tags = probe_interpreter_info(&python_binary)
+        .map(|info| compatible_platform_tags(&python_binary, &info))
+        .unwrap_or_else(|err| {
+            debug!(
+                "Failed to probe platform tags, falling back to none: {}",
+                err
+            );
+            Vec::new()
+        });
     let python_context = PythonContext {
         sys_executable: python_binary,
         version: python_version,
+        implementation: Implementation::CPython,
         pep508_env,
         launch_type: LaunchType::Binary,
+        platform_tags,
     };
 
-    let python_home = unpack_dir.join("python").join("install");
-    Ok((python_context, python_home))
+    (python_context, install_root)
 }
 
-/// Returns a regex matching a compatible optimized build from the indygreg/python-build-standalone
-/// release page.
+/// Returns the target triple and PGO/LTO optimization level indygreg's release naming uses for the
+/// current host, e.g. `("x86_64_v3-unknown-linux-gnu", "pgo+lto")`.
 ///
 /// <https://python-build-standalone.readthedocs.io/en/latest/running.html>
-pub fn filename_regex(major: u8, minor: u8) -> Regex {
+pub fn standalone_target(minor: u8) -> (String, &'static str) {
     let target_triple = target_lexicon::HOST.to_string();
+    // target_lexicon::HOST always reports a `-gnu` triple, even on a musl host, so we have to
+    // detect the actual host libc ourselves and patch the triple if it's musl
+    let target_triple = if is_musl_host() {
+        target_triple.replace("-gnu", "-musl")
+    } else {
+        target_triple
+    };
     // https://python-build-standalone.readthedocs.io/en/latest/running.html#obtaining-distributions
-    let (target_triple, linker_opts) = if target_triple.starts_with("x86_64-unknown-linux") {
+    if target_triple.ends_with("-musl") {
+        // musl builds are only published for the base x86_64 arch, no x86_64_v2/v3 variants
+        (target_triple, "pgo+lto")
+    } else if target_triple.starts_with("x86_64-unknown-linux") {
         cpufeatures::new!(cpu_v3, "avx2");
         cpufeatures::new!(cpu_v2, "sse4.2");
         // For python3.8 there's only the base version
@@ -249,30 +1229,347 @@ pub fn filename_regex(major: u8, minor: u8) -> Regex {
         (format!("{}-shared", target_triple), "pgo")
     } else {
         (target_triple, "pgo+lto")
-    };
+    }
+}
 
-    let version_re = format!(
-        r#"^cpython-{major}\.{minor}\.(\d+)\+(\d+)-{target_triple}-{linker_opts}-full\.tar\.zst$"#,
-        major = major,
-        minor = minor,
-        target_triple = regex::escape(&target_triple),
-        linker_opts = regex::escape(linker_opts),
-    );
-    Regex::new(&version_re)
-        .context("Failed to build version regex")
-        .unwrap()
+/// A parsed python-build-standalone release asset filename, e.g.
+/// `cpython-3.9.12+20220502-x86_64_v3-unknown-linux-gnu-pgo+lto-full.tar.zst`. Parsing the name
+/// instead of just matching it against a regex lets us prefer the newest compatible build (by
+/// patch version, then build tag) instead of whichever asset happens to come first in the release,
+/// and is more resilient to indygreg changing a single field of the convention
+///
+/// <https://github.com/indygreg/python-build-standalone/issues/127>
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct StandaloneAssetName {
+    python_version: (u8, u8, u8),
+    build_tag: u32,
+    triple: String,
+    /// `None` for `install_only` builds, which have no PGO/LTO optimization pass at all
+    optimization: Option<String>,
+    /// `"full"` (build artifacts included) or `"install_only"` (just the installation)
+    flavor: String,
+    /// Archive format, e.g. `"tar.zst"` or `"tar.gz"`; not yet used for selection since we only
+    /// ever look for `.tar.zst`/`.tar.gz` assets, but parsing it out keeps this struct a full
+    /// decomposition of the filename rather than a partial one
+    #[allow(dead_code)]
+    ext: String,
+}
+
+impl StandaloneAssetName {
+    fn parse(name: &str) -> Option<Self> {
+        let body = name.strip_prefix("cpython-")?;
+        let (body, ext) = [".tar.zst", ".tar.gz"].iter().find_map(|ext| {
+            body.strip_suffix(ext)
+                .map(|body| (body, ext.trim_start_matches('.').to_string()))
+        })?;
+
+        let (version, rest) = body.split_once('+')?;
+        let mut version_parts = version.splitn(3, '.');
+        let major = version_parts.next()?.parse().ok()?;
+        let minor = version_parts.next()?.parse().ok()?;
+        let patch = version_parts.next()?.parse().ok()?;
+
+        let (build_tag, rest) = rest.split_once('-')?;
+        let build_tag = build_tag.parse().ok()?;
+
+        let (rest, flavor) = rest.rsplit_once('-')?;
+        let (triple, optimization) = if flavor == "install_only" {
+            (rest.to_string(), None)
+        } else {
+            let (triple, optimization) = rest.rsplit_once('-')?;
+            (triple.to_string(), Some(optimization.to_string()))
+        };
+
+        Some(Self {
+            python_version: (major, minor, patch),
+            build_tag,
+            triple,
+            optimization,
+            flavor: flavor.to_string(),
+            ext,
+        })
+    }
+
+    /// Whether this asset is compatible with `(major, minor[, patch])`, `triple`, `flavor` and
+    /// `optimization`, the selection criteria shared by [`pick_standalone_build`] and
+    /// [`available_patch_versions`]
+    fn matches(
+        &self,
+        major: u8,
+        minor: u8,
+        patch: Option<u8>,
+        triple: &str,
+        flavor: &str,
+        optimization: Option<&str>,
+    ) -> bool {
+        matches_selection(
+            self.python_version.0,
+            self.python_version.1,
+            self.python_version.2,
+            &self.triple,
+            &self.flavor,
+            self.optimization.as_deref(),
+            major,
+            minor,
+            patch,
+            triple,
+            flavor,
+            optimization,
+        )
+    }
+}
+
+/// The `(major, minor[, patch])`/`triple`/`flavor`/`optimization` compatibility check shared by
+/// [`StandaloneAssetName::matches`] (parsed out of a GitHub asset filename) and
+/// [`find_in_versions_manifest`] (read from `versions.json`), so the two candidate sources for a
+/// download can't drift apart on what counts as a match
+#[allow(clippy::too_many_arguments)]
+fn matches_selection(
+    entry_major: u8,
+    entry_minor: u8,
+    entry_patch: u8,
+    entry_triple: &str,
+    entry_flavor: &str,
+    entry_optimization: Option<&str>,
+    major: u8,
+    minor: u8,
+    patch: Option<u8>,
+    triple: &str,
+    flavor: &str,
+    optimization: Option<&str>,
+) -> bool {
+    entry_major == major
+        && entry_minor == minor
+        && patch.map_or(true, |patch| entry_patch == patch)
+        && entry_triple == triple
+        && entry_flavor == flavor
+        && entry_optimization == optimization
+}
+
+/// Picks the newest build (by patch version, then build tag) of the given `flavor` compatible with
+/// `(major, minor[, patch])`, `triple` and `optimization` out of `items`, named by the filename
+/// that comes with each item. Shared between [`select_asset`] (picking a [`GitHubAsset`] out of a
+/// release) and [`find_bootstrap_archive`] (picking a local file out of a directory listing)
+fn pick_standalone_build<'a, T>(
+    items: impl Iterator<Item = (&'a str, T)>,
+    major: u8,
+    minor: u8,
+    patch: Option<u8>,
+    triple: &str,
+    flavor: &str,
+    optimization: Option<&str>,
+) -> Option<T> {
+    items
+        .filter_map(|(name, item)| StandaloneAssetName::parse(name).map(|parsed| (item, parsed)))
+        .filter(|(_, parsed)| parsed.matches(major, minor, patch, triple, flavor, optimization))
+        .max_by_key(|(_, parsed)| (parsed.python_version.2, parsed.build_tag))
+        .map(|(item, _)| item)
+}
+
+/// Picks the newest build (by patch version, then build tag) of the given `flavor` compatible with
+/// `(major, minor[, patch])`, `triple` and `optimization` out of `assets`
+fn select_asset<'a>(
+    assets: &'a [GitHubAsset],
+    major: u8,
+    minor: u8,
+    patch: Option<u8>,
+    triple: &str,
+    flavor: &str,
+    optimization: Option<&str>,
+) -> Option<&'a GitHubAsset> {
+    pick_standalone_build(
+        assets.iter().map(|asset| (asset.name.as_str(), asset)),
+        major,
+        minor,
+        patch,
+        triple,
+        flavor,
+        optimization,
+    )
+}
+
+/// All patch versions of `major.minor` that have a matching `(triple, flavor, optimization)` asset
+/// in `assets`, for hinting which pin would actually work after [`select_asset`] fails to find one
+fn available_patch_versions(
+    assets: &[GitHubAsset],
+    major: u8,
+    minor: u8,
+    triple: &str,
+    flavor: &str,
+    optimization: Option<&str>,
+) -> BTreeSet<u8> {
+    assets
+        .iter()
+        .filter_map(|asset| StandaloneAssetName::parse(&asset.name))
+        .filter(|parsed| parsed.matches(major, minor, None, triple, flavor, optimization))
+        .map(|parsed| parsed.python_version.2)
+        .collect()
+}
+
+/// Looks for a matching `cpython-*.tar.zst`/`.tar.gz` in [`PYTHON_BOOTSTRAP_DIR_VAR`], the same
+/// way [`select_asset`] picks one out of a GitHub release
+fn find_bootstrap_archive(
+    bootstrap_dir: &Path,
+    major: u8,
+    minor: u8,
+    patch: Option<u8>,
+    triple: &str,
+    flavor: &str,
+    optimization: Option<&str>,
+) -> io::Result<Option<PathBuf>> {
+    let names: Vec<String> = fs::read_dir(bootstrap_dir)?
+        .filter_map(|entry| Some(entry.ok()?.file_name().to_string_lossy().into_owned()))
+        .collect();
+    Ok(pick_standalone_build(
+        names.iter().map(|name| (name.as_str(), name.clone())),
+        major,
+        minor,
+        patch,
+        triple,
+        flavor,
+        optimization,
+    )
+    .map(|name| bootstrap_dir.join(name)))
+}
+
+/// Env var opting into the `full` python-build-standalone variant (build artifacts, debug objects,
+/// static libs included) instead of the default `install_only` variant, which only contains what's
+/// needed to run python and is both smaller and faster to provision
+const PYTHON_FULL_BUILD_VAR: &str = "MONOTRAIL_PYTHON_FULL_BUILD";
+
+/// The release flavor to select (`"install_only"` by default, `"full"` under
+/// [`PYTHON_FULL_BUILD_VAR`]) plus, for `"full"`, the PGO/LTO optimization level to require;
+/// `install_only` builds have no optimization field in their filename at all
+fn standalone_variant(minor: u8) -> (String, &'static str, Option<&'static str>) {
+    let (triple, optimization) = standalone_target(minor);
+    if is_full_build_requested() {
+        (triple, "full", Some(optimization))
+    } else {
+        (triple, "install_only", None)
+    }
+}
+
+fn is_full_build_requested() -> bool {
+    env::var_os(PYTHON_FULL_BUILD_VAR).as_deref() == Some(std::ffi::OsStr::new("1"))
+}
+
+/// Cache dir suffix distinguishing a `full` build from the default `install_only` one, so the two
+/// variants of the same python version don't collide in the cache (see [`PYTHON_FULL_BUILD_VAR`])
+fn standalone_flavor_suffix() -> &'static str {
+    if is_full_build_requested() {
+        "-full"
+    } else {
+        ""
+    }
+}
+
+/// Whether the current host uses musl libc rather than glibc. We read the `PT_INTERP` program
+/// header of `/proc/self/exe` (our own running binary) and check whether it points at musl's
+/// loader (`ld-musl-*`, as opposed to glibc's `ld-linux*`); if `/proc/self/exe` isn't available
+/// (e.g. non-Linux, or a restricted container), we fall back to checking whether any
+/// `/lib/ld-musl-*.so*` exists, which is musl's own canonical install location
+fn is_musl_host() -> bool {
+    if let Some(interpreter) = read_elf_interpreter(Path::new("/proc/self/exe")) {
+        return interpreter.contains("ld-musl-");
+    }
+    fs::read_dir("/lib")
+        .map(|entries| {
+            entries.filter_map(Result::ok).any(|entry| {
+                let name = entry.file_name();
+                let name = name.to_string_lossy();
+                name.starts_with("ld-musl-") && name.contains(".so")
+            })
+        })
+        .unwrap_or(false)
+}
+
+/// Reads the `PT_INTERP` program header (the path to the dynamic loader the binary was linked
+/// against) of an ELF binary, or `None` if it can't be read or parsed (e.g. not Linux, not ELF, or
+/// a statically linked binary with no interpreter at all)
+fn read_elf_interpreter(path: &Path) -> Option<String> {
+    let buffer = fs::read(path).ok()?;
+    let elf = goblin::elf::Elf::parse(&buffer).ok()?;
+    elf.interpreter.map(|interpreter| interpreter.to_string())
 }
 
 #[cfg(test)]
 mod test {
     use mockito::Mock;
 
-    #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+    #[cfg(all(target_os = "linux", target_arch = "x86_64", target_env = "gnu"))]
+    use crate::monotrail::Implementation;
+    #[cfg(all(target_os = "linux", target_arch = "x86_64", target_env = "gnu"))]
     use crate::standalone_python::provision_python;
     use crate::standalone_python::{
-        find_python, PYTHON_STANDALONE_KNOWN_GOOD_RELEASE, PYTHON_STANDALONE_LATEST_RELEASE,
+        find_in_versions_manifest, find_python, VersionsManifest, VersionsManifestEntry,
+        PYTHON_FULL_BUILD_VAR, PYTHON_STANDALONE_DIR_VAR, PYTHON_STANDALONE_KNOWN_GOOD_RELEASE,
+        PYTHON_STANDALONE_LATEST_RELEASE, StandaloneAssetName,
     };
     use crate::utils::zstd_json_mock;
+    use std::env;
+    #[cfg(all(target_os = "linux", target_arch = "x86_64", target_env = "gnu"))]
+    use std::path::Path;
+    use std::sync::{Mutex, MutexGuard};
+
+    /// Guards [`PYTHON_FULL_BUILD_VAR`], which is process-global: without this, a test that sets
+    /// it could race a concurrently-running test that relies on the default `install_only` variant
+    static FULL_BUILD_VAR_LOCK: Mutex<()> = Mutex::new(());
+
+    /// Sets [`PYTHON_FULL_BUILD_VAR`] for the lifetime of the returned guard, clearing it again on
+    /// drop even if the test panics while holding it, so a failing assertion can't leave the var
+    /// (or the lock, which tolerates poisoning here) stuck for every later test in this module
+    #[must_use]
+    struct FullBuildVarGuard(MutexGuard<'static, ()>);
+
+    impl FullBuildVarGuard {
+        fn acquire() -> Self {
+            let guard = FULL_BUILD_VAR_LOCK
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            env::set_var(PYTHON_FULL_BUILD_VAR, "1");
+            Self(guard)
+        }
+    }
+
+    impl Drop for FullBuildVarGuard {
+        fn drop(&mut self) {
+            env::remove_var(PYTHON_FULL_BUILD_VAR);
+        }
+    }
+
+    /// Takes [`FULL_BUILD_VAR_LOCK`] without setting [`PYTHON_FULL_BUILD_VAR`], for tests that rely
+    /// on the default `install_only` variant and just need to stay serialized against
+    /// [`FullBuildVarGuard::acquire`]
+    fn lock_default_build_var() -> MutexGuard<'static, ()> {
+        FULL_BUILD_VAR_LOCK
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Sets [`PYTHON_STANDALONE_DIR_VAR`] to `dir` for the lifetime of the returned guard, clearing
+    /// it again on drop even if the test panics. Takes [`FULL_BUILD_VAR_LOCK`] like every other
+    /// test that calls [`provision_python`]/[`find_python`]: this var is process-global too and
+    /// would otherwise let a concurrently-running test race into nondeterministically skipping the
+    /// mocked network lookup it's meant to exercise
+    #[cfg(all(target_os = "linux", target_arch = "x86_64", target_env = "gnu"))]
+    #[must_use]
+    struct StandaloneDirVarGuard(MutexGuard<'static, ()>);
+
+    #[cfg(all(target_os = "linux", target_arch = "x86_64", target_env = "gnu"))]
+    impl StandaloneDirVarGuard {
+        fn acquire(dir: &Path) -> Self {
+            let guard = lock_default_build_var();
+            env::set_var(PYTHON_STANDALONE_DIR_VAR, dir);
+            Self(guard)
+        }
+    }
+
+    #[cfg(all(target_os = "linux", target_arch = "x86_64", target_env = "gnu"))]
+    impl Drop for StandaloneDirVarGuard {
+        fn drop(&mut self) {
+            env::remove_var(PYTHON_STANDALONE_DIR_VAR);
+        }
+    }
 
     fn mock() -> (Mock, Mock) {
         let latest_mock = zstd_json_mock(
@@ -287,31 +1584,273 @@ mod test {
     }
 
     #[test]
-    #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+    #[cfg(all(target_os = "linux", target_arch = "x86_64", target_env = "gnu"))]
     fn test_download_url_from_release_20220502() {
+        // install_only is the default variant now, but the fixture release for this test was
+        // recorded against the full build's url, so opt back into it here
+        let _guard = FullBuildVarGuard::acquire();
         let _mocks = mock();
 
-        let url = find_python(3, 9).unwrap();
-        assert_eq!(url, "https://github.com/indygreg/python-build-standalone/releases/download/20220502/cpython-3.9.12%2B20220502-x86_64_v3-unknown-linux-gnu-pgo%2Blto-full.tar.zst")
+        let download = find_python(3, 9, None).unwrap();
+        assert_eq!(download.url, "https://github.com/indygreg/python-build-standalone/releases/download/20220502/cpython-3.9.12%2B20220502-x86_64_v3-unknown-linux-gnu-pgo%2Blto-full.tar.zst")
     }
 
     #[test]
     fn test_download_url_from_release_20220502_any() {
+        let _guard = lock_default_build_var();
+        let _mocks = mock();
+
+        assert!(find_python(3, 9, None).is_ok());
+    }
+
+    #[test]
+    fn test_download_url_pinned_patch_matches() {
+        let _guard = lock_default_build_var();
+        let _mocks = mock();
+
+        let unpinned = find_python(3, 9, None).unwrap();
+        let asset_name = unpinned.url.rsplit('/').next().unwrap().replace("%2B", "+");
+        let patch = StandaloneAssetName::parse(&asset_name)
+            .unwrap()
+            .python_version
+            .2;
+
+        let pinned = find_python(3, 9, Some(patch)).unwrap();
+        assert_eq!(pinned.url, unpinned.url);
+    }
+
+    #[test]
+    fn test_download_url_pinned_patch_mismatch_lists_available_patches() {
+        let _guard = lock_default_build_var();
         let _mocks = mock();
 
-        assert!(find_python(3, 9).is_ok());
+        let unpinned = find_python(3, 9, None).unwrap();
+        let asset_name = unpinned.url.rsplit('/').next().unwrap().replace("%2B", "+");
+        let patch = StandaloneAssetName::parse(&asset_name)
+            .unwrap()
+            .python_version
+            .2;
+
+        // No release actually ships a .250 patch, so this always misses
+        let err = find_python(3, 9, Some(250)).unwrap_err();
+        let message = err.to_string();
+        assert!(
+            message.contains("Available patch versions for 3.9:"),
+            "unexpected error message: {}",
+            message
+        );
+        assert!(
+            message.contains(&patch.to_string()),
+            "hint doesn't mention the patch that's actually available: {}",
+            message
+        );
     }
 
     #[test]
-    #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+    #[cfg(all(target_os = "linux", target_arch = "x86_64", target_env = "gnu"))]
     fn test_provision_nonexistent_version() {
+        let _guard = lock_default_build_var();
         let _mocks = mock();
-        let err = provision_python((3, 0)).unwrap_err();
+        let err = provision_python(Implementation::CPython, (3, 0), None).unwrap_err();
         let expected = vec![
             r"Couldn't find a matching python 3.0 to download",
-            r"Failed to find a matching python-build-standalone download: /^cpython-3\.0\.(\d+)\+(\d+)-x86_64\-unknown\-linux\-gnu-pgo\+lto-full\.tar\.zst$/. Searched in https://github.com/indygreg/python-build-standalone/releases/latest and https://github.com/indygreg/python-build-standalone/releases/tag/20220502",
+            r"Failed to find a matching python-build-standalone download: cpython-3.0+<build tag> for x86_64-unknown-linux-gnu (no optimization, install_only). Searched in https://github.com/indygreg/python-build-standalone/releases/latest and https://github.com/indygreg/python-build-standalone/releases/tag/20220502",
         ];
         let actual = err.chain().map(|e| e.to_string()).collect::<Vec<_>>();
         assert_eq!(actual, expected);
     }
+
+    #[test]
+    #[cfg(all(target_os = "linux", target_arch = "x86_64", target_env = "gnu"))]
+    fn test_provision_python_standalone_dir_override_skips_network() {
+        // Deliberately no `mock()` here: if `provision_python` ever fell through to the live
+        // lookup instead of stopping at the `PYTHON_STANDALONE_DIR_VAR` override below, it would
+        // hit the unmocked `mockito::server_url()` host and fail with a connection/parse error
+        // instead of the "no pre-provisioned python" message asserted below
+        let override_dir = tempfile::tempdir().unwrap();
+        let _guard = StandaloneDirVarGuard::acquire(override_dir.path());
+
+        let err = provision_python(Implementation::CPython, (3, 9), None).unwrap_err();
+        let message = err
+            .chain()
+            .map(|e| e.to_string())
+            .collect::<Vec<_>>()
+            .join(": ");
+        assert!(
+            message.contains(PYTHON_STANDALONE_DIR_VAR),
+            "unexpected error message: {}",
+            message
+        );
+    }
+
+    fn manifest_entry(major: u8, minor: u8, patch: u8) -> VersionsManifestEntry {
+        manifest_entry_with_build_tag(major, minor, patch, 20220502)
+    }
+
+    fn manifest_entry_with_build_tag(
+        major: u8,
+        minor: u8,
+        patch: u8,
+        build_tag: u32,
+    ) -> VersionsManifestEntry {
+        VersionsManifestEntry {
+            major,
+            minor,
+            patch,
+            build_tag,
+            triple: "x86_64-unknown-linux-gnu".to_string(),
+            flavor: "install_only".to_string(),
+            optimization: None,
+            tag: build_tag.to_string(),
+            url: format!("https://example.com/cpython-{major}.{minor}.{patch}-{build_tag}.tar.gz"),
+            sha256: "deadbeef".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_find_in_versions_manifest_picks_newest_patch_without_pin() {
+        let manifest = VersionsManifest {
+            downloads: vec![manifest_entry(3, 9, 10), manifest_entry(3, 9, 12)],
+        };
+        let download = find_in_versions_manifest(
+            &manifest,
+            3,
+            9,
+            None,
+            "x86_64-unknown-linux-gnu",
+            "install_only",
+            None,
+        )
+        .unwrap();
+        assert_eq!(download.patch, Some(12));
+        assert!(download.url.contains("3.9.12"));
+    }
+
+    #[test]
+    fn test_find_in_versions_manifest_prefers_newest_build_tag_for_same_patch() {
+        let manifest = VersionsManifest {
+            downloads: vec![
+                manifest_entry_with_build_tag(3, 9, 12, 20220502),
+                manifest_entry_with_build_tag(3, 9, 12, 20220528),
+            ],
+        };
+        let download = find_in_versions_manifest(
+            &manifest,
+            3,
+            9,
+            None,
+            "x86_64-unknown-linux-gnu",
+            "install_only",
+            None,
+        )
+        .unwrap();
+        assert!(download.url.contains("20220528"));
+    }
+
+    #[test]
+    fn test_find_in_versions_manifest_respects_exact_patch_pin() {
+        let manifest = VersionsManifest {
+            downloads: vec![manifest_entry(3, 9, 10), manifest_entry(3, 9, 12)],
+        };
+        let download = find_in_versions_manifest(
+            &manifest,
+            3,
+            9,
+            Some(10),
+            "x86_64-unknown-linux-gnu",
+            "install_only",
+            None,
+        )
+        .unwrap();
+        assert_eq!(download.patch, Some(10));
+    }
+
+    #[test]
+    fn test_find_in_versions_manifest_no_match() {
+        let manifest = VersionsManifest {
+            downloads: vec![manifest_entry(3, 9, 12)],
+        };
+        assert!(find_in_versions_manifest(
+            &manifest,
+            3,
+            11,
+            None,
+            "x86_64-unknown-linux-gnu",
+            "install_only",
+            None
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn test_parse_standalone_asset_name_full() {
+        let parsed = super::StandaloneAssetName::parse(
+            "cpython-3.9.12+20220502-x86_64_v3-unknown-linux-gnu-pgo+lto-full.tar.zst",
+        )
+        .unwrap();
+        assert_eq!(parsed.python_version, (3, 9, 12));
+        assert_eq!(parsed.build_tag, 20220502);
+        assert_eq!(parsed.triple, "x86_64_v3-unknown-linux-gnu");
+        assert_eq!(parsed.optimization.as_deref(), Some("pgo+lto"));
+        assert_eq!(parsed.flavor, "full");
+        assert_eq!(parsed.ext, "tar.zst");
+    }
+
+    #[test]
+    fn test_parse_standalone_asset_name_install_only() {
+        let parsed = super::StandaloneAssetName::parse(
+            "cpython-3.9.12+20220502-x86_64-unknown-linux-gnu-install_only.tar.gz",
+        )
+        .unwrap();
+        assert_eq!(parsed.python_version, (3, 9, 12));
+        assert_eq!(parsed.optimization, None);
+        assert_eq!(parsed.flavor, "install_only");
+        assert_eq!(parsed.ext, "tar.gz");
+    }
+
+    #[test]
+    fn test_ensure_host_architecture_ignores_non_elf_files() {
+        // macOS .dylib/Windows .dll builds (and anything else that isn't ELF) aren't something
+        // this check can inspect, so it has to stay silent rather than bail on every non-linux
+        // install
+        let temp_dir = tempfile::tempdir().unwrap();
+        let not_elf = temp_dir.path().join("libpython3.so");
+        std::fs::write(&not_elf, b"not actually an ELF file").unwrap();
+        assert!(super::ensure_host_architecture(&not_elf).is_ok());
+    }
+
+    #[test]
+    #[cfg(all(target_os = "linux", target_arch = "x86_64", target_env = "gnu"))]
+    fn test_ensure_host_architecture_accepts_own_binary() {
+        // `/proc/self/exe` is an ELF binary for this very process, so it always matches the
+        // architecture monotrail itself was compiled for
+        assert!(super::ensure_host_architecture(std::path::Path::new("/proc/self/exe")).is_ok());
+    }
+
+    #[test]
+    fn test_ensure_host_architecture_rejects_mismatch() {
+        // A hand-rolled, minimal ELF64 shared-object header (no program/section headers, which
+        // `e_phnum`/`e_shnum` of 0 makes valid) built for EM_ARM -- this crate is never compiled
+        // as 32-bit arm, so this always disagrees with the actual host, whatever it is
+        let mut header = vec![0u8; 64];
+        header[0..4].copy_from_slice(&[0x7f, b'E', b'L', b'F']);
+        header[4] = 2; // EI_CLASS = ELFCLASS64
+        header[5] = 1; // EI_DATA = ELFDATA2LSB (little endian)
+        header[6] = 1; // EI_VERSION = EV_CURRENT
+        header[16..18].copy_from_slice(&3u16.to_le_bytes()); // e_type = ET_DYN
+        header[18..20].copy_from_slice(&40u16.to_le_bytes()); // e_machine = EM_ARM
+        header[20..24].copy_from_slice(&1u32.to_le_bytes()); // e_version = EV_CURRENT
+        header[52..54].copy_from_slice(&64u16.to_le_bytes()); // e_ehsize
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let fake_lib = temp_dir.path().join("libpython3.so");
+        std::fs::write(&fake_lib, &header).unwrap();
+
+        let err = super::ensure_host_architecture(&fake_lib).unwrap_err();
+        assert!(
+            err.to_string().contains("arm"),
+            "unexpected error message: {}",
+            err
+        );
+    }
 }
@@ -0,0 +1,164 @@
+//! Brings an [`InstallLocation`] exactly into line with a resolved set of specs by removing
+//! whatever's installed but no longer wanted, the counterpart to [`crate::install::install_all`]
+//! which only ever adds packages
+
+use crate::gc::{prune_empty_dirs, DEFAULT_NEVER_REMOVE};
+use crate::monotrail::list_installed;
+use crate::spec::RequestedSpec;
+use crate::venv_parser::VirtualEnvironment;
+use anyhow::Context;
+use fs_err as fs;
+use install_wheel_rs::{read_record_file, InstallLocation, LockedDir};
+use std::collections::HashSet;
+use std::path::Path;
+use tracing::debug;
+
+/// What a [`sync`] run removed
+#[derive(Debug, Clone, Default)]
+pub struct SyncReport {
+    /// The normalized names of the packages that were removed
+    pub removed: Vec<String>,
+}
+
+/// Removes everything installed in `location` that isn't in `specs`, `never_remove`, or the
+/// hard-coded [`DEFAULT_NEVER_REMOVE`] set (pip, setuptools and wheel, which bootstrap
+/// installation itself and so are never safe to remove even when a lockfile doesn't mention
+/// them), bringing the environment exactly into line with the lockfile `specs` was resolved from.
+///
+/// `location` must already be [`InstallLocation::acquire_lock`]ed so the exclusive lock is held
+/// for the whole sync, the same way [`crate::install::install_all`] requires it for installing.
+pub fn sync(
+    location: &InstallLocation<LockedDir>,
+    specs: &[RequestedSpec],
+    root_name: Option<&str>,
+    never_remove: &[String],
+) -> anyhow::Result<SyncReport> {
+    let normalize = |name: &str| name.to_lowercase().replace('-', "_");
+
+    let mut keep: HashSet<String> = specs.iter().map(|spec| spec.normalized_name()).collect();
+    keep.extend(DEFAULT_NEVER_REMOVE.iter().map(|name| normalize(name)));
+    keep.extend(never_remove.iter().map(|name| normalize(name)));
+    keep.extend(root_name.map(normalize));
+
+    match location {
+        InstallLocation::Venv { venv_base, .. } => sync_venv(venv_base, &keep),
+        InstallLocation::Monotrail { monotrail_root, .. } => sync_monotrail(monotrail_root, &keep),
+    }
+}
+
+/// Removes every `.dist-info` in the venv's own `site-packages` whose normalized name isn't in
+/// `keep`. Deliberately scoped to the venv's own `site-packages` only -- even with
+/// `include-system-site-packages` set, syncing must not reach into and delete from the base
+/// interpreter's site-packages, which other venvs or the system itself may depend on.
+fn sync_venv(venv_base: &Path, keep: &HashSet<String>) -> anyhow::Result<SyncReport> {
+    let virtual_env = VirtualEnvironment::from_venv(venv_base)?;
+    let site_packages =
+        VirtualEnvironment::site_packages_below(venv_base, virtual_env.python_version);
+
+    let mut report = SyncReport::default();
+    let entries = match fs::read_dir(&site_packages) {
+        Ok(entries) => entries.collect::<std::io::Result<Vec<_>>>()?,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+        Err(err) => return Err(err.into()),
+    };
+    for entry in entries {
+        let filename = entry.file_name().to_string_lossy().to_string();
+        let Some(name) = filename.strip_suffix(".dist-info") else {
+            continue;
+        };
+        let Some((name, _version)) = name.split_once('-') else {
+            continue;
+        };
+        let normalized_name = name.to_lowercase().replace('-', "_");
+        if keep.contains(&normalized_name) {
+            continue;
+        }
+
+        let dist_info = entry.path();
+        debug!(
+            "Removing {} from {}",
+            normalized_name,
+            site_packages.display()
+        );
+        remove_venv_package(&dist_info, &site_packages)?;
+        report.removed.push(normalized_name);
+    }
+    Ok(report)
+}
+
+/// Removes one venv package: every file `dist_info`'s `RECORD` lists (relative to
+/// `site_packages`), then the `dist-info` directory itself. Missing RECORD entries are ignored --
+/// a partially-broken prior install shouldn't stop the rest of the sync.
+fn remove_venv_package(dist_info: &Path, site_packages: &Path) -> anyhow::Result<()> {
+    let record =
+        fs::read_to_string(dist_info.join("RECORD")).context("Couldn't read RECORD file")?;
+    let record = read_record_file(&mut record.as_bytes()).context("Invalid RECORD file")?;
+    for entry in record {
+        let file = site_packages.join(&entry.path);
+        if file.is_file() {
+            fs::remove_file(&file)
+                .with_context(|| format!("Failed to remove {}", file.display()))?;
+        }
+    }
+    fs::remove_dir_all(dist_info)
+        .with_context(|| format!("Failed to remove {}", dist_info.display()))?;
+    Ok(())
+}
+
+/// Removes every `name/version/tag` directory under `monotrail_root` whose normalized name isn't
+/// in `keep`, mirroring [`crate::gc::prune`]'s removal logic
+fn sync_monotrail(monotrail_root: &Path, keep: &HashSet<String>) -> anyhow::Result<SyncReport> {
+    let mut report = SyncReport::default();
+    for (name, version, tag) in
+        list_installed(monotrail_root, None).context("Failed to list installed packages")?
+    {
+        if keep.contains(&name) {
+            continue;
+        }
+
+        let package_dir = monotrail_root.join(&name).join(&version).join(&tag);
+        debug!("Removing {}", package_dir.display());
+        fs::remove_dir_all(&package_dir)
+            .with_context(|| format!("Failed to remove {}", package_dir.display()))?;
+        report.removed.push(name);
+    }
+    prune_empty_dirs(monotrail_root)?;
+    Ok(report)
+}
+
+#[cfg(test)]
+mod test {
+    use super::sync_monotrail;
+    use fs_err as fs;
+    use std::collections::HashSet;
+
+    /// Creates a fake `name/version/tag/` install directory with a dummy file in it
+    fn fake_install(monotrail_root: &std::path::Path, name: &str, version: &str) {
+        let package_dir = monotrail_root.join(name).join(version).join("py3-none-any");
+        fs::create_dir_all(&package_dir).unwrap();
+        fs::write(package_dir.join("marker.txt"), "x").unwrap();
+    }
+
+    #[test]
+    fn removes_packages_not_in_keep_but_spares_protected_ones() {
+        let root = tempfile::tempdir().unwrap();
+        fake_install(root.path(), "wanted_pkg", "1.0.0");
+        fake_install(root.path(), "pip", "23.0");
+        fake_install(root.path(), "stale_pkg", "2.0.0");
+
+        // Mirrors what `sync()` hands down: the resolved specs plus `DEFAULT_NEVER_REMOVE`
+        let keep: HashSet<String> = ["wanted_pkg".to_string(), "pip".to_string()]
+            .into_iter()
+            .collect();
+
+        let report = sync_monotrail(root.path(), &keep).unwrap();
+
+        assert_eq!(report.removed, vec!["stale_pkg".to_string()]);
+        // In `keep`: survives
+        assert!(root.path().join("wanted_pkg/1.0.0/py3-none-any").is_dir());
+        // Never-remove package, still in `keep`: survives even though nothing else needs it
+        assert!(root.path().join("pip/23.0/py3-none-any").is_dir());
+        // Not in `keep`: actually removed
+        assert!(!root.path().join("stale_pkg").exists());
+    }
+}
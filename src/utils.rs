@@ -44,7 +44,7 @@ pub(crate) fn data_local_dir() -> Result<PathBuf, WheelInstallerError> {
 /// This is used by several places for testing
 #[doc(hidden)]
 pub fn assert_cli_error(cli: Cli, venv: Option<&Path>, expected: &[&str]) {
-    if let Err(err) = run_cli(cli, venv) {
+    if let Err(err) = run_cli(cli, venv, None) {
         let actual = err.chain().map(|e| e.to_string()).collect::<Vec<_>>();
         assert_eq!(expected, actual);
     } else {
@@ -52,6 +52,36 @@ pub fn assert_cli_error(cli: Cli, venv: Option<&Path>, expected: &[&str]) {
     }
 }
 
+/// Levenshtein edit distance between two strings, computed with two rolling rows instead of the
+/// full `(len_a+1) x (len_b+1)` matrix
+fn levenshtein(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut previous_row: Vec<usize> = (0..=b.len()).collect();
+    let mut current_row = vec![0; b.len() + 1];
+    for (i, a_char) in a.iter().enumerate() {
+        current_row[0] = i + 1;
+        for (j, b_char) in b.iter().enumerate() {
+            current_row[j + 1] = (previous_row[j + 1] + 1)
+                .min(current_row[j] + 1)
+                .min(previous_row[j] + usize::from(a_char != b_char));
+        }
+        std::mem::swap(&mut previous_row, &mut current_row);
+    }
+    previous_row[b.len()]
+}
+
+/// Finds the candidate closest to `needle` by edit distance, for "did you mean" style error
+/// messages. Returns `None` if nothing is within `max(3, needle.len() / 3)` edits
+pub fn did_you_mean<'a>(needle: &str, candidates: impl Iterator<Item = &'a String>) -> Option<&'a String> {
+    let threshold = (needle.len() / 3).max(3);
+    candidates
+        .map(|candidate| (levenshtein(needle, candidate), candidate))
+        .filter(|(distance, _)| *distance <= threshold)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
 /// Adds the mock response for a prerecorded .json.zstd response
 #[cfg(test)]
 pub fn zstd_json_mock(url: &str, fixture: impl Into<PathBuf>) -> Mock {
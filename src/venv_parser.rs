@@ -1,7 +1,8 @@
+use crate::monotrail::Implementation;
 use crate::WheelInstallerError;
 use fs_err as fs;
 use std::collections::HashMap;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 /// Parse pyvenv.cfg from the root of the virtualenv and returns the python major and minor version
 pub fn get_venv_python_version(venv: &Path) -> Result<(u8, u8), WheelInstallerError> {
@@ -15,24 +16,21 @@ pub fn get_venv_python_version(venv: &Path) -> Result<(u8, u8), WheelInstallerEr
     get_pyvenv_cfg_python_version(&fs::read_to_string(pyvenv_cfg)?)
 }
 
-/// Parse pyvenv.cfg from the root of the virtualenv and returns the python major and minor version
+/// Parse pyvenv.cfg from the root of the virtualenv and returns the python major and minor version.
+/// Unlike [`VirtualEnvironment::parse`], only `version_info` is required -- callers that just want
+/// the python version shouldn't fail on a pyvenv.cfg that's missing `base-executable`/`home` or has
+/// an `implementation` this crate doesn't recognize yet.
 pub fn get_pyvenv_cfg_python_version(pyvenv_cfg: &str) -> Result<(u8, u8), WheelInstallerError> {
-    let pyvenv_cfg: HashMap<String, String> = pyvenv_cfg
-        .lines()
-        // Actual pyvenv.cfg doesn't have trailing newlines, but some program might insert some
-        .filter(|line| !line.is_empty())
-        .map(|line| {
-            line.split_once(" = ")
-                .map(|(key, value)| (key.to_string(), value.to_string()))
-                .ok_or_else(|| WheelInstallerError::BrokenVenv("Invalid pyvenv.cfg".to_string()))
-        })
-        .collect::<Result<HashMap<String, String>, WheelInstallerError>>()?;
+    parse_python_version(&parse_key_value_lines(pyvenv_cfg)?)
+}
 
-    let version_info = pyvenv_cfg.get("version_info").ok_or_else(|| {
+/// Parses `version_info` (e.g. `3.10.4.final.0`) into its major/minor components
+fn parse_python_version(fields: &HashMap<String, String>) -> Result<(u8, u8), WheelInstallerError> {
+    let version_info = fields.get("version_info").ok_or_else(|| {
         WheelInstallerError::BrokenVenv("Missing version_info in pyvenv.cfg".to_string())
     })?;
-    let python_version: (u8, u8) = match &version_info.split('.').collect::<Vec<_>>()[..] {
-        [major, minor, ..] => (
+    match &version_info.split('.').collect::<Vec<_>>()[..] {
+        [major, minor, ..] => Ok((
             major.parse().map_err(|err| {
                 WheelInstallerError::BrokenVenv(format!(
                     "Invalid major version_info in pyvenv.cfg: {}",
@@ -45,12 +43,130 @@ pub fn get_pyvenv_cfg_python_version(pyvenv_cfg: &str) -> Result<(u8, u8), Wheel
                     err
                 ))
             })?,
-        ),
-        _ => {
-            return Err(WheelInstallerError::BrokenVenv(
-                "Invalid version_info in pyvenv.cfg".to_string(),
-            ))
+        )),
+        _ => Err(WheelInstallerError::BrokenVenv(
+            "Invalid version_info in pyvenv.cfg".to_string(),
+        )),
+    }
+}
+
+/// Splits `pyvenv.cfg`'s `key = value` lines into a map, the way [`VirtualEnvironment::parse`]
+/// needs them
+fn parse_key_value_lines(pyvenv_cfg: &str) -> Result<HashMap<String, String>, WheelInstallerError> {
+    pyvenv_cfg
+        .lines()
+        // Actual pyvenv.cfg doesn't have trailing newlines, but some program might insert some
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            line.split_once(" = ")
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .ok_or_else(|| WheelInstallerError::BrokenVenv("Invalid pyvenv.cfg".to_string()))
+        })
+        .collect()
+}
+
+/// Everything a virtualenv's `pyvenv.cfg` tells us, parsed once instead of re-reading just the
+/// python version: the real base interpreter (`base-executable`/`home`), its `implementation`
+/// (CPython vs PyPy, which changes which wheel tags are compatible), and whether the venv also
+/// searches the base interpreter's own `site-packages` (`include-system-site-packages`)
+#[derive(Debug, Clone)]
+pub struct VirtualEnvironment {
+    /// `version_info`, just the major/minor (e.g. `(3, 10)` for `3.10.4.final.0`)
+    pub python_version: (u8, u8),
+    /// `implementation`, defaulting to CPython since the stdlib `venv` module doesn't bother
+    /// writing this key for its own (CPython) interpreters, only virtualenv reliably does
+    pub implementation: Implementation,
+    /// `base-executable` if present (newer `venv`/virtualenv), otherwise guessed from `home`
+    /// (the directory containing the base interpreter) plus the conventional binary name
+    pub base_executable: PathBuf,
+    /// `include-system-site-packages`, `false` if absent
+    pub system_site_packages: bool,
+}
+
+impl VirtualEnvironment {
+    /// Reads and parses `pyvenv.cfg` from the root of the virtualenv
+    pub fn from_venv(venv: &Path) -> Result<Self, WheelInstallerError> {
+        let pyvenv_cfg = venv.join("pyvenv.cfg");
+        if !pyvenv_cfg.is_file() {
+            return Err(WheelInstallerError::BrokenVenv(format!(
+                "The virtual environment needs to have a pyvenv.cfg, but {} doesn't exist",
+                pyvenv_cfg.display(),
+            )));
         }
-    };
-    Ok(python_version)
+        Self::parse(&fs::read_to_string(pyvenv_cfg)?)
+    }
+
+    /// Parses an already-read `pyvenv.cfg`
+    pub fn parse(pyvenv_cfg: &str) -> Result<Self, WheelInstallerError> {
+        let fields = parse_key_value_lines(pyvenv_cfg)?;
+        let python_version = parse_python_version(&fields)?;
+
+        let implementation = match fields.get("implementation") {
+            Some(name) => Implementation::from_sys_implementation_name(&name.to_lowercase())
+                .map_err(|err| WheelInstallerError::BrokenVenv(err.to_string()))?,
+            None => Implementation::CPython,
+        };
+
+        let base_executable = match fields.get("base-executable") {
+            Some(executable) => PathBuf::from(executable),
+            None => {
+                let home = fields.get("home").ok_or_else(|| {
+                    WheelInstallerError::BrokenVenv(
+                        "Missing base-executable/home in pyvenv.cfg".to_string(),
+                    )
+                })?;
+                Path::new(home).join(format!("python{}.{}", python_version.0, python_version.1))
+            }
+        };
+
+        let system_site_packages = fields
+            .get("include-system-site-packages")
+            .map(|value| value.eq_ignore_ascii_case("true"))
+            .unwrap_or(false);
+
+        Ok(Self {
+            python_version,
+            implementation,
+            base_executable,
+            system_site_packages,
+        })
+    }
+
+    /// This venv's own `site-packages`, plus (when `include-system-site-packages = true`) the
+    /// base interpreter's `site-packages` too, so a package that's only installed into the base
+    /// interpreter is recognized as already satisfying a spec inside this venv
+    pub fn site_packages_dirs(&self, venv_base: &Path) -> Vec<PathBuf> {
+        let mut dirs = vec![Self::site_packages_below(venv_base, self.python_version)];
+        if self.system_site_packages {
+            // `base_executable` points at the *base* interpreter, not this venv's own copy, so
+            // it's conventionally `<prefix>/bin/pythonX.Y` on Unix but `<prefix>/python.exe`
+            // directly on Windows (unlike this venv's own `Scripts/python.exe`) -- one extra
+            // `parent()` call is needed on Unix to get from the binary to `<prefix>`
+            let prefix = if cfg!(windows) {
+                self.base_executable.parent().map(Path::to_path_buf)
+            } else {
+                self.base_executable
+                    .parent()
+                    .and_then(Path::parent)
+                    .map(Path::to_path_buf)
+            };
+            if let Some(prefix) = prefix {
+                dirs.push(Self::site_packages_below(&prefix, self.python_version));
+            }
+        }
+        dirs
+    }
+
+    /// `site-packages` under a venv (or base interpreter) prefix: `Lib/site-packages` on Windows,
+    /// `lib/pythonX.Y/site-packages` everywhere else
+    pub(crate) fn site_packages_below(prefix: &Path, python_version: (u8, u8)) -> PathBuf {
+        if cfg!(windows) {
+            prefix.join("Lib").join("site-packages")
+        } else {
+            prefix
+                .join("lib")
+                .join(format!("python{}.{}", python_version.0, python_version.1))
+                .join("site-packages")
+        }
+    }
 }
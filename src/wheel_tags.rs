@@ -4,6 +4,7 @@ use crate::WheelInstallerError;
 use anyhow::Context;
 use anyhow::{anyhow, Result};
 use fs_err as fs;
+use goblin::elf::header::{EM_386, EM_ARM};
 use goblin::elf::Elf;
 use platform_info::{PlatformInfo, Uname};
 use regex::Regex;
@@ -12,11 +13,20 @@ use std::fmt;
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
 use std::str::FromStr;
+use std::sync::OnceLock;
+use tracing::warn;
+
+/// `e_flags` bit set when a 32-bit ARM binary uses the hard-float (VFP register-based) calling
+/// convention, i.e. what makes it an `armv7l` (as opposed to `armel`) wheel platform
+const EF_ARM_ABI_FLOAT_HARD: u32 = 0x400;
 
 #[derive(Debug)]
 pub struct WheelFilename {
     pub distribution: String,
     pub version: String,
+    /// The optional build tag, split into its leading digit sequence and trailing alphanumeric
+    /// remainder per PEP 427, e.g. `1_ubuntu1` -> `(1, "_ubuntu1")`
+    pub build_tag: Option<(u32, String)>,
     pub python_tag: Vec<String>,
     pub abi_tag: Vec<String>,
     pub platform_tag: Vec<String>,
@@ -34,11 +44,20 @@ impl FromStr for WheelFilename {
         })?;
         // https://www.python.org/dev/peps/pep-0427/#file-name-convention
         match basename.split('-').collect::<Vec<_>>().as_slice() {
-            // TODO: Build tag precedence
-            &[distribution, version, _, python_tag, abi_tag, platform_tag]
-            | &[distribution, version, python_tag, abi_tag, platform_tag] => Ok(WheelFilename {
+            &[distribution, version, build_tag, python_tag, abi_tag, platform_tag] => {
+                Ok(WheelFilename {
+                    distribution: distribution.to_string(),
+                    version: version.to_string(),
+                    build_tag: Some(parse_build_tag(build_tag)?),
+                    python_tag: python_tag.split('.').map(String::from).collect(),
+                    abi_tag: abi_tag.split('.').map(String::from).collect(),
+                    platform_tag: platform_tag.split('.').map(String::from).collect(),
+                })
+            }
+            &[distribution, version, python_tag, abi_tag, platform_tag] => Ok(WheelFilename {
                 distribution: distribution.to_string(),
                 version: version.to_string(),
+                build_tag: None,
                 python_tag: python_tag.split('.').map(String::from).collect(),
                 abi_tag: abi_tag.split('.').map(String::from).collect(),
                 platform_tag: platform_tag.split('.').map(String::from).collect(),
@@ -51,6 +70,23 @@ impl FromStr for WheelFilename {
     }
 }
 
+/// Splits a build tag into its required leading digit sequence and optional trailing
+/// alphanumeric remainder, per PEP 427 (`{number}[{alphanumeric}]`, e.g. `1_ubuntu1`)
+fn parse_build_tag(raw: &str) -> Result<(u32, String), WheelInstallerError> {
+    let invalid = || {
+        WheelInstallerError::InvalidWheelFileName(
+            raw.to_string(),
+            "Build tag must start with a digit".to_string(),
+        )
+    };
+    let digit_end = raw.find(|c: char| !c.is_ascii_digit()).unwrap_or(raw.len());
+    if digit_end == 0 {
+        return Err(invalid());
+    }
+    let number = raw[..digit_end].parse::<u32>().map_err(|_| invalid())?;
+    Ok((number, raw[digit_end..].to_string()))
+}
+
 impl WheelFilename {
     pub fn is_compatible(&self, compatible_tags: &[(String, String, String)]) -> bool {
         for tag in compatible_tags {
@@ -63,6 +99,54 @@ impl WheelFilename {
         }
         false
     }
+
+    /// A sort key giving build tag precedence among otherwise-equally-compatible wheels: a higher
+    /// numeric build tag wins, and no build tag at all sorts lowest
+    pub fn build_tag_ordering(&self) -> (bool, u32, &str) {
+        match &self.build_tag {
+            Some((number, suffix)) => (true, *number, suffix.as_str()),
+            None => (false, 0, ""),
+        }
+    }
+}
+
+/// Which Python implementation we're generating tags for, mirroring the distinction
+/// `packaging.tags.interpreter_name()`/`interpreter_version()` draw between CPython and
+/// everything else
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum InterpreterKind {
+    CPython,
+    /// `abi_tag` is the running interpreter's own ABI tag, e.g. `pypy38_pp73`
+    PyPy { abi_tag: String },
+    /// Any other implementation (GraalPy, Jython, ...); `interpreter_tag` is its short tag (e.g.
+    /// `gp` for GraalPy) and `abi_tag` its own ABI tag, both obtained from the running interpreter
+    /// rather than assumed
+    Other {
+        interpreter_tag: String,
+        abi_tag: String,
+    },
+}
+
+impl InterpreterKind {
+    /// The short interpreter tag `packaging.tags` calls `interpreter_name()`, e.g. `cp`/`pp`
+    fn interpreter_tag(&self) -> &str {
+        match self {
+            InterpreterKind::CPython => "cp",
+            InterpreterKind::PyPy { .. } => "pp",
+            InterpreterKind::Other { interpreter_tag, .. } => interpreter_tag,
+        }
+    }
+
+    /// The interpreter's own concrete ABI tag, or `None` for CPython where it's derived from the
+    /// python version instead (`cp38`, `cp39`, ...)
+    fn abi_tag(&self) -> Option<&str> {
+        match self {
+            InterpreterKind::CPython => None,
+            InterpreterKind::PyPy { abi_tag } | InterpreterKind::Other { abi_tag, .. } => {
+                Some(abi_tag)
+            }
+        }
+    }
 }
 
 /// Returns the compatible tags in a (python_tag, abi_tag, platform_tag) format
@@ -70,34 +154,51 @@ pub fn compatible_tags(
     python_version: (u8, u8),
     os: &Os,
     arch: &Arch,
+    interpreter: &InterpreterKind,
 ) -> Result<Vec<(String, String, String)>, WheelInstallerError> {
     assert_eq!(python_version.0, 3);
     let mut tags = Vec::new();
     let platform_tags = compatible_platform_tags(os, arch)?;
-    // 1. This exact c api version
+    let is_cpython = matches!(interpreter, InterpreterKind::CPython);
+    // 1. This exact c api version, e.g. `pp38-pypy38_pp73-<platform>` under PyPy or
+    // `cp38-cp38-<platform>` under CPython
+    let exact_abi_tag = interpreter
+        .abi_tag()
+        .map(str::to_string)
+        .unwrap_or_else(|| format!("cp{}{}", python_version.0, python_version.1));
     for platform_tag in &platform_tags {
         tags.push((
-            format!("cp{}{}", python_version.0, python_version.1),
-            format!("cp{}{}", python_version.0, python_version.1),
-            platform_tag.clone(),
-        ));
-        tags.push((
-            format!("cp{}{}", python_version.0, python_version.1),
-            "none".to_string(),
+            format!(
+                "{}{}{}",
+                interpreter.interpreter_tag(),
+                python_version.0,
+                python_version.1
+            ),
+            exact_abi_tag.clone(),
             platform_tag.clone(),
         ));
-    }
-    // 2. abi3 and no abi (e.g. executable binary)
-    // For some reason 3.2 is the minimum python for the cp abi
-    for minor in 2..=python_version.1 {
-        for platform_tag in &platform_tags {
+        if is_cpython {
             tags.push((
-                format!("cp{}{}", python_version.0, minor),
-                "abi3".to_string(),
+                format!("cp{}{}", python_version.0, python_version.1),
+                "none".to_string(),
                 platform_tag.clone(),
             ));
         }
     }
+    // 2. abi3 and no abi (e.g. executable binary); abi3 is CPython's stable ABI, so it doesn't
+    // apply to other interpreters
+    // For some reason 3.2 is the minimum python for the cp abi
+    if is_cpython {
+        for minor in 2..=python_version.1 {
+            for platform_tag in &platform_tags {
+                tags.push((
+                    format!("cp{}{}", python_version.0, minor),
+                    "abi3".to_string(),
+                    platform_tag.clone(),
+                ));
+            }
+        }
+    }
     // 3. no abi (e.g. executable binary)
     for minor in 0..=python_version.1 {
         for platform_tag in &platform_tags {
@@ -137,9 +238,14 @@ pub fn compatible_tags(
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub enum Os {
     Manylinux { major: u16, minor: u16 },
+    /// Alpine and other musl-based distros, detected in [`Os::detect_linux_libc`] by reading the
+    /// loader pointed to by `/bin/ls`'s `PT_INTERP` and parsing `Version X.Y.Z` from its
+    /// `--version`-less stderr output
     Musllinux { major: u16, minor: u16 },
     Windows,
     Macos { major: u16, minor: u16 },
+    /// PEP 730
+    Ios { major: u16, minor: u16, simulator: bool },
     FreeBsd { release: String },
     NetBsd { release: String },
     OpenBsd { release: String },
@@ -148,11 +254,54 @@ pub enum Os {
     Haiku { release: String },
 }
 
+/// Asks glibc itself for its version via `gnu_get_libc_version()`, rather than relying on the
+/// `ld-X.Y.so` symlink name being in the expected format. Returns `None` on non-glibc targets or
+/// if the reported version string doesn't parse, in which case [`Os::detect_linux_libc`] falls
+/// back to [`detect_glibc_version_via_getconf`] and then to the symlink-name parse.
+fn detect_glibc_version() -> Option<(u16, u16)> {
+    #[cfg(all(target_os = "linux", target_env = "gnu"))]
+    {
+        let version = unsafe { std::ffi::CStr::from_ptr(libc::gnu_get_libc_version()) };
+        let version = version.to_str().ok()?;
+        let mut parts = version.split('.');
+        let major = parts.next()?.parse().ok()?;
+        let minor = parts.next()?.parse().ok()?;
+        Some((major, minor))
+    }
+    #[cfg(not(all(target_os = "linux", target_env = "gnu")))]
+    None
+}
+
+/// Asks `getconf GNU_LIBC_VERSION` for the glibc version, e.g. `glibc 2.31`. Unlike
+/// [`detect_glibc_version`], this doesn't require monotrail itself to have been built against
+/// glibc (a musl-static monotrail binary running on a glibc host still has a `getconf` that
+/// reports the host's libc), so [`Os::detect_linux_libc`] tries it before falling back to parsing
+/// the `ld-X.Y.so` symlink name.
+fn detect_glibc_version_via_getconf() -> Option<(u16, u16)> {
+    let output = Command::new("getconf")
+        .arg("GNU_LIBC_VERSION")
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let version = stdout.split_whitespace().last()?;
+    let mut parts = version.split('.');
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next()?.parse().ok()?;
+    Some((major, minor))
+}
+
 impl Os {
     fn detect_linux_libc() -> anyhow::Result<Self> {
         let libc = find_libc()?;
         if let Ok(Some((major, minor))) = get_musl_version(&libc) {
             Ok(Os::Musllinux { major, minor })
+        } else if let Some((major, minor)) = detect_glibc_version() {
+            Ok(Os::Manylinux { major, minor })
+        } else if let Some((major, minor)) = detect_glibc_version_via_getconf() {
+            Ok(Os::Manylinux { major, minor })
         } else if let Ok(glibc_ld) = fs::read_link(&libc) {
             let filename = glibc_ld
                 .file_name()
@@ -171,13 +320,33 @@ impl Os {
         }
     }
 
+    /// Same as [`Os::detect_current`], cached: the host's libc kind/version can't change over the
+    /// lifetime of the process, but detecting it spawns subprocesses (`getconf`, the dynamic
+    /// loader) and re-reads `/bin/ls`'s ELF header, which is wasteful across the several call
+    /// sites (`cli`, `monotrail`, `virtual_sprawl`) that each ask for it once per invocation.
     pub fn current() -> std::result::Result<Self, WheelInstallerError> {
+        static CACHE: OnceLock<Os> = OnceLock::new();
+        if let Some(os) = CACHE.get() {
+            return Ok(os.clone());
+        }
+        let os = Self::detect_current()?;
+        Ok(CACHE.get_or_init(|| os.clone()).clone())
+    }
+
+    fn detect_current() -> std::result::Result<Self, WheelInstallerError> {
         let target_triple = target_lexicon::HOST;
 
         let os = match target_triple.operating_system {
-            target_lexicon::OperatingSystem::Linux => {
-                Self::detect_linux_libc().map_err(WheelInstallerError::OsVersionDetectionError)?
-            }
+            target_lexicon::OperatingSystem::Linux => Self::detect_linux_libc().unwrap_or_else(|err| {
+                // Treating an undetectable libc as fatal would mean monotrail can't run at all on
+                // any host we can't positively identify, so we fall back to the oldest manylinux
+                // baseline we still support instead, same as if no newer tags were compatible
+                warn!(
+                    "Couldn't detect the host libc version ({:#}), falling back to manylinux_2_17",
+                    err
+                );
+                Os::Manylinux { major: 2, minor: 17 }
+            }),
             target_lexicon::OperatingSystem::Windows => Os::Windows,
             target_lexicon::OperatingSystem::MacOSX { major, minor, .. } => {
                 Os::Macos { major, minor }
@@ -186,6 +355,11 @@ impl Os {
                 let (major, minor) = get_mac_os_version()?;
                 Os::Macos { major, minor }
             }
+            target_lexicon::OperatingSystem::Ios => {
+                let (major, minor) = get_mac_os_version()?;
+                let simulator = matches!(target_triple.environment, target_lexicon::Environment::Sim);
+                Os::Ios { major, minor, simulator }
+            }
             target_lexicon::OperatingSystem::Netbsd => Os::NetBsd {
                 release: PlatformInfo::new()?.release().to_string(),
             },
@@ -217,6 +391,105 @@ impl Os {
         };
         Ok(os)
     }
+
+    /// Resolves `(Os, Arch)` for an arbitrary Rust/LLVM-style target triple (e.g.
+    /// `aarch64-unknown-linux-gnu`, `x86_64-apple-darwin`, `x86_64-unknown-linux-musl`) instead of
+    /// [`Os::current`]/[`Arch::current`]'s host, so callers can compute the compatible tag set for
+    /// a cross-compilation or CI target they aren't actually running on.
+    ///
+    /// Since there's no running interpreter to probe, the libc/macOS/iOS version is the oldest
+    /// baseline this crate still supports rather than something detected, same as the fallback
+    /// [`Os::current`] uses when host detection fails.
+    pub fn for_target_triple(triple: &str) -> Result<(Os, Arch), WheelInstallerError> {
+        let parsed: target_lexicon::Triple = triple.parse().or_else(|err| {
+            Self::target_triple_via_rustc(triple).ok_or_else(|| {
+                WheelInstallerError::OsVersionDetectionError(anyhow!(
+                    "Invalid target triple {}: {}",
+                    triple,
+                    err
+                ))
+            })
+        })?;
+
+        let arch = match parsed.architecture {
+            target_lexicon::Architecture::X86_64 => Arch::X86_64,
+            target_lexicon::Architecture::X86_32(_) => Arch::X86,
+            target_lexicon::Architecture::Arm(_) => Arch::Armv7L,
+            target_lexicon::Architecture::Aarch64(_) => Arch::Aarch64,
+            target_lexicon::Architecture::Powerpc64 => Arch::Powerpc64,
+            target_lexicon::Architecture::Powerpc64le => Arch::Powerpc64Le,
+            target_lexicon::Architecture::S390x => Arch::S390X,
+            unsupported => {
+                return Err(WheelInstallerError::OsVersionDetectionError(anyhow!(
+                    "The architecture {} in target triple {} is not supported",
+                    unsupported,
+                    triple
+                )));
+            }
+        };
+
+        let is_musl = matches!(
+            parsed.environment,
+            target_lexicon::Environment::Musl
+                | target_lexicon::Environment::Musleabi
+                | target_lexicon::Environment::Musleabihf
+        );
+        let os = match parsed.operating_system {
+            target_lexicon::OperatingSystem::Linux if is_musl => {
+                Os::Musllinux { major: 1, minor: 1 }
+            }
+            target_lexicon::OperatingSystem::Linux => Os::Manylinux { major: 2, minor: 17 },
+            target_lexicon::OperatingSystem::Windows => Os::Windows,
+            target_lexicon::OperatingSystem::MacOSX { major, minor, .. } => {
+                Os::Macos { major, minor }
+            }
+            target_lexicon::OperatingSystem::Darwin => Os::Macos {
+                major: 11,
+                minor: 0,
+            },
+            target_lexicon::OperatingSystem::Ios => {
+                let simulator = matches!(parsed.environment, target_lexicon::Environment::Sim);
+                Os::Ios {
+                    major: 13,
+                    minor: 0,
+                    simulator,
+                }
+            }
+            unsupported => {
+                return Err(WheelInstallerError::OsVersionDetectionError(anyhow!(
+                    "The operating system {:?} in target triple {} is not supported",
+                    unsupported,
+                    triple
+                )));
+            }
+        };
+        Ok((os, arch))
+    }
+
+    /// Fallback for triples `target_lexicon` doesn't recognize: ask rustc itself what
+    /// `target_os`/`target_env`/`target_arch` it would build with
+    fn target_triple_via_rustc(triple: &str) -> Option<target_lexicon::Triple> {
+        let output = Command::new("rustc")
+            .args(["--print", "cfg", "--target", triple])
+            .output()
+            .ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        let cfg = String::from_utf8(output.stdout).ok()?;
+        let field = |key: &str| {
+            cfg.lines()
+                .find_map(|line| line.strip_prefix(&format!("{}=\"", key)))
+                .and_then(|rest| rest.strip_suffix('"'))
+        };
+        let rebuilt = format!(
+            "{}-unknown-{}-{}",
+            field("target_arch")?,
+            field("target_os")?,
+            field("target_env").unwrap_or("gnu"),
+        );
+        rebuilt.parse().ok()
+    }
 }
 
 impl fmt::Display for Os {
@@ -226,6 +499,7 @@ impl fmt::Display for Os {
             Os::Musllinux { .. } => write!(f, "Musllinux"),
             Os::Windows => write!(f, "Windows"),
             Os::Macos { .. } => write!(f, "MacOS"),
+            Os::Ios { .. } => write!(f, "iOS"),
             Os::FreeBsd { .. } => write!(f, "FreeBSD"),
             Os::NetBsd { .. } => write!(f, "NetBSD"),
             Os::OpenBsd { .. } => write!(f, "OpenBSD"),
@@ -241,6 +515,10 @@ impl fmt::Display for Os {
 pub enum Arch {
     Aarch64,
     Armv7L,
+    /// What `uname -m` reports under `linux32` emulation on an aarch64 box running a 32-bit
+    /// interpreter. Not a real PyPI platform tag by itself; [`Arch::normalize`] resolves it to
+    /// `Armv7L` once the interpreter's own ELF header confirms a hard-float ABI.
+    Armv8L,
     Powerpc64Le,
     Powerpc64,
     X86,
@@ -253,6 +531,7 @@ impl fmt::Display for Arch {
         match *self {
             Arch::Aarch64 => write!(f, "aarch64"),
             Arch::Armv7L => write!(f, "armv7l"),
+            Arch::Armv8L => write!(f, "armv8l"),
             Arch::Powerpc64Le => write!(f, "ppc64le"),
             Arch::Powerpc64 => write!(f, "ppc64"),
             Arch::X86 => write!(f, "i686"),
@@ -287,11 +566,78 @@ impl Arch {
     pub fn get_minimum_manylinux_minor(&self) -> u16 {
         match self {
             // manylinux 2014
-            Arch::Aarch64 | Arch::Armv7L | Arch::Powerpc64 | Arch::Powerpc64Le | Arch::S390X => 17,
+            Arch::Aarch64 | Arch::Armv7L | Arch::Armv8L | Arch::Powerpc64 | Arch::Powerpc64Le
+            | Arch::S390X => 17,
             // manylinux 1
             Arch::X86 | Arch::X86_64 => 5,
         }
     }
+
+    /// Parses what `uname -m` reports. `armv8l` and a bare `i686`/`i386` are kept as-is here and
+    /// only resolved into a concrete, tag-eligible [`Arch`] by [`Arch::normalize`], since the
+    /// string alone isn't enough to tell a real 32-bit interpreter from an emulated/misreported
+    /// one.
+    pub fn from_uname(machine: &str) -> Result<Arch, WheelInstallerError> {
+        let arch = match machine {
+            "x86_64" => Arch::X86_64,
+            "aarch64" => Arch::Aarch64,
+            "armv7l" => Arch::Armv7L,
+            "armv8l" => Arch::Armv8L,
+            "i686" | "i386" => Arch::X86,
+            "ppc64le" => Arch::Powerpc64Le,
+            "ppc64" => Arch::Powerpc64,
+            "s390x" => Arch::S390X,
+            unsupported => {
+                return Err(WheelInstallerError::OsVersionDetectionError(anyhow!(
+                    "The architecture {} is not supported",
+                    unsupported
+                )));
+            }
+        };
+        Ok(arch)
+    }
+
+    /// Confirms an [`Arch`] parsed from `uname -m` against the actual ELF header of the Python
+    /// interpreter it's supposed to describe, for the two cases where the machine string alone
+    /// is unreliable:
+    /// - `armv8l`, reported by an aarch64 kernel running a 32-bit interpreter under `linux32`
+    ///   emulation — resolved to `Armv7L` if the ELF header's `EF_ARM_ABI_FLOAT_HARD` bit is set,
+    ///   since armv8l isn't a real PyPI platform and soft-float ARM has no manylinux tags at all
+    /// - `X86`, which some 32-bit-on-64-bit setups report even though the interpreter is actually
+    ///   64-bit
+    pub fn normalize(self, python_binary: &Path) -> Result<Arch, WheelInstallerError> {
+        let buffer = fs::read(python_binary)
+            .with_context(|| format!("Couldn't read {} to detect its architecture", python_binary.display()))
+            .map_err(WheelInstallerError::OsVersionDetectionError)?;
+        let elf = Elf::parse(&buffer)
+            .with_context(|| format!("{} is not a valid ELF file", python_binary.display()))
+            .map_err(WheelInstallerError::OsVersionDetectionError)?;
+        match self {
+            Arch::Armv7L | Arch::Armv8L => {
+                let is_hardfloat = elf.header.e_machine == EM_ARM
+                    && elf.header.e_flags & EF_ARM_ABI_FLOAT_HARD != 0;
+                if is_hardfloat {
+                    Ok(Arch::Armv7L)
+                } else {
+                    Err(WheelInstallerError::OsVersionDetectionError(anyhow!(
+                        "{} has no matching manylinux/musllinux platform tag (soft-float ABI)",
+                        self
+                    )))
+                }
+            }
+            Arch::X86 => {
+                if elf.header.e_machine == EM_386 && !elf.is_64 {
+                    Ok(Arch::X86)
+                } else {
+                    Err(WheelInstallerError::OsVersionDetectionError(anyhow!(
+                        "uname reported i686, but {} is not a 32-bit x86 ELF binary",
+                        python_binary.display()
+                    )))
+                }
+            }
+            other => Ok(other),
+        }
+    }
 }
 
 fn get_mac_os_version() -> Result<(u16, u16), WheelInstallerError> {
@@ -328,11 +674,19 @@ fn get_mac_os_version() -> Result<(u16, u16), WheelInstallerError> {
 }
 
 /// Find musl libc path from executable's ELF header
+///
+/// `/bin/ls` is just a convenient, reliably-present binary to read the `PT_INTERP` program header
+/// from; we fall back to `/bin/sh` in case `/bin/ls` doesn't exist (e.g. some minimal containers)
 pub fn find_libc() -> anyhow::Result<PathBuf> {
+    let candidates = ["/bin/ls", "/bin/sh"];
+    let probe = candidates
+        .iter()
+        .find(|path| Path::new(path).is_file())
+        .with_context(|| format!("None of {:?} exist to detect the ld version from", candidates))?;
     let buffer =
-        fs::read("/bin/ls").context("Couldn't read /bin/ls for detecting the ld version")?;
-    let parse_error = "Couldn't parse /bin/ls for detecting the ld version";
-    let elf = Elf::parse(&buffer).context(parse_error)?;
+        fs::read(probe).with_context(|| format!("Couldn't read {} for detecting the ld version", probe))?;
+    let parse_error = format!("Couldn't parse {} for detecting the ld version", probe);
+    let elf = Elf::parse(&buffer).context(parse_error.clone())?;
     elf.interpreter.map(PathBuf::from).context(parse_error)
 }
 
@@ -438,6 +792,16 @@ pub(crate) fn compatible_platform_tags(
                 .extend((0..=minor).map(|minor| format!("macosx_{}_{}_universal2", major, minor)));
             platform_tags
         }
+        (Os::Ios { major, minor, simulator }, Arch::Aarch64 | Arch::X86_64) => {
+            let platform = if simulator {
+                "iphonesimulator"
+            } else {
+                "iphoneos"
+            };
+            (0..=minor)
+                .map(|minor| format!("ios_{}_{}_{}_{}", major, minor, arch, platform))
+                .collect()
+        }
         (Os::Windows, Arch::X86) => {
             vec!["win32".to_string()]
         }
@@ -602,16 +966,67 @@ mod test {
                     Arch::X86_64,
                 ),
             ),
+            // Pre-PEP600 aliases, still the only tag many older wheels on PyPI publish
+            (
+                "cryptography-3.4.7-cp36-abi3-manylinux2014_x86_64.whl",
+                (
+                    (3, 8),
+                    Os::Manylinux {
+                        major: 2,
+                        minor: 31,
+                    },
+                    Arch::X86_64,
+                ),
+            ),
+            (
+                "cryptography-3.4.7-cp36-abi3-manylinux2010_x86_64.whl",
+                (
+                    (3, 8),
+                    Os::Manylinux {
+                        major: 2,
+                        minor: 31,
+                    },
+                    Arch::X86_64,
+                ),
+            ),
+            (
+                "cryptography-3.4.7-cp36-abi3-manylinux1_x86_64.whl",
+                (
+                    (3, 8),
+                    Os::Manylinux {
+                        major: 2,
+                        minor: 31,
+                    },
+                    Arch::X86_64,
+                ),
+            ),
         ];
 
         for (filename, (python_version, os, arch)) in filenames {
-            let compatible_tags = compatible_tags(python_version, &os, &arch)?;
+            let compatible_tags =
+                compatible_tags(python_version, &os, &arch, &super::InterpreterKind::CPython)?;
             assert!(
                 WheelFilename::from_str(filename)?.is_compatible(&compatible_tags),
                 "{}",
                 filename
             );
         }
+
+        let compatible_tags = compatible_tags(
+            (3, 8),
+            &Os::Manylinux {
+                major: 2,
+                minor: 31,
+            },
+            &Arch::X86_64,
+            &super::InterpreterKind::PyPy {
+                abi_tag: "pypy38_pp73".to_string(),
+            },
+        )?;
+        assert!(WheelFilename::from_str(
+            "numpy-1.22.2-pp38-pypy38_pp73-manylinux_2_17_x86_64.manylinux2014_x86_64.whl"
+        )?
+        .is_compatible(&compatible_tags));
         Ok(())
     }
 
@@ -625,6 +1040,7 @@ mod test {
                 minor: 31,
             },
             &Arch::X86_64,
+            &super::InterpreterKind::CPython,
         )?;
 
         let compatible: Vec<&str> = FILENAMES
@@ -664,6 +1080,7 @@ mod test {
                     minor: 31,
                 },
                 &Arch::X86_64,
+                &super::InterpreterKind::CPython,
             )?;
 
             assert!(
@@ -687,6 +1104,7 @@ mod test {
                 minor: 31,
             },
             &Arch::X86_64,
+            &super::InterpreterKind::CPython,
         )?
         .iter()
         .map(|(python_tag, abi_tag, platform_tag)| {
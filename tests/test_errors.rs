@@ -2,7 +2,7 @@
 
 use anyhow::Result;
 use clap::Parser;
-use monotrail::{assert_cli_error, run_cli, Cli};
+use monotrail::{assert_cli_error, run_cli, Args};
 use std::path::Path;
 use std::process::Command;
 use tempfile::TempDir;
@@ -14,9 +14,8 @@ fn check_error(name: &str, expected: &[&str]) -> Result<()> {
     let venv = temp_dir.path().join(".venv");
     Command::new("virtualenv").arg(&venv).output()?;
     let wheel = Path::new("test-data").join("pip-test-packages").join(name);
-    let cli: Cli =
-        Cli::try_parse_from(["monotrail", "venv-install", &wheel.display().to_string()])?;
-    assert_cli_error(cli, Some(&venv), expected);
+    let args = Args::try_parse_from(["monotrail", "venv-install", &wheel.display().to_string()])?;
+    assert_cli_error(args.command, Some(&venv), expected);
     Ok(())
 }
 
@@ -67,7 +66,7 @@ fn test_priority() -> Result<()> {
 /// but we load python so i'm not putting this into a unit test
 #[test]
 fn test_cli_python_hyphen() {
-    let cli = Cli::try_parse_from([
+    let args = Args::try_parse_from([
         BIN,
         "run",
         "--root",
@@ -77,12 +76,12 @@ fn test_cli_python_hyphen() {
         "fail()",
     ])
     .unwrap();
-    assert_eq!(run_cli(cli, None).unwrap(), Some(1));
+    assert_eq!(run_cli(args.command, None, None).unwrap(), Some(1));
 }
 
 #[test]
 fn test_neither_command_nor_python() {
-    let cli = Cli::try_parse_from([BIN, "run", "bogus"]).unwrap();
+    let args = Args::try_parse_from([BIN, "run", "bogus"]).unwrap();
     let expected = &["invalid command `bogus`, must be 'python' or 'command'"];
-    assert_cli_error(cli, None, expected);
+    assert_cli_error(args.command, None, expected);
 }